@@ -0,0 +1,128 @@
+//! Compares tracing scenes with a linear scan against tracing them with a BVH
+//!
+//! The second scene is a finely tessellated mesh on a ground of two large triangles, which trees
+//! that split at the median of the list handle poorly
+
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::camera::{Camera, CameraSettings};
+use ray_tracing::consts::PI;
+use ray_tracing::hittable::TriangleMesh;
+use ray_tracing::material::Lambertian;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::texture::SolidColor;
+use ray_tracing::world::World;
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::time::Instant;
+
+fn trace(world: &World, rays: &[Ray]) -> usize {
+    rays.iter()
+        .filter(|ray| world.hit(ray, 0.001, Float::INFINITY).is_some())
+        .count()
+}
+
+/// A sphere of `rings` by `2 * rings` quads, sitting on a ground made of two triangles
+fn mesh_world(rings: usize) -> World {
+    let mut world = World::default();
+    let grey = || Lambertian::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+    let mut positions = Vec::new();
+    for ring in 0..=rings {
+        let theta = PI * ring as Float / rings as Float;
+        for segment in 0..=2 * rings {
+            let phi = PI * segment as Float / rings as Float;
+            let (x, z) = (theta.sin() * phi.cos(), theta.sin() * phi.sin());
+            positions.push(point3!(x, 1. + theta.cos(), z));
+        }
+    }
+    let corner = |ring: usize, segment: usize| ring * (2 * rings + 1) + segment;
+    let mut indices = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..2 * rings {
+            let (a, b) = (corner(ring, segment), corner(ring, segment + 1));
+            let (c, d) = (corner(ring + 1, segment), corner(ring + 1, segment + 1));
+            indices.push([a, c, b]);
+            indices.push([b, c, d]);
+        }
+    }
+    world.add_mesh(TriangleMesh::new(
+        positions,
+        Vec::new(),
+        Vec::new(),
+        indices,
+        grey(),
+    ));
+    let ground = vec![
+        point3!(-100., 0., -100.),
+        point3!(100., 0., -100.),
+        point3!(-100., 0., 100.),
+        point3!(100., 0., 100.),
+    ];
+    let indices = vec![[0, 2, 1], [1, 2, 3]];
+    world.add_mesh(TriangleMesh::new(
+        ground,
+        Vec::new(),
+        Vec::new(),
+        indices,
+        grey(),
+    ));
+    world
+}
+
+fn benchmark(name: &str, mut world: World, settings: &CameraSettings) {
+    let camera = Camera::new(settings, 16. / 9.);
+    let mut ctx = SampleCtx::from_seed(Some(0));
+    let (width, height) = (160, 90);
+    let rays: Vec<Ray> = (0..height)
+        .flat_map(|j| (0..width).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            let u = i as Float / (width - 1) as Float;
+            let v = j as Float / (height - 1) as Float;
+            camera.get_ray(u, v, &mut ctx)
+        })
+        .collect();
+
+    println!(
+        "{}: {} hittables, {} rays",
+        name,
+        world.hittables.len(),
+        rays.len()
+    );
+
+    let start = Instant::now();
+    let hits = trace(&world, &rays);
+    println!("Linear scan: {} hits in {:?}", hits, start.elapsed());
+
+    let start = Instant::now();
+    world
+        .build_bvh(settings.t0, settings.t1)
+        .expect("Error building BVH");
+    println!("Built BVH in {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let hits = trace(&world, &rays);
+    println!("BVH: {} hits in {:?}", hits, start.elapsed());
+}
+
+fn main() {
+    benchmark(
+        "Spheres",
+        World::benchmark_world(),
+        &CameraSettings::cover_camera(),
+    );
+    let mesh_camera = CameraSettings {
+        look_from: point3!(4., 3., 6.),
+        look_at: point3!(0., 0.8, 0.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 30.,
+        aperture: 0.,
+        focus_dist: 7.,
+        t0: 0.,
+        t1: 1.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    };
+    benchmark("Mesh", mesh_world(50), &mesh_camera);
+}
@@ -0,0 +1,48 @@
+//! Renders the camera flying half way around the cover scene to `frames/`
+
+use ray_tracing::animation::{Easing, Keyframes, Sequence};
+use ray_tracing::background::GradientBackground;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::error::Error;
+use ray_tracing::integrator::PathIntegrator;
+use ray_tracing::world::World;
+use ray_tracing::{Point3, RenderSettings};
+
+fn main() -> Result<(), Error> {
+    let start = CameraSettings::cover_camera();
+    let side = CameraSettings {
+        look_from: Point3::new(3., 3., 13.),
+        ..start.clone()
+    };
+    let end = CameraSettings {
+        look_from: Point3::new(-13., 2., 3.),
+        ..start.clone()
+    };
+    let camera = Keyframes::new(start)
+        .key(1., side)
+        .key(2., end)
+        .with_easing(Easing::Smooth);
+
+    let mut settings = RenderSettings {
+        samples_per_pixel: 16,
+        background: Box::new(GradientBackground::sky()),
+        seed: Some(0),
+        ..Default::default()
+    };
+    // Build the random scene once so it stays the same in every frame
+    let file = ray_tracing::scene::SceneFile::from_world(
+        &World::cover_world(),
+        &CameraSettings::cover_camera(),
+        &settings,
+        320,
+        180,
+    )?;
+    let sequence = Sequence::new(0..48, 320, 180);
+    sequence.render_to_files(
+        &mut settings,
+        &PathIntegrator,
+        &camera,
+        |_| file.build().expect("Error building scene").world,
+        "frames",
+    )
+}
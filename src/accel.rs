@@ -0,0 +1,696 @@
+//! Acceleration structures, which find the hittable a ray meets first without testing them all
+//!
+//! `World::build_accelerator` works with anything implementing `Accelerator`, so other
+//! structures such as kd-trees or grids can be tried without changing the world. `Bvh` is the
+//! one `World::build_bvh` uses, and `Grid` is quicker to rebuild for every frame of a preview
+
+use crate::error::Error;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::world::{BoxPacket, SphereBatch, World, AABB, BOX_LANES};
+use crate::{Float, Vec3};
+
+/// A structure holding many hittables that is hit like one of them
+///
+/// `Hittable::hit` finds the closest hit and `Hittable::bounding_box` bounds everything inside.
+/// `Hittable::children` should list the hittables it was built from, so tools that walk the
+/// scene still find them, and `Hittable::accelerator` should return itself
+pub trait Accelerator: Hittable {
+    /// Builds the structure around a list of hittables, bounded between the shutter times `t0`
+    /// and `t1`. Fails if the list is empty or any hittable has no bounding box
+    fn build(
+        hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+        t0: Float,
+        t1: Float,
+    ) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// Boxes the structure divides space into, each with how deep in the structure it is
+    /// starting from 0, for inspecting it. Empty by default
+    fn node_boxes(&self) -> Vec<(u32, AABB)> {
+        Vec::new()
+    }
+}
+
+/// Which acceleration structure a render builds, see `RenderSettings::accelerator`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AcceleratorKind {
+    /// `Bvh`, the fastest to trace
+    Bvh,
+    /// `Grid`, the fastest to build
+    Grid,
+}
+
+impl AcceleratorKind {
+    /// Builds this kind of structure around the world's hittables, like `World::build_bvh`
+    pub fn build(self, world: &mut World, t0: Float, t1: Float) -> Result<(), Error> {
+        match self {
+            AcceleratorKind::Bvh => world.build_accelerator::<Bvh>(t0, t1),
+            AcceleratorKind::Grid => world.build_accelerator::<Grid>(t0, t1),
+        }
+    }
+}
+
+/// Buckets the centers of hittables are sorted into along each axis when looking for the
+/// cheapest split
+const SAH_BINS: usize = 12;
+
+/// Entries `Stack` holds before spilling onto the heap. Larger stacks take longer to set up for
+/// each ray than the rare spill costs
+const STACK_CAPACITY: usize = 16;
+
+/// Stack of nodes still to visit, kept in a fixed size array so traversal doesn't allocate
+/// unless the tree is unusually deep
+struct Stack<T> {
+    entries: [T; STACK_CAPACITY],
+    len: usize,
+    spilled: Vec<T>,
+}
+
+impl<T: Copy> Stack<T> {
+    fn new(first: T) -> Self {
+        Self {
+            entries: [first; STACK_CAPACITY],
+            len: 1,
+            spilled: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, entry: T) {
+        if self.len < STACK_CAPACITY {
+            self.entries[self.len] = entry;
+            self.len += 1;
+        } else {
+            self.spilled.push(entry);
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if let Some(entry) = self.spilled.pop() {
+            return Some(entry);
+        }
+        self.len = self.len.checked_sub(1)?;
+        Some(self.entries[self.len])
+    }
+}
+
+/// A hittable with its bounding box, while building a tree
+type Bounded = (AABB, Box<dyn Hittable + Send + Sync>);
+
+/// Expected cost of tracing `count` hittables inside `bbox`, relative to how likely a ray is to
+/// enter their parent
+fn sah_cost(count: usize, bbox: &Option<AABB>) -> Float {
+    bbox.as_ref()
+        .map_or(0., |bbox| count as Float * bbox.surface_area())
+}
+
+/// What a child of a `BvhNode` is
+#[derive(Clone, Copy)]
+enum Link {
+    /// Index of another node
+    Node(u32),
+    /// Index of a hittable or batch of spheres
+    Leaf(u32),
+}
+
+/// A node of a `Bvh` with up to `BOX_LANES` children, whose boxes are tested together
+struct BvhNode {
+    /// Boxes of the children, so they can be tested before visiting any of them
+    boxes: BoxPacket,
+    children: [Option<Link>; BOX_LANES],
+}
+
+/// Bounding volume hierarchy stored as flat arrays and traversed with a stack, rather than as
+/// nodes pointing at nodes
+///
+/// Nodes come right before the subtrees of their children, so the start of a ray's walk down the
+/// tree is mostly in one place in memory. Leaves with only still spheres become a `SphereBatch`
+pub struct Bvh {
+    /// Root first
+    nodes: Vec<BvhNode>,
+    leaves: Vec<Box<dyn Hittable + Send + Sync>>,
+    /// Box around each node, kept apart from `nodes` as traversal only needs their children's
+    node_bounds: Vec<AABB>,
+}
+
+impl Bvh {
+    /// Adds a node for at least one hittable, then the subtrees of its children, returning its
+    /// index
+    ///
+    /// Splits the list twice with the surface area heuristic, giving up to four children. A
+    /// single hittable becomes the only child
+    fn add_node(&mut self, bounded: Vec<Bounded>) -> u32 {
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            boxes: BoxPacket::new(&[]),
+            children: [None; BOX_LANES],
+        });
+        let bounds = bounded
+            .iter()
+            .map(|(bbox, _)| bbox.clone())
+            .reduce(|a, b| AABB::surrounding_box(&a, &b))
+            .expect("Error building a tree of nothing");
+        self.node_bounds.push(bounds);
+
+        let mut groups = vec![bounded];
+        while groups.len() * 2 <= BOX_LANES && groups.iter().any(|group| group.len() >= 2) {
+            let mut halves = Vec::new();
+            for group in groups {
+                if group.len() < 2 {
+                    halves.push(group);
+                    continue;
+                }
+                let (lower, upper) = split(group);
+                halves.push(lower);
+                halves.push(upper);
+            }
+            groups = halves;
+        }
+
+        let mut boxes = Vec::new();
+        let mut children = [None; BOX_LANES];
+        for (child, group) in children.iter_mut().zip(groups) {
+            let bbox = group
+                .iter()
+                .map(|(bbox, _)| bbox.clone())
+                .reduce(|a, b| AABB::surrounding_box(&a, &b));
+            boxes.push(bbox);
+            *child = Some(self.add_child(group));
+        }
+        self.nodes[index] = BvhNode {
+            boxes: BoxPacket::new(&boxes),
+            children,
+        };
+        index as u32
+    }
+
+    /// A leaf for one hittable or few enough spheres, otherwise a new node
+    fn add_child(&mut self, mut group: Vec<Bounded>) -> Link {
+        let leaf: Box<dyn Hittable + Send + Sync> = if group.len() == 1 {
+            group.pop().unwrap().1
+        } else if group.len() <= SphereBatch::MAX_LEN
+            && group
+                .iter()
+                .all(|(_, hittable)| hittable.sphere().is_some())
+        {
+            let spheres = group.into_iter().map(|(_, hittable)| hittable).collect();
+            Box::new(SphereBatch::new(spheres))
+        } else {
+            return Link::Node(self.add_node(group));
+        };
+        self.leaves.push(leaf);
+        Link::Leaf(self.leaves.len() as u32 - 1)
+    }
+}
+
+/// Splits at least two hittables in two where the surface area heuristic expects tracing them to
+/// be cheapest
+///
+/// Tries the boundaries between `SAH_BINS` buckets of box centers along each axis, weighing the
+/// number of hittables on each side by the area of the box around them, as a ray passing through
+/// the parent box is that much more likely to enter it. Halves the list if every center is in the
+/// same place
+fn split(mut group: Vec<Bounded>) -> (Vec<Bounded>, Vec<Bounded>) {
+    let centers = group
+        .iter()
+        .map(|(bbox, _)| AABB::new(bbox.centroid(), bbox.centroid()))
+        .reduce(|a, b| AABB::surrounding_box(&a, &b))
+        .expect("Error splitting an empty list");
+    let (low, high) = (centers.min, centers.max);
+    let bin = |bbox: &AABB, axis: usize| {
+        let offset = (bbox.centroid()[axis] - low[axis]) / (high[axis] - low[axis]);
+        ((offset * SAH_BINS as Float) as usize).min(SAH_BINS - 1)
+    };
+
+    // Cheapest (cost, axis, first bin of the upper side)
+    let mut best: Option<(Float, usize, usize)> = None;
+    for axis in 0..3 {
+        if high[axis] <= low[axis] {
+            continue;
+        }
+        let mut counts = [0; SAH_BINS];
+        let mut boxes: Vec<Option<AABB>> = vec![None; SAH_BINS];
+        for (bbox, _) in &group {
+            let index = bin(bbox, axis);
+            counts[index] += 1;
+            boxes[index] = AABB::surrounding_option(boxes[index].take(), Some(bbox.clone()));
+        }
+        // Count and cost of everything below each boundary, then everything above it
+        let mut below = [(0, 0.); SAH_BINS];
+        let (mut count, mut bbox) = (0, None);
+        for boundary in 1..SAH_BINS {
+            count += counts[boundary - 1];
+            bbox = AABB::surrounding_option(bbox, boxes[boundary - 1].clone());
+            below[boundary] = (count, sah_cost(count, &bbox));
+        }
+        let (mut count, mut bbox) = (0, None);
+        for boundary in (1..SAH_BINS).rev() {
+            count += counts[boundary];
+            bbox = AABB::surrounding_option(bbox, boxes[boundary].clone());
+            let (lower_count, lower_cost) = below[boundary];
+            if count == 0 || lower_count == 0 {
+                continue;
+            }
+            let total = lower_cost + sah_cost(count, &bbox);
+            if best.is_none_or(|(cheapest, _, _)| total < cheapest) {
+                best = Some((total, axis, boundary));
+            }
+        }
+    }
+
+    match best {
+        Some((_, axis, boundary)) => group
+            .into_iter()
+            .partition(|(bbox, _)| bin(bbox, axis) < boundary),
+        None => {
+            let upper = group.split_off(group.len() / 2);
+            (group, upper)
+        }
+    }
+}
+
+impl Accelerator for Bvh {
+    fn build(
+        hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+        t0: Float,
+        t1: Float,
+    ) -> Result<Self, Error> {
+        if hittables.is_empty() {
+            return Err(Error::Empty);
+        }
+        let bounded = hittables
+            .into_iter()
+            .map(|hittable| {
+                Ok((
+                    hittable.bounding_box(t0, t1).ok_or(Error::Unbounded)?,
+                    hittable,
+                ))
+            })
+            .collect::<Result<Vec<Bounded>, Error>>()?;
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            leaves: Vec::new(),
+            node_bounds: Vec::new(),
+        };
+        bvh.add_node(bounded);
+        Ok(bvh)
+    }
+
+    /// Each node's box, the root's at depth 0
+    fn node_boxes(&self) -> Vec<(u32, AABB)> {
+        let mut boxes = Vec::new();
+        let mut stack = vec![(0, 0)];
+        while let Some((depth, index)) = stack.pop() {
+            boxes.push((depth, self.node_bounds[index as usize].clone()));
+            for child in self.nodes[index as usize].children.iter().flatten() {
+                if let Link::Node(child) = child {
+                    stack.push((depth + 1, *child));
+                }
+            }
+        }
+        boxes
+    }
+}
+
+impl Hittable for Bvh {
+    /// Visits the children of each node in the order the ray enters them, skipping any it enters
+    /// after the closest hit so far
+    ///
+    /// The root's own box isn't tested, as its children's boxes are tested anyway
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        let mut stack = Stack::new((t_min, Link::Node(0)));
+        while let Some((entry, link)) = stack.pop() {
+            if entry > t_max {
+                continue;
+            }
+            let index = match link {
+                Link::Leaf(leaf) => {
+                    if let Some(rec) = self.leaves[leaf as usize].hit(ray, t_min, t_max) {
+                        t_max = rec.t;
+                        closest = Some(rec);
+                    }
+                    continue;
+                }
+                Link::Node(index) => index as usize,
+            };
+            let node = &self.nodes[index];
+            let entries = node.boxes.entries(ray, t_min, t_max);
+            let mut order = [(Float::INFINITY, Link::Leaf(0)); BOX_LANES];
+            let mut count = 0;
+            for (child, entry) in node.children.iter().zip(entries) {
+                if let (Some(child), Some(entry)) = (child, entry) {
+                    order[count] = (entry, *child);
+                    count += 1;
+                }
+            }
+            // Furthest first, so the nearest child is visited next
+            let order = &mut order[..count];
+            order.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            for &child in order.iter() {
+                stack.push(child);
+            }
+        }
+        closest
+    }
+
+    /// Stops at the first hit in any leaf, visiting children in no particular order
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let mut stack = Stack::new(Link::Node(0));
+        while let Some(link) = stack.pop() {
+            match link {
+                Link::Leaf(leaf) => {
+                    if self.leaves[leaf as usize].hit_any(ray, t_min, t_max) {
+                        return true;
+                    }
+                }
+                Link::Node(index) => {
+                    let node = &self.nodes[index as usize];
+                    let entries = node.boxes.entries(ray, t_min, t_max);
+                    let children = node.children.iter().zip(entries);
+                    for child in children.filter_map(|(child, entry)| entry.and(*child)) {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(self.node_bounds[0].clone())
+    }
+
+    /// The hittables and sphere batches at the leaves
+    fn children(&self) -> Vec<&dyn Hittable> {
+        self.leaves
+            .iter()
+            .map(|leaf| leaf.as_ref() as &dyn Hittable)
+            .collect()
+    }
+
+    fn accelerator(&self) -> Option<&dyn Accelerator> {
+        Some(self)
+    }
+}
+
+/// Cells a `Grid` aims for per hittable. More cells hold fewer hittables each but take longer to
+/// step through
+const GRID_DENSITY: Float = 3.;
+
+/// Most cells along each axis of a `Grid`
+const GRID_MAX_CELLS: usize = 128;
+
+/// How many times the median diagonal a hittable's box must be for a `Grid` to test it for every
+/// ray rather than put it in cells, so a huge ground sphere doesn't stretch the grid over empty
+/// space
+const GRID_LARGE_SIZE: Float = 32.;
+
+/// Equal cells filling the box around the hittables, each listing the hittables whose boxes
+/// overlap it, walked through in the order a ray passes them
+///
+/// Building only sorts the hittables into cells, so it's much quicker than building a `Bvh` but
+/// slower to trace, especially when the hittables are bunched up. Suits previews of moving
+/// objects, which rebuild the structure every frame. Hittables far larger than most are tested
+/// by every ray instead of being put in cells
+pub struct Grid {
+    hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+    /// Indices of the hittables tested by every ray
+    large: Vec<u32>,
+    /// Box the cells fill, `None` if every hittable is large
+    bounds: Option<AABB>,
+    /// Cells along each axis
+    resolution: [usize; 3],
+    cell_size: Vec3,
+    /// Where each cell's hittables start in `cell_items`, then the end of the last cell's
+    cell_starts: Vec<u32>,
+    cell_items: Vec<u32>,
+    /// Box around every hittable, large ones included
+    bbox: AABB,
+}
+
+impl Grid {
+    /// Lowest and highest cell along each axis that `bbox` overlaps
+    fn cell_range(&self, bounds: &AABB, bbox: &AABB) -> ([usize; 3], [usize; 3]) {
+        let cell = |value: Float, axis: usize| {
+            let offset = (value - bounds.min[axis]) / self.cell_size[axis];
+            (offset.max(0.) as usize).min(self.resolution[axis] - 1)
+        };
+        let low = [
+            cell(bbox.min.x, 0),
+            cell(bbox.min.y, 1),
+            cell(bbox.min.z, 2),
+        ];
+        let high = [
+            cell(bbox.max.x, 0),
+            cell(bbox.max.y, 1),
+            cell(bbox.max.z, 2),
+        ];
+        (low, high)
+    }
+
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        cell[0] + self.resolution[0] * (cell[1] + self.resolution[1] * cell[2])
+    }
+
+    /// Calls `visit(hittables, exit)` for each cell `ray` passes through between `t_min` and
+    /// `t_max`, nearest first, with the indices of the hittables in the cell and where the ray
+    /// leaves it, until `visit` returns true
+    ///
+    /// Steps from cell to cell across whichever boundary the ray reaches first, Amanatides and
+    /// Woo's traversal
+    fn walk<F>(&self, ray: &Ray, t_min: Float, t_max: Float, mut visit: F)
+    where
+        F: FnMut(&[u32], Float) -> bool,
+    {
+        let bounds = match &self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let (mut enter, mut leave) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1. / ray.dir[axis];
+            let t0 = (bounds.min[axis] - ray.origin[axis]) * inv_d;
+            let t1 = (bounds.max[axis] - ray.origin[axis]) * inv_d;
+            let (t0, t1) = if inv_d < 0. { (t1, t0) } else { (t0, t1) };
+            enter = enter.max(t0);
+            leave = leave.min(t1);
+            if leave < enter {
+                return;
+            }
+        }
+
+        let start = ray.at(enter);
+        let (start_cell, _) = self.cell_range(bounds, &AABB::new(start, start));
+        let mut cell = start_cell;
+        // Where the ray crosses the next boundary along each axis and how far apart they are
+        let mut next = [Float::INFINITY; 3];
+        let mut delta = [Float::INFINITY; 3];
+        for axis in 0..3 {
+            let dir = ray.dir[axis];
+            let boundary = match dir {
+                d if d > 0. => cell[axis] + 1,
+                d if d < 0. => cell[axis],
+                _ => continue,
+            };
+            let position = bounds.min[axis] + boundary as Float * self.cell_size[axis];
+            next[axis] = enter + (position - start[axis]) / dir;
+            delta[axis] = self.cell_size[axis] / dir.abs();
+        }
+
+        loop {
+            let axis = (0..3)
+                .min_by(|&a, &b| next[a].total_cmp(&next[b]))
+                .unwrap_or(0);
+            let index = self.cell_index(cell);
+            let items = &self.cell_items
+                [self.cell_starts[index] as usize..self.cell_starts[index + 1] as usize];
+            if visit(items, next[axis].min(leave)) || next[axis] > leave {
+                return;
+            }
+            if ray.dir[axis] > 0. {
+                cell[axis] += 1;
+                if cell[axis] == self.resolution[axis] {
+                    return;
+                }
+            } else {
+                cell[axis] = match cell[axis].checked_sub(1) {
+                    Some(previous) => previous,
+                    None => return,
+                };
+            }
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+impl Accelerator for Grid {
+    fn build(
+        hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+        t0: Float,
+        t1: Float,
+    ) -> Result<Self, Error> {
+        let boxes = hittables
+            .iter()
+            .map(|hittable| hittable.bounding_box(t0, t1).ok_or(Error::Unbounded))
+            .collect::<Result<Vec<AABB>, Error>>()?;
+        let bbox = boxes
+            .iter()
+            .cloned()
+            .reduce(|a, b| AABB::surrounding_box(&a, &b))
+            .ok_or(Error::Empty)?;
+        let diagonal = |bbox: &AABB| (bbox.max - bbox.min).length();
+        let mut diagonals: Vec<Float> = boxes.iter().map(diagonal).collect();
+        diagonals.sort_unstable_by(Float::total_cmp);
+        let limit = diagonals[diagonals.len() / 2] * GRID_LARGE_SIZE;
+        let (large, small): (Vec<u32>, Vec<u32>) = (0..boxes.len() as u32)
+            .partition(|&index| limit > 0. && diagonal(&boxes[index as usize]) > limit);
+
+        let bounds = small
+            .iter()
+            .map(|&index| boxes[index as usize].clone())
+            .reduce(|a, b| AABB::surrounding_box(&a, &b))
+            .map(|bounds| {
+                // Give flat or empty boxes some depth so they can be divided
+                let size = bounds.max - bounds.min;
+                let longest = size.x.max(size.y).max(size.z);
+                let least = if longest > 0. { longest * 1e-3 } else { 1. };
+                let size = vec3!(size.x.max(least), size.y.max(least), size.z.max(least));
+                AABB::new(bounds.min, bounds.min + size.conv())
+            });
+
+        let mut grid = Grid {
+            hittables,
+            large,
+            bounds: None,
+            resolution: [1; 3],
+            cell_size: vec3!(1., 1., 1.),
+            cell_starts: vec![0, 0],
+            cell_items: Vec::new(),
+            bbox,
+        };
+        let bounds = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(grid),
+        };
+        let size = (bounds.max - bounds.min).conv::<Vec3>();
+        let cells = GRID_DENSITY * small.len() as Float;
+        let side = (size.x * size.y * size.z / cells).cbrt();
+        let divide = |length: Float| ((length / side).round() as usize).clamp(1, GRID_MAX_CELLS);
+        grid.resolution = [divide(size.x), divide(size.y), divide(size.z)];
+        grid.cell_size = vec3!(
+            size.x / grid.resolution[0] as Float,
+            size.y / grid.resolution[1] as Float,
+            size.z / grid.resolution[2] as Float
+        );
+
+        // Count the hittables in each cell, then place them after the counts of the cells before
+        let cell_count = grid.resolution.iter().product::<usize>();
+        let mut counts = vec![0u32; cell_count + 1];
+        let ranges: Vec<_> = small
+            .iter()
+            .map(|&index| grid.cell_range(&bounds, &boxes[index as usize]))
+            .collect();
+        let cells_of = |(low, high): ([usize; 3], [usize; 3])| {
+            (low[2]..=high[2]).flat_map(move |z| {
+                (low[1]..=high[1]).flat_map(move |y| (low[0]..=high[0]).map(move |x| [x, y, z]))
+            })
+        };
+        for &range in &ranges {
+            for cell in cells_of(range) {
+                counts[grid.cell_index(cell) + 1] += 1;
+            }
+        }
+        for index in 1..counts.len() {
+            counts[index] += counts[index - 1];
+        }
+        let mut filled = counts.clone();
+        grid.cell_items = vec![0; counts[cell_count] as usize];
+        for (&hittable, &range) in small.iter().zip(&ranges) {
+            for cell in cells_of(range) {
+                let slot = &mut filled[grid.cell_index(cell)];
+                grid.cell_items[*slot as usize] = hittable;
+                *slot += 1;
+            }
+        }
+        grid.cell_starts = counts;
+        grid.bounds = Some(bounds);
+        Ok(grid)
+    }
+
+    /// The box the cells fill at depth 0, then each cell holding anything at depth 1
+    fn node_boxes(&self) -> Vec<(u32, AABB)> {
+        let bounds = match &self.bounds {
+            Some(bounds) => bounds,
+            None => return Vec::new(),
+        };
+        let mut boxes = vec![(0, bounds.clone())];
+        for z in 0..self.resolution[2] {
+            for y in 0..self.resolution[1] {
+                for x in 0..self.resolution[0] {
+                    let index = self.cell_index([x, y, z]);
+                    if self.cell_starts[index] == self.cell_starts[index + 1] {
+                        continue;
+                    }
+                    let offset = vec3!(x as Float, y as Float, z as Float) * self.cell_size;
+                    let min = bounds.min + offset.conv();
+                    boxes.push((1, AABB::new(min, min + self.cell_size.conv())));
+                }
+            }
+        }
+        boxes
+    }
+}
+
+impl Hittable for Grid {
+    /// Tests the large hittables, then the cells in order until the closest hit so far is inside
+    /// the cell being tested. Hittables in several cells may be tested more than once
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        for &index in &self.large {
+            if let Some(rec) = self.hittables[index as usize].hit(ray, t_min, t_max) {
+                t_max = rec.t;
+                closest = Some(rec);
+            }
+        }
+        self.walk(ray, t_min, t_max, |items, exit| {
+            for &index in items {
+                if let Some(rec) = self.hittables[index as usize].hit(ray, t_min, t_max) {
+                    t_max = rec.t;
+                    closest = Some(rec);
+                }
+            }
+            t_max <= exit
+        });
+        closest
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let hits = |index: &u32| self.hittables[*index as usize].hit_any(ray, t_min, t_max);
+        if self.large.iter().any(hits) {
+            return true;
+        }
+        let mut found = false;
+        self.walk(ray, t_min, t_max, |items, _| {
+            found = items.iter().any(hits);
+            found
+        });
+        found
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(self.bbox.clone())
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        self.hittables
+            .iter()
+            .map(|hittable| hittable.as_ref() as &dyn Hittable)
+            .collect()
+    }
+
+    fn accelerator(&self) -> Option<&dyn Accelerator> {
+        Some(self)
+    }
+}
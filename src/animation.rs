@@ -0,0 +1,382 @@
+//! Rendering sequences of frames, with cameras and objects moved by keyframes
+
+use crate::camera::CameraSettings;
+use crate::error::Error;
+use crate::image::Image;
+use crate::integrator::Integrator;
+use crate::temporal::{MotionVectors, TemporalAccumulator};
+use crate::transform::Matrix4;
+use crate::world::World;
+use crate::{raytrace_image, Float, Point3, RenderSettings, Vec3};
+use rand::Rng;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Values that can be blended between keyframes
+pub trait Lerp {
+    /// `self` at `t` = 0 through to `other` at `t` = 1
+    fn lerp(&self, other: &Self, t: Float) -> Self;
+}
+
+impl Lerp for Float {
+    /// Equal values stay put, so infinite ones don't turn into NaN
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        if self == other {
+            return *self;
+        }
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Lerp for Point3 {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        *self + (*other - *self) * t
+    }
+}
+
+/// Blends every setting, including the shutter times. `Sequence::render_keyframed` replaces those
+/// with the frame's own. Aperture masks can't blend, so they switch halfway
+impl Lerp for CameraSettings {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        Self {
+            look_from: self.look_from.lerp(&other.look_from, t),
+            look_at: self.look_at.lerp(&other.look_at, t),
+            vup: self.vup.lerp(&other.vup, t),
+            vfov: self.vfov.lerp(&other.vfov, t),
+            aperture: self.aperture.lerp(&other.aperture, t),
+            focus_dist: self.focus_dist.lerp(&other.focus_dist, t),
+            t0: self.t0.lerp(&other.t0, t),
+            t1: self.t1.lerp(&other.t1, t),
+            near: self.near.lerp(&other.near, t),
+            far: self.far.lerp(&other.far, t),
+            aperture_mask: if t < 0.5 { self } else { other }.aperture_mask.clone(),
+        }
+    }
+}
+
+/// How values move from one keyframe to the next
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    /// At a constant speed, changing speed suddenly at each key
+    Linear,
+    /// Speeding up after each key and slowing down before the next, stopping at every key
+    Smooth,
+    /// Holding each key's value until the next
+    Step,
+}
+
+impl Easing {
+    /// How far between two keys to blend, `t` of the way from one to the next in time
+    fn apply(self, t: Float) -> Float {
+        match self {
+            Easing::Linear => t,
+            Easing::Smooth => t * t * (3. - 2. * t),
+            Easing::Step => 0.,
+        }
+    }
+}
+
+/// A value that changes over time, given at key times and blended in between
+///
+/// Holds the first key's value before it and the last key's after it
+#[derive(Clone, Debug)]
+pub struct Keyframes<T> {
+    /// Keys in order of time
+    keys: Vec<(Float, T)>,
+    /// Defaults to `Easing::Linear`
+    pub easing: Easing,
+}
+
+impl<T: Lerp + Clone> Keyframes<T> {
+    /// Starts with a single key at time 0
+    pub fn new(value: T) -> Self {
+        Self {
+            keys: vec![(0., value)],
+            easing: Easing::Linear,
+        }
+    }
+
+    /// Adds a key at `time`, replacing any already there
+    pub fn key(mut self, time: Float, value: T) -> Self {
+        let index = self.keys.partition_point(|(key_time, _)| *key_time < time);
+        match self.keys.get_mut(index) {
+            Some(key) if key.0 == time => key.1 = value,
+            _ => self.keys.insert(index, (time, value)),
+        }
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// The value at `time`
+    pub fn at(&self, time: Float) -> T {
+        let next = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        if next == 0 {
+            return self.keys[0].1.clone();
+        }
+        let (t0, previous) = &self.keys[next - 1];
+        let (t1, next) = match self.keys.get(next) {
+            Some(key) => key,
+            None => return previous.clone(),
+        };
+        let t = self.easing.apply((time - t0) / (t1 - t0));
+        previous.lerp(next, t)
+    }
+
+    /// Times of the first and last keys
+    pub fn span(&self) -> (Float, Float) {
+        (self.keys[0].0, self.keys[self.keys.len() - 1].0)
+    }
+
+    /// Times of the keys, in order
+    pub fn times(&self) -> impl Iterator<Item = Float> + '_ {
+        self.keys.iter().map(|(time, _)| *time)
+    }
+}
+
+/// Where an object is placed, blended part by part between keyframes so rotations turn smoothly
+/// rather than squashing the object the way blending matrices would
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub translation: Vec3,
+    /// Degrees around the x, then y, then z axis
+    pub rotation: Vec3,
+    pub scale: Vec3,
+}
+
+impl Pose {
+    pub fn matrix(&self) -> Matrix4 {
+        Matrix4::translation(self.translation)
+            * Matrix4::rotation(vec3!(0., 0., 1.), self.rotation.z)
+            * Matrix4::rotation(vec3!(0., 1., 0.), self.rotation.y)
+            * Matrix4::rotation(vec3!(1., 0., 0.), self.rotation.x)
+            * Matrix4::scale(self.scale)
+    }
+
+    /// Inverse of `matrix`, built from the inverse of each part rather than by inverting the
+    /// matrix. Panics if any part of `scale` is 0
+    pub fn inverse_matrix(&self) -> Matrix4 {
+        let s = self.scale;
+        assert!(
+            s.x != 0. && s.y != 0. && s.z != 0.,
+            "Pose scale must be non-zero"
+        );
+        Matrix4::scale(vec3!(1. / s.x, 1. / s.y, 1. / s.z))
+            * Matrix4::rotation(vec3!(1., 0., 0.), -self.rotation.x)
+            * Matrix4::rotation(vec3!(0., 1., 0.), -self.rotation.y)
+            * Matrix4::rotation(vec3!(0., 0., 1.), -self.rotation.z)
+            * Matrix4::translation(-self.translation)
+    }
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            translation: vec3!(),
+            rotation: vec3!(),
+            scale: vec3!(1., 1., 1.),
+        }
+    }
+}
+
+impl Lerp for Pose {
+    fn lerp(&self, other: &Self, t: Float) -> Self {
+        Self {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self.rotation.lerp(&other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+}
+
+/// How the noise pattern changes from one frame to the next
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameNoise {
+    /// Each frame gets its own seed, so noise flickers like film grain instead of looking like a
+    /// dirty lens the scene slides behind
+    Decorrelated,
+    /// Every frame uses the same seed, so noise only changes where the image does. Temporal
+    /// denoisers can use this to tell noise from detail
+    Correlated,
+}
+
+impl FrameNoise {
+    /// Seed to render `frame` with, for a sequence seeded with `seed`
+    pub fn frame_seed(self, seed: u64, frame: u32) -> u64 {
+        match self {
+            FrameNoise::Decorrelated => {
+                mix(seed ^ (frame as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            }
+            FrameNoise::Correlated => seed,
+        }
+    }
+}
+
+/// A range of frames to render at one size
+pub struct Sequence {
+    pub frames: Range<u32>,
+    pub width: u32,
+    pub height: u32,
+    /// Defaults to `FrameNoise::Decorrelated`
+    pub noise: FrameNoise,
+    /// Blends each frame with the ones before it, lined up by the camera's motion, with this
+    /// weight for the new frame. Cuts noise when the camera moves slowly, at the cost of some lag.
+    /// Defaults to `None`, rendering each frame on its own
+    pub temporal_blend: Option<Float>,
+    /// Frames per second, for turning frames into keyframe times. Defaults to 24
+    pub fps: Float,
+    /// Share of each frame the shutter is open for, for motion blur in keyframed renders. Defaults
+    /// to 0.5
+    pub shutter: Float,
+}
+
+impl Sequence {
+    pub fn new(frames: Range<u32>, width: u32, height: u32) -> Self {
+        Self {
+            frames,
+            width,
+            height,
+            noise: FrameNoise::Decorrelated,
+            temporal_blend: None,
+            fps: 24.,
+            shutter: 0.5,
+        }
+    }
+
+    /// Time in seconds at the start of `frame`
+    pub fn time(&self, frame: u32) -> Float {
+        frame as Float / self.fps
+    }
+
+    /// Renders each frame of the sequence
+    ///
+    /// `scene(frame)` builds the world and camera for a frame and `output(frame, image)` is given
+    /// each image as it's finished. Frames are seeded from `settings.seed`, or from one random
+    /// seed for the whole sequence if that's `None`
+    ///
+    /// Stops at the first frame that fails to render or that `output` returns an error for
+    pub fn render<S, O>(
+        &self,
+        settings: &mut RenderSettings,
+        integrator: &(dyn Integrator + Sync),
+        mut scene: S,
+        mut output: O,
+    ) -> Result<(), Error>
+    where
+        S: FnMut(u32) -> (World, CameraSettings),
+        O: FnMut(u32, Image) -> Result<(), Error>,
+    {
+        let base_seed = settings.seed;
+        let seed = base_seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut accumulator = self.temporal_blend.map(TemporalAccumulator::new);
+        let mut previous_camera: Option<CameraSettings> = None;
+        let result = self.frames.clone().try_for_each(|frame| {
+            settings.seed = Some(self.noise.frame_seed(seed, frame));
+            let (world, camera) = scene(frame);
+            let motion = match (&accumulator, &previous_camera) {
+                (Some(_), Some(previous)) => Some(MotionVectors::new(
+                    &world,
+                    &camera,
+                    previous,
+                    self.width,
+                    self.height,
+                )),
+                _ => None,
+            };
+            if accumulator.is_some() {
+                previous_camera = Some(camera.clone());
+            }
+            let mut image =
+                raytrace_image(world, camera, settings, integrator, self.width, self.height)?;
+            if let Some(accumulator) = &mut accumulator {
+                image = accumulator.accumulate(image, motion.as_ref());
+            }
+            output(frame, image)
+        });
+        settings.seed = base_seed;
+        result
+    }
+
+    /// Renders each frame with the camera placed by `camera` at the frame's time, opening the
+    /// shutter from then for `shutter` of a frame
+    ///
+    /// `scene(time)` builds the world at the start of a frame, placing objects with keyframes
+    /// such as `Keyframes<Pose>`
+    pub fn render_keyframed<S, O>(
+        &self,
+        settings: &mut RenderSettings,
+        integrator: &(dyn Integrator + Sync),
+        camera: &Keyframes<CameraSettings>,
+        mut scene: S,
+        output: O,
+    ) -> Result<(), Error>
+    where
+        S: FnMut(Float) -> World,
+        O: FnMut(u32, Image) -> Result<(), Error>,
+    {
+        self.render(
+            settings,
+            integrator,
+            |frame| (scene(self.time(frame)), self.camera_at(camera, frame)),
+            output,
+        )
+    }
+
+    /// The camera placed by `camera` at the start of `frame`, with its shutter open from then for
+    /// `shutter` of a frame
+    pub fn camera_at(&self, camera: &Keyframes<CameraSettings>, frame: u32) -> CameraSettings {
+        let time = self.time(frame);
+        let mut camera = camera.at(time);
+        camera.t0 = time;
+        camera.t1 = time + self.shutter / self.fps;
+        camera
+    }
+
+    /// Like `render_keyframed`, writing each frame to `frame_0001.png` and so on in `dir`,
+    /// tonemapped with `settings.tonemap`
+    pub fn render_to_files<S, P>(
+        &self,
+        settings: &mut RenderSettings,
+        integrator: &(dyn Integrator + Sync),
+        camera: &Keyframes<CameraSettings>,
+        scene: S,
+        dir: P,
+    ) -> Result<(), Error>
+    where
+        S: FnMut(Float) -> World,
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let (tonemap, exposure) = (settings.tonemap, settings.exposure);
+        self.render_keyframed(settings, integrator, camera, scene, |frame, mut image| {
+            image.tonemap(tonemap, exposure);
+            image.write_png(frame_path(dir, frame))
+        })
+    }
+}
+
+/// Where `Sequence::render_to_files` writes `frame`, numbered from 1 with at least four digits
+pub fn frame_path(dir: &Path, frame: u32) -> PathBuf {
+    dir.join(format!("frame_{:04}.png", frame + 1))
+}
+
+/// Scrambles the bits of a seed, from SplitMix64
+///
+/// Each line of a render is seeded with the seed plus its line number, so nearby seeds would give
+/// frames the same noise shifted by a few lines
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,115 @@
+//! Auxiliary render passes alongside the beauty image, for denoisers and compositing
+
+use crate::image::Image;
+use crate::integrator::hit_visible;
+use crate::ray::Ray;
+use crate::world::World;
+use crate::{Color, Float, PixelSample};
+use std::iter::Sum;
+use std::ops::{Div, Mul};
+
+/// Arbitrary output variables, images of what camera rays first hit rather than of the light
+/// arriving along them
+///
+/// All are linear and best written to EXR, see `image::write_exr_layers`
+pub struct Aovs {
+    /// World space normal of the first surface seen, facing the camera, with each axis from -1 to
+    /// 1 in a channel. Averaged over the pixel, so shorter than 1 along edges. 0 where only the
+    /// background is seen
+    pub normal: Image,
+    /// Distance from the camera to the first surface seen, in all three channels. Averaged over
+    /// the samples that hit something, and infinite where none did
+    pub depth: Image,
+    /// Base color of the first surface seen, see `Material::albedo`. 0 where only the
+    /// background is seen
+    pub albedo: Image,
+    /// Number of the object seen through the center of the pixel, in all three channels, see
+    /// `HitRecord::object_id`. 0 for the background
+    pub object_id: Image,
+}
+
+/// A sample of the beauty pass along with what its camera ray first hit
+#[derive(Clone, Copy, Default)]
+pub(crate) struct AovSample {
+    pub beauty: Color,
+    normal: Color,
+    albedo: Color,
+    depth: Float,
+    /// 1 if the ray hit anything, so depths can be averaged over only the rays that did
+    hits: Float,
+}
+
+impl AovSample {
+    /// Traces the camera ray `ray` to its first visible surface
+    pub fn new(beauty: Color, ray: &Ray, world: &World) -> Self {
+        match hit_visible(world, ray, true) {
+            Some((rec, _)) => Self {
+                beauty,
+                normal: rec.normal.conv(),
+                albedo: rec.material.albedo(&rec),
+                depth: rec.t * ray.dir.length(),
+                hits: 1.,
+            },
+            None => Self {
+                beauty,
+                ..Self::default()
+            },
+        }
+    }
+
+    pub fn normal(&self) -> Color {
+        self.normal
+    }
+
+    pub fn albedo(&self) -> Color {
+        self.albedo
+    }
+
+    pub fn depth(&self) -> Float {
+        if self.hits > 0. {
+            self.depth / self.hits
+        } else {
+            Float::INFINITY
+        }
+    }
+}
+
+impl Sum for AovSample {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, sample| Self {
+            beauty: total.beauty + sample.beauty,
+            normal: total.normal + sample.normal,
+            albedo: total.albedo + sample.albedo,
+            depth: total.depth + sample.depth,
+            hits: total.hits + sample.hits,
+        })
+    }
+}
+
+impl Mul<Float> for AovSample {
+    type Output = Self;
+
+    fn mul(self, value: Float) -> Self {
+        Self {
+            beauty: self.beauty * value,
+            normal: self.normal * value,
+            albedo: self.albedo * value,
+            depth: self.depth * value,
+            hits: self.hits * value,
+        }
+    }
+}
+
+impl Div<Float> for AovSample {
+    type Output = Self;
+
+    fn div(self, value: Float) -> Self {
+        self * (1. / value)
+    }
+}
+
+impl PixelSample for AovSample {
+    fn luminance(&self) -> Float {
+        self.beauty.luminance()
+    }
+}
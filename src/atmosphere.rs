@@ -0,0 +1,193 @@
+//! Scattering in the air around a planet, seen as a blue limb and red sunsets
+
+use crate::consts::PI;
+use crate::light::SunLight;
+use crate::ray::Ray;
+use crate::{Color, Float, Point3, Vec3};
+
+/// Earth's radius in meters, used to scale real world coefficients
+const EARTH_RADIUS: Float = 6.371e6;
+
+/// A shell of air around a sphere, lit by the world's sun
+///
+/// Light scattered towards the camera is found by ray marching through the shell, with Rayleigh
+/// scattering off air molecules turning the sky blue and Mie scattering off haze brightening
+/// around the sun. The air thins out exponentially with height
+#[derive(Clone, Copy)]
+pub struct Atmosphere {
+    pub center: Point3,
+    /// Radius of the solid planet
+    pub planet_radius: Float,
+    /// Radius of the outer edge of the atmosphere
+    pub radius: Float,
+    /// Rayleigh scattering coefficients at ground level
+    pub rayleigh: Color,
+    /// Height over which Rayleigh scattering falls by a factor of e
+    pub rayleigh_height: Float,
+    /// Mie scattering coefficient at ground level
+    pub mie: Float,
+    /// Height over which Mie scattering falls by a factor of e
+    pub mie_height: Float,
+    /// How much Mie scattering favours the forward direction, between -1 and 1
+    pub mie_g: Float,
+    /// Number of samples along each ray through the atmosphere
+    pub steps: u32,
+    /// Number of samples along each path towards the sun
+    pub light_steps: u32,
+}
+
+impl Atmosphere {
+    /// Earth's atmosphere scaled to a planet of any size
+    ///
+    /// `exaggeration` makes the atmosphere taller while keeping it as clear looking straight
+    /// up, which helps where the real atmosphere would be too thin to see. 1 is physically correct
+    pub fn earth_like(center: Point3, planet_radius: Float, exaggeration: Float) -> Self {
+        let scale = planet_radius / EARTH_RADIUS;
+        let thin = scale * exaggeration;
+        Self {
+            center,
+            planet_radius,
+            radius: planet_radius + 60e3 * thin,
+            rayleigh: color!(5.8e-6, 13.5e-6, 33.1e-6) / thin,
+            rayleigh_height: 8e3 * thin,
+            mie: 21e-6 / thin,
+            mie_height: 1.2e3 * thin,
+            mie_g: 0.76,
+            steps: 16,
+            light_steps: 8,
+        }
+    }
+
+    /// Where a ray is inside the shell, as the range of `t` between `t_min` and `t_max`
+    fn span(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let (t0, t1) = intersect_sphere(ray, self.center, self.radius)?;
+        let t0 = t0.max(t_min);
+        // Stop at the ground, allowing for rays that start on it
+        let surface = 1e-6 * self.planet_radius / ray.dir.length();
+        let t1 = match intersect_sphere(ray, self.center, self.planet_radius) {
+            Some((ground, _)) if ground > t_min + surface => t1.min(ground),
+            _ => t1,
+        }
+        .min(t_max);
+        if t0 < t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+
+    /// Rayleigh and Mie densities at a point, relative to ground level
+    fn density(&self, point: Point3) -> (Float, Float) {
+        let height = ((point - self.center).length() - self.planet_radius).max(0.);
+        (
+            (-height / self.rayleigh_height).exp(),
+            (-height / self.mie_height).exp(),
+        )
+    }
+
+    /// Extinction for Rayleigh and Mie optical depths
+    fn extinction(&self, rayleigh: Float, mie: Float) -> Color {
+        // Mie particles also absorb a little light
+        self.rayleigh * rayleigh + color!(1., 1., 1.) * (self.mie * 1.1 * mie)
+    }
+
+    /// Optical depth along a ray through the whole shell, or `None` if it hits the planet
+    fn optical_depth_to_space(&self, origin: Point3, dir: Vec3) -> Option<(Float, Float)> {
+        let ray = Ray::new(origin, dir, 0.);
+        if let Some((ground, _)) = intersect_sphere(&ray, self.center, self.planet_radius) {
+            if ground > 1e-6 * self.planet_radius {
+                return None;
+            }
+        }
+        let (_, t1) = intersect_sphere(&ray, self.center, self.radius)?;
+        let steps = self.light_steps.max(1);
+        let step = t1.max(0.) / steps as Float;
+        let mut depth = (0., 0.);
+        for i in 0..steps {
+            let (rayleigh, mie) = self.density(ray.at((i as Float + 0.5) * step));
+            depth.0 += rayleigh * step;
+            depth.1 += mie * step;
+        }
+        Some(depth)
+    }
+
+    /// Fraction of light passing through the air between the origin of `ray` and `t`
+    pub fn transmittance(&self, ray: &Ray, t: Float) -> Color {
+        self.march(ray, t, None).0
+    }
+
+    /// Light passing through the air from `t` back along `ray`, and sunlight scattered towards
+    /// the ray's origin on the way
+    ///
+    /// `t` can be infinite for rays that leave the scene
+    pub fn segment(&self, ray: &Ray, t: Float, sun: Option<&SunLight>) -> (Color, Color) {
+        self.march(ray, t, sun)
+    }
+
+    fn march(&self, ray: &Ray, t: Float, sun: Option<&SunLight>) -> (Color, Color) {
+        let length = ray.dir.length();
+        let (t0, t1) = match self.span(ray, 0., t) {
+            Some(span) if length > 0. => span,
+            _ => return (color!(1., 1., 1.), color!()),
+        };
+        let dir = ray.dir / length;
+        let steps = self.steps.max(1);
+        let step = (t1 - t0) / steps as Float;
+        let distance = step * length;
+
+        // Phase functions only depend on the angle to the sun
+        let phases = sun.map(|sun| {
+            let mu = dir.dot(&sun.direction);
+            let rayleigh = 3. / (16. * PI) * (1. + mu * mu);
+            let g = self.mie_g;
+            let mie = (1. - g * g) / (4. * PI * (1. + g * g - 2. * g * mu).powf(1.5));
+            (rayleigh, mie)
+        });
+
+        let mut depth = (0., 0.);
+        let mut scattered = color!();
+        for i in 0..steps {
+            let point = ray.at(t0 + (i as Float + 0.5) * step);
+            let (rayleigh, mie) = self.density(point);
+            // Depth up to the middle of this step
+            let view = (
+                depth.0 + rayleigh * distance / 2.,
+                depth.1 + mie * distance / 2.,
+            );
+            depth.0 += rayleigh * distance;
+            depth.1 += mie * distance;
+
+            let (sun, (rayleigh_phase, mie_phase)) = match (sun, phases) {
+                (Some(sun), Some(phases)) => (sun, phases),
+                _ => continue,
+            };
+            let light = match self.optical_depth_to_space(point, sun.direction) {
+                Some(light) => light,
+                None => continue,
+            };
+            let attenuation = exp(-self.extinction(view.0 + light.0, view.1 + light.1));
+            let scattering = self.rayleigh * (rayleigh * rayleigh_phase)
+                + color!(1., 1., 1.) * (self.mie * mie * mie_phase);
+            scattered += attenuation * scattering * sun.irradiance * distance;
+        }
+        (exp(-self.extinction(depth.0, depth.1)), scattered)
+    }
+}
+
+fn exp(color: Color) -> Color {
+    color!(color.red.exp(), color.green.exp(), color.blue.exp())
+}
+
+/// Distances along a ray to where it enters and leaves a sphere
+fn intersect_sphere(ray: &Ray, center: Point3, radius: Float) -> Option<(Float, Float)> {
+    let oc = ray.origin - center;
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir.conv());
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. || a == 0. {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some(((-half_b - root) / a, (-half_b + root) / a))
+}
@@ -0,0 +1,440 @@
+//! What rays that escape the scene see
+
+use crate::consts::PI;
+use crate::error::Error;
+use crate::integrator::PathState;
+use crate::light::SunLight;
+use crate::ray::Ray;
+use crate::scene::BackgroundDesc;
+use crate::Color;
+use crate::Float;
+use crate::Vec3;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Controls which rays see the background, so it can light a scene without being seen or the
+/// other way around
+///
+/// Hidden from the camera but lighting the scene is a studio render against black
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackgroundVisibility {
+    /// Whether camera rays see the background
+    pub camera: bool,
+    /// Whether rays reflected or refracted in a specific direction see it, like in mirrors and glass
+    pub specular: bool,
+    /// Whether rays scattered off diffuse surfaces see it, so it lights them
+    pub diffuse: bool,
+}
+
+impl BackgroundVisibility {
+    /// Whether the next ray of a path in `state` sees the background
+    pub fn sees(&self, state: &PathState) -> bool {
+        if state.depth == 0 {
+            self.camera
+        } else if state.specular {
+            self.specular
+        } else {
+            self.diffuse
+        }
+    }
+}
+
+impl Default for BackgroundVisibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            specular: true,
+            diffuse: true,
+        }
+    }
+}
+
+pub trait Background {
+    /// Light arriving along the reverse of `ray`
+    fn color(&self, ray: &Ray) -> Color;
+
+    fn visibility(&self) -> BackgroundVisibility {
+        BackgroundVisibility::default()
+    }
+
+    /// Light arriving along the reverse of `ray` on a path in `state`, or black if the background
+    /// is hidden from it
+    fn color_seen(&self, ray: &Ray, state: &PathState) -> Color {
+        if self.visibility().sees(state) {
+            self.color(ray)
+        } else {
+            color!()
+        }
+    }
+
+    /// Description of the background for saving to a scene file, if it can be saved
+    fn describe(&self) -> Option<BackgroundDesc> {
+        None
+    }
+}
+
+/// The same color in every direction
+pub struct SolidBackground {
+    color: Color,
+    pub visibility: BackgroundVisibility,
+}
+
+impl SolidBackground {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            visibility: BackgroundVisibility::default(),
+        }
+    }
+}
+
+impl Background for SolidBackground {
+    fn color(&self, _: &Ray) -> Color {
+        self.color
+    }
+
+    fn visibility(&self) -> BackgroundVisibility {
+        self.visibility
+    }
+
+    fn describe(&self) -> Option<BackgroundDesc> {
+        Some(BackgroundDesc::Solid {
+            color: self.color.into(),
+        })
+    }
+}
+
+/// Blends from one color looking straight down to another looking straight up
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color,
+    pub visibility: BackgroundVisibility,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self {
+            bottom,
+            top,
+            visibility: BackgroundVisibility::default(),
+        }
+    }
+
+    /// The white to blue sky gradient
+    pub fn sky() -> Self {
+        Self::new(color!(1., 1., 1.), color!(0.5, 0.7, 1.))
+    }
+}
+
+impl Background for GradientBackground {
+    fn color(&self, ray: &Ray) -> Color {
+        let unit_dir = ray.dir.unit_vector();
+        let t = 0.5 * (unit_dir.y + 1.);
+        (1. - t) * self.bottom + t * self.top
+    }
+
+    fn visibility(&self) -> BackgroundVisibility {
+        self.visibility
+    }
+
+    fn describe(&self) -> Option<BackgroundDesc> {
+        Some(BackgroundDesc::Gradient {
+            bottom: self.bottom.into(),
+            top: self.top.into(),
+        })
+    }
+}
+
+/// Default `PreethamSky::intensity`, bringing a clear sky near to `GradientBackground::sky`'s
+/// brightness
+pub const SKY_INTENSITY: Float = 0.1;
+
+/// Illuminance from the sun above the atmosphere, in kilolux
+const SUN_ILLUMINANCE: Float = 128.;
+
+/// A clear daytime sky from the analytic model of Preetham, Shirley and Smits, brightening
+/// around the sun and towards the horizon and reddening as the sun sets
+///
+/// Only the sky is drawn, not the sun's disc, so pair it with the `SunLight` from `sun_light` for
+/// sharp shadows. Directions below the horizon see the horizon's color, and a sun below the
+/// horizon is taken as setting
+pub struct PreethamSky {
+    /// Unit vector towards the sun
+    sun_direction: Vec3,
+    /// Haziness, from 2 for a very clear sky to around 10 for a hazy one
+    turbidity: Float,
+    /// Multiplier taking luminance in kilocandelas per square metre to radiance. Defaults to
+    /// `SKY_INTENSITY`
+    pub intensity: Float,
+    pub visibility: BackgroundVisibility,
+    /// Coefficients of the Perez distribution for luminance and the two chromaticities
+    coefficients: [[Float; 5]; 3],
+    /// Luminance and chromaticities at the zenith, divided by the distribution there
+    zenith: [Float; 3],
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: Vec3, turbidity: Float) -> Self {
+        let sun_direction = sun_direction.unit_vector();
+        let t = turbidity;
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta = sun_direction.y.clamp(0., 1.).acos();
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.);
+        // Polynomials in turbidity and the sun's zenith angle
+        let chromaticity = |m: [[Float; 4]; 3]| {
+            let angles = [theta.powi(3), theta * theta, theta, 1.];
+            let row = |i: usize| (0..4).map(|j| m[i][j] * angles[j]).sum::<Float>();
+            t * t * row(0) + t * row(1) + row(2)
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let mut zenith = [luminance, x, y];
+        for (value, coefficients) in zenith.iter_mut().zip(&coefficients) {
+            *value /= perez(coefficients, 1., theta);
+        }
+        Self {
+            sun_direction,
+            turbidity,
+            intensity: SKY_INTENSITY,
+            visibility: BackgroundVisibility::default(),
+            coefficients,
+            zenith,
+        }
+    }
+
+    /// The sun the sky is lit by, `angular_diameter` degrees across, dimmed and reddened by the
+    /// air it shines through and scaled by `intensity` like the sky. Black below the horizon
+    pub fn sun_light(&self, angular_diameter: Float) -> SunLight {
+        let cos_theta = self.sun_direction.y;
+        let irradiance = if cos_theta > 0. {
+            // Preetham et al.'s attenuation by air and haze, for red, green and blue wavelengths
+            // in micrometres
+            let degrees = cos_theta.acos().to_degrees();
+            let mass = 1. / (cos_theta + 0.15 * (93.885 - degrees).powf(-1.253));
+            let beta = 0.04608 * self.turbidity - 0.04586;
+            let transmittance = |wavelength: Float| {
+                let rayleigh = 0.008735 * wavelength.powf(-4.08);
+                let haze = beta * wavelength.powf(-1.3);
+                (-(rayleigh + haze) * mass).exp()
+            };
+            color!(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44)
+            ) * (SUN_ILLUMINANCE * self.intensity)
+        } else {
+            color!()
+        };
+        SunLight::new(self.sun_direction, angular_diameter, irradiance)
+    }
+}
+
+impl Background for PreethamSky {
+    fn color(&self, ray: &Ray) -> Color {
+        let mut dir = ray.dir.unit_vector();
+        dir.y = dir.y.max(0.);
+        let dir = if dir.length_squared() > 0. {
+            dir.unit_vector()
+        } else {
+            vec3!(1., 0., 0.)
+        };
+        let gamma = dir.dot(&self.sun_direction).clamp(-1., 1.).acos();
+        let cos_theta = dir.y.max(1e-3);
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez(&self.coefficients[i], cos_theta, gamma));
+        if y <= 0. {
+            return color!();
+        }
+        // xyY to CIE XYZ to linear sRGB
+        let big_x = x / y * luminance;
+        let big_z = (1. - x - y) / y * luminance;
+        let big_y = luminance;
+        let rgb = color!(
+            3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z
+        );
+        color!(rgb.red.max(0.), rgb.green.max(0.), rgb.blue.max(0.)) * self.intensity
+    }
+
+    fn visibility(&self) -> BackgroundVisibility {
+        self.visibility
+    }
+
+    fn describe(&self) -> Option<BackgroundDesc> {
+        Some(BackgroundDesc::Preetham {
+            sun_direction: self.sun_direction.into(),
+            turbidity: self.turbidity,
+            intensity: self.intensity,
+        })
+    }
+}
+
+/// The Perez sky distribution, relative brightness looking at `cos_theta` from the zenith and
+/// `gamma` radians from the sun
+fn perez([a, b, c, d, e]: &[Float; 5], cos_theta: Float, gamma: Float) -> Float {
+    (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// An equirectangular panorama surrounding the scene, lighting it as well as being seen
+///
+/// The middle of the image faces along -z with +y up
+pub struct EnvironmentMap {
+    /// File the map was loaded from, if any
+    path: Option<PathBuf>,
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+    /// Brightness multiplier. Defaults to 1
+    pub intensity: Float,
+    /// Rotation around the y axis in degrees
+    pub rotation: Float,
+    pub visibility: BackgroundVisibility,
+}
+
+impl EnvironmentMap {
+    /// Loads a Radiance `.hdr` or OpenEXR `.exr` image
+    ///
+    /// Other formats are treated as sRGB and converted to linear
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let (width, height, data) = match extension.as_deref() {
+            Some("hdr") => load_hdr(path)?,
+            Some("exr") => load_exr(path)?,
+            _ => load_ldr(path)?,
+        };
+        Ok(Self {
+            path: Some(path.to_owned()),
+            ..Self::from_pixels(width, height, data)
+        })
+    }
+
+    /// Creates a map from linear pixels in rows from the top
+    pub fn from_pixels(width: usize, height: usize, data: Vec<Color>) -> Self {
+        assert_eq!(
+            data.len(),
+            width * height,
+            "Environment map size doesn't match its pixels"
+        );
+        Self {
+            path: None,
+            width,
+            height,
+            data,
+            intensity: 1.,
+            rotation: 0.,
+            visibility: BackgroundVisibility::default(),
+        }
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, ray: &Ray) -> Color {
+        if self.data.is_empty() {
+            return color!();
+        }
+        let dir = ray.dir.unit_vector();
+        let phi = dir.x.atan2(-dir.z) - self.rotation.to_radians();
+        let theta = dir.y.clamp(-1., 1.).acos();
+        let u = (0.5 + phi / (2. * PI)).rem_euclid(1.);
+        let v = theta / PI;
+        let x = ((u * self.width as Float) as usize).min(self.width - 1);
+        let y = ((v * self.height as Float) as usize).min(self.height - 1);
+        self.data[y * self.width + x] * self.intensity
+    }
+
+    fn visibility(&self) -> BackgroundVisibility {
+        self.visibility
+    }
+
+    fn describe(&self) -> Option<BackgroundDesc> {
+        Some(BackgroundDesc::Environment {
+            path: self.path.as_ref()?.to_string_lossy().into_owned(),
+            intensity: self.intensity,
+            rotation: self.rotation,
+        })
+    }
+}
+
+fn load_hdr(path: &Path) -> Result<(usize, usize, Vec<Color>), Error> {
+    let file = File::open(path)?;
+    let decoder = image::hdr::HdrDecoder::new(BufReader::new(file))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let data = pixels
+        .iter()
+        .map(|pixel| color!(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float))
+        .collect();
+    Ok((metadata.width as usize, metadata.height as usize, data))
+}
+
+fn load_exr(path: &Path) -> Result<(usize, usize, Vec<Color>), Error> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![color!(); resolution.width() * resolution.height()],
+            )
+        },
+        |(width, data), position, (r, g, b, _): (f32, f32, f32, f32)| {
+            data[position.y() * *width + position.x()] = color!(r as Float, g as Float, b as Float);
+        },
+    )?;
+    let size = image.layer_data.size;
+    let (_, data) = image.layer_data.channel_data.pixels;
+    Ok((size.width(), size.height(), data))
+}
+
+fn load_ldr(path: &Path) -> Result<(usize, usize, Vec<Color>), Error> {
+    let image = image::open(path)?.to_rgb();
+    let to_linear = |value: u8| (value as Float / 255.).powf(2.2);
+    let data = image
+        .pixels()
+        .map(|pixel| {
+            color!(
+                to_linear(pixel[0]),
+                to_linear(pixel[1]),
+                to_linear(pixel[2])
+            )
+        })
+        .collect();
+    Ok((image.width() as usize, image.height() as usize, data))
+}
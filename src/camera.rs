@@ -1,18 +1,48 @@
+use crate::error::Error;
 use crate::ray::Ray;
-use crate::{Point3, Vec3};
-use rand::distributions::{Distribution, Uniform};
-use rand::rngs::ThreadRng;
+use crate::sampler::SampleCtx;
+use crate::sampling;
+use crate::{Float, Point3, Vec3};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[derive(Default)]
+#[derive(Clone)]
 pub struct CameraSettings {
     pub look_from: Point3,
     pub look_at: Point3,
     pub vup: Vec3,
-    pub vfov: f64,
-    pub aperture: f64,
-    pub focus_dist: f64,
-    pub t0: f64,
-    pub t1: f64,
+    pub vfov: Float,
+    pub aperture: Float,
+    pub focus_dist: Float,
+    pub t0: Float,
+    pub t1: Float,
+    /// Distance in front of the lens, along the view direction, closer than which nothing is
+    /// seen. 0 to see everything
+    pub near: Float,
+    /// Distance in front of the lens beyond which nothing is seen, so the background shows
+    /// instead. Infinite to see everything
+    pub far: Float,
+    /// Shape of the lens, so out of focus highlights take the mask's shape rather than a
+    /// circle's. `aperture` is the width of the mask's longer side
+    pub aperture_mask: Option<Arc<ApertureMask>>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::default(),
+            look_at: Point3::default(),
+            vup: Vec3::default(),
+            vfov: 0.,
+            aperture: 0.,
+            focus_dist: 0.,
+            t0: 0.,
+            t1: 0.,
+            near: 0.,
+            far: Float::INFINITY,
+            aperture_mask: None,
+        }
+    }
 }
 
 impl CameraSettings {
@@ -26,10 +56,162 @@ impl CameraSettings {
             focus_dist: 10.,
             t0: 0.,
             t1: 1.,
+            near: 0.,
+            far: Float::INFINITY,
+            aperture_mask: None,
+        }
+    }
+
+    /// Camera looking into `World::cornell_box`
+    pub fn cornell_camera() -> Self {
+        CameraSettings {
+            look_from: point3!(278., 278., -800.),
+            look_at: point3!(278., 278., 0.),
+            vup: vec3!(0., 1., 0.),
+            vfov: 40.,
+            aperture: 0.,
+            focus_dist: 10.,
+            t0: 0.,
+            t1: 1.,
+            near: 0.,
+            far: Float::INFINITY,
+            aperture_mask: None,
+        }
+    }
+
+    /// Camera looking at the sphere and its caustic in `World::caustic_sphere`
+    pub fn caustic_camera() -> Self {
+        CameraSettings {
+            look_from: point3!(5., 3.5, 7.),
+            look_at: point3!(-0.5, 0.3, 0.),
+            vup: vec3!(0., 1., 0.),
+            vfov: 30.,
+            aperture: 0.,
+            focus_dist: 10.,
+            t0: 0.,
+            t1: 1.,
+            near: 0.,
+            far: Float::INFINITY,
+            aperture_mask: None,
+        }
+    }
+
+    /// Camera looking down into the pool in `World::caustic_pool`
+    pub fn pool_camera() -> Self {
+        CameraSettings {
+            look_from: point3!(0., 6., 7.),
+            look_at: point3!(0., -1., 0.),
+            vup: vec3!(0., 1., 0.),
+            vfov: 45.,
+            aperture: 0.,
+            focus_dist: 10.,
+            t0: 0.,
+            t1: 1.,
+            near: 0.,
+            far: Float::INFINITY,
+            aperture_mask: None,
+        }
+    }
+}
+
+/// A grayscale image the lens is cut to, like the hearts and stars of cut-out lens caps
+///
+/// Points on the lens are picked in proportion to the mask's brightness, so the mask shapes the
+/// blur without darkening the image
+pub struct ApertureMask {
+    /// File the mask was loaded from
+    path: Option<PathBuf>,
+    width: usize,
+    height: usize,
+    /// Running totals of the rows' brightness, from the top, ending at 1
+    rows: Vec<Float>,
+    /// Running totals of the pixels' brightness within each row, each ending at 1
+    columns: Vec<Float>,
+}
+
+impl ApertureMask {
+    /// Loads an image, taking its brightness as sRGB. Fails if it can't be read or it's all black
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let image = image::open(path)?.to_luma();
+        let data = image
+            .pixels()
+            .map(|pixel| (pixel[0] as Float / 255.).powf(2.2))
+            .collect();
+        Ok(Self {
+            path: Some(path.to_owned()),
+            ..Self::from_pixels(image.width() as usize, image.height() as usize, data)?
+        })
+    }
+
+    /// Creates a mask from how much light each pixel lets through, in rows from the top. Fails
+    /// if none gets through
+    pub fn from_pixels(width: usize, height: usize, data: Vec<Float>) -> Result<Self, Error> {
+        assert_eq!(
+            data.len(),
+            width * height,
+            "Aperture mask size doesn't match its pixels"
+        );
+        let mut rows = Vec::with_capacity(height);
+        let mut columns = Vec::with_capacity(data.len());
+        let mut total = 0.;
+        for row in data.chunks(width.max(1)) {
+            let start = columns.len();
+            let mut sum = 0.;
+            for &value in row {
+                sum += value.max(0.);
+                columns.push(sum);
+            }
+            if sum > 0. {
+                columns[start..]
+                    .iter_mut()
+                    .for_each(|column| *column /= sum);
+            }
+            total += sum;
+            rows.push(total);
+        }
+        if total <= 0. || !total.is_finite() {
+            return Err(Error::Scene(
+                "aperture masks must let some light through".to_owned(),
+            ));
         }
+        rows.iter_mut().for_each(|row| *row /= total);
+        Ok(Self {
+            path: None,
+            width,
+            height,
+            rows,
+            columns,
+        })
+    }
+
+    /// File the mask was loaded from, if it was
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Point on the mask for a pair of numbers from 0 to 1, picked in proportion to the mask's
+    /// brightness. The longer side runs from -1 to 1, with y up
+    pub fn sample(&self, (u, v): (Float, Float)) -> (Float, Float) {
+        let (row, v) = invert(&self.rows, v);
+        let (column, u) = invert(&self.columns[row * self.width..(row + 1) * self.width], u);
+        let scale = 2. / self.width.max(self.height) as Float;
+        (
+            (column as Float + u - self.width as Float / 2.) * scale,
+            (self.height as Float / 2. - row as Float - v) * scale,
+        )
     }
 }
 
+/// Index of the bin of running totals `cdf` that `u` falls in, and how far through it
+fn invert(cdf: &[Float], u: Float) -> (usize, Float) {
+    let index = cdf.partition_point(|&total| total <= u).min(cdf.len() - 1);
+    let low = if index == 0 { 0. } else { cdf[index - 1] };
+    let width = cdf[index] - low;
+    let along = if width > 0. { (u - low) / width } else { 0.5 };
+    (index, along.clamp(0., 1.))
+}
+
 pub struct Camera {
     origin: Point3,
     lower_left_corner: Point3,
@@ -37,8 +219,14 @@ pub struct Camera {
     vertical: Vec3,
     u: Point3,
     v: Point3,
-    lens_radius: f64,
-    time_dist: Uniform<f64>,
+    lens_radius: Float,
+    t0: Float,
+    t1: Float,
+    /// Unit vector the camera looks along
+    forward: Vec3,
+    near: Float,
+    far: Float,
+    aperture_mask: Option<Arc<ApertureMask>>,
 }
 
 impl Camera {
@@ -59,7 +247,11 @@ impl Camera {
     /// - `aperture`
     ///
     /// - `focus_dist`
-    pub fn new(settings: &CameraSettings, aspect_ratio: f64) -> Self {
+    ///
+    /// - `near` and `far` - Clipping distances
+    ///
+    /// - `aperture_mask` - Shape of the lens, a disk if `None`
+    pub fn new(settings: &CameraSettings, aspect_ratio: Float) -> Self {
         let theta = settings.vfov.to_radians();
         let h = (theta / 2.).tan();
         let viewport_height = 2. * h;
@@ -75,8 +267,6 @@ impl Camera {
         let lower_left_corner =
             origin - (horizontal / 2.).conv() - (vertical / 2.).conv() - settings.focus_dist * w;
 
-        let time_dist = Uniform::from(settings.t0..settings.t1);
-
         Camera {
             origin,
             lower_left_corner,
@@ -85,29 +275,100 @@ impl Camera {
             u,
             v,
             lens_radius: settings.aperture / 2.,
-            time_dist,
+            t0: settings.t0,
+            t1: settings.t1,
+            forward: -w.conv::<Vec3>(),
+            near: settings.near,
+            far: settings.far,
+            aperture_mask: settings.aperture_mask.clone(),
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64, rng: &mut ThreadRng, uniform_unit: &Uniform<f64>) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk(rng, uniform_unit);
-        let offset = self.u * rd.x + self.v * rd.y;
-        let time = self.time_dist.sample(rng);
-        Ray {
-            origin: self.origin + offset,
-            dir: self.lower_left_corner.conv::<Vec3>() + s * self.horizontal + t * self.vertical
-                - self.origin.conv()
-                - offset.conv(),
-            time,
+    /// Center of the lens
+    pub fn origin(&self) -> Point3 {
+        self.origin
+    }
+
+    /// Point on the plane of focus seen at image position `s`, `t`, each between 0 and 1
+    pub fn focus_point(&self, s: Float, t: Float) -> Point3 {
+        self.lower_left_corner + (s * self.horizontal + t * self.vertical).conv()
+    }
+
+    /// Image position `s`, `t` that `point` is seen at through the center of the lens, or `None`
+    /// if it's behind the camera
+    ///
+    /// Positions outside 0 to 1 are off the edge of the image
+    pub fn project(&self, point: Point3) -> Option<(Float, Float)> {
+        let normal = self.horizontal.cross(&self.vertical);
+        let dir = (point - self.origin).conv::<Vec3>();
+        let along = normal.dot(&dir);
+        if along == 0. {
+            return None;
         }
+        let t = normal.dot(&(self.lower_left_corner - self.origin).conv()) / along;
+        if t <= 0. {
+            return None;
+        }
+        let offset = (self.origin - self.lower_left_corner).conv::<Vec3>() + dir * t;
+        Some((
+            offset.dot(&self.horizontal) / self.horizontal.length_squared(),
+            offset.dot(&self.vertical) / self.vertical.length_squared(),
+        ))
     }
-}
 
-fn random_in_unit_disk(rng: &mut ThreadRng, uniform_unit: &Uniform<f64>) -> Vec3 {
-    loop {
-        let tmp = vec3!(uniform_unit.sample(rng), uniform_unit.sample(rng), 0.);
-        if tmp.length_squared() < 1. {
-            return tmp;
+    /// Ray through image position `s`, `t`, with its lens position and time from `ctx`
+    ///
+    /// The ray's range of `t` runs between the clipping distances
+    pub fn get_ray(&self, s: Float, t: Float, ctx: &mut SampleCtx) -> Ray {
+        let (x, y) = match &self.aperture_mask {
+            Some(mask) => mask.sample(ctx.lens_sample()),
+            None => {
+                let rd = sampling::disk(ctx.lens_sample());
+                (rd.x, rd.y)
+            }
+        };
+        let offset = self.u * (self.lens_radius * x) + self.v * (self.lens_radius * y);
+        let time = self.t0 + (self.t1 - self.t0) * ctx.time_sample();
+        let dir = self.lower_left_corner.conv::<Vec3>() + s * self.horizontal + t * self.vertical
+            - self.origin.conv()
+            - offset.conv();
+        // The lens is flat, so distances from it along the view direction grow in step with t
+        let along = dir.dot(&self.forward);
+        Ray {
+            t_min: self.near / along,
+            t_max: self.far / along,
+            sample: ctx.ray_sample(),
+            ..Ray::new(self.origin + offset, dir, time)
         }
     }
+
+    /// `n` rays through the pixel at column `i` and row `j`, counted from the bottom, of an
+    /// image `image_width` by `image_height`, as the renderer traces them
+    ///
+    /// The pixel is split into the largest grid of cells `n` fills, with one ray jittered
+    /// within each. Leftover rays go anywhere in the pixel. Jitter, lens positions and times
+    /// come from `ctx`, which is started on the pixel
+    pub fn rays_for_pixel<'a>(
+        &'a self,
+        i: u32,
+        j: u32,
+        n: u32,
+        image_width: u32,
+        image_height: u32,
+        ctx: &'a mut SampleCtx,
+    ) -> impl Iterator<Item = Ray> + 'a {
+        ctx.start_pixel(i, j);
+        let strata = (n as Float).sqrt() as u32;
+        (0..n).map(move |k| {
+            ctx.start_sample(k);
+            let (mut x, mut y) = ctx.pixel_sample();
+            if k < strata * strata {
+                x = ((k % strata) as Float + x) / strata as Float;
+                y = ((k / strata) as Float + y) / strata as Float;
+            }
+            let s = (i as Float + x) / (image_width - 1) as Float;
+            let t = (j as Float + y) / (image_height - 1) as Float;
+            self.get_ray(s, t, ctx)
+        })
+    }
 }
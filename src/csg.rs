@@ -0,0 +1,141 @@
+//! Constructive solid geometry, combining two solid hittables into the shape they make together
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene::ObjectDesc;
+use crate::world::AABB;
+use crate::{Float, Point3};
+use serde::{Deserialize, Serialize};
+
+/// How `Csg` combines its two hittables
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    /// Inside either
+    Union,
+    /// Inside both, such as a lens made of two spheres
+    Intersection,
+    /// Inside the first but not the second, such as a hollowed sphere
+    Difference,
+}
+
+impl CsgOperation {
+    /// Whether a point inside `a` or not and inside `b` or not is inside the combination
+    fn inside(self, a: bool, b: bool) -> bool {
+        match self {
+            CsgOperation::Union => a || b,
+            CsgOperation::Intersection => a && b,
+            CsgOperation::Difference => a && !b,
+        }
+    }
+}
+
+/// The union, intersection or difference of two hittables
+///
+/// Works out where rays are inside each hittable from whether its hits face them, so both need to
+/// be closed with normals facing out, like spheres, boxes and capped cylinders. Each surface
+/// keeps its own material, so the inside of a hollowed sphere can differ from the outside
+pub struct Csg {
+    operation: CsgOperation,
+    a: Box<dyn Hittable + Send + Sync>,
+    b: Box<dyn Hittable + Send + Sync>,
+}
+
+impl Csg {
+    pub fn new<A, B>(operation: CsgOperation, a: A, b: B) -> Self
+    where
+        A: Hittable + Send + Sync + 'static,
+        B: Hittable + Send + Sync + 'static,
+    {
+        Self::new_boxed(operation, Box::new(a), Box::new(b))
+    }
+
+    pub fn new_boxed(
+        operation: CsgOperation,
+        a: Box<dyn Hittable + Send + Sync>,
+        b: Box<dyn Hittable + Send + Sync>,
+    ) -> Self {
+        Self { operation, a, b }
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Later hits decide whether the ray starts inside, so look past `t_max`
+        let mut hit_a = self.a.hit(ray, t_min, Float::INFINITY);
+        let mut hit_b = self.b.hit(ray, t_min, Float::INFINITY);
+        let mut inside_a = hit_a.as_ref().is_some_and(|rec| !rec.front_face);
+        let mut inside_b = hit_b.as_ref().is_some_and(|rec| !rec.front_face);
+        let mut inside = self.operation.inside(inside_a, inside_b);
+        // Walk through the surfaces of both in order until crossing the combination's
+        loop {
+            let from_a = match (&hit_a, &hit_b) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let rec = if from_a { hit_a.take() } else { hit_b.take() }?;
+            if rec.t >= t_max {
+                return None;
+            }
+            if from_a {
+                inside_a = rec.front_face;
+                hit_a = self.a.hit(ray, rec.t, Float::INFINITY);
+            } else {
+                inside_b = rec.front_face;
+                hit_b = self.b.hit(ray, rec.t, Float::INFINITY);
+            }
+            let now_inside = self.operation.inside(inside_a, inside_b);
+            if now_inside != inside {
+                // The normal already faces the ray. Leaving `b` enters a difference
+                return Some(HitRecord {
+                    front_face: now_inside,
+                    ..rec
+                });
+            }
+            inside = now_inside;
+        }
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let (a, b) = (self.a.bounding_box(t0, t1), self.b.bounding_box(t0, t1));
+        match self.operation {
+            CsgOperation::Union => Some(AABB::surrounding_box(&a?, &b?)),
+            CsgOperation::Intersection => match (a, b) {
+                (Some(a), Some(b)) => {
+                    let min = point3!(
+                        a.min.x.max(b.min.x),
+                        a.min.y.max(b.min.y),
+                        a.min.z.max(b.min.z)
+                    );
+                    let max = point3!(
+                        a.max.x.min(b.max.x),
+                        a.max.y.min(b.max.y),
+                        a.max.z.min(b.max.z)
+                    );
+                    // Boxes that don't overlap leave an empty box at the corner of one
+                    let max = point3!(max.x.max(min.x), max.y.max(min.y), max.z.max(min.z));
+                    Some(AABB::new(min, max))
+                }
+                (a, b) => a.or(b),
+            },
+            CsgOperation::Difference => a,
+        }
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        let mut materials = self.a.materials();
+        materials.extend(self.b.materials());
+        materials
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Csg {
+            operation: self.operation,
+            a: Box::new(self.a.describe()?),
+            b: Box::new(self.b.describe()?),
+        })
+    }
+}
@@ -0,0 +1,208 @@
+//! Exports scene internals as lines for inspecting in a 3D viewer
+
+use crate::camera::{Camera, CameraSettings};
+use crate::error::Error;
+use crate::hittable::HIT_EPSILON;
+use crate::ray::Ray;
+use crate::sampler::SampleCtx;
+use crate::world::{World, AABB};
+use crate::{Color, Float, Point3};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A colored line segment, tagged with the group it belongs to
+struct Line {
+    group: &'static str,
+    start: Point3,
+    end: Point3,
+    color: Color,
+}
+
+/// A set of lines describing the camera, sampled rays, surface normals and BVH boxes
+///
+/// Write it out as OBJ, where each kind of line is its own object, or as PLY, which keeps the
+/// colors
+#[derive(Default)]
+pub struct LineSet {
+    lines: Vec<Line>,
+}
+
+impl LineSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_line(&mut self, group: &'static str, start: Point3, end: Point3, color: Color) {
+        self.lines.push(Line {
+            group,
+            start,
+            end,
+            color,
+        });
+    }
+
+    /// Adds the twelve edges of a box
+    pub fn add_box(&mut self, group: &'static str, bbox: &AABB, color: Color) {
+        let corner = |i: usize| {
+            point3!(
+                if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                if i & 4 == 0 { bbox.min.z } else { bbox.max.z }
+            )
+        };
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.add_line(group, corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Adds the camera's view frustum out to its plane of focus
+    pub fn add_camera(&mut self, camera_settings: &CameraSettings, aspect_ratio: Float) {
+        let camera = Camera::new(camera_settings, aspect_ratio);
+        let color = color!(1., 1., 0.);
+        let corners = [(0., 0.), (1., 0.), (1., 1.), (0., 1.)];
+        for (i, &(s, t)) in corners.iter().enumerate() {
+            let (next_s, next_t) = corners[(i + 1) % corners.len()];
+            let corner = camera.focus_point(s, t);
+            self.add_line("frustum", camera.origin(), corner, color);
+            self.add_line("frustum", corner, camera.focus_point(next_s, next_t), color);
+        }
+        // Mark which way is up
+        let top = camera.focus_point(0.5, 1.);
+        let above = top + (top - camera.focus_point(0.5, 0.9));
+        self.add_line("frustum", top, above, color);
+    }
+
+    /// Traces a grid of camera rays, adding each ray up to what it hits and the surface normal
+    /// there
+    ///
+    /// Rays that hit nothing are drawn out to `miss_length`. Normals are drawn `normal_length`
+    /// long
+    pub fn add_camera_rays(
+        &mut self,
+        world: &World,
+        camera_settings: &CameraSettings,
+        aspect_ratio: Float,
+        grid: (u32, u32),
+        miss_length: Float,
+        normal_length: Float,
+    ) {
+        let camera = Camera::new(camera_settings, aspect_ratio);
+        // Fixed seed so exports don't change between runs
+        let mut ctx = SampleCtx::from_seed(Some(0));
+        let (columns, rows) = (grid.0.max(1), grid.1.max(1));
+        for j in 0..rows {
+            for i in 0..columns {
+                let s = (i as Float + 0.5) / columns as Float;
+                let t = (j as Float + 0.5) / rows as Float;
+                let ray = camera.get_ray(s, t, &mut ctx);
+                self.add_ray(world, &ray, miss_length, normal_length);
+            }
+        }
+    }
+
+    fn add_ray(&mut self, world: &World, ray: &Ray, miss_length: Float, normal_length: Float) {
+        match world.hit(ray, HIT_EPSILON, Float::INFINITY) {
+            Some(rec) => {
+                self.add_line("rays", ray.origin, rec.point, color!(1., 1., 1.));
+                let tip = rec.point + (rec.normal.unit_vector() * normal_length).conv();
+                self.add_line("normals", rec.point, tip, color!(0., 0.5, 1.));
+            }
+            None => {
+                let end = ray.origin + (ray.dir.unit_vector() * miss_length).conv();
+                self.add_line("rays", ray.origin, end, color!(1., 0., 0.));
+            }
+        }
+    }
+
+    /// Adds the bounding boxes of every hittable in the world, or of the nodes of acceleration
+    /// structures up to `max_depth` levels down
+    ///
+    /// Call `World::build_bvh` first to see the tree
+    pub fn add_bvh(&mut self, world: &World, t0: Float, t1: Float, max_depth: u32) {
+        for hittable in world.hittables.iter().chain(world.lights.iter()) {
+            let boxes = match hittable.accelerator() {
+                Some(accelerator) => accelerator.node_boxes(),
+                None => hittable
+                    .bounding_box(t0, t1)
+                    .into_iter()
+                    .map(|bbox| (0, bbox))
+                    .collect(),
+            };
+            for (depth, bbox) in boxes.into_iter().filter(|(depth, _)| *depth <= max_depth) {
+                // Shade from green at the root to red at the leaves
+                let fraction = depth as Float / max_depth.max(1) as Float;
+                let color = color!(fraction, 1. - fraction, 0.);
+                self.add_box("bvh", &bbox, color);
+            }
+        }
+    }
+
+    /// Writes a Wavefront OBJ file with one object per kind of line
+    pub fn write_obj<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let mut out = BufWriter::new(file);
+        let mut groups: Vec<&str> = self.lines.iter().map(|line| line.group).collect();
+        groups.sort_unstable();
+        groups.dedup();
+        let mut vertex = 1;
+        for group in groups {
+            writeln!(out, "o {}", group)?;
+            for line in self.lines.iter().filter(|line| line.group == group) {
+                writeln!(
+                    out,
+                    "v {} {} {}\nv {} {} {}\nl {} {}",
+                    line.start.x,
+                    line.start.y,
+                    line.start.z,
+                    line.end.x,
+                    line.end.y,
+                    line.end.z,
+                    vertex,
+                    vertex + 1
+                )?;
+                vertex += 2;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes an ASCII PLY file of colored edges
+    pub fn write_ply<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let mut out = BufWriter::new(file);
+        writeln!(
+            out,
+            "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\n\
+             property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element edge {}\nproperty int vertex1\nproperty int vertex2\nend_header",
+            self.lines.len() * 2,
+            self.lines.len()
+        )?;
+        let byte = |value: Float| (value.clamp(0., 1.) * 255.).round() as u8;
+        for line in &self.lines {
+            for point in [line.start, line.end] {
+                writeln!(
+                    out,
+                    "{} {} {} {} {} {}",
+                    point.x,
+                    point.y,
+                    point.z,
+                    byte(line.color.red),
+                    byte(line.color.green),
+                    byte(line.color.blue)
+                )?;
+            }
+        }
+        for i in 0..self.lines.len() {
+            writeln!(out, "{} {}", 2 * i, 2 * i + 1)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Removing noise from low sample renders, guided by the normal, depth and albedo passes
+//!
+//! Uses Intel Open Image Denoise with the `oidn` feature, which needs the library installed, and a
+//! joint bilateral filter otherwise
+
+use crate::aov::Aovs;
+use crate::image::Image;
+use crate::Color;
+use crate::Float;
+use rayon::prelude::*;
+
+/// Denoises `image` using the passes rendered alongside it, see `raytrace_with_aovs`
+///
+/// With the `oidn` feature this is `denoise_oidn`, otherwise `denoise_bilateral` with the default
+/// settings
+pub fn denoise(image: &Image, aovs: &Aovs) -> Image {
+    #[cfg(feature = "oidn")]
+    let denoised = denoise_oidn(image, aovs);
+    #[cfg(not(feature = "oidn"))]
+    let denoised = denoise_bilateral(image, aovs, &BilateralSettings::default());
+    denoised
+}
+
+/// How widely the bilateral filter blurs, and how different neighbors can be before they're left
+/// out
+///
+/// Each `sigma` is the difference at which a neighbor's weight has fallen to about 60%
+#[derive(Clone, Copy, Debug)]
+pub struct BilateralSettings {
+    /// Pixels to look in each direction. The filter takes time growing with its square
+    pub radius: u32,
+    /// In pixels
+    pub spatial_sigma: Float,
+    /// Between the noisy colors, relative to their brightness. Large, as the noise itself
+    /// shouldn't keep neighbors apart
+    pub color_sigma: Float,
+    /// Between normals, so edges between faces stay sharp
+    pub normal_sigma: Float,
+    /// Between depths, relative to the nearer depth, so objects don't blur into what's behind
+    pub depth_sigma: Float,
+    /// Between albedos, so texture detail stays sharp
+    pub albedo_sigma: Float,
+}
+
+impl Default for BilateralSettings {
+    fn default() -> Self {
+        Self {
+            radius: 5,
+            spatial_sigma: 3.,
+            color_sigma: 1.,
+            normal_sigma: 0.3,
+            depth_sigma: 0.05,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Blurs `image` only between neighbors with similar normals, depths and albedos, so noise is
+/// smoothed away while edges and textures stay sharp
+///
+/// The image is divided by the albedo first and multiplied back after, so only the lighting is
+/// blurred
+pub fn denoise_bilateral(image: &Image, aovs: &Aovs, settings: &BilateralSettings) -> Image {
+    let (width, height) = (image.width as i64, image.height as i64);
+    let lighting: Vec<Color> = image
+        .data
+        .iter()
+        .zip(aovs.albedo.data.iter())
+        .map(|(&color, &albedo)| demodulate(color, albedo))
+        .collect();
+    let radius = settings.radius as i64;
+    let weight = |sigma: Float, difference_squared: Float| {
+        (-difference_squared / (2. * sigma * sigma)).exp()
+    };
+
+    let data = (0..image.data.len())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index as i64 % width, index as i64 / width);
+            let center = lighting[index];
+            let normal = aovs.normal.data[index];
+            let depth = aovs.depth.data[index].red;
+            let albedo = aovs.albedo.data[index];
+            let brightness = center.luminance().max(1e-3);
+
+            let mut total = color!();
+            let mut total_weight = 0.;
+            for ny in (y - radius).max(0)..=(y + radius).min(height - 1) {
+                for nx in (x - radius).max(0)..=(x + radius).min(width - 1) {
+                    let neighbor = (ny * width + nx) as usize;
+                    let other_depth = aovs.depth.data[neighbor].red;
+                    // Infinite where only the background is seen
+                    let depth_difference = if depth.is_infinite() || other_depth.is_infinite() {
+                        if depth == other_depth {
+                            0.
+                        } else {
+                            continue;
+                        }
+                    } else {
+                        (depth - other_depth) / depth.min(other_depth).max(1e-6)
+                    };
+                    let distance_squared = ((nx - x).pow(2) + (ny - y).pow(2)) as Float;
+                    let color_difference = (lighting[neighbor] - center).luminance() / brightness;
+                    let w = weight(settings.spatial_sigma, distance_squared)
+                        * weight(settings.color_sigma, color_difference * color_difference)
+                        * weight(
+                            settings.normal_sigma,
+                            (aovs.normal.data[neighbor] - normal).length_squared(),
+                        )
+                        * weight(settings.depth_sigma, depth_difference * depth_difference)
+                        * weight(
+                            settings.albedo_sigma,
+                            (aovs.albedo.data[neighbor] - albedo).length_squared(),
+                        );
+                    total += lighting[neighbor] * w;
+                    total_weight += w;
+                }
+            }
+            // The center pixel always has a weight of 1
+            remodulate(total / total_weight, albedo)
+        })
+        .collect();
+    Image {
+        width: image.width,
+        height: image.height,
+        data,
+        alpha: image.alpha.clone(),
+    }
+}
+
+/// Smallest albedo divided out of a channel, so dark surfaces don't blow up their noise
+const MIN_ALBEDO: Float = 0.01;
+
+/// The light arriving at a surface, from the light it reflects
+fn demodulate(color: Color, albedo: Color) -> Color {
+    color!(
+        color.red / albedo.red.max(MIN_ALBEDO),
+        color.green / albedo.green.max(MIN_ALBEDO),
+        color.blue / albedo.blue.max(MIN_ALBEDO)
+    )
+}
+
+/// Inverse of `demodulate`
+fn remodulate(lighting: Color, albedo: Color) -> Color {
+    color!(
+        lighting.red * albedo.red.max(MIN_ALBEDO),
+        lighting.green * albedo.green.max(MIN_ALBEDO),
+        lighting.blue * albedo.blue.max(MIN_ALBEDO)
+    )
+}
+
+/// Denoises `image` with Intel Open Image Denoise's ray tracing filter, guided by the normal and
+/// albedo passes
+///
+/// Needs the `oidn` feature
+#[cfg(feature = "oidn")]
+pub fn denoise_oidn(image: &Image, aovs: &Aovs) -> Image {
+    let to_floats = |image: &Image| -> Vec<f32> {
+        image
+            .data
+            .iter()
+            .flat_map(|color| [color.red as f32, color.green as f32, color.blue as f32])
+            .collect()
+    };
+    let color = to_floats(image);
+    let mut albedo = to_floats(&aovs.albedo);
+    // OIDN wants albedos between 0 and 1
+    albedo
+        .iter_mut()
+        .for_each(|value| *value = value.clamp(0., 1.));
+    let normal = to_floats(&aovs.normal);
+    let mut output = vec![0.; color.len()];
+
+    let device = oidn::Device::new().expect("Error creating denoising device");
+    oidn::RayTracing::new(&device)
+        .hdr(true)
+        .image_dimensions(image.width as usize, image.height as usize)
+        .albedo_normal(&albedo, &normal)
+        .filter(&color, &mut output)
+        .expect("Error denoising image");
+
+    let data = output
+        .chunks_exact(3)
+        .map(|rgb| color!(rgb[0] as Float, rgb[1] as Float, rgb[2] as Float))
+        .collect();
+    Image {
+        width: image.width,
+        height: image.height,
+        data,
+        alpha: image.alpha.clone(),
+    }
+}
@@ -0,0 +1,147 @@
+//! Orthographic depth images of a scene, for heightmaps and quick occlusion maps
+//!
+//! Rays run in parallel along one direction rather than spreading out from a camera, so each
+//! pixel covers the same area of the scene wherever it is
+
+use crate::error::Error;
+use crate::image::Image;
+use crate::integrator::hit_visible;
+use crate::ray::Ray;
+use crate::world::{World, AABB};
+use crate::{Color, Float, Point3, Vec3};
+use rayon::prelude::*;
+
+/// Distance rays start in front of the plane depths are measured from, so surfaces lying in it
+/// are still hit
+const START_OFFSET: Float = 1.;
+
+/// Where to look from for `render_depth_map`
+pub struct DepthMapSettings {
+    /// Direction the rays travel in, such as straight down for a heightmap
+    pub direction: Vec3,
+    /// Which way is up in the image
+    pub vup: Vec3,
+    /// Part of the scene to cover. Defaults to the world's bounding box, which scenes with huge
+    /// ground spheres make too large
+    pub bounds: Option<AABB>,
+    /// Time the rays are traced at, for moving objects
+    pub time: Float,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DepthMapSettings {
+    /// Looks straight down, with -z up in the image like a map
+    pub fn top_down(width: u32, height: u32) -> Self {
+        Self {
+            direction: vec3!(0., -1., 0.),
+            vup: vec3!(0., 0., -1.),
+            bounds: None,
+            time: 0.,
+            width,
+            height,
+        }
+    }
+}
+
+pub struct DepthMap {
+    /// Distance along the direction from the face of the bounds nearest the viewer to the first
+    /// visible surface, in all three channels. Infinite where rays hit nothing
+    pub image: Image,
+    /// Width and height of the square each pixel covers, in scene units
+    pub pixel_size: Float,
+}
+
+impl DepthMap {
+    /// Depths scaled so the nearest surface is 1 and the furthest is 0, as are misses, for
+    /// writing to 8-bit images. Looking down, this is a heightmap
+    pub fn heightmap(&self) -> Image {
+        let depths = self.image.data.iter().map(|color| color.red);
+        let finite = depths.filter(|depth| depth.is_finite());
+        let (near, far) = finite.fold(
+            (Float::INFINITY, Float::NEG_INFINITY),
+            |(near, far), depth| (near.min(depth), far.max(depth)),
+        );
+        let range = (far - near).max(Float::EPSILON);
+        let mut image = self.image.clone();
+        for color in image.data.iter_mut() {
+            let height = if color.red.is_finite() {
+                1. - (color.red - near) / range
+            } else {
+                0.
+            };
+            *color = color!(height, height, height);
+        }
+        image
+    }
+}
+
+/// Renders the distance to the scene along parallel rays covering `settings.bounds`, one ray
+/// through the center of each pixel
+///
+/// Pixels are square, so the bounds are centered with room to spare along one side unless the
+/// image has the same aspect ratio as they do. Surfaces hidden from the camera are passed
+/// through. Fails if the BVH can't be built, or without bounds if the world has no bounding box
+pub fn render_depth_map(mut world: World, settings: &DepthMapSettings) -> Result<DepthMap, Error> {
+    let (t0, t1) = (settings.time, settings.time);
+    let bounds = match &settings.bounds {
+        Some(bounds) => bounds.clone(),
+        None => world.bounding_box(t0, t1).ok_or(Error::Unbounded)?,
+    };
+    world.build_bvh(t0, t1)?;
+
+    let forward = settings.direction.unit_vector();
+    let right = forward.cross(&settings.vup).unit_vector();
+    let up = right.cross(&forward);
+    // Extent of the bounds along each axis of the view, from their corners
+    let corners = (0..8).map(|corner: usize| {
+        let pick = |axis: usize| match corner >> axis & 1 {
+            0 => bounds.min[axis],
+            _ => bounds.max[axis],
+        };
+        vec3!(pick(0), pick(1), pick(2))
+    });
+    let ranges = corners.fold(
+        [(Float::INFINITY, Float::NEG_INFINITY); 3],
+        |ranges, corner| {
+            let mut ranges = ranges;
+            for (range, axis) in ranges.iter_mut().zip([right, up, forward]) {
+                let along = corner.dot(&axis);
+                *range = (range.0.min(along), range.1.max(along));
+            }
+            ranges
+        },
+    );
+    let [(left, right_edge), (bottom, top), (near, _)] = ranges;
+
+    let (width, height) = (settings.width, settings.height);
+    let pixel_size = ((right_edge - left) / width as Float).max((top - bottom) / height as Float);
+    // Center of the top left pixel, on the plane rays start from
+    let center = 0.5 * (left + right_edge) * right + 0.5 * (bottom + top) * up;
+    let top_left = center - 0.5 * pixel_size * ((width - 1) as Float * right)
+        + 0.5 * pixel_size * ((height - 1) as Float * up)
+        + (near - START_OFFSET) * forward;
+
+    let data = (0..height)
+        .into_par_iter()
+        .flat_map(|y| {
+            let world = &world;
+            (0..width).into_par_iter().map(move |x| {
+                let origin = top_left + pixel_size * (x as Float * right - y as Float * up);
+                let ray = Ray::new(origin.conv::<Point3>(), forward, settings.time);
+                let depth = hit_visible(world, &ray, true)
+                    .map_or(Float::INFINITY, |(rec, _)| rec.t - START_OFFSET);
+                color!(depth, depth, depth)
+            })
+        })
+        .collect();
+    Ok(DepthMap {
+        image: Image {
+            width,
+            height,
+            data,
+            alpha: None,
+        },
+        pixel_size,
+    })
+}
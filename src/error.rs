@@ -0,0 +1,93 @@
+//! What can go wrong loading files, writing images and building scenes
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a file couldn't be read or written, or a scene couldn't be built
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Image(image::ImageError),
+    Exr(exr::error::Error),
+    /// A scene file that isn't JSON or doesn't match `SceneFile`
+    Json(serde_json::Error),
+    /// The path's extension isn't one of the `FileFormat`s
+    UnknownFormat(PathBuf),
+    /// A file that could be read but not understood, like an OBJ face with a missing vertex
+    Parse(String),
+    /// A scene that can't be built, like one using a material that isn't defined
+    Scene(String),
+    /// A hittable without a bounding box was put in a BVH
+    Unbounded,
+    /// An acceleration structure was built around no hittables
+    Empty,
+    /// Images to be written as layers of one file that are missing or not all the same size
+    Layers(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Image(error) => write!(f, "{}", error),
+            Error::Exr(error) => write!(f, "{}", error),
+            Error::Json(error) => write!(f, "invalid scene file: {}", error),
+            Error::UnknownFormat(path) => {
+                write!(f, "can't tell the format of `{}`", path.display())
+            }
+            Error::Parse(reason) | Error::Scene(reason) | Error::Layers(reason) => {
+                write!(f, "{}", reason)
+            }
+            Error::Unbounded => write!(f, "a hittable in the BVH has no bounding box"),
+            Error::Empty => write!(f, "an acceleration structure needs at least one hittable"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Image(error) => Some(error),
+            Error::Exr(error) => Some(error),
+            Error::Json(error) => Some(error),
+            Error::UnknownFormat(_)
+            | Error::Parse(_)
+            | Error::Scene(_)
+            | Error::Unbounded
+            | Error::Empty
+            | Error::Layers(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Error::Image(error)
+    }
+}
+
+impl From<exr::error::Error> for Error {
+    fn from(error: exr::error::Error) -> Self {
+        Error::Exr(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::Io(error.into())
+    }
+}
@@ -0,0 +1,125 @@
+//! Reconstruction filters, which weight samples by how far they land from each pixel's center
+
+use crate::tile::Tile;
+use crate::Float;
+use crate::PixelSample;
+
+/// How samples are weighted by their distance from a pixel's center
+///
+/// Filters wider than a pixel spread each sample over the pixels around it, smoothing out
+/// aliasing at the cost of a little sharpness
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter {
+    /// Each sample counts only towards the pixel it lands in
+    Box,
+    /// Weight falling linearly to 0 at `radius` pixels
+    Tent { radius: Float },
+    /// Gaussian with a standard deviation of `sigma` pixels, cut off at `radius`
+    Gaussian { radius: Float, sigma: Float },
+    /// The Mitchell-Netravali cubic with B = C = 1/3, 2 pixels wide. Sharper than the others, but
+    /// slightly rings around edges
+    Mitchell,
+}
+
+impl PixelFilter {
+    /// How far from a pixel's center samples still count towards it, in pixels
+    pub fn radius(&self) -> Float {
+        match *self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent { radius } | PixelFilter::Gaussian { radius, .. } => radius,
+            PixelFilter::Mitchell => 2.,
+        }
+    }
+
+    /// Whether samples reach pixels other than the one they land in
+    pub fn splats(&self) -> bool {
+        self.radius() > 0.5
+    }
+
+    /// Weight of a sample `dx`, `dy` pixels from a pixel's center
+    pub fn weight(&self, dx: Float, dy: Float) -> Float {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: Float) -> Float {
+        let d = d.abs();
+        if d >= self.radius() {
+            return 0.;
+        }
+        match *self {
+            PixelFilter::Box => 1.,
+            PixelFilter::Tent { radius } => 1. - d / radius,
+            PixelFilter::Gaussian { radius, sigma } => {
+                // Shifted down so the weight reaches 0 at the cut off
+                let gaussian = |d: Float| (-d * d / (2. * sigma * sigma)).exp();
+                (gaussian(d) - gaussian(radius)).max(0.)
+            }
+            PixelFilter::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                let weight = if d < 1. {
+                    (12. - 9. * b - 6. * c) * d * d * d
+                        + (-18. + 12. * b + 6. * c) * d * d
+                        + (6. - 2. * b)
+                } else {
+                    (-b - 6. * c) * d * d * d
+                        + (6. * b + 30. * c) * d * d
+                        + (-12. * b - 48. * c) * d
+                        + (8. * b + 24. * c)
+                };
+                weight / 6.
+            }
+        }
+    }
+}
+
+/// Weighted sums of the samples splatted onto an area of the image
+///
+/// Each tile splats into its own buffer, reaching past the tile's edges by the filter's radius,
+/// and the buffers are added together once all tiles are done. Samples near tile borders so
+/// count towards pixels in both tiles, without threads writing to the same memory
+pub(crate) struct SplatBuffer<T> {
+    area: Tile,
+    sums: Vec<(T, Float)>,
+}
+
+impl<T: PixelSample> SplatBuffer<T> {
+    pub fn new(area: Tile) -> Self {
+        Self {
+            area,
+            sums: vec![(T::default(), 0.); area.pixel_count() as usize],
+        }
+    }
+
+    /// Adds `value` to the pixels around where it landed, `x`, `y` pixels from the top left of
+    /// the image
+    pub fn splat(&mut self, filter: &PixelFilter, x: Float, y: Float, value: T) {
+        let radius = filter.radius();
+        let area = self.area;
+        // Pixels whose centers are within the radius
+        let x0 = ((x - radius - 0.5).ceil().max(area.x as Float)) as u32;
+        let x1 = ((x + radius - 0.5).floor() as i64).min((area.x + area.width) as i64 - 1);
+        let y0 = ((y - radius - 0.5).ceil().max(area.y as Float)) as u32;
+        let y1 = ((y + radius - 0.5).floor() as i64).min((area.y + area.height) as i64 - 1);
+        for py in y0 as i64..=y1 {
+            for px in x0 as i64..=x1 {
+                let weight = filter.weight(px as Float + 0.5 - x, py as Float + 0.5 - y);
+                if weight == 0. {
+                    continue;
+                }
+                let index = (py as u32 - area.y) * area.width + (px as u32 - area.x);
+                let (sum, total) = &mut self.sums[index as usize];
+                *sum = [*sum, value * weight].iter().copied().sum();
+                *total += weight;
+            }
+        }
+    }
+
+    /// Adds the buffer's sums to `sums`, which covers the whole image
+    pub fn add_to(self, sums: &mut [(T, Float)], image_width: u32) {
+        for ((x, y), (sum, weight)) in self.area.pixels().zip(self.sums) {
+            let (image_sum, image_weight) = &mut sums[(y * image_width + x) as usize];
+            *image_sum = [*image_sum, sum].iter().copied().sum();
+            *image_weight += weight;
+        }
+    }
+}
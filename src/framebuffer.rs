@@ -0,0 +1,116 @@
+//! Buffers that accumulate samples over many passes
+
+use crate::image::Image;
+use crate::Color;
+use crate::Float;
+use half::f16;
+
+/// How precisely a `FrameBuffer` stores each pixel
+///
+/// Each pixel holds the running mean of its samples rather than their sum, so values don't grow
+/// with the number of samples and lower precisions lose little
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferPrecision {
+    /// 64-bit floats, 24 bytes a pixel
+    Double,
+    /// 32-bit floats, 12 bytes a pixel. About 7 significant digits, plenty for millions of
+    /// samples
+    Single,
+    /// 16-bit floats, 6 bytes a pixel. Within about half a percent for the first thousand
+    /// samples, but the mean stops settling after that, as each sample moves it by less than
+    /// half float precision. For previews and renders with few samples. Values above 65504
+    /// become infinite
+    Half,
+}
+
+enum Storage {
+    Double(Vec<Color>),
+    Single(Vec<[f32; 3]>),
+    Half(Vec<[f16; 3]>),
+}
+
+/// Mean of the samples taken in each pixel, in rows from the top left
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    counts: Vec<u32>,
+    storage: Storage,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32, precision: BufferPrecision) -> Self {
+        let len = (width * height) as usize;
+        let storage = match precision {
+            BufferPrecision::Double => Storage::Double(vec![color!(); len]),
+            BufferPrecision::Single => Storage::Single(vec![[0.; 3]; len]),
+            BufferPrecision::Half => Storage::Half(vec![[f16::ZERO; 3]; len]),
+        };
+        Self {
+            width,
+            height,
+            counts: vec![0; len],
+            storage,
+        }
+    }
+
+    /// Adds a sample to the pixel at `index`
+    pub fn add(&mut self, index: usize, color: Color) {
+        self.counts[index] += 1;
+        let count = self.counts[index] as Float;
+        match &mut self.storage {
+            Storage::Double(means) => {
+                let mean = means[index];
+                means[index] = mean + (color - mean) / count;
+            }
+            Storage::Single(means) => {
+                let mean = &mut means[index];
+                for (channel, value) in [color.red, color.green, color.blue].iter().enumerate() {
+                    let old = mean[channel] as Float;
+                    mean[channel] = (old + (value - old) / count) as f32;
+                }
+            }
+            Storage::Half(means) => {
+                let mean = &mut means[index];
+                for (channel, value) in [color.red, color.green, color.blue].iter().enumerate() {
+                    let old = mean[channel].to_f32();
+                    mean[channel] = f16::from_f32(old + (*value as f32 - old) / count as f32);
+                }
+            }
+        }
+    }
+
+    /// Number of samples added to the pixel at `index`
+    pub fn count(&self, index: usize) -> u32 {
+        self.counts[index]
+    }
+
+    /// Mean of the samples added to the pixel at `index`
+    pub fn mean(&self, index: usize) -> Color {
+        match &self.storage {
+            Storage::Double(means) => means[index],
+            Storage::Single(means) => {
+                let [r, g, b] = means[index];
+                color!(r as Float, g as Float, b as Float)
+            }
+            Storage::Half(means) => {
+                let [r, g, b] = means[index];
+                color!(
+                    r.to_f32() as Float,
+                    g.to_f32() as Float,
+                    b.to_f32() as Float
+                )
+            }
+        }
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            data: (0..self.counts.len())
+                .map(|index| self.mean(index))
+                .collect(),
+            alpha: None,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Furnace tests, checking materials don't create or lose light
+//!
+//! A sphere inside a uniformly white background is lit the same from every direction, so if its
+//! material reflects and transmits all the light arriving it looks exactly as bright as the
+//! background, wherever it's seen from. Materials that absorb some light look darker, and any
+//! brighter than the background are creating light
+
+use crate::background::SolidBackground;
+use crate::integrator::{Integrator, PathIntegrator};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::SampleCtx;
+use crate::sampling;
+use crate::world::World;
+use crate::{Color, Float, RenderSettings};
+use std::sync::Arc;
+
+/// How long a furnace test runs
+#[derive(Clone, Copy, Debug)]
+pub struct FurnaceSettings {
+    /// Number of rays traced at the sphere
+    pub samples: u32,
+    /// Bounces before paths are cut off. Paths bouncing around inside glass lose the light they
+    /// carry when they're cut off, so this needs to be high for a lossless result
+    pub max_depth: u32,
+    pub seed: u64,
+}
+
+impl Default for FurnaceSettings {
+    fn default() -> Self {
+        Self {
+            samples: 20_000,
+            max_depth: 64,
+            seed: 0,
+        }
+    }
+}
+
+/// What a furnace test measured
+#[derive(Clone, Copy, Debug)]
+pub struct FurnaceResult {
+    /// Average light leaving the sphere in each channel, relative to the background. 1 for
+    /// materials that keep all the light
+    pub radiance: Color,
+    /// Uncertainty in each channel of `radiance`, with 95% confidence
+    pub error: Color,
+}
+
+impl FurnaceResult {
+    /// Whether no channel is measurably brighter than the background, allowing `tolerance` on
+    /// top of the measurement's uncertainty
+    pub fn conserves_energy(&self, tolerance: Float) -> bool {
+        channels(self.radiance)
+            .iter()
+            .zip(channels(self.error).iter())
+            .all(|(radiance, error)| *radiance <= 1. + error + tolerance)
+    }
+
+    /// Whether every channel matches the background, so no light is lost either
+    pub fn is_lossless(&self, tolerance: Float) -> bool {
+        channels(self.radiance)
+            .iter()
+            .zip(channels(self.error).iter())
+            .all(|(radiance, error)| (radiance - 1.).abs() <= error + tolerance)
+    }
+}
+
+fn channels(color: Color) -> [Float; 3] {
+    [color.red, color.green, color.blue]
+}
+
+/// Render settings for furnace tests, with a white background and every path kept to its full
+/// length
+pub fn furnace_render_settings(max_depth: u32) -> RenderSettings {
+    RenderSettings {
+        max_depth,
+        background: Box::new(SolidBackground::new(color!(1., 1., 1.))),
+        russian_roulette_depth: None,
+        ..Default::default()
+    }
+}
+
+/// Runs a furnace test on `material`, tracing rays at `World::furnace` from all around
+pub fn furnace_test<M: Material + Send + Sync + 'static>(
+    material: M,
+    settings: &FurnaceSettings,
+) -> FurnaceResult {
+    let world = World::furnace(Arc::new(material));
+    let render_settings = furnace_render_settings(settings.max_depth);
+    let mut ctx = SampleCtx::from_seed(Some(settings.seed));
+    let mut sum = color!();
+    let mut squares = color!();
+    for _ in 0..settings.samples {
+        // Aim from a random point outside the sphere at a random point inside, so every ray hits
+        let origin = sampling::uniform_sphere(ctx.get_2d()) * 4.;
+        let target = sampling::uniform_sphere(ctx.get_2d()) * 0.9 * ctx.get_1d();
+        let ray = Ray::new(origin.conv(), target - origin, 0.);
+        debug_assert!(world.hit(&ray, 0., Float::INFINITY).is_some());
+        let color = PathIntegrator.li(&ray, &world, &render_settings, &mut ctx);
+        sum += color;
+        squares += color * color;
+    }
+    let n = settings.samples.max(1) as Float;
+    let radiance = sum / n;
+    let variance = squares / n - radiance * radiance;
+    let error = |variance: Float| 1.96 * (variance.max(0.) / n).sqrt();
+    FurnaceResult {
+        radiance,
+        error: color!(
+            error(variance.red),
+            error(variance.green),
+            error(variance.blue)
+        ),
+    }
+}
@@ -0,0 +1,604 @@
+//! Path tracing simple scenes on the GPU with a compute shader
+//!
+//! Scenes of spheres, triangles, rectangles and boxes, moved with `Translate` and `RotateY`, made
+//! of Lambertian, metal, dielectric and light materials can be rendered, under a solid or
+//! gradient background. Everything else is left to the CPU. The shader follows the CPU renderer's
+//! camera and materials, but in single precision and without light sampling, so small lights
+//! take more samples to converge
+//!
+//! Needs the `gpu` feature
+
+use crate::camera::{Camera, CameraSettings};
+use crate::hittable::Hittable;
+use crate::image::Image;
+use crate::scene::{
+    BackgroundDesc, MaterialDesc, MaterialRef, ObjectDesc, ScalarDesc, TextureDesc, TextureKind,
+};
+use crate::world::World;
+use crate::{Color, Float, Point3, RenderSettings};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// Primitives per leaf of the BVH
+const LEAF_SIZE: usize = 4;
+
+/// Side of the square of pixels each workgroup renders, matching the shader
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuParams {
+    origin: [f32; 4],
+    lower_left: [f32; 4],
+    horizontal: [f32; 4],
+    vertical: [f32; 4],
+    u: [f32; 4],
+    v: [f32; 4],
+    bottom: [f32; 4],
+    top: [f32; 4],
+    width: u32,
+    height: u32,
+    sample: u32,
+    max_depth: u32,
+    seed: u32,
+    node_count: u32,
+    padding: [u32; 2],
+}
+
+const SPHERE: u32 = 0;
+const TRIANGLE: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuPrimitive {
+    p0: [f32; 3],
+    radius: f32,
+    p1: [f32; 3],
+    kind: u32,
+    p2: [f32; 3],
+    material: u32,
+}
+
+const LAMBERTIAN: u32 = 0;
+const METAL: u32 = 1;
+const DIELECTRIC: u32 = 2;
+const LIGHT: u32 = 3;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuMaterial {
+    color: [f32; 3],
+    kind: u32,
+    even: [f32; 3],
+    param: f32,
+    checker: u32,
+    two_sided: u32,
+    padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuNode {
+    min: [f32; 3],
+    next: u32,
+    max: [f32; 3],
+    count: u32,
+}
+
+/// A world flattened into the buffers the shader reads
+#[derive(Default)]
+struct GpuScene {
+    primitives: Vec<GpuPrimitive>,
+    materials: Vec<GpuMaterial>,
+    nodes: Vec<GpuNode>,
+}
+
+impl GpuScene {
+    fn from_world(world: &World) -> Result<Self, String> {
+        if world.sun.is_some() || world.fog.is_some() || world.atmosphere.is_some() {
+            return Err("suns, fog and atmospheres aren't supported".to_owned());
+        }
+        let mut scene = Self::default();
+        for hittable in world.hittables.iter().chain(world.lights.iter()) {
+            scene.add_hittable(hittable.as_ref())?;
+        }
+        let mut primitives = std::mem::take(&mut scene.primitives);
+        build_bvh(&mut primitives, 0, &mut scene.nodes);
+        scene.primitives = primitives;
+        Ok(scene)
+    }
+
+    /// Adds what `hittable` describes itself as, or its children for groups like BVH nodes
+    fn add_hittable(&mut self, hittable: &dyn Hittable) -> Result<(), String> {
+        if let Some(object) = hittable.describe() {
+            return self.add_object(&object, &|p| p);
+        }
+        let children = hittable.children();
+        if children.is_empty() {
+            return Err("the world holds something that can't be described, like a mesh".into());
+        }
+        children
+            .into_iter()
+            .try_for_each(|child| self.add_hittable(child))
+    }
+
+    /// Adds `object` with its points moved by `transform`
+    fn add_object(
+        &mut self,
+        object: &ObjectDesc,
+        transform: &dyn Fn(Point3) -> Point3,
+    ) -> Result<(), String> {
+        match object {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let material = self.add_material(material)?;
+                self.primitives.push(GpuPrimitive {
+                    p0: to_f32(transform(Point3::from(*center)).into()),
+                    radius: *radius as f32,
+                    p1: [0.; 3],
+                    kind: SPHERE,
+                    p2: [0.; 3],
+                    material,
+                });
+            }
+            ObjectDesc::Triangle { vertices, material } => {
+                let material = self.add_material(material)?;
+                let [p0, p1, p2] = vertices.map(|p| transform(Point3::from(p)));
+                self.add_triangle(p0, p1, p2, material);
+            }
+            ObjectDesc::XyRect {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => {
+                let material = self.add_material(material)?;
+                let corner = |x, y| transform(point3!(x, y, *k));
+                let corners = [corner(*x0, *y0), corner(*x1, *y0), corner(*x1, *y1)];
+                self.add_quad(corners, corner(*x0, *y1), material);
+            }
+            ObjectDesc::XzRect {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let material = self.add_material(material)?;
+                let corner = |x, z| transform(point3!(x, *k, z));
+                let corners = [corner(*x0, *z0), corner(*x0, *z1), corner(*x1, *z1)];
+                self.add_quad(corners, corner(*x1, *z0), material);
+            }
+            ObjectDesc::YzRect {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let material = self.add_material(material)?;
+                let corner = |y, z| transform(point3!(*k, y, z));
+                let corners = [corner(*y0, *z0), corner(*y1, *z0), corner(*y1, *z1)];
+                self.add_quad(corners, corner(*y0, *z1), material);
+            }
+            ObjectDesc::Cuboid { min, max, material } => {
+                let material = self.add_material(material)?;
+                let corner = |x: usize, y: usize, z: usize| {
+                    let pick = |axis: usize, high: usize| {
+                        if high == 1 {
+                            max[axis]
+                        } else {
+                            min[axis]
+                        }
+                    };
+                    transform(point3!(pick(0, x), pick(1, y), pick(2, z)))
+                };
+                // Each face anticlockwise seen from outside, so normals face out
+                let faces = [
+                    [(0, 0, 0), (0, 1, 0), (1, 1, 0), (1, 0, 0)],
+                    [(0, 0, 1), (1, 0, 1), (1, 1, 1), (0, 1, 1)],
+                    [(0, 0, 0), (1, 0, 0), (1, 0, 1), (0, 0, 1)],
+                    [(0, 1, 0), (0, 1, 1), (1, 1, 1), (1, 1, 0)],
+                    [(0, 0, 0), (0, 0, 1), (0, 1, 1), (0, 1, 0)],
+                    [(1, 0, 0), (1, 1, 0), (1, 1, 1), (1, 0, 1)],
+                ];
+                for face in faces.iter() {
+                    let [a, b, c, d] = face.map(|(x, y, z)| corner(x, y, z));
+                    self.add_quad([a, b, c], d, material);
+                }
+            }
+            ObjectDesc::Translate { object, offset } => {
+                let offset = Point3::from(*offset);
+                self.add_object(object, &|p| transform(p + offset))?;
+            }
+            ObjectDesc::RotateY { object, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                self.add_object(object, &|p| {
+                    transform(point3!(cos * p.x + sin * p.z, p.y, -sin * p.x + cos * p.z))
+                })?;
+            }
+            ObjectDesc::MovingSphere { .. } => {
+                return Err("moving spheres aren't supported".to_owned())
+            }
+            ObjectDesc::Cylinder { .. } | ObjectDesc::Cone { .. } => {
+                return Err("cylinders and cones aren't supported".to_owned())
+            }
+            ObjectDesc::ConstantMedium { .. } => return Err("volumes aren't supported".to_owned()),
+            ObjectDesc::Csg { .. } => return Err("CSG isn't supported".to_owned()),
+            ObjectDesc::Plugin { name, .. } => {
+                return Err(format!("the plugin object `{}` isn't supported", name))
+            }
+        }
+        Ok(())
+    }
+
+    fn add_triangle(&mut self, p0: Point3, p1: Point3, p2: Point3, material: u32) {
+        self.primitives.push(GpuPrimitive {
+            p0: to_f32(p0.into()),
+            radius: 0.,
+            p1: to_f32(p1.into()),
+            kind: TRIANGLE,
+            p2: to_f32(p2.into()),
+            material,
+        });
+    }
+
+    /// Adds the quad `a`, `b`, `c`, `d` as two triangles, facing the way `a`, `b`, `c` winds
+    fn add_quad(&mut self, [a, b, c]: [Point3; 3], d: Point3, material: u32) {
+        self.add_triangle(a, b, c, material);
+        self.add_triangle(a, c, d, material);
+    }
+
+    /// Adds a material, returning its index
+    fn add_material(&mut self, material: &MaterialRef) -> Result<u32, String> {
+        let material = match material {
+            MaterialRef::Inline(material) => material.as_ref(),
+            MaterialRef::Named(name) => return Err(format!("unknown material `{}`", name)),
+        };
+        let plain = |kind, color: [Float; 3], param| GpuMaterial {
+            color: to_f32(color),
+            kind,
+            even: [0.; 3],
+            param,
+            checker: 0,
+            two_sided: 0,
+            padding: [0; 2],
+        };
+        let gpu_material = match material {
+            MaterialDesc::Lambertian { albedo } => match albedo {
+                TextureDesc::Color(color) => plain(LAMBERTIAN, *color, 0.),
+                TextureDesc::Texture(TextureKind::Checker { odd, even }) => GpuMaterial {
+                    even: to_f32(*even),
+                    checker: 1,
+                    ..plain(LAMBERTIAN, *odd, 0.)
+                },
+                _ => return Err("only plain colors and checkers are supported".to_owned()),
+            },
+            MaterialDesc::Metal {
+                albedo: TextureDesc::Color(albedo),
+                fuzz: ScalarDesc::Value(fuzz),
+                emission: None,
+            } => plain(METAL, *albedo, *fuzz as f32),
+            MaterialDesc::Dielectric {
+                ior,
+                tint: None,
+                emission: None,
+                absorption: None,
+            } => plain(DIELECTRIC, [1.; 3], *ior as f32),
+            MaterialDesc::Light {
+                color,
+                texture: TextureDesc::Color(texture),
+                two_sided,
+                spread,
+                power: None,
+            } => {
+                let emitted = Color::from(*color) * Color::from(*texture);
+                let cutoff = (spread.min(180.).to_radians() / 2.).cos();
+                GpuMaterial {
+                    two_sided: *two_sided as u32,
+                    ..plain(LIGHT, emitted.into(), cutoff as f32)
+                }
+            }
+            MaterialDesc::Light { .. } => {
+                return Err("textured lights and lights given a power aren't supported".into())
+            }
+            MaterialDesc::Metal { .. } | MaterialDesc::Dielectric { .. } => return Err(
+                "textured, tinted, glowing and absorbing metals and dielectrics aren't supported"
+                    .into(),
+            ),
+            _ => {
+                return Err(
+                    "only Lambertian, metal, dielectric and light materials are supported".into(),
+                )
+            }
+        };
+        self.materials.push(gpu_material);
+        Ok(self.materials.len() as u32 - 1)
+    }
+}
+
+fn to_f32(v: [Float; 3]) -> [f32; 3] {
+    [v[0] as f32, v[1] as f32, v[2] as f32]
+}
+
+/// Bounds of a primitive, padded so flat triangles still have some thickness
+fn primitive_bounds(primitive: &GpuPrimitive) -> ([f32; 3], [f32; 3]) {
+    if primitive.kind == SPHERE {
+        let r = primitive.radius;
+        let c = primitive.p0;
+        return (
+            [c[0] - r, c[1] - r, c[2] - r],
+            [c[0] + r, c[1] + r, c[2] + r],
+        );
+    }
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for p in [primitive.p0, primitive.p1, primitive.p2].iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis] - 1e-4);
+            max[axis] = max[axis].max(p[axis] + 1e-4);
+        }
+    }
+    (min, max)
+}
+
+/// Sorts `primitives` into the leaves of a BVH, appending its nodes to `nodes` depth first
+///
+/// `offset` is where `primitives` starts in the whole list. Splits at the median along the
+/// longest axis of the primitives' centers
+fn build_bvh(primitives: &mut [GpuPrimitive], offset: u32, nodes: &mut Vec<GpuNode>) {
+    if primitives.is_empty() {
+        return;
+    }
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    let mut center_min = [f32::INFINITY; 3];
+    let mut center_max = [f32::NEG_INFINITY; 3];
+    for primitive in primitives.iter() {
+        let (low, high) = primitive_bounds(primitive);
+        for axis in 0..3 {
+            min[axis] = min[axis].min(low[axis]);
+            max[axis] = max[axis].max(high[axis]);
+            let center = (low[axis] + high[axis]) / 2.;
+            center_min[axis] = center_min[axis].min(center);
+            center_max[axis] = center_max[axis].max(center);
+        }
+    }
+    let index = nodes.len();
+    nodes.push(GpuNode {
+        min,
+        next: offset,
+        max,
+        count: primitives.len() as u32,
+    });
+    if primitives.len() <= LEAF_SIZE {
+        return;
+    }
+
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            let extent = |axis: usize| center_max[axis] - center_min[axis];
+            extent(a).partial_cmp(&extent(b)).unwrap()
+        })
+        .unwrap();
+    let center = |primitive: &GpuPrimitive| {
+        let (low, high) = primitive_bounds(primitive);
+        low[axis] + high[axis]
+    };
+    primitives.sort_by(|a, b| center(a).partial_cmp(&center(b)).unwrap());
+    let middle = primitives.len() / 2;
+    let (left, right) = primitives.split_at_mut(middle);
+    nodes[index].count = 0;
+    build_bvh(left, offset, nodes);
+    nodes[index].next = nodes.len() as u32;
+    build_bvh(right, offset + middle as u32, nodes);
+}
+
+fn entry(binding: u32, buffer: &wgpu::Buffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: buffer.as_entire_binding(),
+    }
+}
+
+/// Renders `world` on the GPU, or says why it can't
+///
+/// Fails when there's no GPU to use or the world or background has something the shader doesn't
+/// support, see the module documentation, so the caller can render on the CPU instead. Ignores
+/// the sampler, filter, clamping and Russian roulette settings
+pub fn raytrace_gpu(
+    world: &World,
+    camera_settings: &CameraSettings,
+    render_settings: &RenderSettings,
+    image_width: u32,
+    image_height: u32,
+) -> Result<Image, String> {
+    if render_settings.alpha {
+        return Err("transparent backgrounds aren't supported".to_owned());
+    }
+    if camera_settings.near > 0. || camera_settings.far.is_finite() {
+        return Err("clipping distances aren't supported".to_owned());
+    }
+    if camera_settings.aperture_mask.is_some() {
+        return Err("aperture masks aren't supported".to_owned());
+    }
+    let (bottom, top) = match render_settings.background.describe() {
+        Some(BackgroundDesc::Solid { color }) => (color, color),
+        Some(BackgroundDesc::Gradient { bottom, top }) => (bottom, top),
+        Some(BackgroundDesc::Sky) => ([1., 1., 1.], [0.5, 0.7, 1.]),
+        _ => return Err("only solid and gradient backgrounds are supported".to_owned()),
+    };
+    let scene = GpuScene::from_world(world)?;
+
+    let camera = Camera::new(
+        camera_settings,
+        image_width as Float / image_height as Float,
+    );
+    let lower_left = camera.focus_point(0., 0.);
+    let w = (camera_settings.look_from - camera_settings.look_at).unit_vector();
+    let u = camera_settings.vup.conv::<Point3>().cross(&w).unit_vector();
+    let v = w.cross(&u);
+    let vec4 = |v: [Float; 3], w: Float| [v[0] as f32, v[1] as f32, v[2] as f32, w as f32];
+    let params = GpuParams {
+        origin: vec4(camera.origin().into(), camera_settings.aperture / 2.),
+        lower_left: vec4(lower_left.into(), 0.),
+        horizontal: vec4((camera.focus_point(1., 0.) - lower_left).into(), 0.),
+        vertical: vec4((camera.focus_point(0., 1.) - lower_left).into(), 0.),
+        u: vec4(u.into(), 0.),
+        v: vec4(v.into(), 0.),
+        bottom: vec4(bottom, 0.),
+        top: vec4(top, 0.),
+        width: image_width,
+        height: image_height,
+        sample: 0,
+        max_depth: render_settings.max_depth,
+        seed: render_settings
+            .seed
+            .map_or_else(rand::random, |seed| (seed ^ (seed >> 32)) as u32),
+        node_count: scene.nodes.len() as u32,
+        padding: [0; 2],
+    };
+    let sums = pollster::block_on(render(
+        &scene,
+        params,
+        render_settings.samples_per_pixel.max(1),
+    ))?;
+    let data = sums
+        .chunks_exact(4)
+        .map(|sum| {
+            let samples = sum[3].max(1.) as Float;
+            color!(sum[0] as Float, sum[1] as Float, sum[2] as Float) / samples
+        })
+        .collect();
+    Ok(Image {
+        width: image_width,
+        height: image_height,
+        data,
+        alpha: None,
+    })
+}
+
+/// Runs the shader `samples` times, returning the sum of each pixel's samples followed by how
+/// many there were
+async fn render(scene: &GpuScene, mut params: GpuParams, samples: u32) -> Result<Vec<f32>, String> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })
+        .await
+        .map_err(|error| format!("no GPU found: {}", error))?;
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor::default())
+        .await
+        .map_err(|error| format!("couldn't open the GPU: {}", error))?;
+
+    let pixel_count = (params.width * params.height) as u64;
+    let output_size = pixel_count * std::mem::size_of::<[f32; 4]>() as u64;
+    let largest = output_size.max(std::mem::size_of_val(scene.primitives.as_slice()) as u64);
+    if largest > device.limits().max_storage_buffer_binding_size {
+        return Err("the image or scene is too large for the GPU".to_owned());
+    }
+
+    // Storage buffers can't be empty
+    let storage = |label, contents: &[u8]| {
+        let zero = [0_u8; 64];
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: if contents.is_empty() { &zero } else { contents },
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    };
+    let primitives = storage("primitives", bytemuck::cast_slice(&scene.primitives));
+    let materials = storage("materials", bytemuck::cast_slice(&scene.materials));
+    let nodes = storage("nodes", bytemuck::cast_slice(&scene.nodes));
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("params"),
+        contents: bytemuck::bytes_of(&params),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let accumulated = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("accumulated"),
+        size: output_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: output_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("path tracer"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("path tracer"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("scene"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            entry(0, &params_buffer),
+            entry(1, &primitives),
+            entry(2, &materials),
+            entry(3, &nodes),
+            entry(4, &accumulated),
+        ],
+    });
+
+    let prog_bar = indicatif::ProgressBar::new(samples as u64);
+    prog_bar.set_style(indicatif::ProgressStyle::default_bar().template(
+        "Rendering - Done {elapsed:>3} Estimated {eta:>3} {wide_bar} {pos:>4}/{len:4} Samples",
+    ));
+    let groups = |size: u32| size.div_ceil(WORKGROUP_SIZE);
+    for sample in 0..samples {
+        params.sample = sample;
+        queue.write_buffer(&params_buffer, 0, bytemuck::bytes_of(&params));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups(params.width), groups(params.height), 1);
+        }
+        if sample + 1 == samples {
+            encoder.copy_buffer_to_buffer(&accumulated, 0, &readback, 0, output_size);
+        }
+        queue.submit(Some(encoder.finish()));
+        // Wait for each sample, so the progress bar is right and the queue doesn't fill up
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|error| format!("lost the GPU: {}", error))?;
+        prog_bar.inc(1);
+    }
+    prog_bar.finish();
+
+    let slice = readback.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |_| {});
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|error| format!("lost the GPU: {}", error))?;
+    let sums = bytemuck::cast_slice(
+        &slice
+            .get_mapped_range()
+            .map_err(|error| format!("couldn't read the image back: {}", error))?,
+    )
+    .to_vec();
+    Ok(sums)
+}
@@ -0,0 +1,332 @@
+// Path tracer run by `gpu::raytrace_gpu`, adding one sample to every pixel per dispatch
+//
+// Mirrors the CPU renderer's camera, materials and backgrounds, without light sampling
+
+struct Params {
+    // `w` is the lens radius
+    origin: vec4<f32>,
+    lower_left: vec4<f32>,
+    horizontal: vec4<f32>,
+    vertical: vec4<f32>,
+    u: vec4<f32>,
+    v: vec4<f32>,
+    // Background colors looking straight down and straight up
+    bottom: vec4<f32>,
+    top: vec4<f32>,
+    width: u32,
+    height: u32,
+    sample: u32,
+    max_depth: u32,
+    seed: u32,
+    node_count: u32,
+    padding: vec2<u32>,
+}
+
+const SPHERE: u32 = 0u;
+const TRIANGLE: u32 = 1u;
+
+// A sphere around `p0` of `radius`, or the triangle `p0`, `p1`, `p2`
+struct Primitive {
+    p0: vec3<f32>,
+    radius: f32,
+    p1: vec3<f32>,
+    kind: u32,
+    p2: vec3<f32>,
+    material: u32,
+}
+
+const LAMBERTIAN: u32 = 0u;
+const METAL: u32 = 1u;
+const DIELECTRIC: u32 = 2u;
+const LIGHT: u32 = 3u;
+
+struct Material {
+    // Albedo, or the odd squares of a checker, or emitted light
+    color: vec3<f32>,
+    kind: u32,
+    // The even squares of a checker
+    even: vec3<f32>,
+    // Metal fuzz, dielectric index of refraction, or the cosine of half a light's spread
+    param: f32,
+    checker: u32,
+    two_sided: u32,
+    padding: vec2<u32>,
+}
+
+// A leaf holding `count` primitives from `next`, or an inner node with its first child
+// following it and its second at `next`
+struct Node {
+    min: vec3<f32>,
+    next: u32,
+    max: vec3<f32>,
+    count: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> primitives: array<Primitive>;
+@group(0) @binding(2) var<storage, read> materials: array<Material>;
+@group(0) @binding(3) var<storage, read> nodes: array<Node>;
+@group(0) @binding(4) var<storage, read_write> accumulated: array<vec4<f32>>;
+
+const PI: f32 = 3.14159265358979;
+const HIT_EPSILON: f32 = 0.001;
+const STACK_SIZE: u32 = 64u;
+
+var<private> rng_state: u32;
+
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Between 0 and 1
+fn random() -> f32 {
+    rng_state = pcg_hash(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn unit_vector(u: f32, v: f32) -> vec3<f32> {
+    let z = 1.0 - 2.0 * u;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    let phi = 2.0 * PI * v;
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn unit_disk(u: f32, v: f32) -> vec2<f32> {
+    let r = sqrt(u);
+    let phi = 2.0 * PI * v;
+    return vec2<f32>(r * cos(phi), r * sin(phi));
+}
+
+struct Ray {
+    origin: vec3<f32>,
+    dir: vec3<f32>,
+}
+
+struct Hit {
+    t: f32,
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    front_face: bool,
+    material: u32,
+}
+
+// Nearest `t` between `t_min` and `t_max` where the ray meets the sphere, or -1. Works out the
+// discriminant from the center's distance to the ray like `sphere_roots`
+fn hit_sphere(primitive: Primitive, ray: Ray, t_min: f32, t_max: f32) -> f32 {
+    let oc = ray.origin - primitive.p0;
+    let a = dot(ray.dir, ray.dir);
+    let half_b = dot(oc, ray.dir);
+    let c = dot(oc, oc) - primitive.radius * primitive.radius;
+    let distance = length(oc - (half_b / a) * ray.dir);
+    let discriminant = a * (primitive.radius - distance) * (primitive.radius + distance);
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+    var root = sqrt(discriminant);
+    if half_b < 0.0 {
+        root = -root;
+    }
+    let q = -(half_b + root);
+    if q == 0.0 {
+        return -1.0;
+    }
+    let near = min(q / a, c / q);
+    let far = max(q / a, c / q);
+    if near > t_min && near < t_max {
+        return near;
+    }
+    if far > t_min && far < t_max {
+        return far;
+    }
+    return -1.0;
+}
+
+// Moller-Trumbore, like `intersect_triangle`
+fn hit_triangle(primitive: Primitive, ray: Ray, t_min: f32, t_max: f32) -> f32 {
+    let edge1 = primitive.p1 - primitive.p0;
+    let edge2 = primitive.p2 - primitive.p0;
+    let h = cross(ray.dir, edge2);
+    let a = dot(edge1, h);
+    if abs(a) < 1e-12 {
+        return -1.0;
+    }
+    let f = 1.0 / a;
+    let s = ray.origin - primitive.p0;
+    let b1 = f * dot(s, h);
+    if b1 < 0.0 || b1 > 1.0 {
+        return -1.0;
+    }
+    let q = cross(s, edge1);
+    let b2 = f * dot(ray.dir, q);
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return -1.0;
+    }
+    let t = f * dot(edge2, q);
+    if t > t_min && t < t_max {
+        return t;
+    }
+    return -1.0;
+}
+
+fn hit_box(node: Node, ray: Ray, inverse_dir: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.min - ray.origin) * inverse_dir;
+    let t1 = (node.max - ray.origin) * inverse_dir;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), HIT_EPSILON));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+// Closest hit along the ray, with `t` left at -1 for misses
+fn hit_world(ray: Ray) -> Hit {
+    var hit: Hit;
+    hit.t = -1.0;
+    if params.node_count == 0u {
+        return hit;
+    }
+    var closest = 3.4e38;
+    var closest_index = 0u;
+    let inverse_dir = 1.0 / ray.dir;
+    var stack: array<u32, STACK_SIZE>;
+    var top = 1u;
+    stack[0] = 0u;
+    while top > 0u {
+        top -= 1u;
+        let index = stack[top];
+        let node = nodes[index];
+        if !hit_box(node, ray, inverse_dir, closest) {
+            continue;
+        }
+        if node.count > 0u {
+            for (var i = node.next; i < node.next + node.count; i++) {
+                let primitive = primitives[i];
+                var t: f32;
+                if primitive.kind == SPHERE {
+                    t = hit_sphere(primitive, ray, HIT_EPSILON, closest);
+                } else {
+                    t = hit_triangle(primitive, ray, HIT_EPSILON, closest);
+                }
+                if t > 0.0 {
+                    closest = t;
+                    closest_index = i;
+                    hit.t = t;
+                }
+            }
+        } else if top + 2u <= STACK_SIZE {
+            stack[top] = node.next;
+            stack[top + 1u] = index + 1u;
+            top += 2u;
+        }
+    }
+    if hit.t < 0.0 {
+        return hit;
+    }
+
+    let primitive = primitives[closest_index];
+    hit.point = ray.origin + hit.t * ray.dir;
+    var outward: vec3<f32>;
+    if primitive.kind == SPHERE {
+        outward = (hit.point - primitive.p0) / primitive.radius;
+    } else {
+        outward = normalize(cross(primitive.p1 - primitive.p0, primitive.p2 - primitive.p0));
+    }
+    hit.front_face = dot(ray.dir, outward) < 0.0;
+    hit.normal = select(-outward, outward, hit.front_face);
+    hit.material = primitive.material;
+    return hit;
+}
+
+fn albedo(material: Material, point: vec3<f32>) -> vec3<f32> {
+    if material.checker == 0u {
+        return material.color;
+    }
+    let sines = sin(10.0 * point.x) * sin(10.0 * point.y) * sin(10.0 * point.z);
+    return select(material.even, material.color, sines < 0.0);
+}
+
+fn schlick(cosine: f32, ref_idx: f32) -> f32 {
+    var r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+fn background(dir: vec3<f32>) -> vec3<f32> {
+    let t = 0.5 * (normalize(dir).y + 1.0);
+    return (1.0 - t) * params.bottom.xyz + t * params.top.xyz;
+}
+
+fn trace(first: Ray) -> vec3<f32> {
+    var ray = first;
+    var throughput = vec3<f32>(1.0);
+    var color = vec3<f32>(0.0);
+    for (var depth = 0u; depth < params.max_depth; depth++) {
+        let hit = hit_world(ray);
+        if hit.t < 0.0 {
+            return color + throughput * background(ray.dir);
+        }
+        let material = materials[hit.material];
+        let unit_dir = normalize(ray.dir);
+        var dir: vec3<f32>;
+        switch material.kind {
+            case LAMBERTIAN: {
+                dir = hit.normal + unit_vector(random(), random());
+                if dot(dir, dir) < 1e-12 {
+                    dir = hit.normal;
+                }
+                throughput *= albedo(material, hit.point);
+            }
+            case METAL: {
+                dir = reflect(unit_dir, hit.normal) + material.param * unit_vector(random(), random());
+                if dot(dir, hit.normal) <= 0.0 {
+                    return color;
+                }
+                throughput *= material.color;
+            }
+            case DIELECTRIC: {
+                let ratio = select(material.param, 1.0 / material.param, hit.front_face);
+                let cos_theta = min(dot(-unit_dir, hit.normal), 1.0);
+                let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+                if ratio * sin_theta > 1.0 || random() < schlick(cos_theta, ratio) {
+                    dir = reflect(unit_dir, hit.normal);
+                } else {
+                    dir = refract(unit_dir, hit.normal, ratio);
+                }
+            }
+            default: {
+                let cosine = dot(hit.normal, -unit_dir);
+                if (material.two_sided != 0u || hit.front_face) && cosine >= material.param {
+                    color += throughput * material.color;
+                }
+                return color;
+            }
+        }
+        ray = Ray(hit.point, dir);
+    }
+    return color;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+    let index = id.y * params.width + id.x;
+    rng_state = pcg_hash(index ^ pcg_hash(params.sample ^ pcg_hash(params.seed)));
+
+    // Rows go from the top, image coordinates from the bottom
+    let i = f32(id.x);
+    let j = f32(params.height - 1u - id.y);
+    let s = (i + random()) / f32(params.width - 1u);
+    let t = (j + random()) / f32(params.height - 1u);
+    let rd = params.origin.w * unit_disk(random(), random());
+    let offset = params.u.xyz * rd.x + params.v.xyz * rd.y;
+    let origin = params.origin.xyz + offset;
+    let dir = params.lower_left.xyz + s * params.horizontal.xyz + t * params.vertical.xyz - origin;
+
+    let color = trace(Ray(origin, dir));
+    // Paths that go wrong in single precision shouldn't spoil the whole pixel
+    if all(color == color) && all(abs(color) < vec3<f32>(3.4e38)) {
+        accumulated[index] += vec4<f32>(color, 1.0);
+    }
+}
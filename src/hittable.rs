@@ -1,54 +1,248 @@
-use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::accel::Accelerator;
+use crate::consts::PI;
+use crate::integrator::PathState;
+use crate::material::{check_albedo, Isotropic, Material};
+use crate::pdf::sample_cone;
 use crate::ray::Ray;
+use crate::sampler::{SampleCtx, SampleRng};
+use crate::sampling;
+use crate::scene::{MaterialDesc, MaterialRef, ObjectDesc};
+use crate::texture::{SolidColor, Texture};
 use crate::world::AABB;
-use crate::{Color, Point3, Vec3};
-use rand::distributions::{Distribution, Standard, Uniform};
-use rand::rngs::ThreadRng;
+use crate::{Color, Float, Point3, Vec3};
+use rand::distributions::Standard;
 use rand::Rng;
-use std::f64::consts::PI;
+use std::sync::Arc;
 
 /// The object can be raytraced
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
-    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>>;
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB>;
+
+    /// Whether `ray` hits anything visible to bounced rays between `t_min` and `t_max`, for
+    /// shadow rays
+    ///
+    /// Only needs to find some hit rather than the closest, so hittables can stop at the first
+    /// and skip filling in a `HitRecord`. Defaults to `hit`, passing through hidden surfaces
+    fn hit_any(&self, ray: &Ray, mut t_min: Float, t_max: Float) -> bool {
+        while let Some(rec) = self.hit(ray, t_min, t_max) {
+            if rec.material.visibility().indirect {
+                return true;
+            }
+            t_min = rec.t + HIT_EPSILON;
+        }
+        false
+    }
+
+    /// Picks a random point on the surface, returning it with the outward normal there
+    ///
+    /// Needed for hittables used as lights. `None` if the surface can't be sampled
+    fn random_point(&self, _: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        None
+    }
+
+    /// Surface area, used alongside `random_point`
+    fn area(&self) -> Float {
+        0.
+    }
+
+    /// Picks a random direction from `origin` towards the surface, for sampling lights directly
+    ///
+    /// Defaults to aiming at `random_point`, which is only right for surfaces no line crosses
+    /// twice, such as flat ones. Other shapes should override this and `pdf_value`
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        let (point, _) = self.random_point(rng)?;
+        Some((point - *origin).conv())
+    }
+
+    /// Hittables this one is built from, for tools that walk the scene. Empty by default
+    fn children(&self) -> Vec<&dyn Hittable> {
+        Vec::new()
+    }
+
+    /// Materials used by this hittable and those it's built from, for checking scenes
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        self.children()
+            .into_iter()
+            .flat_map(|child| child.materials())
+            .collect()
+    }
+
+    /// Description of the hittable for saving to a scene file, if it can be saved
+    fn describe(&self) -> Option<ObjectDesc> {
+        None
+    }
+
+    /// Center and radius if this is a still sphere, so the BVH can intersect it alongside others
+    fn sphere(&self) -> Option<(Point3, Float)> {
+        None
+    }
+
+    /// This hittable as an acceleration structure, for tools that inspect them
+    fn accelerator(&self) -> Option<&dyn Accelerator> {
+        None
+    }
+
+    /// Probability density of `random_direction` choosing `dir` from `origin`, over solid angle
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        let area = self.area();
+        if area <= 0. {
+            return 0.;
+        }
+        let rec = match self.hit(&Ray::new(*origin, *dir, 0.), HIT_EPSILON, Float::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.,
+        };
+        // Convert from density over the surface to density over directions
+        let distance_squared = rec.t * rec.t * dir.length_squared();
+        let cosine = rec.normal.dot(&dir.unit_vector()).abs();
+        if cosine < 1e-9 {
+            return 0.;
+        }
+        distance_squared / (cosine * area)
+    }
 }
 
 /// Records a raytrace hit
 pub struct HitRecord<'a> {
     pub point: Point3,
     pub normal: Vec3,
-    pub t: f64,
+    pub t: Float,
     pub front_face: bool,
-    pub material: &'a dyn Material,
-    pub u: f64,
-    pub v: f64,
+    pub material: &'a (dyn Material + Sync),
+    /// Texture coordinates of the hit, each between 0 and 1
+    pub u: Float,
+    pub v: Float,
+    /// Unit vectors along the surface the ways `u` and `v` increase, for normal mapping. Not
+    /// always perpendicular to each other
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    /// Which object in the world was hit, numbered from 1 by `World::build_bvh` in the order
+    /// objects were added, then lights. 0 until then
+    pub object_id: u32,
 }
 
-fn get_sphere_uv(p: Point3) -> (f64, f64) {
+/// Texture coordinates for a point on the unit sphere
+///
+/// `u` goes around the y axis starting from -x, and `v` goes from the bottom pole to the top
+fn get_sphere_uv(p: Point3) -> (Float, Float) {
     let phi = p.z.atan2(p.x);
-    let theta = p.y.asin();
+    // Rounding can push points just off the sphere
+    let theta = p.y.clamp(-1., 1.).asin();
     let u = 1. - (phi + PI) / (2. * PI);
     let v = (theta + PI / 2.) / PI;
     (u, v)
 }
 
+/// Unit vector around the y axis at `p`, the way `u` increases in `get_sphere_uv`
+fn around_y(p: Vec3) -> Vec3 {
+    let r = (p.x * p.x + p.z * p.z).sqrt();
+    if r > 0. {
+        vec3!(p.z / r, 0., -p.x / r)
+    } else {
+        // On the axis, where any direction will do
+        vec3!(0., 0., -1.)
+    }
+}
+
+/// Rays ignore hits closer than this to their origin, so rays leaving a surface don't hit it again
+/// through rounding
+pub const HIT_EPSILON: Float = 0.001;
+
+/// The roots of `a t² + 2 half_b t + c`, nearest first, given its discriminant `half_b² - a c`.
+/// `None` if the discriminant is negative
+///
+/// Takes the discriminant rather than working it out, so primitives can compute it in whichever
+/// form is accurate for their shape, see `sphere_roots`. Finds the root where `-half_b` and the
+/// square root have the same sign first, so they don't cancel, then the other from the product
+/// of the roots, `c / a`
+pub fn quadratic_roots(
+    a: Float,
+    half_b: Float,
+    c: Float,
+    discriminant: Float,
+) -> Option<(Float, Float)> {
+    if discriminant < 0. {
+        return None;
+    }
+    let q = -(half_b + discriminant.sqrt().copysign(half_b));
+    if q == 0. {
+        // Both roots are zero, and `c / q` would be NaN
+        return Some((0., 0.));
+    }
+    let (t0, t1) = (q / a, c / q);
+    Some(if t0 <= t1 { (t0, t1) } else { (t1, t0) })
+}
+
+/// Where a ray meets a sphere of `radius`, as the nearest and farthest `t`, given the ray's
+/// origin relative to the sphere's center `oc` and its direction
+///
+/// The textbook discriminant takes two large and nearly equal numbers from each other when the
+/// sphere is big compared to how far the ray passes from its edge, like a ground sphere seen at a
+/// grazing angle, and rounding then scatters speckles over the surface. This works it out from
+/// how far the center is from the ray's line instead
+pub fn sphere_roots(oc: Vec3, dir: Vec3, radius: Float) -> Option<(Float, Float)> {
+    let a = dir.length_squared();
+    let half_b = oc.dot(&dir);
+    let c = oc.length_squared() - radius * radius;
+    let distance = (oc - (half_b / a) * dir).length();
+    let discriminant = a * (radius - distance) * (radius + distance);
+    quadratic_roots(a, half_b, c, discriminant)
+}
+
+/// Fills in a hit record for a sphere hit at `t`, including its texture coordinates
+fn sphere_hit<'a>(
+    ray: &Ray,
+    t: Float,
+    center: Point3,
+    radius: Float,
+    material: &'a (dyn Material + Sync),
+) -> HitRecord<'a> {
+    let point = ray.at(t);
+    let outward = (point - center) / radius;
+    let front_face = ray.dir.dot(&outward.conv()) < 0.;
+    let normal = if front_face { outward } else { -outward };
+    let (u, v) = get_sphere_uv(outward);
+    let tangent = around_y(outward.conv());
+    HitRecord {
+        t,
+        point,
+        normal: normal.conv(),
+        front_face,
+        material,
+        u,
+        v,
+        tangent,
+        bitangent: outward.conv::<Vec3>().cross(&tangent),
+        object_id: 0,
+    }
+}
+
 /// A sphere
-pub struct Sphere<'a> {
+pub struct Sphere {
     center: Point3,
-    radius: f64,
-    material: Box<dyn Material + Sync + 'a>,
+    radius: Float,
+    material: Arc<dyn Material + Send + Sync>,
 }
 
-impl<'a> Sphere<'a> {
-    pub fn new<T: Material + Sync + 'a>(center: Point3, radius: f64, material: T) -> Self {
+impl Sphere {
+    pub fn new<T: Material + Send + Sync + 'static>(
+        center: Point3,
+        radius: Float,
+        material: T,
+    ) -> Self {
         Self {
             center,
             radius,
-            material: Box::new(material),
+            material: Arc::new(material),
         }
     }
 
-    pub fn new_boxed(center: Point3, radius: f64, material: Box<dyn Material + Sync + 'a>) -> Self {
+    pub fn new_shared(
+        center: Point3,
+        radius: Float,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
         Self {
             center,
             radius,
@@ -57,81 +251,124 @@ impl<'a> Sphere<'a> {
     }
 }
 
-impl<'a> Hittable for Sphere<'a> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.dir.length_squared();
-        let half_b = oc.dot(&ray.dir.conv());
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (near, far) = sphere_roots((ray.origin - self.center).conv(), ray.dir, self.radius)?;
+        let t = if near > t_min && near < t_max {
+            near
+        } else if far > t_min && far < t_max {
+            far
+        } else {
             return None;
-        }
+        };
+        Some(sphere_hit(
+            ray,
+            t,
+            self.center,
+            self.radius,
+            self.material.as_ref(),
+        ))
+    }
 
-        let root = discriminant.sqrt();
-        let t = (-half_b - root) / a;
-        if t > t_min && t < t_max {
-            let point = ray.at(t);
-            let normal = (point - self.center) / self.radius;
-            let front_face = ray.dir.dot(&normal.conv()) < 0.;
-            let normal = if front_face { normal } else { -normal };
-            let (u_res, v_res) = get_sphere_uv((point - self.center) / self.radius);
-            return Some(HitRecord {
-                t,
-                point,
-                normal: normal.conv(),
-                front_face,
-                material: self.material.as_ref(),
-                u: u_res,
-                v: v_res,
-            });
-        }
-
-        let t = (-half_b + root) / a;
-        if t > t_min && t < t_max {
-            let point = ray.at(t);
-            let normal = (point - self.center) / self.radius;
-            let front_face = ray.dir.dot(&normal.conv()) < 0.;
-            let normal = if front_face { normal } else { -normal };
-            let (u_res, v_res) = get_sphere_uv((point - self.center) / self.radius);
-            return Some(HitRecord {
-                t,
-                point,
-                normal: normal.conv(),
-                front_face,
-                material: self.material.as_ref(),
-                u: u_res,
-                v: v_res,
-            });
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        if !self.material.visibility().indirect {
+            return false;
         }
-
-        None
+        let oc = (ray.origin - self.center).conv();
+        let (near, far) = match sphere_roots(oc, ray.dir, self.radius) {
+            Some(roots) => roots,
+            None => return false,
+        };
+        (near > t_min && near < t_max) || (far > t_min && far < t_max)
     }
 
-    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
         Some(AABB {
             min: self.center - point3!(self.radius, self.radius, self.radius),
             max: self.center + point3!(self.radius, self.radius, self.radius),
         })
     }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn sphere(&self) -> Option<(Point3, Float)> {
+        Some((self.center, self.radius))
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Sphere {
+            center: self.center.into(),
+            radius: self.radius,
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        let z = 1. - 2. * rng.sample::<Float, _>(Standard);
+        let r = (1. - z * z).sqrt();
+        let phi = 2. * PI * rng.sample::<Float, _>(Standard);
+        let normal = vec3!(r * phi.cos(), r * phi.sin(), z);
+        Some((self.center + (self.radius * normal).conv(), normal))
+    }
+
+    fn area(&self) -> Float {
+        4. * PI * self.radius * self.radius
+    }
+
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        let to_center: Vec3 = (self.center - *origin).conv();
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // From inside every direction crosses the surface once
+            let (point, _) = self.random_point(rng)?;
+            return Some((point - *origin).conv());
+        }
+        // Aim within the cone the sphere covers
+        let cos_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        Some(sample_cone(&to_center, cos_max, rng))
+    }
+
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        let distance_squared = (self.center - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // Uniform over the surface, seen from inside
+            let rec = match self.hit(&Ray::new(*origin, *dir, 0.), 0., Float::INFINITY) {
+                Some(rec) => rec,
+                None => return 0.,
+            };
+            let distance_squared = rec.t * rec.t * dir.length_squared();
+            let cosine = rec.normal.dot(&dir.unit_vector()).abs().max(1e-9);
+            return distance_squared / (cosine * self.area());
+        }
+        if self
+            .hit(&Ray::new(*origin, *dir, 0.), HIT_EPSILON, Float::INFINITY)
+            .is_none()
+        {
+            return 0.;
+        }
+        let cos_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        sampling::uniform_cone_pdf(cos_max)
+    }
 }
 
-pub struct MovingSphere<'a> {
+pub struct MovingSphere {
     center0: Point3,
     center1: Point3,
-    t0: f64,
-    t1: f64,
-    radius: f64,
-    material: Box<dyn Material + Sync + 'a>,
+    t0: Float,
+    t1: Float,
+    radius: Float,
+    material: Arc<dyn Material + Send + Sync>,
 }
 
-impl<'a> MovingSphere<'a> {
-    pub fn new<T: Material + Sync + 'a>(
+impl MovingSphere {
+    pub fn new<T: Material + Send + Sync + 'static>(
         center0: Point3,
         center1: Point3,
-        t0: f64,
-        t1: f64,
-        radius: f64,
+        t0: Float,
+        t1: Float,
+        radius: Float,
         material: T,
     ) -> Self {
         Self {
@@ -140,17 +377,17 @@ impl<'a> MovingSphere<'a> {
             t0,
             t1,
             radius,
-            material: Box::new(material),
+            material: Arc::new(material),
         }
     }
 
-    pub fn new_boxed(
+    pub fn new_shared(
         center0: Point3,
         center1: Point3,
-        t0: f64,
-        t1: f64,
-        radius: f64,
-        material: Box<dyn Material + Sync + 'a>,
+        t0: Float,
+        t1: Float,
+        radius: Float,
+        material: Arc<dyn Material + Send + Sync>,
     ) -> Self {
         Self {
             center0,
@@ -162,64 +399,32 @@ impl<'a> MovingSphere<'a> {
         }
     }
 
-    pub fn center(&self, t: f64) -> Point3 {
+    pub fn center(&self, t: Float) -> Point3 {
         self.center0 + ((t - self.t0) / (self.t1 - self.t0)) * (self.center1 - self.center0)
     }
 }
 
-impl<'a> Hittable for MovingSphere<'a> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         let center = self.center(ray.time);
-        let oc = ray.origin - center;
-        let a = ray.dir.length_squared();
-        let half_b = oc.dot(&ray.dir.conv());
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
+        let (near, far) = sphere_roots((ray.origin - center).conv(), ray.dir, self.radius)?;
+        let t = if near > t_min && near < t_max {
+            near
+        } else if far > t_min && far < t_max {
+            far
+        } else {
             return None;
-        }
-
-        let root = discriminant.sqrt();
-        let t = (-half_b - root) / a;
-        if t > t_min && t < t_max {
-            let point = ray.at(t);
-            let normal = (point - center) / self.radius;
-            let front_face = ray.dir.dot(&normal.conv()) < 0.;
-            let normal = if front_face { normal } else { -normal };
-            let (u_res, v_res) = get_sphere_uv((point - self.center(ray.time)) / self.radius);
-            return Some(HitRecord {
-                t,
-                point,
-                normal: normal.conv(),
-                front_face,
-                material: self.material.as_ref(),
-                u: u_res,
-                v: v_res,
-            });
-        }
-
-        let t = (-half_b + root) / a;
-        if t > t_min && t < t_max {
-            let point = ray.at(t);
-            let normal = (point - center) / self.radius;
-            let front_face = ray.dir.dot(&normal.conv()) < 0.;
-            let normal = if front_face { normal } else { -normal };
-            let (u_res, v_res) = get_sphere_uv((point - self.center(ray.time)) / self.radius);
-            return Some(HitRecord {
-                t,
-                point,
-                normal: normal.conv(),
-                front_face,
-                material: self.material.as_ref(),
-                u: u_res,
-                v: v_res,
-            });
-        }
-
-        None
+        };
+        Some(sphere_hit(
+            ray,
+            t,
+            center,
+            self.radius,
+            self.material.as_ref(),
+        ))
     }
 
-    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
         let box0 = AABB {
             min: self.center(t0) - point3!(self.radius, self.radius, self.radius),
             max: self.center(t0) + point3!(self.radius, self.radius, self.radius),
@@ -231,4 +436,1153 @@ impl<'a> Hittable for MovingSphere<'a> {
 
         Some(AABB::surrounding_box(&box0, &box1))
     }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::MovingSphere {
+            center0: self.center0.into(),
+            center1: self.center1.into(),
+            time0: self.t0,
+            time1: self.t1,
+            radius: self.radius,
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+}
+
+/// Which surface of a cylinder or cone a ray hit
+#[derive(Clone, Copy)]
+enum QuadricPart {
+    Side,
+    Bottom,
+    Top,
+}
+
+/// Where a ray meets an infinite cylinder of `radius` around the y axis, given the ray's origin
+/// relative to a point on the axis. `None` for rays parallel to the axis
+///
+/// Works out the discriminant from how far the axis is from the ray's line, like `sphere_roots`
+fn cylinder_roots(origin: Vec3, dir: Vec3, radius: Float) -> Option<(Float, Float)> {
+    let a = dir.x * dir.x + dir.z * dir.z;
+    if a == 0. {
+        return None;
+    }
+    let half_b = origin.x * dir.x + origin.z * dir.z;
+    let c = origin.x * origin.x + origin.z * origin.z - radius * radius;
+    let (x, z) = (origin.x - half_b / a * dir.x, origin.z - half_b / a * dir.z);
+    let distance = (x * x + z * z).sqrt();
+    let discriminant = a * (radius - distance) * (radius + distance);
+    quadratic_roots(a, half_b, c, discriminant)
+}
+
+/// Where a ray meets the disc of `radius` centered on the y axis at height `y`, given the ray's
+/// origin relative to the axis at height 0
+fn disc_t(origin: Vec3, dir: Vec3, y: Float, radius: Float) -> Option<Float> {
+    let t = (y - origin.y) / dir.y;
+    let (x, z) = (origin.x + t * dir.x, origin.z + t * dir.z);
+    if t.is_finite() && x * x + z * z <= radius * radius {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// The nearest of `candidates` between `t_min` and `t_max`
+fn nearest_part(
+    candidates: &[(Option<Float>, QuadricPart)],
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, QuadricPart)> {
+    let hits = candidates
+        .iter()
+        .filter_map(|&(t, part)| t.filter(|&t| t > t_min && t < t_max).map(|t| (t, part)));
+    hits.fold(None, |nearest, (t, part)| match nearest {
+        Some((best, _)) if best <= t => nearest,
+        _ => Some((t, part)),
+    })
+}
+
+/// Fills in a hit record for a cylinder or cone hit at `local`, relative to the center of its
+/// base, given the outward normal there
+///
+/// The side's texture coordinates go around the axis like `get_sphere_uv` and up from 0 at the
+/// base to 1 at `height`. The caps' are x and z across the disc
+#[allow(clippy::too_many_arguments)]
+fn quadric_hit<'a>(
+    ray: &Ray,
+    t: Float,
+    part: QuadricPart,
+    local: Vec3,
+    outward: Vec3,
+    radius: Float,
+    height: Float,
+    material: &'a (dyn Material + Sync),
+) -> HitRecord<'a> {
+    let front_face = ray.dir.dot(&outward) < 0.;
+    let normal = if front_face { outward } else { -outward };
+    let (u, v, tangent, bitangent) = match part {
+        QuadricPart::Side => {
+            let phi = local.z.atan2(local.x);
+            let tangent = around_y(local);
+            let u = 1. - (phi + PI) / (2. * PI);
+            (u, local.y / height, tangent, outward.cross(&tangent))
+        }
+        QuadricPart::Bottom | QuadricPart::Top => (
+            0.5 + 0.5 * local.x / radius,
+            0.5 + 0.5 * local.z / radius,
+            vec3!(1., 0., 0.),
+            vec3!(0., 0., 1.),
+        ),
+    };
+    HitRecord {
+        t,
+        point: ray.at(t),
+        normal,
+        front_face,
+        material,
+        u: u.clamp(0., 1.),
+        v: v.clamp(0., 1.),
+        tangent,
+        bitangent,
+        object_id: 0,
+    }
+}
+
+/// A cylinder around the y axis, standing on the center of its base at `base` and reaching
+/// `height` above it
+///
+/// Open at both ends unless capped. Place it at other angles with `Transform`
+pub struct Cylinder {
+    base: Point3,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Cylinder {
+    pub fn new<T: Material + Send + Sync + 'static>(
+        base: Point3,
+        radius: Float,
+        height: Float,
+        capped: bool,
+        material: T,
+    ) -> Self {
+        Self::new_shared(base, radius, height, capped, Arc::new(material))
+    }
+
+    pub fn new_shared(
+        base: Point3,
+        radius: Float,
+        height: Float,
+        capped: bool,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let origin: Vec3 = (ray.origin - self.base).conv();
+        let on_side = |t: Float| {
+            let y = origin.y + t * ray.dir.y;
+            Some(t).filter(|_| (0. ..=self.height).contains(&y))
+        };
+        let (near, far) = cylinder_roots(origin, ray.dir, self.radius)
+            .map_or((None, None), |(near, far)| (on_side(near), on_side(far)));
+        let (bottom, top) = if self.capped {
+            (
+                disc_t(origin, ray.dir, 0., self.radius),
+                disc_t(origin, ray.dir, self.height, self.radius),
+            )
+        } else {
+            (None, None)
+        };
+        let (t, part) = nearest_part(
+            &[
+                (near, QuadricPart::Side),
+                (far, QuadricPart::Side),
+                (bottom, QuadricPart::Bottom),
+                (top, QuadricPart::Top),
+            ],
+            t_min,
+            t_max,
+        )?;
+        let local = origin + t * ray.dir;
+        let outward = match part {
+            QuadricPart::Side => vec3!(local.x, 0., local.z) / self.radius,
+            QuadricPart::Bottom => vec3!(0., -1., 0.),
+            QuadricPart::Top => vec3!(0., 1., 0.),
+        };
+        Some(quadric_hit(
+            ray,
+            t,
+            part,
+            local,
+            outward,
+            self.radius,
+            self.height,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(AABB::new(
+            self.base - point3!(self.radius, 0., self.radius),
+            self.base + point3!(self.radius, self.height, self.radius),
+        ))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Cylinder {
+            base: self.base.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.capped,
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+
+    fn area(&self) -> Float {
+        let caps = if self.capped { 2. } else { 0. };
+        2. * PI * self.radius * self.height + caps * PI * self.radius * self.radius
+    }
+}
+
+/// A cone around the y axis, with its base centered on `base` and its tip `height` above it
+///
+/// Open at the base unless capped. Place it at other angles with `Transform`
+pub struct Cone {
+    base: Point3,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Cone {
+    pub fn new<T: Material + Send + Sync + 'static>(
+        base: Point3,
+        radius: Float,
+        height: Float,
+        capped: bool,
+        material: T,
+    ) -> Self {
+        Self::new_shared(base, radius, height, capped, Arc::new(material))
+    }
+
+    pub fn new_shared(
+        base: Point3,
+        radius: Float,
+        height: Float,
+        capped: bool,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let origin: Vec3 = (ray.origin - self.base).conv();
+        let dir = ray.dir;
+        // The double cone `x² + z² = k² y²` around the tip, of which this is the lower half
+        let k2 = (self.radius / self.height).powi(2);
+        let below_tip = origin.y - self.height;
+        let a = dir.x * dir.x + dir.z * dir.z - k2 * dir.y * dir.y;
+        let half_b = origin.x * dir.x + origin.z * dir.z - k2 * below_tip * dir.y;
+        let c = origin.x * origin.x + origin.z * origin.z - k2 * below_tip * below_tip;
+        let on_side = |t: Float| {
+            let y = origin.y + t * dir.y;
+            Some(t).filter(|_| (0. ..=self.height).contains(&y))
+        };
+        let (near, far) = quadratic_roots(a, half_b, c, half_b * half_b - a * c)
+            .map_or((None, None), |(near, far)| (on_side(near), on_side(far)));
+        let bottom = if self.capped {
+            disc_t(origin, dir, 0., self.radius)
+        } else {
+            None
+        };
+        let (t, part) = nearest_part(
+            &[
+                (near, QuadricPart::Side),
+                (far, QuadricPart::Side),
+                (bottom, QuadricPart::Bottom),
+            ],
+            t_min,
+            t_max,
+        )?;
+        let local = origin + t * dir;
+        let outward = match part {
+            QuadricPart::Side => {
+                let gradient = vec3!(local.x, k2 * (self.height - local.y), local.z);
+                if gradient.length_squared() > 0. {
+                    gradient.unit_vector()
+                } else {
+                    // Right on the tip
+                    vec3!(0., 1., 0.)
+                }
+            }
+            _ => vec3!(0., -1., 0.),
+        };
+        Some(quadric_hit(
+            ray,
+            t,
+            part,
+            local,
+            outward,
+            self.radius,
+            self.height,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(AABB::new(
+            self.base - point3!(self.radius, 0., self.radius),
+            self.base + point3!(self.radius, self.height, self.radius),
+        ))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Cone {
+            base: self.base.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.capped,
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+
+    fn area(&self) -> Float {
+        let slant = (self.radius * self.radius + self.height * self.height).sqrt();
+        let cap = if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.
+        };
+        PI * self.radius * slant + cap
+    }
+}
+
+/// Intersects a ray with the triangle `p0`, `p1`, `p2`
+///
+/// Returns the ray's `t` and the barycentric coordinates of `p1` and `p2` at the hit
+fn intersect_triangle(
+    ray: &Ray,
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float, Float)> {
+    // Moller-Trumbore
+    let edge1: Vec3 = (p1 - p0).conv();
+    let edge2: Vec3 = (p2 - p0).conv();
+    let h = ray.dir.cross(&edge2);
+    let a = edge1.dot(&h);
+    if a.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1. / a;
+    let s: Vec3 = (ray.origin - p0).conv();
+    let b1 = f * s.dot(&h);
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let b2 = f * ray.dir.dot(&q);
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+    let t = f * edge2.dot(&q);
+    if t > t_min && t < t_max {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+/// Bounding box of a triangle, padded so flat triangles still have some thickness
+fn triangle_box(p0: Point3, p1: Point3, p2: Point3) -> AABB {
+    let pad = point3!(1e-4, 1e-4, 1e-4);
+    let min = point3!(
+        p0.x.min(p1.x).min(p2.x),
+        p0.y.min(p1.y).min(p2.y),
+        p0.z.min(p1.z).min(p2.z)
+    );
+    let max = point3!(
+        p0.x.max(p1.x).max(p2.x),
+        p0.y.max(p1.y).max(p2.y),
+        p0.z.max(p1.z).max(p2.z)
+    );
+    AABB::new(min - pad, max + pad)
+}
+
+/// A single triangle
+///
+/// Texture coordinates are the barycentric coordinates of `p1` and `p2`
+pub struct Triangle {
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Triangle {
+    pub fn new<T: Material + Send + Sync + 'static>(
+        p0: Point3,
+        p1: Point3,
+        p2: Point3,
+        material: T,
+    ) -> Self {
+        Self {
+            p0,
+            p1,
+            p2,
+            material: Arc::new(material),
+        }
+    }
+
+    pub fn new_shared(
+        p0: Point3,
+        p1: Point3,
+        p2: Point3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            p0,
+            p1,
+            p2,
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t, b1, b2) = intersect_triangle(ray, self.p0, self.p1, self.p2, t_min, t_max)?;
+        let normal = (self.p1 - self.p0)
+            .cross(&(self.p2 - self.p0))
+            .unit_vector()
+            .conv::<Vec3>();
+        let front_face = ray.dir.dot(&normal) < 0.;
+        let normal = if front_face { normal } else { -normal };
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal,
+            front_face,
+            material: self.material.as_ref(),
+            u: b1,
+            v: b2,
+            tangent: (self.p1 - self.p0).unit_vector().conv(),
+            bitangent: (self.p2 - self.p0).unit_vector().conv(),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(triangle_box(self.p0, self.p1, self.p2))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Triangle {
+            vertices: [self.p0.into(), self.p1.into(), self.p2.into()],
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        let r1 = rng.sample::<Float, _>(Standard).sqrt();
+        let r2 = rng.sample::<Float, _>(Standard);
+        let point = (1. - r1) * self.p0 + (r1 * (1. - r2)) * self.p1 + (r1 * r2) * self.p2;
+        let normal = (self.p1 - self.p0)
+            .cross(&(self.p2 - self.p0))
+            .unit_vector();
+        Some((point, normal.conv()))
+    }
+
+    fn area(&self) -> Float {
+        (self.p1 - self.p0).cross(&(self.p2 - self.p0)).length() / 2.
+    }
+}
+
+/// A triangle mesh with vertex data shared between triangles
+///
+/// Add it to a `World` with `World::add_mesh`, which adds each triangle separately so the BVH
+/// can split the mesh up
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    /// Per vertex normals. Flat shaded if empty
+    normals: Vec<Vec3>,
+    /// Per vertex texture coordinates. Uses barycentric coordinates if empty
+    uvs: Vec<(Float, Float)>,
+    /// Indices into the vertex data for each triangle
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl TriangleMesh {
+    pub fn new<T: Material + Send + Sync + 'static>(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(Float, Float)>,
+        indices: Vec<[usize; 3]>,
+        material: T,
+    ) -> Self {
+        Self::new_shared(positions, normals, uvs, indices, Arc::new(material))
+    }
+
+    pub fn new_shared(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(Float, Float)>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// A hittable for each triangle, sharing the vertex data
+    pub fn triangles(self: &Arc<Self>) -> impl Iterator<Item = MeshTriangle> + '_ {
+        (0..self.indices.len()).map(move |index| MeshTriangle {
+            mesh: Arc::clone(self),
+            index,
+        })
+    }
+}
+
+/// One triangle of a `TriangleMesh`
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> (Point3, Point3, Point3) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+        (positions[i0], positions[i1], positions[i2])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (p0, p1, p2) = self.vertices();
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1. - b1 - b2;
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector().conv::<Vec3>();
+        let front_face = ray.dir.dot(&geometric_normal) < 0.;
+        let normal = if self.mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let normals = &self.mesh.normals;
+            (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).unit_vector()
+        };
+        let normal = if front_face { normal } else { -normal };
+
+        let (e1, e2): (Vec3, Vec3) = ((p1 - p0).conv(), (p2 - p0).conv());
+        let (u, v, tangent, bitangent) = if self.mesh.uvs.is_empty() {
+            (b1, b2, e1, e2)
+        } else {
+            let uvs = &self.mesh.uvs;
+            let u = b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0;
+            let v = b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1;
+            // Solve for the directions along the triangle that `u` and `v` change along
+            let (du1, dv1) = (uvs[i1].0 - uvs[i0].0, uvs[i1].1 - uvs[i0].1);
+            let (du2, dv2) = (uvs[i2].0 - uvs[i0].0, uvs[i2].1 - uvs[i0].1);
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() > 1e-12 {
+                let tangent = (e1 * dv2 - e2 * dv1) / det;
+                let bitangent = (e2 * du1 - e1 * du2) / det;
+                (u, v, tangent, bitangent)
+            } else {
+                (u, v, e1, e2)
+            }
+        };
+
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal,
+            front_face,
+            material: self.mesh.material.as_ref(),
+            u,
+            v,
+            tangent: tangent.unit_vector(),
+            bitangent: bitangent.unit_vector(),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        let (p0, p1, p2) = self.vertices();
+        Some(triangle_box(p0, p1, p2))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.mesh.material.as_ref()]
+    }
+}
+
+// Rectangles aligned to two axes
+// `$desc` is the rectangle's `ObjectDesc` variant
+// `$a` and `$b` are the axes the rectangle lies along and `$k` is the axis it's fixed on
+macro_rules! axis_rect {
+    ($name:ident, $desc:ident, $a:ident, $b:ident, $k:ident, $a0:ident, $a1:ident, $b0:ident, $b1:ident) => {
+        pub struct $name {
+            $a0: Float,
+            $a1: Float,
+            $b0: Float,
+            $b1: Float,
+            k: Float,
+            material: Arc<dyn Material + Send + Sync>,
+        }
+
+        impl $name {
+            pub fn new<T: Material + Send + Sync + 'static>(
+                $a0: Float,
+                $a1: Float,
+                $b0: Float,
+                $b1: Float,
+                k: Float,
+                material: T,
+            ) -> Self {
+                Self::new_shared($a0, $a1, $b0, $b1, k, Arc::new(material))
+            }
+
+            pub fn new_shared(
+                $a0: Float,
+                $a1: Float,
+                $b0: Float,
+                $b1: Float,
+                k: Float,
+                material: Arc<dyn Material + Send + Sync>,
+            ) -> Self {
+                Self {
+                    $a0,
+                    $a1,
+                    $b0,
+                    $b1,
+                    k,
+                    material,
+                }
+            }
+        }
+
+        impl Hittable for $name {
+            fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+                let t = (self.k - ray.origin.$k) / ray.dir.$k;
+                if !(t > t_min && t < t_max) {
+                    return None;
+                }
+                let $a = ray.origin.$a + t * ray.dir.$a;
+                let $b = ray.origin.$b + t * ray.dir.$b;
+                if $a < self.$a0 || $a > self.$a1 || $b < self.$b0 || $b > self.$b1 {
+                    return None;
+                }
+
+                let mut normal = Vec3::default();
+                normal.$k = 1.;
+                let (mut tangent, mut bitangent) = (Vec3::default(), Vec3::default());
+                tangent.$a = 1.;
+                bitangent.$b = 1.;
+                let front_face = ray.dir.dot(&normal) < 0.;
+                let normal = if front_face { normal } else { -normal };
+                Some(HitRecord {
+                    t,
+                    point: ray.at(t),
+                    normal,
+                    front_face,
+                    material: self.material.as_ref(),
+                    u: ($a - self.$a0) / (self.$a1 - self.$a0),
+                    v: ($b - self.$b0) / (self.$b1 - self.$b0),
+                    tangent,
+                    bitangent,
+                    object_id: 0,
+                })
+            }
+
+            fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+                // Pad the fixed axis so the box has some thickness
+                let mut min = Point3::default();
+                let mut max = Point3::default();
+                min.$a = self.$a0;
+                min.$b = self.$b0;
+                min.$k = self.k - 1e-4;
+                max.$a = self.$a1;
+                max.$b = self.$b1;
+                max.$k = self.k + 1e-4;
+                Some(AABB::new(min, max))
+            }
+
+            fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+                vec![self.material.as_ref()]
+            }
+
+            fn describe(&self) -> Option<ObjectDesc> {
+                Some(ObjectDesc::$desc {
+                    $a0: self.$a0,
+                    $a1: self.$a1,
+                    $b0: self.$b0,
+                    $b1: self.$b1,
+                    k: self.k,
+                    material: describe_material(self.material.as_ref())?,
+                })
+            }
+
+            fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+                let mut point = Point3::default();
+                point.$a = self.$a0 + rng.sample::<Float, _>(Standard) * (self.$a1 - self.$a0);
+                point.$b = self.$b0 + rng.sample::<Float, _>(Standard) * (self.$b1 - self.$b0);
+                point.$k = self.k;
+                let mut normal = Vec3::default();
+                normal.$k = 1.;
+                Some((point, normal))
+            }
+
+            fn area(&self) -> Float {
+                (self.$a1 - self.$a0) * (self.$b1 - self.$b0)
+            }
+        }
+    };
+}
+
+axis_rect!(XYRect, XyRect, x, y, z, x0, x1, y0, y1);
+axis_rect!(XZRect, XzRect, x, z, y, x0, x1, z0, z1);
+axis_rect!(YZRect, YzRect, y, z, x, y0, y1, z0, z1);
+
+/// Stands in for the material of hittables that are only used for their shape
+struct NoMaterial;
+
+impl Material for NoMaterial {
+    fn scatter(
+        &self,
+        _: &Ray,
+        _: &HitRecord,
+        _: &PathState,
+        _: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        None
+    }
+}
+
+/// An axis-aligned box made of six rectangles
+pub struct Cuboid {
+    min: Point3,
+    max: Point3,
+    sides: Vec<Box<dyn Hittable + Send + Sync>>,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Cuboid {
+    /// Creates a box with opposite corners `p0` and `p1`
+    pub fn new<T: Material + Send + Sync + 'static>(p0: Point3, p1: Point3, material: T) -> Self {
+        Self::new_shared(p0, p1, Arc::new(material))
+    }
+
+    pub fn new_shared(p0: Point3, p1: Point3, material: Arc<dyn Material + Send + Sync>) -> Self {
+        let min = point3!(p0.x.min(p1.x), p0.y.min(p1.y), p0.z.min(p1.z));
+        let max = point3!(p0.x.max(p1.x), p0.y.max(p1.y), p0.z.max(p1.z));
+        // The sides share the box's material, which is filled in when they're hit
+        let sides: Vec<Box<dyn Hittable + Send + Sync>> = vec![
+            Box::new(XYRect::new(min.x, max.x, min.y, max.y, min.z, NoMaterial)),
+            Box::new(XYRect::new(min.x, max.x, min.y, max.y, max.z, NoMaterial)),
+            Box::new(XZRect::new(min.x, max.x, min.z, max.z, min.y, NoMaterial)),
+            Box::new(XZRect::new(min.x, max.x, min.z, max.z, max.y, NoMaterial)),
+            Box::new(YZRect::new(min.y, max.y, min.z, max.z, min.x, NoMaterial)),
+            Box::new(YZRect::new(min.y, max.y, min.z, max.z, max.x, NoMaterial)),
+        ];
+        Self {
+            min,
+            max,
+            sides,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(HitRecord, usize)> = None;
+        for (index, side) in self.sides.iter().enumerate() {
+            let t_max = closest.as_ref().map_or(t_max, |(rec, _)| rec.t);
+            if let Some(rec) = side.hit(ray, t_min, t_max) {
+                closest = Some((rec, index));
+            }
+        }
+        let (rec, index) = closest?;
+        // The sides face along their axis, so work out whether the ray enters the box from
+        // which of each pair was hit
+        let axis = [2, 1, 0][index / 2];
+        let entering = if index % 2 == 0 {
+            ray.dir[axis] > 0.
+        } else {
+            ray.dir[axis] < 0.
+        };
+        Some(HitRecord {
+            front_face: entering,
+            material: self.material.as_ref(),
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        Some(AABB::new(self.min, self.max))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.material.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Cuboid {
+            min: self.min.into(),
+            max: self.max.into(),
+            material: describe_material(self.material.as_ref())?,
+        })
+    }
+}
+
+/// Random numbers for the collisions of a medium entered at `enter` along `ray`
+///
+/// Hits don't have access to the render's sampler, so it draws `Ray::sample` for each ray it
+/// traces. Mixing in where the ray meets the boundary gives each medium along the ray its own
+/// numbers
+struct MediumRandom(u64);
+
+impl MediumRandom {
+    fn new(ray: &Ray, enter: Float) -> Self {
+        Self(ray.sample ^ u64::from((enter as f32).to_bits()))
+    }
+
+    /// The next number, in `(0, 1]`, by SplitMix64
+    fn next(&mut self) -> Float {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        ((z >> 11) as Float + 1.) / (1_u64 << 53) as Float
+    }
+}
+
+/// A volume of constant density, like smoke or fog, filling a boundary hittable
+///
+/// The boundary must be closed and convex
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable + Send + Sync>,
+    neg_inv_density: Float,
+    phase_function: Arc<dyn Material + Send + Sync>,
+}
+
+impl ConstantMedium {
+    /// Creates a medium scattering light equally in all directions, tinted by `albedo`
+    pub fn new<H: Hittable + Send + Sync + 'static, T: Texture + Send + Sync + 'static>(
+        boundary: H,
+        density: Float,
+        albedo: T,
+    ) -> Self {
+        Self::new_boxed(
+            Box::new(boundary),
+            density,
+            Arc::new(Isotropic::new(albedo)),
+        )
+    }
+
+    pub fn new_boxed(
+        boundary: Box<dyn Hittable + Send + Sync>,
+        density: Float,
+        phase_function: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Find where the ray enters and leaves the boundary, even if it starts inside
+        let enter = self.boundary.hit(ray, -Float::INFINITY, Float::INFINITY)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001, Float::INFINITY)?;
+        let t_enter = enter.t.max(t_min).max(0.);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.dir.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let random = MediumRandom::new(ray, enter.t).next();
+        let hit_distance = self.neg_inv_density * random.ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(HitRecord {
+            point: ray.at(t),
+            // Arbitrary, as volumes have no surface
+            normal: vec3!(1., 0., 0.),
+            t,
+            front_face: true,
+            material: self.phase_function.as_ref(),
+            u: 0.,
+            v: 0.,
+            tangent: vec3!(0., 1., 0.),
+            bitangent: vec3!(0., 0., 1.),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self.phase_function.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        let albedo = match self.phase_function.describe()? {
+            MaterialDesc::Isotropic { albedo } => albedo,
+            _ => return None,
+        };
+        Some(ObjectDesc::ConstantMedium {
+            boundary: Box::new(self.boundary.describe()?),
+            density: -1. / self.neg_inv_density,
+            albedo,
+        })
+    }
+}
+
+/// A volume whose density varies through space, like clouds, fire or nebulae
+///
+/// Density is read from the brightness of a texture at each point, clamped to between 0 and 1
+/// and scaled by `max_density`. Collisions are found with delta tracking, so the volume is
+/// rendered without bias however the density varies. The boundary must be closed and convex
+pub struct HeterogeneousMedium {
+    boundary: Box<dyn Hittable + Send + Sync>,
+    density: Arc<dyn Texture + Send + Sync>,
+    max_density: Float,
+    /// Fraction of extinction that absorbs light. Absorbing collisions give off `emission`
+    pub absorption: Float,
+    /// Fraction of extinction that scatters light
+    pub scattering: Float,
+    /// Tint applied to scattered light
+    pub albedo: Color,
+    /// Light given off where light is absorbed, such as `Blackbody` for fire. Defaults to black
+    pub emission: Arc<dyn Texture + Send + Sync>,
+}
+
+impl HeterogeneousMedium {
+    /// Creates a white, purely scattering medium
+    pub fn new<H: Hittable + Send + Sync + 'static, T: Texture + Send + Sync + 'static>(
+        boundary: H,
+        density: T,
+        max_density: Float,
+    ) -> Self {
+        Self::new_boxed(Box::new(boundary), Arc::new(density), max_density)
+    }
+
+    pub fn new_boxed(
+        boundary: Box<dyn Hittable + Send + Sync>,
+        density: Arc<dyn Texture + Send + Sync>,
+        max_density: Float,
+    ) -> Self {
+        Self {
+            boundary,
+            density,
+            max_density,
+            absorption: 0.,
+            scattering: 1.,
+            albedo: color!(1., 1., 1.),
+            emission: Arc::new(SolidColor::new(color!())),
+        }
+    }
+
+    fn density_at(&self, point: Point3) -> Float {
+        self.density.value(0., 0., point).luminance().clamp(0., 1.) * self.max_density
+    }
+
+    fn extinction(&self) -> Float {
+        self.absorption + self.scattering
+    }
+
+    /// Where the ray enters and leaves the boundary, clipped to the given range, and where it
+    /// enters unclipped
+    fn span(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float, Float)> {
+        let enter = self.boundary.hit(ray, -Float::INFINITY, Float::INFINITY)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001, Float::INFINITY)?;
+        let t_enter = enter.t.max(t_min).max(0.);
+        let t_exit = exit.t.min(t_max);
+        if t_enter < t_exit {
+            Some((t_enter, t_exit, enter.t))
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of light passing through the medium along a ray, estimated with ratio tracking
+    pub fn transmittance(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rng: &mut SampleRng,
+    ) -> Float {
+        let (mut t, t_exit, _) = match self.span(ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.,
+        };
+        let majorant = self.max_density * self.extinction() * ray.dir.length();
+        if majorant <= 0. {
+            return 1.;
+        }
+        let mut transmittance = 1.;
+        loop {
+            t -= (1. - rng.sample::<Float, _>(Standard)).ln() / majorant;
+            if t >= t_exit {
+                return transmittance;
+            }
+            let density = self.density_at(ray.at(t)) / self.max_density;
+            transmittance *= 1. - density;
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (mut t, t_exit, enter) = self.span(ray, t_min, t_max)?;
+        let majorant = self.max_density * self.extinction() * ray.dir.length();
+        if majorant <= 0. {
+            return None;
+        }
+
+        // Delta tracking: step through the volume as if it were as dense as it ever gets, and
+        // treat each step as a real collision with probability of the actual density
+        let mut random = MediumRandom::new(ray, enter);
+        loop {
+            t -= random.next().ln() / majorant;
+            if t >= t_exit {
+                return None;
+            }
+            let point = ray.at(t);
+            if (1. - random.next()) * self.max_density < self.density_at(point) {
+                return Some(HitRecord {
+                    point,
+                    // Arbitrary, as volumes have no surface
+                    normal: vec3!(1., 0., 0.),
+                    t,
+                    front_face: true,
+                    material: self,
+                    u: 0.,
+                    v: 0.,
+                    tangent: vec3!(0., 1., 0.),
+                    bitangent: vec3!(0., 0., 1.),
+                    object_id: 0,
+                });
+            }
+        }
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        vec![self]
+    }
+}
+
+/// Collisions inside the medium absorb or scatter in proportion to the coefficients. Rather than
+/// choosing randomly, each collision gives off the absorbed share of emission and scatters the
+/// rest. As collisions are spread along the ray in proportion to density, this integrates the
+/// emission along each ray segment through the medium
+impl Material for HeterogeneousMedium {
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_albedo("HeterogeneousMedium albedo", self.albedo);
+        if self.absorption < 0. || self.scattering < 0. {
+            problems.push(format!(
+                "HeterogeneousMedium absorption {} and scattering {} must not be negative",
+                self.absorption, self.scattering
+            ));
+        }
+        problems
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        _: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        if self.scattering <= 0. {
+            return None;
+        }
+        let ray = Ray::new(rec.point, sampling::uniform_sphere(ctx.get_2d()), ray.time);
+        Some((ray, self.albedo * (self.scattering / self.extinction())))
+    }
+
+    fn emitted(&self, _: &Ray, rec: &HitRecord) -> Color {
+        self.emission.value(rec.u, rec.v, rec.point) * (self.absorption / self.extinction())
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo * (self.scattering / self.extinction())
+    }
+}
+
+/// Describes a shape's material inline, for `Hittable::describe`
+fn describe_material(material: &(dyn Material + Sync)) -> Option<MaterialRef> {
+    Some(MaterialRef::Inline(Box::new(material.describe()?)))
 }
@@ -1,108 +1,607 @@
-use crate::Color;
+use crate::error::Error;
+use crate::{Color, Float, RenderSettings};
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 use std::path::Path;
+use std::time::Duration;
 
 impl Color {
     /// Returns a byte array of the data
     pub fn get_bytes(&self) -> Vec<u8> {
         vec![
-            (self.red.sqrt().min(1.).max(0.) * 255.999) as u8,
-            (self.green.sqrt().min(1.).max(0.) * 255.999) as u8,
-            (self.blue.sqrt().min(1.).max(0.) * 255.999) as u8,
+            (self.red.sqrt().clamp(0., 1.) * 255.999) as u8,
+            (self.green.sqrt().clamp(0., 1.) * 255.999) as u8,
+            (self.blue.sqrt().clamp(0., 1.) * 255.999) as u8,
         ]
     }
 }
 
+/// How light brighter than 1 is squeezed into the range of 8-bit images
+///
+/// Without tonemapping, bright lights and their reflections clip to flat white
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tonemap {
+    /// Leave colors as they are, clipping at 1
+    None,
+    /// Scales each color by `1 / (1 + luminance)`, keeping hues but flattening contrast in
+    /// highlights
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a film-like toe and shoulder
+    Aces,
+}
+
+impl Tonemap {
+    pub fn apply(&self, color: Color) -> Color {
+        match self {
+            Tonemap::None => color,
+            Tonemap::Reinhard => color / (1. + color.luminance().max(0.)),
+            Tonemap::Aces => {
+                let curve = |x: Float| {
+                    let x = x.max(0.);
+                    (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+                };
+                color!(curve(color.red), curve(color.green), curve(color.blue))
+            }
+        }
+    }
+}
+
+/// File formats images can be written in, see `Image::write`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tiff,
+    /// Binary PPM
+    Ppm,
+    /// OpenEXR, keeping the full range of light
+    Exr,
+    /// Radiance HDR, keeping the full range of light
+    Hdr,
+}
+
+impl FileFormat {
+    /// The format matching the extension of `path`, ignoring case
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(FileFormat::Png),
+            "jpg" | "jpeg" => Some(FileFormat::Jpeg),
+            "bmp" => Some(FileFormat::Bmp),
+            "tif" | "tiff" => Some(FileFormat::Tiff),
+            "ppm" => Some(FileFormat::Ppm),
+            "exr" => Some(FileFormat::Exr),
+            "hdr" => Some(FileFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format clips colors to 8 bits, so images should be tonemapped before writing
+    pub fn is_8_bit(self) -> bool {
+        !matches!(self, FileFormat::Exr | FileFormat::Hdr)
+    }
+}
+
+#[derive(Clone)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
-    pub data: Vec<Vec<Color>>,
+    /// Pixels in rows from the top left, `width` to a row
+    pub data: Vec<Color>,
+    /// How much of each pixel is covered by the scene rather than the background, from 0 to 1.
+    /// `None` for opaque images, see `RenderSettings::alpha`
+    ///
+    /// Colors are premultiplied, so they're already scaled down by their alpha
+    pub alpha: Option<Vec<Float>>,
 }
 
 impl Image {
     /// Creates a new `width`x`height` white image.
     pub fn new(width: u32, height: u32) -> Self {
-        let data = vec![vec![Color::default(); width as usize]; height as usize];
+        let data = vec![Color::default(); (width * height) as usize];
         Image {
             width,
             height,
             data,
+            alpha: None,
         }
     }
 
     /// Creates a new test image with colors relative to their offset
     pub fn new_test(width: u32, height: u32) -> Self {
         let data = (0..height)
-            .map(|cur_height| {
-                let h_float = cur_height as f64;
-                (0..width)
-                    .map(|cur_width| {
-                        let w_float = cur_width as f64;
-                        let r_float = w_float / (width - 1) as f64;
-                        let g_float = (height as f64 - 1_f64 - h_float) / (height - 1) as f64;
-                        let b_float = 0.25;
-                        Color {
-                            red: r_float,
-                            green: g_float,
-                            blue: b_float,
-                        }
-                    })
-                    .collect()
+            .flat_map(|cur_height| {
+                let h_float = cur_height as Float;
+                (0..width).map(move |cur_width| {
+                    let w_float = cur_width as Float;
+                    let r_float = w_float / (width - 1) as Float;
+                    let g_float = (height as Float - 1. - h_float) / (height - 1) as Float;
+                    let b_float = 0.25;
+                    Color {
+                        red: r_float,
+                        green: g_float,
+                        blue: b_float,
+                    }
+                })
             })
             .collect();
         Image {
             width,
             height,
             data,
+            alpha: None,
         }
     }
 
-    /// Writes the image to a file in ppm format
-    pub fn write_ppm<P: AsRef<Path>>(self, path: P) {
+    /// Color of the pixel `x` from the left and `y` from the top
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Color {
+        &mut self.data[(y * self.width + x) as usize]
+    }
+
+    /// Rows of pixels from the top
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, Color> {
+        self.data.chunks_exact(self.width as usize)
+    }
+
+    /// Brightens the image by `exposure` stops, then applies `tonemap`, ready for writing to
+    /// 8-bit formats
+    ///
+    /// Linear formats like EXR should be written without tonemapping so it can be done later
+    pub fn tonemap(&mut self, tonemap: Tonemap, exposure: Float) {
+        let scale = exposure.exp2();
+        for color in self.data.iter_mut() {
+            *color = tonemap.apply(*color * scale);
+        }
+    }
+
+    /// Writes the image to a file in plain text ppm format (P3), gamma corrected like PNGs
+    pub fn write_ppm<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
         // Create file
-        let file = File::create(path).expect("Error creating file");
+        let file = File::create(path)?;
         let mut w = BufWriter::new(file);
 
         // Write header
-        writeln!(w, "P3").unwrap();
-        writeln!(w, "{} {}", self.width, self.height).unwrap();
-        writeln!(w, "255").unwrap();
+        writeln!(w, "P3")?;
+        writeln!(w, "{} {}", self.width, self.height)?;
+        writeln!(w, "255")?;
 
         // Write data
-        self.data.iter().for_each(|line| {
-            line.iter().for_each(|color| {
-                writeln!(
-                    w,
-                    "{} {} {}",
-                    color.red as u8, color.green as u8, color.blue as u8
-                )
-                .unwrap();
-            })
+        for pixel in self.bytes().chunks_exact(3) {
+            writeln!(w, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Writes the image to a file in binary ppm format (P6), gamma corrected like PNGs
+    ///
+    /// A quarter of the size of `write_ppm`'s text
+    pub fn write_ppm_binary<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        Ok(self.encode_ppm(path.as_ref())?)
+    }
+
+    fn encode_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.bytes())?;
+        w.flush()
+    }
+
+    /// Writes the image in the format matching the extension of `path`, see `FileFormat`
+    ///
+    /// 8-bit formats clip colors, so tonemap the image first if it's brighter than 1, see
+    /// `Image::tonemap`
+    pub fn write<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        match FileFormat::from_path(path) {
+            Some(format) => self.write_as(path, format),
+            None => Err(Error::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Writes the image to `path` in `format`, whatever the extension
+    ///
+    /// PNG, TIFF and BMP files get an alpha channel if the image has one. Other 8-bit formats
+    /// show the image over black
+    pub fn write_as<P: AsRef<Path>>(self, path: P, format: FileFormat) -> Result<(), Error> {
+        let path = path.as_ref();
+        let (width, height) = (self.width, self.height);
+        let format = match format {
+            FileFormat::Ppm => return Ok(self.encode_ppm(path)?),
+            FileFormat::Exr => return Ok(self.encode_exr(path)?),
+            FileFormat::Hdr => return self.encode_hdr(path),
+            FileFormat::Png => image::ImageFormat::Png,
+            FileFormat::Jpeg => image::ImageFormat::Jpeg,
+            FileFormat::Bmp => image::ImageFormat::Bmp,
+            FileFormat::Tiff => image::ImageFormat::Tiff,
+        };
+        let (data, color) = match (&self.alpha, format) {
+            (
+                Some(_),
+                image::ImageFormat::Png | image::ImageFormat::Tiff | image::ImageFormat::Bmp,
+            ) => {
+                let data = self.png_samples(|value, data| data.push((value * 255.999) as u8));
+                (data, image::ColorType::Rgba8)
+            }
+            _ => (self.bytes(), image::ColorType::Rgb8),
+        };
+        image::save_buffer_with_format(path, &data, width, height, color, format)?;
+        Ok(())
+    }
+
+    /// Gamma corrected RGB bytes of each pixel, in rows from the top
+    fn bytes(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|color| color.get_bytes())
+            .collect()
+    }
+
+    /// Writes the image to a file in png format, with an alpha channel if the image has one
+    pub fn write_png<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        self.encode_png(path, png::BitDepth::Eight, &[])
+    }
+
+    /// Writes the image to a png file, with `info` stored as text chunks
+    pub fn write_png_with_info<P: AsRef<Path>>(
+        self,
+        path: P,
+        info: &RenderInfo,
+    ) -> Result<(), Error> {
+        self.encode_png(path, png::BitDepth::Eight, &info.entries())
+    }
+
+    /// Writes the image to a png file with 16 bits per channel, which avoids banding in smooth
+    /// gradients and leaves more room for editing
+    pub fn write_png16<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        self.encode_png(path, png::BitDepth::Sixteen, &[])
+    }
+
+    /// Like `write_png16`, with `info` stored as text chunks
+    pub fn write_png16_with_info<P: AsRef<Path>>(
+        self,
+        path: P,
+        info: &RenderInfo,
+    ) -> Result<(), Error> {
+        self.encode_png(path, png::BitDepth::Sixteen, &info.entries())
+    }
+
+    fn encode_png<P: AsRef<Path>>(
+        &self,
+        path: P,
+        depth: png::BitDepth,
+        text: &[(&str, String)],
+    ) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(match self.alpha {
+            Some(_) => png::ColorType::RGBA,
+            None => png::ColorType::RGB,
         });
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header()?;
+        for (key, value) in text {
+            // tEXt chunks hold a keyword and text separated by a null byte
+            let mut chunk = key.as_bytes().to_vec();
+            chunk.push(0);
+            chunk.extend(value.bytes());
+            writer.write_chunk(*b"tEXt", &chunk)?;
+        }
+        let data = match depth {
+            png::BitDepth::Sixteen => self
+                .png_samples(|value, data| data.extend(((value * 65535.999) as u16).to_be_bytes())),
+            _ => self.png_samples(|value, data| data.push((value * 255.999) as u8)),
+        };
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    /// Gamma corrected channels of each pixel, then alpha if the image has it, each between 0 and
+    /// 1 and added to the data by `encode`
+    ///
+    /// PNG alpha isn't premultiplied, so colors are divided by their alpha first
+    fn png_samples(&self, encode: impl Fn(Float, &mut Vec<u8>)) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (index, color) in self.data.iter().enumerate() {
+            let alpha = self.alpha.as_ref().map(|alpha| alpha[index].clamp(0., 1.));
+            let color = match alpha {
+                Some(alpha) if alpha > 0. => *color / alpha,
+                _ => *color,
+            };
+            for value in [color.red, color.green, color.blue] {
+                encode(value.sqrt().clamp(0., 1.), &mut data);
+            }
+            if let Some(alpha) = alpha {
+                encode(alpha, &mut data);
+            }
+        }
+        data
+    }
+
+    /// Writes the linear radiance of each pixel to an OpenEXR file, without clamping or gamma
+    ///
+    /// Images with alpha get an alpha channel too, with colors premultiplied as EXR expects
+    pub fn write_exr<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        Ok(self.encode_exr(path.as_ref())?)
     }
 
-    /// Writes the image to a file in png format
-    pub fn write_png<P: AsRef<Path>>(self, path: P) {
-        // Convert to png data
-        let data: Vec<u8> = self
+    fn encode_exr(&self, path: &Path) -> exr::error::UnitResult {
+        let (width, height) = (self.width as usize, self.height as usize);
+        match &self.alpha {
+            Some(alpha) => exr::prelude::write_rgba_file(path, width, height, |x, y| {
+                let color = self.pixel(x as u32, y as u32);
+                let alpha = alpha[y * width + x];
+                let (r, g, b) = (color.red as f32, color.green as f32, color.blue as f32);
+                (r, g, b, alpha as f32)
+            }),
+            None => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                let color = self.pixel(x as u32, y as u32);
+                (color.red as f32, color.green as f32, color.blue as f32)
+            }),
+        }
+    }
+
+    /// Writes the linear radiance of each pixel to a Radiance `.hdr` file, without clamping or
+    /// gamma
+    pub fn write_hdr<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        self.encode_hdr(path.as_ref())
+    }
+
+    fn encode_hdr(&self, path: &Path) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let data: Vec<_> = self
             .data
             .iter()
-            .flat_map(|line| line.iter().flat_map(|color| color.get_bytes()))
+            .map(|color| image::Rgb([color.red as f32, color.green as f32, color.blue as f32]))
             .collect();
-
-        // Write data
-        // if path.as_ref().exists() {
-        //     std::fs::remove_file(&path).unwrap();
-        // }
-        image::save_buffer_with_format(
-            path,
+        image::hdr::HDREncoder::new(BufWriter::new(file)).encode(
             &data,
-            self.width,
-            self.height,
-            image::ColorType::Rgb8,
-            image::ImageFormat::Png,
-        )
-        .unwrap()
+            self.width as usize,
+            self.height as usize,
+        )?;
+        Ok(())
+    }
+
+    /// Burns `info` into the bottom left corner of the image as white text on black
+    pub fn stamp(&mut self, info: &RenderInfo) {
+        let lines: Vec<String> = info
+            .entries()
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value).to_ascii_uppercase())
+            .collect();
+        // Keep the text readable on large images
+        let scale = (self.height as usize / 300).max(1);
+        let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let box_width = (columns * GLYPH_ADVANCE + 1) * scale;
+        let box_height = (lines.len() * LINE_ADVANCE + 1) * scale;
+        let top = (self.height as usize).saturating_sub(box_height);
+
+        let width = self.width as usize;
+        if let Some(alpha) = &mut self.alpha {
+            for row in alpha.chunks_exact_mut(width).skip(top) {
+                row.iter_mut().take(box_width).for_each(|alpha| *alpha = 1.);
+            }
+        }
+        for (y, row) in self.data.chunks_exact_mut(width).enumerate().skip(top) {
+            for pixel in row.iter_mut().take(box_width) {
+                *pixel = color!();
+            }
+            // Position in the text box, in font pixels
+            let text_y = (y - top) / scale;
+            if text_y == 0 {
+                continue;
+            }
+            let (line, glyph_y) = ((text_y - 1) / LINE_ADVANCE, (text_y - 1) % LINE_ADVANCE);
+            let line = match lines.get(line) {
+                Some(line) if glyph_y < GLYPH_HEIGHT => line.as_bytes(),
+                _ => continue,
+            };
+            for (x, pixel) in row.iter_mut().enumerate().take(box_width) {
+                let text_x = x / scale;
+                if text_x == 0 {
+                    continue;
+                }
+                let (column, glyph_x) =
+                    ((text_x - 1) / GLYPH_ADVANCE, (text_x - 1) % GLYPH_ADVANCE);
+                let glyph_row = match line.get(column) {
+                    Some(&c) if glyph_x < GLYPH_WIDTH => glyph(c as char)[glyph_y],
+                    _ => continue,
+                };
+                if glyph_row & (1 << (GLYPH_WIDTH - 1 - glyph_x)) != 0 {
+                    *pixel = color!(1., 1., 1.);
+                }
+            }
+        }
+    }
+}
+
+/// Writes images as named layers of one OpenEXR file, keeping the full linear range
+///
+/// Compositors load each layer as a separate pass. `info` is stored as text attributes if given
+pub fn write_exr_layers<P: AsRef<Path>>(
+    path: P,
+    layers: &[(&str, &Image)],
+    info: Option<&RenderInfo>,
+) -> Result<(), crate::error::Error> {
+    use exr::prelude::*;
+
+    let (width, height) = match layers.first() {
+        Some((_, image)) => (image.width as usize, image.height as usize),
+        None => {
+            let reason = "an EXR file needs at least one layer".to_owned();
+            return Err(crate::error::Error::Layers(reason));
+        }
+    };
+    if let Some((name, image)) = layers
+        .iter()
+        .find(|(_, image)| image.width as usize != width || image.height as usize != height)
+    {
+        return Err(crate::error::Error::Layers(format!(
+            "EXR layer `{}` is {}x{}, but the first layer is {}x{}",
+            name, image.width, image.height, width, height
+        )));
+    }
+    let size = Vec2(width, height);
+    let rgb = |image: &crate::image::Image, position: Vec2<usize>| {
+        let color = image.pixel(position.x() as u32, position.y() as u32);
+        (color.red as f32, color.green as f32, color.blue as f32)
+    };
+
+    let mut attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+    if let Some(info) = info {
+        for (key, value) in info.entries() {
+            attributes
+                .other
+                .insert(Text::from(key), AttributeValue::Text(Text::from(&*value)));
+        }
+    }
+    // Every layer gets an alpha channel if any has one, opaque where they don't
+    if layers.iter().any(|(_, image)| image.alpha.is_some()) {
+        let layers: Vec<_> = layers
+            .iter()
+            .map(|&(name, image)| {
+                let channels = SpecificChannels::rgba(move |position: Vec2<usize>| {
+                    let (r, g, b) = rgb(image, position);
+                    let index = position.y() * width + position.x();
+                    let alpha = image.alpha.as_ref().map_or(1., |alpha| alpha[index]);
+                    (r, g, b, alpha as f32)
+                });
+                Layer::new(
+                    size,
+                    LayerAttributes::named(name),
+                    Encoding::FAST_LOSSLESS,
+                    channels,
+                )
+            })
+            .collect();
+        Image::from_layers(attributes, layers).write().to_file(path)
+    } else {
+        let layers: Vec<_> = layers
+            .iter()
+            .map(|&(name, image)| {
+                let channels = SpecificChannels::rgb(move |position| rgb(image, position));
+                Layer::new(
+                    size,
+                    LayerAttributes::named(name),
+                    Encoding::FAST_LOSSLESS,
+                    channels,
+                )
+            })
+            .collect();
+        Image::from_layers(attributes, layers).write().to_file(path)
+    }?;
+    Ok(())
+}
+
+/// What a render was made with, for keeping renders traceable to their settings
+pub struct RenderInfo {
+    pub scene: String,
+    pub samples_per_pixel: u32,
+    pub seed: Option<u64>,
+    pub width: u32,
+    pub height: u32,
+    /// How long the render took
+    pub duration: Duration,
+}
+
+impl RenderInfo {
+    pub fn new(
+        scene: &str,
+        settings: &RenderSettings,
+        width: u32,
+        height: u32,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            scene: scene.to_string(),
+            samples_per_pixel: settings.samples_per_pixel,
+            seed: settings.seed,
+            width,
+            height,
+            duration,
+        }
+    }
+
+    /// Names and values of each setting
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let seed = match self.seed {
+            Some(seed) => seed.to_string(),
+            None => "random".to_string(),
+        };
+        vec![
+            ("Scene", self.scene.clone()),
+            ("Samples per pixel", self.samples_per_pixel.to_string()),
+            ("Resolution", format!("{}x{}", self.width, self.height)),
+            ("Seed", seed),
+            (
+                "Render time",
+                format!("{:.2}s", self.duration.as_secs_f64()),
+            ),
+        ]
+    }
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Glyph width plus spacing
+const GLYPH_ADVANCE: usize = GLYPH_WIDTH + 1;
+/// Glyph height plus spacing
+const LINE_ADVANCE: usize = GLYPH_HEIGHT + 1;
+
+/// Rows of a tiny 3x5 pixel font, from the top with the high bit on the left
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ' ' => [0, 0, 0, 0, 0],
+        ':' => [0, 2, 0, 2, 0],
+        '.' => [0, 0, 0, 0, 2],
+        ',' => [0, 0, 0, 2, 4],
+        '-' => [0, 0, 7, 0, 0],
+        '_' => [0, 0, 0, 0, 7],
+        '=' => [0, 7, 0, 7, 0],
+        '/' => [1, 1, 2, 4, 4],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        _ => [7, 1, 3, 0, 2],
     }
 }
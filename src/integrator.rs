@@ -0,0 +1,656 @@
+use crate::hittable::{HitRecord, HIT_EPSILON};
+use crate::material::Material;
+use crate::pdf::{power_heuristic, HittablePdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler::SampleCtx;
+use crate::world::World;
+use crate::{Color, Float, Point3, RenderSettings, Vec3};
+use std::iter::Sum;
+use std::ops::{Div, Index, Mul};
+
+/// Computes the light arriving back along a ray
+///
+/// Implement this to plug custom shading into `raytrace_image`
+pub trait Integrator {
+    fn li(&self, ray: &Ray, scene: &World, settings: &RenderSettings, ctx: &mut SampleCtx)
+        -> Color;
+}
+
+/// How a path scattered off a surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bounce {
+    Diffuse,
+    /// Scattered in a specific direction, like a mirror or glass
+    Specular,
+}
+
+/// The state of a path being traced, passed to materials as they scatter
+#[derive(Clone, Copy)]
+pub struct PathState {
+    /// Product of the attenuation of every bounce so far
+    pub throughput: Color,
+    /// Number of bounces so far
+    pub depth: u32,
+    /// Whether the last bounce scattered in a specific direction, so lights could only be seen
+    /// by following the ray
+    pub specular: bool,
+    /// How the path scattered off the first surface it hit
+    pub first_bounce: Option<Bounce>,
+    /// Dielectrics the path is inside
+    pub media: MediumStack,
+    /// Bounces in a row off the material the path last scattered from, see
+    /// `Visibility::max_depth`
+    pub material_depth: u32,
+    /// Address of that material
+    material: usize,
+}
+
+impl PathState {
+    /// The state of a ray leaving the camera
+    pub fn camera() -> Self {
+        Self {
+            throughput: color!(1., 1., 1.),
+            depth: 0,
+            specular: true,
+            first_bounce: None,
+            media: MediumStack::new(),
+            material_depth: 0,
+            material: 0,
+        }
+    }
+
+    /// Bounces the path has already taken in a row off `material`
+    fn depth_on(&self, material: &dyn Material) -> u32 {
+        if address(material) == self.material {
+            self.material_depth
+        } else {
+            0
+        }
+    }
+
+    /// Counts a bounce off `material`
+    fn bounce_off(&mut self, material: &dyn Material) {
+        self.material_depth = self.depth_on(material) + 1;
+        self.material = address(material);
+    }
+
+    /// The type of path formed by reaching a light after `bounces` bounces
+    fn path_type(&self, bounces: u32, background: bool) -> PathType {
+        match (self.first_bounce, bounces) {
+            (_, 0) if background => PathType::Background,
+            (_, 0) => PathType::Emission,
+            (Some(Bounce::Specular), 1) => PathType::SpecularDirect,
+            (Some(Bounce::Specular), _) => PathType::SpecularIndirect,
+            (_, 1) => PathType::DiffuseDirect,
+            (_, _) => PathType::DiffuseIndirect,
+        }
+    }
+}
+
+/// Most nested dielectrics a path keeps track of
+const MAX_MEDIA: usize = 8;
+
+/// Refractive indices of the dielectrics a path is inside, innermost last
+///
+/// Glass holding water holding air bubbles refracts at each surface by the ratio of the indices on
+/// either side, not as if every surface bordered vacuum
+#[derive(Clone, Copy, Debug)]
+pub struct MediumStack {
+    iors: [Float; MAX_MEDIA],
+    len: usize,
+}
+
+impl MediumStack {
+    /// A path in vacuum
+    pub fn new() -> Self {
+        Self {
+            iors: [1.; MAX_MEDIA],
+            len: 0,
+        }
+    }
+
+    /// Refractive index of the innermost medium, or 1 in vacuum
+    pub fn current(&self) -> Float {
+        self.iors[..self.len].last().copied().unwrap_or(1.)
+    }
+
+    /// Refractive index on the other side of leaving a medium with index `ior`
+    ///
+    /// Leaving a medium the path never entered, like one the camera starts in, leads to the
+    /// innermost medium the path did enter
+    pub fn outside(&self, ior: Float) -> Float {
+        let media = &self.iors[..self.len];
+        match media.iter().rposition(|&other| other == ior) {
+            Some(index) => media[..index]
+                .iter()
+                .chain(&media[index + 1..])
+                .last()
+                .copied()
+                .unwrap_or(1.),
+            None => self.current(),
+        }
+    }
+
+    /// Enters a medium with index `ior`, forgetting the outermost if too many are nested
+    pub fn enter(&mut self, ior: Float) {
+        if self.len == MAX_MEDIA {
+            self.iors.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.iors[self.len] = ior;
+        self.len += 1;
+    }
+
+    /// Leaves the innermost medium with index `ior`
+    pub fn leave(&mut self, ior: Float) {
+        if let Some(index) = self.iors[..self.len]
+            .iter()
+            .rposition(|&other| other == ior)
+        {
+            self.iors.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    /// Keeps track of a ray scattering off `rec` as `scattered`, entering or leaving the surface's
+    /// medium if it passed through
+    pub fn cross(&mut self, rec: &HitRecord, scattered: &Ray) {
+        let ior = match rec.material.ior() {
+            Some(ior) => ior,
+            None => return,
+        };
+        // The normal faces the side the ray came from
+        if scattered.dir.dot(&rec.normal) < 0. {
+            if rec.front_face {
+                self.enter(ior);
+            } else {
+                self.leave(ior);
+            }
+        }
+    }
+}
+
+impl Default for MediumStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Types of light path, told apart by how they leave the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathType {
+    /// Lights seen directly by the camera
+    Emission,
+    /// The background seen directly by the camera
+    Background,
+    /// Light reaching a diffuse surface straight from a light
+    DiffuseDirect,
+    /// Light reaching a diffuse surface after bouncing off other surfaces
+    DiffuseIndirect,
+    /// Light reflected or refracted straight from a light
+    SpecularDirect,
+    /// Light reflected or refracted after bouncing off other surfaces
+    SpecularIndirect,
+}
+
+impl PathType {
+    pub const ALL: [PathType; 6] = [
+        PathType::Emission,
+        PathType::Background,
+        PathType::DiffuseDirect,
+        PathType::DiffuseIndirect,
+        PathType::SpecularDirect,
+        PathType::SpecularIndirect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PathType::Emission => "emission",
+            PathType::Background => "background",
+            PathType::DiffuseDirect => "diffuse_direct",
+            PathType::DiffuseIndirect => "diffuse_indirect",
+            PathType::SpecularDirect => "specular_direct",
+            PathType::SpecularIndirect => "specular_indirect",
+        }
+    }
+
+    /// Whether the light bounced more than once before reaching the camera
+    pub fn is_indirect(self) -> bool {
+        matches!(self, PathType::DiffuseIndirect | PathType::SpecularIndirect)
+    }
+}
+
+/// Where a radiance clamp is applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClampMode {
+    /// To the light each bounce of a path adds
+    PerBounce,
+    /// To the total a path adds to its pixel
+    PerSample,
+}
+
+/// Caps how much light a path can carry, removing fireflies from bright lights found by unlikely
+/// paths at the cost of darkening the image slightly
+#[derive(Clone, Copy, Debug)]
+pub struct RadianceClamp {
+    /// Brightest a color can be, measured by its largest channel. Brighter colors are scaled down
+    /// so their hue stays the same
+    pub max: Float,
+    pub mode: ClampMode,
+    /// Only clamp light that bounced more than once, see `PathType::is_indirect`, so lights and
+    /// what they light directly stay as bright as they should be
+    pub indirect_only: bool,
+}
+
+impl RadianceClamp {
+    /// Clamps light that bounced more than once after each bounce
+    pub fn indirect(max: Float) -> Self {
+        Self {
+            max,
+            mode: ClampMode::PerBounce,
+            indirect_only: true,
+        }
+    }
+
+    fn applies_to(&self, path_type: PathType) -> bool {
+        !self.indirect_only || path_type.is_indirect()
+    }
+
+    fn clamp(&self, color: Color) -> Color {
+        let brightest = color.red.max(color.green).max(color.blue);
+        if brightest > self.max {
+            color * (self.max / brightest)
+        } else {
+            color
+        }
+    }
+}
+
+/// Light arriving along a ray, split up by the type of path it took
+#[derive(Clone, Copy, Default)]
+pub struct LightPaths {
+    colors: [Color; 6],
+}
+
+impl LightPaths {
+    pub fn add(&mut self, path_type: PathType, color: Color) {
+        self.colors[path_type as usize] += color;
+    }
+
+    /// All the light added together
+    pub fn total(&self) -> Color {
+        self.colors.iter().copied().sum()
+    }
+
+    /// Adds light, clamping it first if `clamp` is per bounce and applies to `path_type`
+    fn add_clamped(&mut self, path_type: PathType, color: Color, clamp: Option<RadianceClamp>) {
+        let color = match clamp {
+            Some(clamp) if clamp.mode == ClampMode::PerBounce && clamp.applies_to(path_type) => {
+                clamp.clamp(color)
+            }
+            _ => color,
+        };
+        self.add(path_type, color);
+    }
+
+    /// Scales down the light of the types `clamp` applies to so their total is within its limit
+    fn clamp_total(&mut self, clamp: &RadianceClamp) {
+        let total: Color = PathType::ALL
+            .iter()
+            .filter(|&&path_type| clamp.applies_to(path_type))
+            .map(|&path_type| self[path_type])
+            .sum();
+        let brightest = total.red.max(total.green).max(total.blue);
+        if brightest <= clamp.max {
+            return;
+        }
+        let scale = clamp.max / brightest;
+        for path_type in PathType::ALL {
+            if clamp.applies_to(path_type) {
+                self.colors[path_type as usize] *= scale;
+            }
+        }
+    }
+}
+
+impl Index<PathType> for LightPaths {
+    type Output = Color;
+
+    fn index(&self, path_type: PathType) -> &Self::Output {
+        &self.colors[path_type as usize]
+    }
+}
+
+impl Div<Float> for LightPaths {
+    type Output = Self;
+
+    fn div(self, value: Float) -> Self::Output {
+        let mut colors = self.colors;
+        colors.iter_mut().for_each(|color| *color /= value);
+        Self { colors }
+    }
+}
+
+impl Mul<Float> for LightPaths {
+    type Output = Self;
+
+    fn mul(self, value: Float) -> Self::Output {
+        let mut colors = self.colors;
+        colors.iter_mut().for_each(|color| *color *= value);
+        Self { colors }
+    }
+}
+
+impl Sum<Self> for LightPaths {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        iter.fold(Self::default(), |mut a, b| {
+            for (color, other) in a.colors.iter_mut().zip(b.colors.iter()) {
+                *color += *other;
+            }
+            a
+        })
+    }
+}
+
+/// The default path tracer
+pub struct PathIntegrator;
+
+impl PathIntegrator {
+    /// Traces a path, keeping track of the type of path each bit of light took
+    pub fn light_paths(
+        &self,
+        ray: &Ray,
+        scene: &World,
+        settings: &RenderSettings,
+        ctx: &mut SampleCtx,
+    ) -> LightPaths {
+        let mut ray = *ray;
+        let mut state = PathState::camera();
+        let mut paths = LightPaths::default();
+        // Where the last diffuse bounce was and the density of scattering in the chosen direction
+        let mut last_scatter: Option<(Point3, Float)> = None;
+        let clamp = settings.clamp;
+        while state.depth < settings.max_depth {
+            let hit = hit_visible(scene, &ray, state.depth == 0);
+
+            // Fog and atmosphere hide what's behind them, adding their own light
+            let t = hit.as_ref().map_or(Float::INFINITY, |(rec, _)| rec.t);
+            let (transmittance, added) = scene.attenuation(&ray, t);
+            let path_type = state.path_type(state.depth, true);
+            paths.add_clamped(path_type, state.throughput * added, clamp);
+            state.throughput = state.throughput * transmittance;
+
+            let (rec, is_light) = match hit {
+                Some(hit) => hit,
+                None => {
+                    let mut background = settings.background.color_seen(&ray, &state);
+                    if let (Some(sun), true) = (&scene.sun, state.specular) {
+                        background += sun.radiance(&ray.dir);
+                    }
+                    let path_type = state.path_type(state.depth, true);
+                    paths.add_clamped(path_type, state.throughput * background, clamp);
+                    break;
+                }
+            };
+            // Lights are also sampled directly from diffuse surfaces, so weight the two ways of
+            // finding them
+            let weight = match (is_light, last_scatter) {
+                (true, Some((origin, scattering_pdf))) => {
+                    let light_pdf = light_pdf(scene, origin, &ray.dir);
+                    power_heuristic(scattering_pdf, light_pdf)
+                }
+                _ => 1.,
+            };
+            if weight > 0. {
+                let path_type = state.path_type(state.depth, false);
+                let emitted = rec.material.emitted(&ray, &rec);
+                paths.add_clamped(path_type, state.throughput * emitted * weight, clamp);
+            }
+
+            // Materials that can't be lit directly only scatter in specific directions
+            let specular = rec.material.eval(&ray, &rec, &rec.normal).is_none();
+            let bounce = if specular {
+                Bounce::Specular
+            } else {
+                Bounce::Diffuse
+            };
+
+            // Sample the sun directly
+            if let (Some(sun), false) = (&scene.sun, specular) {
+                let dir = sun.sample_direction(ctx.rng());
+                if let Some(f) = rec.material.eval(&ray, &rec, &dir) {
+                    let shadow_ray = Ray {
+                        sample: ctx.ray_sample(),
+                        ..Ray::new(rec.point, dir, ray.time)
+                    };
+                    if f.length_squared() > 0. && !occluded(scene, &shadow_ray, Float::INFINITY) {
+                        let mut next = state;
+                        next.first_bounce = state.first_bounce.or(Some(bounce));
+                        let path_type = next.path_type(state.depth + 1, false);
+                        let transmittance = scene.transmittance(&shadow_ray, Float::INFINITY);
+                        let light = f * sun.irradiance * transmittance;
+                        paths.add_clamped(path_type, state.throughput * light, clamp);
+                    }
+                }
+            }
+
+            // Sample a light directly
+            if !specular && !scene.lights.is_empty() {
+                let light = sample_light(scene, &ray, &rec, ctx);
+                if light.length_squared() > 0. {
+                    let mut next = state;
+                    next.first_bounce = state.first_bounce.or(Some(bounce));
+                    let path_type = next.path_type(state.depth + 1, false);
+                    paths.add_clamped(path_type, state.throughput * light, clamp);
+                }
+            }
+
+            if let Some(max_depth) = rec.material.visibility().max_depth {
+                if state.depth_on(rec.material) >= max_depth {
+                    break;
+                }
+            }
+            let (scattered, attenuation) = match rec.material.scatter(&ray, &rec, &state, ctx) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+            last_scatter = if specular {
+                None
+            } else {
+                let scattering_pdf = rec.material.scattering_pdf(&ray, &rec, &scattered.dir);
+                Some((rec.point, scattering_pdf))
+            };
+            state.media.cross(&rec, &scattered);
+            ray = scattered;
+            ray.sample = ctx.ray_sample();
+            state.throughput = state.throughput * attenuation;
+            state.depth += 1;
+            state.bounce_off(rec.material);
+            state.specular = specular;
+            state.first_bounce = state.first_bounce.or(Some(bounce));
+
+            // Randomly end dim paths, boosting the survivors to keep the result unbiased
+            if let Some(rr_depth) = settings.russian_roulette_depth {
+                if state.depth >= rr_depth {
+                    let throughput = state.throughput;
+                    let survive = throughput.red.max(throughput.green).max(throughput.blue);
+                    let survive = survive.clamp(0.05, 1.);
+                    if ctx.get_1d() > survive {
+                        break;
+                    }
+                    state.throughput /= survive;
+                }
+            }
+        }
+        if let Some(clamp) = clamp.filter(|clamp| clamp.mode == ClampMode::PerSample) {
+            paths.clamp_total(&clamp);
+        }
+        paths
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &World,
+        settings: &RenderSettings,
+        ctx: &mut SampleCtx,
+    ) -> Color {
+        self.light_paths(ray, scene, settings, ctx).total()
+    }
+}
+
+/// Finds the closest hit within `ray`'s range of `t`, passing through surfaces hidden from it
+///
+/// `primary` is whether the ray comes from the camera. Also returns whether the hit is on one of
+/// the world's lights
+pub(crate) fn hit_visible<'a>(
+    scene: &'a World,
+    ray: &Ray,
+    primary: bool,
+) -> Option<(HitRecord<'a>, bool)> {
+    let mut t_min = ray.t_min.max(HIT_EPSILON);
+    loop {
+        let (rec, is_light) = scene.hit_source(ray, t_min, ray.t_max)?;
+        let visibility = rec.material.visibility();
+        if (primary && visibility.camera) || (!primary && visibility.indirect) {
+            return Some((rec, is_light));
+        }
+        t_min = rec.t + HIT_EPSILON;
+    }
+}
+
+/// Whether anything visible to bounced rays blocks `ray` before `t_max`
+fn occluded(scene: &World, ray: &Ray, t_max: Float) -> bool {
+    scene.occluded(ray, HIT_EPSILON, t_max)
+}
+
+/// Address of `material`, to tell when a path moves from one material to another
+fn address(material: &dyn Material) -> usize {
+    material as *const dyn Material as *const () as usize
+}
+
+/// Density of `sample_light` choosing `dir` from `origin`, picking each light equally often
+fn light_pdf(scene: &World, origin: Point3, dir: &Vec3) -> Float {
+    let total: Float = scene
+        .lights
+        .iter()
+        .map(|light| light.pdf_value(&origin, dir))
+        .sum();
+    total / scene.lights.len() as Float
+}
+
+/// Light reaching the surface at `rec` from a randomly chosen light, weighted by the BRDF
+///
+/// Weighted against finding the light by scattering, which `light_paths` also does
+fn sample_light(scene: &World, ray: &Ray, rec: &HitRecord, ctx: &mut SampleCtx) -> Color {
+    let light = &scene.lights[ctx.choose(scene.lights.len())];
+    let dir = match HittablePdf::new(light.as_ref(), rec.point).generate(ctx) {
+        Some(dir) => dir,
+        None => return color!(),
+    };
+    let light_pdf = light_pdf(scene, rec.point, &dir);
+    let f = match rec.material.eval(ray, rec, &dir) {
+        Some(f) if light_pdf > 0. && f.length_squared() > 0. => f,
+        _ => return color!(),
+    };
+
+    // Whichever light is in front gets the sample, as `light_pdf` counts every light
+    let shadow_ray = Ray {
+        sample: ctx.ray_sample(),
+        ..Ray::new(rec.point, dir, ray.time)
+    };
+    let light_rec = match hit_visible(scene, &shadow_ray, false) {
+        Some((light_rec, true)) => light_rec,
+        _ => return color!(),
+    };
+    let transmittance = scene.transmittance(&shadow_ray, light_rec.t);
+    let weight = power_heuristic(light_pdf, rec.material.scattering_pdf(ray, rec, &dir));
+    f * light_rec.material.emitted(&shadow_ray, &light_rec) * transmittance * (weight / light_pdf)
+}
+
+/// Stylized cel shading with flat bands of light and a rim highlight
+///
+/// Surfaces are lit by a single directional light with hard shadows. For outlines, render with
+/// `raytrace_with_aovs` and draw them over the image with `outline::draw_outlines`
+pub struct ToonIntegrator {
+    /// Direction towards the light
+    pub light_dir: Vec3,
+    /// Number of flat diffuse bands
+    pub bands: u32,
+    /// Brightness of the darkest band
+    pub ambient: Float,
+    pub rim_color: Color,
+    /// Surfaces more edge-on than this get the rim highlight. 0 is edge-on and 1 is facing the camera
+    pub rim_width: Float,
+}
+
+impl Default for ToonIntegrator {
+    fn default() -> Self {
+        Self {
+            light_dir: vec3!(1., 1., 1.),
+            bands: 3,
+            ambient: 0.2,
+            rim_color: color!(0.4, 0.4, 0.4),
+            rim_width: 0.3,
+        }
+    }
+}
+
+impl Integrator for ToonIntegrator {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &World,
+        settings: &RenderSettings,
+        ctx: &mut SampleCtx,
+    ) -> Color {
+        let rec = match hit_visible(scene, ray, true) {
+            Some((rec, _)) => rec,
+            None => {
+                let (transmittance, added) = scene.attenuation(ray, Float::INFINITY);
+                let background = settings.background.color_seen(ray, &PathState::camera());
+                return background * transmittance + added;
+            }
+        };
+
+        // How much the surface faces the camera
+        let facing = rec.normal.dot(&-ray.dir.unit_vector());
+
+        // Use the material's attenuation as its flat color
+        let base = rec
+            .material
+            .scatter(ray, &rec, &PathState::camera(), ctx)
+            .map(|(_, attenuation)| attenuation)
+            .unwrap_or_default();
+
+        // Quantize direct light into bands
+        let light_dir = self.light_dir.unit_vector();
+        let shadow_ray = Ray {
+            sample: ctx.ray_sample(),
+            ..Ray::new(rec.point, light_dir, ray.time)
+        };
+        let light = if occluded(scene, &shadow_ray, Float::INFINITY) {
+            0.
+        } else {
+            rec.normal.dot(&light_dir).max(0.)
+        };
+        let bands = self.bands.max(1) as Float;
+        let light = (light * bands).ceil() / bands;
+        let shade = self.ambient + (1. - self.ambient) * light;
+
+        let rim = if facing < self.rim_width && light > 0. {
+            self.rim_color
+        } else {
+            color!()
+        };
+
+        let color = base * shade + rim + rec.material.emitted(ray, &rec);
+        let (transmittance, added) = scene.attenuation(ray, rec.t);
+        color * transmittance + added
+    }
+}
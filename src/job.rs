@@ -0,0 +1,244 @@
+//! Render farm jobs, bundling a scene file with everything it reads so it renders the same on
+//! other machines
+//!
+//! A job is a directory or an uncompressed zip file holding:
+//!
+//! - `job.json`, a `JobFile` with the settings to render with and the frames to render
+//! - `scene.json`, the scene file, with its paths pointing into `files/`
+//! - `files/`, copies of the textures, environment maps and other files the scene reads
+//!
+//! Workers unpack zipped jobs and load the directory with `Job::load`
+
+use crate::animation::FrameNoise;
+use crate::error::Error;
+use crate::scene::{RenderDesc, SceneFile};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Name of the `JobFile` in a job
+pub const JOB_FILE: &str = "job.json";
+
+/// Name of the scene file in a job
+const SCENE_FILE: &str = "scene.json";
+
+/// Directory in a job the files the scene reads are copied to
+const FILES_DIR: &str = "files";
+
+/// Date zip entries are stamped with, 1980-01-01, so the same job always gives the same zip
+const ZIP_DATE: u16 = 0x21;
+
+/// The contents of `job.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobFile {
+    /// Scene file in the job
+    pub scene: String,
+    /// Size, samples and seed to render with, replacing the scene file's. Edit this to queue the
+    /// same job at another quality
+    pub render: RenderDesc,
+    /// Frames for workers to render, each seeded with `Job::frame_seed`
+    pub frames: Vec<u32>,
+    /// Files the scene reads, in the job
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// A scene and the frames of it to render, to hand out to workers
+pub struct Job {
+    pub scene: SceneFile,
+    pub frames: Vec<u32>,
+}
+
+impl Job {
+    pub fn new(scene: SceneFile, frames: Vec<u32>) -> Self {
+        Self { scene, frames }
+    }
+
+    /// Loads a job from its directory, pointing the scene's paths at the files in it
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let job: JobFile = serde_json::from_str(&std::fs::read_to_string(dir.join(JOB_FILE))?)?;
+        let mut scene: Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(&job.scene))?)?;
+        visit_paths(&mut scene, &mut |path| {
+            if Path::new(path).is_relative() {
+                *path = dir.join(&*path).to_string_lossy().into_owned();
+            }
+        });
+        let mut scene: SceneFile = serde_json::from_value(scene)?;
+        scene.render = job.render;
+        Ok(Self::new(scene, job.frames))
+    }
+
+    /// Files the scene reads, which is every string called `path` in the scene file, including
+    /// any in plugin parameters. Relative paths are from the working directory, as when rendering
+    pub fn dependencies(&self) -> Result<Vec<String>, Error> {
+        let mut scene = serde_json::to_value(&self.scene)?;
+        let mut paths = BTreeSet::new();
+        visit_paths(&mut scene, &mut |path| {
+            paths.insert(path.clone());
+        });
+        Ok(paths.into_iter().collect())
+    }
+
+    /// Seed to render `frame` with, scrambled from the scene's seed like `Sequence` does. `None`
+    /// if the scene has no seed
+    pub fn frame_seed(&self, frame: u32) -> Option<u64> {
+        let seed = self.scene.render.seed?;
+        Some(FrameNoise::Decorrelated.frame_seed(seed, frame))
+    }
+
+    /// Writes the job to a zip file if `path` ends in `.zip`, otherwise to a directory
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("zip") => self.write_zip(path),
+            _ => self.write_dir(path),
+        }
+    }
+
+    /// Writes the job to `dir`, creating it if needed
+    pub fn write_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        let entries = self.entries()?;
+        std::fs::create_dir_all(dir.join(FILES_DIR))?;
+        for (name, data) in entries {
+            std::fs::write(dir.join(name), data)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the job to an uncompressed zip file. Fails if the job is too large for a zip file
+    /// without the 64-bit extensions, over 4 GiB or 65535 files
+    pub fn write_zip<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let entries = self.entries()?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_zip(&mut writer, &entries)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Everything in the job, by where it goes in it
+    ///
+    /// Each file the scene reads is copied to `files/` with its index in front of its name, so
+    /// files from different directories with the same name don't collide
+    fn entries(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let mut renamed = BTreeMap::new();
+        let mut files = Vec::new();
+        for (index, source) in self.dependencies()?.into_iter().enumerate() {
+            let name = Path::new(&source)
+                .file_name()
+                .map_or_else(|| "file".into(), |name| name.to_string_lossy());
+            let bundled = format!("{}/{}_{}", FILES_DIR, index, name);
+            let data = std::fs::read(&source).map_err(|error| {
+                Error::Scene(format!("Couldn't read `{}` for the job: {}", source, error))
+            })?;
+            files.push((bundled.clone(), data));
+            renamed.insert(source, bundled);
+        }
+
+        let mut scene = serde_json::to_value(&self.scene)?;
+        visit_paths(&mut scene, &mut |path| {
+            if let Some(bundled) = renamed.get(path) {
+                *path = bundled.clone();
+            }
+        });
+        let job = JobFile {
+            scene: SCENE_FILE.to_owned(),
+            render: self.scene.render.clone(),
+            frames: self.frames.clone(),
+            files: files.iter().map(|(name, _)| name.clone()).collect(),
+        };
+        let mut entries = vec![
+            (JOB_FILE.to_owned(), serde_json::to_vec_pretty(&job)?),
+            (SCENE_FILE.to_owned(), serde_json::to_vec_pretty(&scene)?),
+        ];
+        entries.append(&mut files);
+        Ok(entries)
+    }
+}
+
+/// Calls `visit` with every string called `path` in `value`
+fn visit_paths<F: FnMut(&mut String)>(value: &mut Value, visit: &mut F) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                match value {
+                    Value::String(path) if key == "path" => visit(path),
+                    value => visit_paths(value, visit),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                visit_paths(value, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Writes `entries` as a zip file without compression, as textures are mostly compressed already
+fn write_zip<W: Write>(writer: &mut W, entries: &[(String, Vec<u8>)]) -> Result<(), Error> {
+    let too_large = || Error::Scene("The job is too large for a zip file".to_owned());
+    let count = u16::try_from(entries.len()).map_err(|_| too_large())?;
+    let mut central = Vec::new();
+    let mut offset = 0u32;
+    for (name, data) in entries {
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(data);
+        let crc = hasher.finalize();
+        // Version 2.0, names in UTF-8, stored, with the same fields in both headers
+        let common = [
+            &20u16.to_le_bytes()[..],
+            &0x0800u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &ZIP_DATE.to_le_bytes(),
+            &crc.to_le_bytes(),
+            &size.to_le_bytes(),
+            &size.to_le_bytes(),
+            &name_len.to_le_bytes(),
+            &0u16.to_le_bytes(),
+        ]
+        .concat();
+
+        let mut local = 0x0403_4b50u32.to_le_bytes().to_vec();
+        local.extend_from_slice(&common);
+        local.extend_from_slice(name.as_bytes());
+        writer.write_all(&local)?;
+        writer.write_all(data)?;
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&common);
+        // No comment, first disk, no attributes
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        let length = u32::try_from(local.len()).map_err(|_| too_large())?;
+        offset = offset
+            .checked_add(length)
+            .and_then(|offset| offset.checked_add(size))
+            .ok_or_else(too_large)?;
+    }
+    let central_size = u32::try_from(central.len()).map_err(|_| too_large())?;
+    writer.write_all(&central)?;
+
+    let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+    end.extend_from_slice(&[0; 4]);
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&central_size.to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes());
+    writer.write_all(&end)?;
+    Ok(())
+}
@@ -1,21 +1,50 @@
+// Casts between `Float` and `f32` are only needed in double precision
+#![cfg_attr(
+    feature = "f32",
+    allow(clippy::unnecessary_cast, clippy::excessive_precision)
+)]
+
+use crate::accel::AcceleratorKind;
+use crate::aov::{AovSample, Aovs};
+use crate::background::{Background, SolidBackground};
 use crate::camera::{Camera, CameraSettings};
-use crate::hittable::Hittable;
-use crate::image::Image;
+use crate::error::Error;
+use crate::filter::{PixelFilter, SplatBuffer};
+use crate::framebuffer::{BufferPrecision, FrameBuffer};
+use crate::hittable::HitRecord;
+use crate::image::{Image, Tonemap};
+use crate::integrator::{
+    hit_visible, Integrator, LightPaths, PathIntegrator, PathType, RadianceClamp,
+};
+use crate::progress::{estimate_line_costs, RenderProgress};
 use crate::ray::Ray;
-use crate::world::{BvhNode, World};
-use indicatif::ParallelProgressIterator;
-use rand::distributions::{Distribution, Standard, Uniform};
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use crate::sampler::{pixel_rng, sample_rng, seeded_rng, SampleCtx, SamplerKind};
+use crate::tile::Tile;
+use crate::world::World;
 use rayon::prelude::*;
 use std::fmt::Display;
 use std::iter::Sum;
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, ControlFlow, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
+
+/// Scalar type of all geometry and color math
+///
+/// `f64` by default. The `f32` feature halves the memory vectors and meshes take, which is
+/// plenty precise for most scenes
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+/// Constants like `PI` for `Float`
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
 
 // Create basic Vec3 structs
 // They all behave the same but have different identifiers and can't be interchanged directly
 pub trait VecType {
-    fn from_params(a: f64, b: f64, c: f64) -> Self;
+    fn from_params(a: Float, b: Float, c: Float) -> Self;
 }
 macro_rules! vec3_struct {
     ($name:ident, $x:ident, $y:ident, $z:ident, $macro_name:ident) => {
@@ -31,28 +60,28 @@ macro_rules! vec3_struct {
 
         #[derive(Clone, Copy, Debug, Default, PartialEq)]
         pub struct $name {
-            $x: f64,
-            $y: f64,
-            $z: f64,
+            $x: Float,
+            $y: Float,
+            $z: Float,
         }
 
         impl $name {
             /// Initalize with default values
-            pub fn new($x: f64, $y: f64, $z: f64) -> Self {
+            pub fn new($x: Float, $y: Float, $z: Float) -> Self {
                 Self { $x, $y, $z }
             }
 
             /// Returns the length of the vector
-            pub fn length(&self) -> f64 {
+            pub fn length(&self) -> Float {
                 self.length_squared().sqrt()
             }
 
-            pub fn length_squared(&self) -> f64 {
+            pub fn length_squared(&self) -> Float {
                 self.$x * self.$x + self.$y * self.$y + self.$z * self.$z
             }
 
             /// The dot product
-            pub fn dot(&self, other: &Self) -> f64 {
+            pub fn dot(&self, other: &Self) -> Float {
                 self.$x * other.$x + self.$y * other.$y + self.$z * other.$z
             }
 
@@ -91,7 +120,7 @@ macro_rules! vec3_struct {
         }
 
         impl VecType for $name {
-            fn from_params(a: f64, b: f64, c: f64) -> Self {
+            fn from_params(a: Float, b: Float, c: Float) -> Self {
                 Self {
                     $x: a,
                     $y: b,
@@ -100,8 +129,20 @@ macro_rules! vec3_struct {
             }
         }
 
+        impl From<[Float; 3]> for $name {
+            fn from([$x, $y, $z]: [Float; 3]) -> Self {
+                Self { $x, $y, $z }
+            }
+        }
+
+        impl From<$name> for [Float; 3] {
+            fn from(v: $name) -> Self {
+                [v.$x, v.$y, v.$z]
+            }
+        }
+
         impl Index<usize> for $name {
-            type Output = f64;
+            type Output = Float;
 
             fn index(&self, i: usize) -> &Self::Output {
                 match i {
@@ -185,10 +226,10 @@ macro_rules! vec3_struct {
             }
         }
 
-        impl Mul<f64> for $name {
+        impl Mul<Float> for $name {
             type Output = Self;
 
-            fn mul(self, value: f64) -> Self::Output {
+            fn mul(self, value: Float) -> Self::Output {
                 Self {
                     $x: self.$x * value,
                     $y: self.$y * value,
@@ -197,7 +238,7 @@ macro_rules! vec3_struct {
             }
         }
 
-        impl Mul<$name> for f64 {
+        impl Mul<$name> for Float {
             type Output = $name;
 
             fn mul(self, value: $name) -> Self::Output {
@@ -209,7 +250,7 @@ macro_rules! vec3_struct {
             }
         }
 
-        impl Mul<&$name> for f64 {
+        impl Mul<&$name> for Float {
             type Output = $name;
 
             fn mul(self, value: &$name) -> Self::Output {
@@ -221,10 +262,10 @@ macro_rules! vec3_struct {
             }
         }
 
-        impl Div<f64> for $name {
+        impl Div<Float> for $name {
             type Output = Self;
 
-            fn div(self, value: f64) -> Self::Output {
+            fn div(self, value: Float) -> Self::Output {
                 Self {
                     $x: self.$x / value,
                     $y: self.$y / value,
@@ -241,17 +282,17 @@ macro_rules! vec3_struct {
             }
         }
 
-        impl MulAssign<f64> for $name {
-            fn mul_assign(&mut self, value: f64) {
+        impl MulAssign<Float> for $name {
+            fn mul_assign(&mut self, value: Float) {
                 self.$x *= value;
                 self.$y *= value;
                 self.$z *= value;
             }
         }
 
-        impl DivAssign<f64> for $name {
-            fn div_assign(&mut self, value: f64) {
-                *self *= 1_f64 / value;
+        impl DivAssign<Float> for $name {
+            fn div_assign(&mut self, value: Float) {
+                *self *= 1. / value;
             }
         }
 
@@ -274,13 +315,76 @@ vec3_struct!(Vec3, x, y, z, vec3);
 vec3_struct!(Color, red, green, blue, color);
 vec3_struct!(Point3, x, y, z, point3);
 
+impl Color {
+    /// Perceived brightness
+    pub fn luminance(&self) -> Float {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    /// Color of light given off by a black body at a temperature in kelvin, with luminance 1
+    ///
+    /// Candle light is about 1900K, household bulbs 2700K, noon daylight 5500K and an overcast
+    /// sky 6500K. Found by weighting `blackbody` with an analytic fit of the CIE color matching
+    /// functions
+    pub fn from_kelvin(kelvin: Float) -> Color {
+        if kelvin <= 0. {
+            return color!();
+        }
+        // Gaussian with different widths either side of the peak
+        let lobe = |lambda: Float, mean: Float, below: Float, above: Float| {
+            let sigma = if lambda < mean { below } else { above };
+            (-0.5 * ((lambda - mean) / sigma).powi(2)).exp()
+        };
+        let (mut x, mut y, mut z) = (0., 0., 0.);
+        for step in 0..=80 {
+            let lambda = 380. + 5. * step as Float;
+            let planck = blackbody(lambda, kelvin);
+            x += planck
+                * (1.056 * lobe(lambda, 599.8, 37.9, 31.0)
+                    + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+                    - 0.065 * lobe(lambda, 501.1, 20.4, 26.2));
+            y += planck
+                * (0.821 * lobe(lambda, 568.8, 46.9, 40.5)
+                    + 0.286 * lobe(lambda, 530.9, 16.3, 31.1));
+            z += planck
+                * (1.217 * lobe(lambda, 437.0, 11.8, 36.0)
+                    + 0.681 * lobe(lambda, 459.0, 26.0, 13.8));
+        }
+        if y <= 0. || !y.is_finite() {
+            return color!();
+        }
+        let (x, z) = (x / y, z / y);
+        // Linear sRGB from CIE XYZ, dropping colors outside the gamut
+        let rgb = color!(
+            (3.2406 * x - 1.5372 - 0.4986 * z).max(0.),
+            (-0.9689 * x + 1.8758 + 0.0415 * z).max(0.),
+            (0.0557 * x - 0.2040 + 1.0570 * z).max(0.)
+        );
+        rgb / rgb.luminance()
+    }
+}
+
+/// Spectral radiance of a black body from Planck's law, in watts per steradian per square meter
+/// per meter of wavelength
+pub fn blackbody(wavelength_nm: Float, kelvin: Float) -> Float {
+    const PLANCK: Float = 6.626_070_15e-34;
+    const LIGHT_SPEED: Float = 2.997_924_58e8;
+    const BOLTZMANN: Float = 1.380_649e-23;
+    if kelvin <= 0. {
+        return 0.;
+    }
+    let lambda = wavelength_nm * 1e-9;
+    2. * PLANCK * LIGHT_SPEED * LIGHT_SPEED
+        / (lambda.powi(5) * ((PLANCK * LIGHT_SPEED / (lambda * BOLTZMANN * kelvin)).exp() - 1.))
+}
+
 impl Vec3 {
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
         let v = *self;
         v - 2. * v.dot(normal) * normal
     }
 
-    pub fn refract(&self, normal: &Vec3, etai_over_etat: f64) -> Vec3 {
+    pub fn refract(&self, normal: &Vec3, etai_over_etat: Float) -> Vec3 {
         let uv = *self;
         let cos_theta = (-uv).dot(normal);
         let r_out_parallel = etai_over_etat * (cos_theta * normal + uv);
@@ -289,112 +393,844 @@ impl Vec3 {
     }
 }
 
+pub mod accel;
+pub mod animation;
+pub mod aov;
+pub mod atmosphere;
+pub mod background;
 pub mod camera;
+pub mod csg;
+pub mod debug;
+pub mod denoise;
+pub mod depth_map;
+pub mod error;
+pub mod filter;
+pub mod framebuffer;
+pub mod furnace;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod hittable;
 pub mod image;
+pub mod integrator;
+pub mod job;
+pub mod light;
+pub mod loader;
 pub mod material;
+pub mod outline;
+pub mod pdf;
+pub mod plugin;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod probe;
+pub mod progress;
 pub mod ray;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+pub mod sppm;
+pub mod temporal;
 pub mod texture;
+pub mod tile;
+pub mod transform;
+pub mod upsample;
 pub mod world;
 
+/// Settings that control the quality and look of a render
+pub struct RenderSettings {
+    pub samples_per_pixel: u32,
+    /// Maximum number of times a ray can bounce
+    pub max_depth: u32,
+    /// What rays that escape the scene see. Defaults to black
+    pub background: Box<dyn Background + Sync>,
+    /// Bounce after which paths are randomly ended based on how much light they carry
+    pub russian_roulette_depth: Option<u32>,
+    /// Splits each pixel's samples into this many batches and uses the median batch average,
+    /// which rejects rare very bright samples. Slightly darkens the image, so use an odd number of
+    /// at least 3 batches only when fireflies are a problem. Pixels with fewer samples than
+    /// batches use one batch per sample
+    pub outlier_batches: Option<u32>,
+    /// Seed for reproducible renders. Each render is different if `None`
+    pub seed: Option<u64>,
+    /// Where samples are taken in each pixel and along each path. Defaults to
+    /// `SamplerKind::Random`
+    pub sampler: SamplerKind,
+    /// Stops sampling each pixel once it's converged, replacing `samples_per_pixel` and
+    /// `outlier_batches`. Defaults to `None`
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Width and height of the square tiles the image is split into for rendering in parallel.
+    /// Defaults to 32
+    pub tile_size: u32,
+    /// How samples are weighted towards the pixels around them. Filters wider than
+    /// `PixelFilter::Box` ignore `outlier_batches`, and progressive renders always use a box.
+    /// Defaults to `PixelFilter::Box`
+    pub filter: PixelFilter,
+    /// How precisely progressive renders store the samples accumulated so far. Defaults to
+    /// `BufferPrecision::Double`
+    pub buffer_precision: BufferPrecision,
+    /// Tonemapping for 8-bit output, see `Image::tonemap`. Renders stay linear until then.
+    /// Defaults to `Tonemap::None`
+    pub tonemap: Tonemap,
+    /// Stops to brighten 8-bit output by before tonemapping. Defaults to 0
+    pub exposure: Float,
+    /// Makes the background transparent, for compositing renders over other images. Camera rays
+    /// that miss the scene add nothing to their pixel and lower its alpha, see `Image::alpha`.
+    /// The background is still seen in reflections, but fog in front of it is lost. Defaults to
+    /// false
+    pub alpha: bool,
+    /// Caps the light paths carry to remove fireflies, see `RadianceClamp::indirect`. Defaults to
+    /// `None`
+    pub clamp: Option<RadianceClamp>,
+    /// Structure built around the scene to find what rays hit. Defaults to
+    /// `AcceleratorKind::Bvh`; `AcceleratorKind::Grid` builds faster for quick previews
+    pub accelerator: AcceleratorKind,
+}
+
+/// Takes more samples in noisy pixels than in smooth ones
+///
+/// Tracks the mean and variance of each pixel's brightness, stopping once the mean is known to
+/// within `threshold` of itself with 95% confidence
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    /// Samples every pixel takes before checking whether it's converged
+    pub min_samples: u32,
+    pub max_samples: u32,
+    /// Relative error to stop at. 0.05 stops once the mean is within 5%
+    pub threshold: Float,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, max_samples: u32, threshold: Float) -> Self {
+        Self {
+            min_samples,
+            max_samples,
+            threshold,
+        }
+    }
+
+    /// Takes samples until the pixel converges, returning their mean and how many were taken
+    fn sample_pixel<T: PixelSample>(&self, mut sample: impl FnMut(u32) -> T) -> (T, u32) {
+        let max_samples = self.max_samples.max(1);
+        let min_samples = self.min_samples.clamp(2, max_samples);
+        let mut total = sample(0);
+        let mut stats = LuminanceStats::default();
+        stats.add(total.luminance());
+        while stats.count < max_samples {
+            let value = sample(stats.count);
+            total = [total, value].iter().copied().sum();
+            stats.add(value.luminance());
+            if stats.count >= min_samples && stats.relative_error() <= self.threshold {
+                break;
+            }
+        }
+        (total / stats.count as Float, stats.count)
+    }
+
+    /// Tiles to render in the next progressive pass, given how many samples each has and its
+    /// mean relative error
+    ///
+    /// Every tile takes `min_samples` first. After that only the noisiest unconverged tiles are
+    /// rendered, keeping enough to busy every thread. Empty once all tiles are done
+    fn prioritize(&self, samples: &[u32], errors: &[Float]) -> Vec<usize> {
+        let max_samples = self.max_samples.max(1);
+        let min_samples = self.min_samples.clamp(2, max_samples);
+        let warming_up: Vec<usize> = (0..samples.len())
+            .filter(|&index| samples[index] < min_samples)
+            .collect();
+        if !warming_up.is_empty() {
+            return warming_up;
+        }
+        let mut noisy: Vec<usize> = (0..samples.len())
+            .filter(|&index| samples[index] < max_samples && errors[index] > self.threshold)
+            .collect();
+        noisy.sort_by(|&a, &b| errors[b].total_cmp(&errors[a]));
+        let count = (samples.len() / PRIORITY_SHARE).max(rayon::current_num_threads());
+        noisy.truncate(count);
+        noisy
+    }
+}
+
+/// Progressive passes after the first `min_samples` render at most this share of the tiles
+const PRIORITY_SHARE: usize = 4;
+
+/// Running mean and sum of squared differences of a pixel's luminance, from Welford's method
+#[derive(Clone, Copy, Debug, Default)]
+struct LuminanceStats {
+    count: u32,
+    mean: Float,
+    squares: Float,
+}
+
+impl LuminanceStats {
+    fn add(&mut self, luminance: Float) {
+        self.count += 1;
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as Float;
+        self.squares += delta * (luminance - self.mean);
+    }
+
+    /// Error in the mean relative to itself, with 95% confidence
+    fn relative_error(&self) -> Float {
+        if self.count < 2 {
+            return Float::INFINITY;
+        }
+        let variance = self.squares / (self.count - 1) as Float;
+        let error = 1.96 * (variance / self.count as Float).sqrt();
+        // Allow some absolute error so black pixels don't need to be exact
+        error / self.mean.abs().max(1e-3)
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            samples_per_pixel: 100,
+            max_depth: 50,
+            background: Box::new(SolidBackground::new(color!())),
+            russian_roulette_depth: Some(5),
+            outlier_batches: None,
+            seed: None,
+            sampler: SamplerKind::Random,
+            adaptive: None,
+            tile_size: 32,
+            filter: PixelFilter::Box,
+            buffer_precision: BufferPrecision::Double,
+            tonemap: Tonemap::None,
+            exposure: 0.,
+            alpha: false,
+            clamp: None,
+            accelerator: AcceleratorKind::Bvh,
+        }
+    }
+}
+
 pub fn raytrace_image(
     world: World,
     camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
     image_width: u32,
     image_height: u32,
-) -> Image {
-    let aspect_ratio = image_width as f64 / image_height as f64;
-    let samples_per_pixel = 10000;
-    let camera = Camera::new(&camera_settings, aspect_ratio);
+) -> Result<Image, Error> {
+    let mut world = world;
+    prepare_world(&mut world, &camera_settings, render_settings)?;
+    let (data, alpha) = render_pixels(
+        &world,
+        &camera_settings,
+        render_settings,
+        image_width,
+        image_height,
+        |ray, world, ctx| integrator.li(ray, world, render_settings, ctx),
+    );
+
+    Ok(Image {
+        width: image_width,
+        height: image_height,
+        data,
+        alpha,
+    })
+}
+
+/// Like `raytrace_image`, also rendering the normal, depth, albedo and object ID passes
+pub fn raytrace_with_aovs(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    image_width: u32,
+    image_height: u32,
+) -> Result<(Image, Aovs), Error> {
+    let mut world = world;
+    prepare_world(&mut world, &camera_settings, render_settings)?;
+    let (data, alpha) = render_pixels(
+        &world,
+        &camera_settings,
+        render_settings,
+        image_width,
+        image_height,
+        |ray, world, ctx| {
+            let beauty = integrator.li(ray, world, render_settings, ctx);
+            AovSample::new(beauty, ray, world)
+        },
+    );
+    let image = |data: Vec<Color>| Image {
+        width: image_width,
+        height: image_height,
+        data,
+        alpha: alpha.clone(),
+    };
+    let gray = |value: Float| color!(value, value, value);
 
-    // Setup progress bar
-    let prog_bar = indicatif::ProgressBar::new(image_height as u64);
-    prog_bar.set_style(indicatif::ProgressStyle::default_bar().template(
-        "Rendering - Done {elapsed:>3} Estimated {eta:>3} {wide_bar} {pos:>4}/{len:4} Lines",
-    ));
-
-    // Setup tree
-    let tree = BvhNode::make_tree(
-        world.hittables,
-        camera_settings.t0,
-        camera_settings.t1,
-        &mut rand::thread_rng(),
+    // IDs can't be averaged, so take them from a single ray through each pixel's center
+    let camera = Camera::new(
+        &camera_settings,
+        image_width as Float / image_height as Float,
+    );
+    let object_ids = trace_pixel_centers(
+        &world,
+        &camera,
+        render_settings.seed,
+        image_width,
+        image_height,
+        |rec| rec.map_or(0., |rec| rec.object_id as Float),
     );
 
-    let data: Vec<Vec<Color>> = (0..image_height)
-        // Parallel iter over each line starting from the top
-        .into_par_iter()
-        .progress_with(prog_bar)
-        .map(|j| {
-            let mut rng = rand::thread_rng();
-            let uniform_unit = Uniform::from(-1.0..1.0);
-            (0..image_width)
-                // For each pixel along the line
-                .map(|i| {
-                    (0..samples_per_pixel)
-                        // For each sample
-                        .map(|_| {
-                            let u = (i as f64 + rng.sample::<f64, _>(Standard))
-                                / (image_width - 1) as f64;
-                            let v = (j as f64 + rng.sample::<f64, _>(Standard))
-                                / (image_height - 1) as f64;
-                            let ray = camera.get_ray(u, v, &mut rng, &uniform_unit);
-                            ray_color(&ray, &tree, &mut rng, &uniform_unit, 0)
-                        })
-                        .sum::<Color>()
-                        / samples_per_pixel as f64
-                })
-                .collect::<Vec<Color>>()
-        })
-        .collect();
+    let aovs = Aovs {
+        normal: image(data.iter().map(AovSample::normal).collect()),
+        depth: image(data.iter().map(|sample| gray(sample.depth())).collect()),
+        albedo: image(data.iter().map(AovSample::albedo).collect()),
+        object_id: image(object_ids),
+    };
+    Ok((
+        image(data.iter().map(|sample| sample.beauty).collect()),
+        aovs,
+    ))
+}
 
-    let data = data.into_iter().rev().collect();
+/// What `render_mask` marks each pixel with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskKind {
+    /// 1 where something is seen through the pixel's center, 0 where only the background is
+    Coverage,
+    /// Number of the object seen through the pixel's center, see `HitRecord::object_id`. 0 for
+    /// the background
+    ObjectId,
+}
 
-    Image {
+/// Quick render of which pixels the scene covers, or which object each shows, in all three
+/// channels
+///
+/// Traces one unshaded camera ray through the center of each pixel, with a pinhole lens at the
+/// start of the shutter interval, so masks are sharp and the same every time. Useful for mattes
+/// and for checking cameras and geometry line up. Fails if the BVH can't be built
+pub fn render_mask(
+    world: World,
+    camera_settings: CameraSettings,
+    kind: MaskKind,
+    image_width: u32,
+    image_height: u32,
+) -> Result<Image, Error> {
+    let mut world = world;
+    world.build_bvh(camera_settings.t0, camera_settings.t1)?;
+    let pinhole = CameraSettings {
+        aperture: 0.,
+        t1: camera_settings.t0,
+        ..camera_settings
+    };
+    let camera = Camera::new(&pinhole, image_width as Float / image_height as Float);
+    let data = trace_pixel_centers(
+        &world,
+        &camera,
+        Some(0),
+        image_width,
+        image_height,
+        |rec| match (kind, rec) {
+            (_, None) => 0.,
+            (MaskKind::Coverage, Some(_)) => 1.,
+            (MaskKind::ObjectId, Some(rec)) => rec.object_id as Float,
+        },
+    );
+    Ok(Image {
         width: image_width,
         height: image_height,
         data,
+        alpha: None,
+    })
+}
+
+/// `value` of what one camera ray through the center of each pixel first sees, in all three
+/// channels, with rows from the top
+fn trace_pixel_centers<F>(
+    world: &World,
+    camera: &Camera,
+    seed: Option<u64>,
+    image_width: u32,
+    image_height: u32,
+    value: F,
+) -> Vec<Color>
+where
+    F: Fn(Option<HitRecord>) -> Float + Sync,
+{
+    (0..image_height)
+        .into_par_iter()
+        .flat_map(|y| {
+            let value = &value;
+            (0..image_width).into_par_iter().map_init(
+                || SampleCtx::from_seed(seed),
+                move |ctx, i| {
+                    let j = image_height - 1 - y;
+                    ctx.start_pixel_with(i, j, pixel_rng(seed, i, j));
+                    let u = (i as Float + 0.5) / (image_width - 1) as Float;
+                    let v = (j as Float + 0.5) / (image_height - 1) as Float;
+                    let ray = camera.get_ray(u, v, ctx);
+                    let value = value(hit_visible(world, &ray, true).map(|(rec, _)| rec));
+                    color!(value, value, value)
+                },
+            )
+        })
+        .collect()
+}
+
+/// Builds the world's acceleration structure for the shutter interval
+fn prepare_world(
+    world: &mut World,
+    camera_settings: &CameraSettings,
+    render_settings: &RenderSettings,
+) -> Result<(), Error> {
+    let (t0, t1) = (camera_settings.t0, camera_settings.t1);
+    render_settings.accelerator.build(world, t0, t1)
+}
+
+/// Renders the image one sample per pixel at a time, calling `pass(image, passes)` after each
+/// pass with the average of the samples so far
+///
+/// Return `ControlFlow::Break` from `pass` to stop early, otherwise rendering stops after
+/// `samples_per_pixel` passes. Returns the last image passed to `pass`, or an error if the BVH
+/// can't be built.
+///
+/// With adaptive sampling, passes after the first `min_samples` only render the tiles with the
+/// highest estimated error, so the image improves fastest where it's noisiest, and rendering
+/// stops once every tile has converged. Outlier rejection is ignored. Each pass takes the next
+/// sample of every pixel's sampler, so the result doesn't depend on the tile size
+pub fn render_progressive<P>(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    image_width: u32,
+    image_height: u32,
+    mut pass: P,
+) -> Result<Image, Error>
+where
+    P: FnMut(&Image, u32) -> ControlFlow<()>,
+{
+    let aspect_ratio = image_width as Float / image_height as Float;
+    let camera = Camera::new(&camera_settings, aspect_ratio);
+    let tiles = Tile::split(image_width, image_height, render_settings.tile_size);
+    let mut world = world;
+    prepare_world(&mut world, &camera_settings, render_settings)?;
+    let world = &world;
+
+    let mut buffer = FrameBuffer::new(image_width, image_height, render_settings.buffer_precision);
+    // Only adaptive sampling needs the spread of each pixel's samples
+    let pixel_count = match render_settings.adaptive {
+        Some(_) => (image_width * image_height) as usize,
+        None => 0,
+    };
+    let mut stats = vec![LuminanceStats::default(); pixel_count];
+    // Samples in each pixel whose camera ray hit the scene, for its alpha
+    let mut hits = if render_settings.alpha {
+        vec![0; (image_width * image_height) as usize]
+    } else {
+        Vec::new()
+    };
+    let mut tile_samples = vec![0; tiles.len()];
+    // Unseeded renders pick a seed up front, so each pixel's sampler is the same every pass
+    let seed = render_settings.seed.unwrap_or_else(rand::random);
+    let mut image = Image::new(image_width, image_height);
+    let mut passes = 0;
+    loop {
+        let selected = match &render_settings.adaptive {
+            Some(adaptive) => {
+                let errors: Vec<Float> = tiles
+                    .iter()
+                    .map(|tile| {
+                        let sum: Float = tile
+                            .pixels()
+                            .map(|(x, y)| stats[(y * image_width + x) as usize].relative_error())
+                            .sum();
+                        sum / tile.pixel_count() as Float
+                    })
+                    .collect();
+                adaptive.prioritize(&tile_samples, &errors)
+            }
+            None if passes < render_settings.samples_per_pixel.max(1) => (0..tiles.len()).collect(),
+            None => Vec::new(),
+        };
+        if selected.is_empty() {
+            break;
+        }
+        let rendered: Vec<Vec<(Color, bool)>> = selected
+            .par_iter()
+            .map(|&index| {
+                let tile = &tiles[index];
+                let sample_index = tile_samples[index];
+                let mut ctx = render_settings
+                    .sampler
+                    .create_ctx(render_settings.samples_per_pixel, seeded_rng(Some(seed)));
+                tile.pixels()
+                    .map(|(i, y)| {
+                        let j = image_height - 1 - y;
+                        // Every pass starts the pixel the same way so its samples stay spread
+                        // out, then takes its own random numbers for the sample
+                        ctx.start_pixel_with(i, j, pixel_rng(Some(seed), i, j));
+                        *ctx.rng() = sample_rng(seed, i, j, sample_index);
+                        ctx.start_sample(sample_index);
+                        let (x, y) = ctx.pixel_sample();
+                        let u = (i as Float + x) / (image_width - 1) as Float;
+                        let v = (j as Float + y) / (image_height - 1) as Float;
+                        let ray = camera.get_ray(u, v, &mut ctx);
+                        if covers(world, &ray, render_settings) {
+                            let color = integrator.li(&ray, world, render_settings, &mut ctx);
+                            (color, true)
+                        } else {
+                            (color!(), false)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        for (&index, pixels) in selected.iter().zip(rendered) {
+            for ((x, y), (color, hit)) in tiles[index].pixels().zip(pixels) {
+                let pixel = (y * image_width + x) as usize;
+                buffer.add(pixel, color);
+                if let Some(hits) = hits.get_mut(pixel) {
+                    *hits += hit as u32;
+                }
+                if let Some(stats) = stats.get_mut(pixel) {
+                    stats.add(color.luminance());
+                }
+            }
+            tile_samples[index] += 1;
+        }
+        image = buffer.to_image();
+        if render_settings.alpha {
+            let alpha = hits.iter().enumerate();
+            let alpha =
+                alpha.map(|(pixel, &hits)| hits as Float / buffer.count(pixel).max(1) as Float);
+            image.alpha = Some(alpha.collect());
+        }
+        passes += 1;
+        if let ControlFlow::Break(()) = pass(&image, passes) {
+            break;
+        }
+    }
+    Ok(image)
+}
+
+/// Width of images from `render_thumbnail`
+pub const THUMBNAIL_WIDTH: u32 = 160;
+/// Height of images from `render_thumbnail`
+pub const THUMBNAIL_HEIGHT: u32 = 90;
+/// Most samples per pixel `render_thumbnail` takes
+pub const THUMBNAIL_SAMPLES: u32 = 8;
+/// Most bounces in `render_thumbnail`
+const THUMBNAIL_MAX_DEPTH: u32 = 8;
+
+/// Quick, noisy render of the scene at `THUMBNAIL_WIDTH` by `THUMBNAIL_HEIGHT`, for scene
+/// browsers and previews
+///
+/// Renders progressively with at most `THUMBNAIL_SAMPLES` random samples per pixel and
+/// `THUMBNAIL_MAX_DEPTH` bounces, calling `pass` after each pass like `render_progressive`, so a
+/// thumbnail can be shown while it sharpens. Adaptive sampling is turned off. Returns the last
+/// image passed to `pass`
+pub fn render_thumbnail<P>(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    pass: P,
+) -> Result<Image, Error>
+where
+    P: FnMut(&Image, u32) -> ControlFlow<()>,
+{
+    let render_settings = RenderSettings {
+        samples_per_pixel: render_settings.samples_per_pixel.min(THUMBNAIL_SAMPLES),
+        max_depth: render_settings.max_depth.min(THUMBNAIL_MAX_DEPTH),
+        sampler: SamplerKind::Random,
+        adaptive: None,
+        // Small tiles so the few pixels are still shared between threads
+        tile_size: 16,
+        ..render_settings
+    };
+    render_progressive(
+        world,
+        camera_settings,
+        &render_settings,
+        integrator,
+        THUMBNAIL_WIDTH,
+        THUMBNAIL_HEIGHT,
+        pass,
+    )
+}
+
+/// Renders the scene with the path tracer, keeping each type of light path in its own image
+///
+/// The images add up to the full render, so they can be rebalanced when compositing
+pub fn raytrace_light_paths(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    image_width: u32,
+    image_height: u32,
+) -> Result<Vec<(PathType, Image)>, Error> {
+    let mut world = world;
+    prepare_world(&mut world, &camera_settings, render_settings)?;
+    let (data, alpha) = render_pixels(
+        &world,
+        &camera_settings,
+        render_settings,
+        image_width,
+        image_height,
+        |ray, world, ctx| PathIntegrator.light_paths(ray, world, render_settings, ctx),
+    );
+
+    let images = PathType::ALL.iter().map(|&path_type| {
+        let data = data.iter().map(|paths| paths[path_type]).collect();
+        let image = Image {
+            width: image_width,
+            height: image_height,
+            data,
+            alpha: alpha.clone(),
+        };
+        (path_type, image)
+    });
+    Ok(images.collect())
+}
+
+/// Values that can be averaged over the samples of a pixel
+pub trait PixelSample:
+    Copy + Default + Send + Sum + Mul<Float, Output = Self> + Div<Float, Output = Self>
+{
+    /// Brightness used to compare samples
+    fn luminance(&self) -> Float;
+}
+
+impl PixelSample for Color {
+    fn luminance(&self) -> Float {
+        Color::luminance(self)
     }
 }
 
-const MAX_CHILD_RAY_DEPTH: u32 = 50;
-
-fn ray_color(
-    ray: &Ray,
-    world: &BvhNode,
-    rng: &mut ThreadRng,
-    uniform_unit: &Uniform<f64>,
-    depth: u32,
-) -> Color {
-    if depth >= MAX_CHILD_RAY_DEPTH {
-        return color!();
+impl PixelSample for LightPaths {
+    fn luminance(&self) -> Float {
+        self.total().luminance()
+    }
+}
+
+/// A sample along with whether its camera ray hit the scene, averaged into the pixel's alpha
+#[derive(Clone, Copy, Default)]
+struct Covered<T> {
+    value: T,
+    alpha: Float,
+}
+
+impl<T: PixelSample> Sum for Covered<T> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, sample| Covered {
+            value: [total.value, sample.value].iter().copied().sum(),
+            alpha: total.alpha + sample.alpha,
+        })
     }
-    if let Some(rec) = world.hit(ray, 0.001, std::f64::INFINITY) {
-        let emitted = rec.material.emitted(rec.u, rec.v, rec.point);
-        if let Some((ray, attenuation)) = rec.material.scatter(ray, &rec, rng, uniform_unit) {
-            return emitted + attenuation * ray_color(&ray, world, rng, uniform_unit, depth + 1);
+}
+
+impl<T: PixelSample> Mul<Float> for Covered<T> {
+    type Output = Self;
+
+    fn mul(self, value: Float) -> Self {
+        Covered {
+            value: self.value * value,
+            alpha: self.alpha * value,
         }
-        return emitted;
     }
+}
 
-    color!(0., 0., 0.)
-    // let unit_dir = ray.dir.unit_vector();
-    // let t = 0.5 * (unit_dir.y + 1.);
-    // ((1.0 - t) * color!(1.0, 1.0, 1.0)) + (t * color!(0.5, 0.7, 1.0))
+impl<T: PixelSample> Div<Float> for Covered<T> {
+    type Output = Self;
+
+    fn div(self, value: Float) -> Self {
+        Covered {
+            value: self.value / value,
+            alpha: self.alpha / value,
+        }
+    }
 }
 
-fn rand_unit_vector(rng: &mut ThreadRng, uniform_unit: &Uniform<f64>) -> Point3 {
-    let a: f64 = rng.sample::<f64, _>(Standard) * 2. * std::f64::consts::PI;
-    let z = uniform_unit.sample(rng);
-    let r = (1. - z * z).sqrt();
-    Point3 {
-        x: r * a.cos(),
-        y: r * a.sin(),
-        z,
+impl<T: PixelSample> PixelSample for Covered<T> {
+    fn luminance(&self) -> Float {
+        self.value.luminance()
     }
 }
 
-fn schlick(cosine: f64, ref_idx: f64) -> f64 {
+/// Whether a camera ray counts towards its pixel's alpha, which is always the case without
+/// `RenderSettings::alpha`
+fn covers(world: &World, ray: &Ray, render_settings: &RenderSettings) -> bool {
+    !render_settings.alpha || hit_visible(world, ray, true).is_some()
+}
+
+/// Averages `sample` over the samples of each pixel of a world ready to render, see
+/// `prepare_world`, returning pixels in rows from the top and
+/// their alpha if `render_settings.alpha` is set
+///
+/// The image is rendered in tiles in parallel, so slow parts of the scene are spread across
+/// threads. With a filter wider than a pixel, samples are instead splatted onto the pixels
+/// around them, weighted by the filter
+fn render_pixels<T, F>(
+    world: &World,
+    camera_settings: &CameraSettings,
+    render_settings: &RenderSettings,
+    image_width: u32,
+    image_height: u32,
+    sample: F,
+) -> (Vec<T>, Option<Vec<Float>>)
+where
+    T: PixelSample,
+    F: Fn(&Ray, &World, &mut SampleCtx) -> T + Sync,
+{
+    let aspect_ratio = image_width as Float / image_height as Float;
+    let samples_per_pixel = render_settings.samples_per_pixel;
+    // Most samples any pixel can take
+    let max_samples = match render_settings.adaptive {
+        Some(adaptive) => adaptive.max_samples,
+        None => samples_per_pixel,
+    };
+    let camera = Camera::new(camera_settings, aspect_ratio);
+    let tiles = Tile::split(image_width, image_height, render_settings.tile_size);
+
+    // Time a sample on a few pixels of each line, so the progress bar knows which tiles are slow
+    let line_costs = estimate_line_costs(image_height, image_width, |j, i| {
+        let mut ctx = SampleCtx::from_seed(Some(j as u64));
+        let u = i as Float / (image_width - 1) as Float;
+        let v = j as Float / (image_height - 1) as Float;
+        let ray = camera.get_ray(u, v, &mut ctx);
+        sample(&ray, world, &mut ctx);
+    });
+    let tile_costs = tiles
+        .iter()
+        .map(|tile| {
+            // Lines are counted from the bottom
+            let lines = image_height - tile.y - tile.height..image_height - tile.y;
+            lines.map(|j| line_costs[j as usize]).sum::<f64>() / tile.height as f64
+        })
+        .collect();
+    let planned_samples = tiles
+        .iter()
+        .map(|tile| tile.pixel_count() as u64 * max_samples as u64)
+        .collect();
+    let progress = RenderProgress::new(tile_costs, planned_samples);
+    let filter = render_settings.filter;
+    let margin = (filter.radius() - 0.5).ceil() as u32;
+
+    let rendered: Vec<_> = tiles
+        .par_iter()
+        .enumerate()
+        .map(|(index, tile)| {
+            let mut ctx = render_settings
+                .sampler
+                .create_ctx(max_samples, seeded_rng(render_settings.seed));
+            let mut samples_taken = 0;
+            let mut splats = if filter.splats() {
+                Some(SplatBuffer::new(tile.expand(
+                    margin,
+                    image_width,
+                    image_height,
+                )))
+            } else {
+                None
+            };
+            let pixels = tile
+                .pixels()
+                // For each pixel in the tile
+                .map(|(i, y)| {
+                    let j = image_height - 1 - y;
+                    // Seed each pixel separately so the result doesn't depend on scheduling or
+                    // tile size
+                    ctx.start_pixel_with(i, j, pixel_rng(render_settings.seed, i, j));
+                    // Takes the `k`th sample of the pixel
+                    let mut take_sample = |k: u32| {
+                        ctx.start_sample(k);
+                        let (x, y) = ctx.pixel_sample();
+                        let u = (i as Float + x) / (image_width - 1) as Float;
+                        let v = (j as Float + y) / (image_height - 1) as Float;
+                        let ray = camera.get_ray(u, v, &mut ctx);
+                        // Rays that show the transparent background add nothing
+                        let value = if covers(world, &ray, render_settings) {
+                            Covered {
+                                value: sample(&ray, world, &mut ctx),
+                                alpha: 1.,
+                            }
+                        } else {
+                            Covered::default()
+                        };
+                        if let Some(splats) = &mut splats {
+                            // Rows count from the top of the image
+                            let top = image_height as Float - (j as Float + y);
+                            splats.splat(&filter, i as Float + x, top, value);
+                        }
+                        value
+                    };
+                    match (render_settings.adaptive, render_settings.outlier_batches) {
+                        (Some(adaptive), _) => {
+                            let (mean, count) = adaptive.sample_pixel(take_sample);
+                            samples_taken += count as u64;
+                            mean
+                        }
+                        (None, Some(batches)) if batches > 1 && samples_per_pixel > 1 => {
+                            // Every batch takes at least one sample, and the first few take
+                            // one more so all the pixel's samples are used
+                            let batches = batches.min(samples_per_pixel);
+                            let batch_size = samples_per_pixel / batches;
+                            let remainder = samples_per_pixel % batches;
+                            samples_taken += samples_per_pixel as u64;
+                            let mut means: Vec<Covered<T>> = (0..batches)
+                                .map(|batch| {
+                                    let first = batch * batch_size + batch.min(remainder);
+                                    let size = batch_size + (batch < remainder) as u32;
+                                    let batch = (first..first + size).map(&mut take_sample);
+                                    batch.sum::<Covered<T>>() / size as Float
+                                })
+                                .collect();
+                            means.sort_by(|a, b| a.luminance().total_cmp(&b.luminance()));
+                            means[means.len() / 2]
+                        }
+                        _ => {
+                            samples_taken += samples_per_pixel as u64;
+                            (0..samples_per_pixel).map(take_sample).sum::<Covered<T>>()
+                                / samples_per_pixel as Float
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            progress.finish_tile(index, samples_taken);
+            (pixels, splats)
+        })
+        .collect();
+    progress.finish();
+
+    let pixel_count = (image_width * image_height) as usize;
+    let data = if filter.splats() {
+        // Add up the tiles in order so the result doesn't depend on scheduling
+        let mut sums = vec![(Covered::default(), 0.); pixel_count];
+        for (_, splats) in rendered {
+            splats
+                .expect("Tiles splat with wide filters")
+                .add_to(&mut sums, image_width);
+        }
+        sums.into_iter()
+            .map(|(sum, weight)| {
+                if weight > 0. {
+                    sum / weight
+                } else {
+                    Covered::default()
+                }
+            })
+            .collect()
+    } else {
+        // Copy each tile into place
+        let mut data = vec![Covered::default(); pixel_count];
+        for (tile, (pixels, _)) in tiles.iter().zip(rendered) {
+            for ((x, y), pixel) in tile.pixels().zip(pixels) {
+                data[(y * image_width + x) as usize] = pixel;
+            }
+        }
+        data
+    };
+    let alpha = render_settings
+        .alpha
+        .then(|| data.iter().map(|pixel| pixel.alpha).collect());
+    (data.into_iter().map(|pixel| pixel.value).collect(), alpha)
+}
+
+fn schlick(cosine: Float, ref_idx: Float) -> Float {
     let r0 = (1. - ref_idx) / (1. + ref_idx);
     let r0 = r0 * r0;
     r0 + (1. - r0) * (1. - cosine).powf(5.)
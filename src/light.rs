@@ -0,0 +1,74 @@
+use crate::consts::PI;
+use crate::pdf::sample_cone;
+use crate::sampler::SampleRng;
+use crate::{Color, Float, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Luminous efficacy of light at 555nm, where the eye is most sensitive, in lumens per watt
+pub const LUMENS_PER_WATT: Float = 683.;
+
+/// Total light given off by an emitter
+///
+/// Scene units are taken as metres and radiance as watts per square metre per steradian
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightPower {
+    Watts(Float),
+    /// Visible light output, as printed on bulbs. Converted at `LUMENS_PER_WATT`
+    Lumens(Float),
+}
+
+impl LightPower {
+    pub fn watts(self) -> Float {
+        match self {
+            LightPower::Watts(watts) => watts,
+            LightPower::Lumens(lumens) => lumens / LUMENS_PER_WATT,
+        }
+    }
+}
+
+/// A light infinitely far away, like the sun
+///
+/// Lights the whole scene from a small cone of directions so shadows get a soft penumbra
+pub struct SunLight {
+    /// Direction towards the sun
+    pub direction: Vec3,
+    /// Apparent size of the sun in degrees. The real sun is about 0.53
+    pub angular_diameter: Float,
+    /// Light arriving on a surface facing the sun
+    pub irradiance: Color,
+}
+
+impl SunLight {
+    pub fn new(direction: Vec3, angular_diameter: Float, irradiance: Color) -> Self {
+        Self {
+            direction: direction.unit_vector(),
+            angular_diameter,
+            irradiance,
+        }
+    }
+
+    fn cos_max(&self) -> Float {
+        (self.angular_diameter.to_radians() / 2.).cos()
+    }
+
+    /// Solid angle covered by the sun's disc
+    pub fn solid_angle(&self) -> Float {
+        2. * PI * (1. - self.cos_max())
+    }
+
+    /// Light seen looking along `dir`
+    pub fn radiance(&self, dir: &Vec3) -> Color {
+        let solid_angle = self.solid_angle();
+        if solid_angle > 0. && dir.unit_vector().dot(&self.direction) >= self.cos_max() {
+            self.irradiance / solid_angle
+        } else {
+            color!()
+        }
+    }
+
+    /// Picks a random direction towards the sun's disc
+    pub fn sample_direction(&self, rng: &mut SampleRng) -> Vec3 {
+        sample_cone(&self.direction, self.cos_max(), rng)
+    }
+}
@@ -0,0 +1,3 @@
+//! Loaders for scene data made in other programs
+
+pub mod obj;
@@ -0,0 +1,297 @@
+//! Wavefront OBJ loading
+//!
+//! Supports positions, normals, texture coordinates and polygon faces, along with `.mtl` material
+//! libraries. Materials become `Lambertian` using `Kd`/`map_Kd`, or `Metal` using `Ks`/`Ns` when
+//! `illum` is 3 (reflective)
+
+use crate::error::Error;
+use crate::hittable::TriangleMesh;
+use crate::material::{Lambertian, Material, Metal};
+use crate::texture::{ImageTexture, SolidColor};
+use crate::{Color, Float, Point3, Vec3};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Loads an OBJ file as one mesh per material
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<TriangleMesh>, Error> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials = HashMap::new();
+    // Faces grouped by material name, keeping the order materials were first used
+    let mut groups: Vec<(String, MeshBuilder)> = vec![(String::new(), MeshBuilder::default())];
+
+    for line in source.lines() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("v") => {
+                let [x, y, z] = parse_floats(parts)?;
+                positions.push(point3!(x, y, z));
+            }
+            Some("vn") => {
+                let [x, y, z] = parse_floats(parts)?;
+                normals.push(vec3!(x, y, z).unit_vector());
+            }
+            Some("vt") => {
+                let [u, v] = parse_floats(parts)?;
+                uvs.push((u, v));
+            }
+            Some("f") => {
+                let corners = parts
+                    .map(|part| Corner::parse(part, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let builder = &mut groups.last_mut().unwrap().1;
+                // Triangulate polygons as a fan
+                for i in 1..corners.len().saturating_sub(1) {
+                    let triangle = [
+                        builder.vertex(corners[0], &positions, &normals, &uvs),
+                        builder.vertex(corners[i], &positions, &normals, &uvs),
+                        builder.vertex(corners[i + 1], &positions, &normals, &uvs),
+                    ];
+                    builder.indices.push(triangle);
+                }
+            }
+            Some("usemtl") => {
+                let name = parts.collect::<Vec<_>>().join(" ");
+                groups.push((name, MeshBuilder::default()));
+            }
+            Some("mtllib") => {
+                for file in parts {
+                    load_mtl(&dir.join(file), &mut materials)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Merge groups using the same material
+    let mut merged: Vec<(String, MeshBuilder)> = Vec::new();
+    for (name, builder) in groups {
+        match merged.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, existing)) => existing.append(builder),
+            None => merged.push((name, builder)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .filter(|(_, builder)| !builder.indices.is_empty())
+        .map(|(name, builder)| {
+            let material = match materials.get(&name) {
+                Some(mtl) => mtl.to_material()?,
+                None => Arc::new(Lambertian::new(SolidColor::new(color!(0.8, 0.8, 0.8)))),
+            };
+            Ok(builder.build(material))
+        })
+        .collect()
+}
+
+fn parse_floats<'a, I: Iterator<Item = &'a str>, const N: usize>(
+    mut parts: I,
+) -> Result<[Float; N], Error> {
+    let mut values = [0.; N];
+    for value in values.iter_mut() {
+        let part = parts
+            .next()
+            .ok_or_else(|| Error::Parse("Missing value in obj file".to_owned()))?;
+        *value = part
+            .parse()
+            .map_err(|_| Error::Parse(format!("Invalid number `{}` in obj file", part)))?;
+    }
+    Ok(values)
+}
+
+/// The indices of one corner of a face
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl Corner {
+    /// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn parse(part: &str, positions: usize, uvs: usize, normals: usize) -> Result<Self, Error> {
+        let mut indices = part.split('/');
+        let position = indices.next().and_then(|i| resolve_index(i, positions));
+        let uv = indices.next().and_then(|i| resolve_index(i, uvs));
+        let normal = indices.next().and_then(|i| resolve_index(i, normals));
+        Ok(Corner {
+            position: position
+                .ok_or_else(|| Error::Parse(format!("Invalid face `{}` in obj file", part)))?,
+            uv,
+            normal,
+        })
+    }
+}
+
+/// Turns a 1-based or negative relative index into a 0-based index, or `None` if it's out of range
+fn resolve_index(index: &str, count: usize) -> Option<usize> {
+    let index: i64 = index.parse().ok()?;
+    let index = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    usize::try_from(index).ok().filter(|&index| index < count)
+}
+
+/// Collects the vertices of a mesh, deduplicating corners shared between faces
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(Float, Float)>,
+    indices: Vec<[usize; 3]>,
+    has_normals: bool,
+    has_uvs: bool,
+    lookup: HashMap<Corner, usize>,
+}
+
+impl MeshBuilder {
+    fn vertex(
+        &mut self,
+        corner: Corner,
+        positions: &[Point3],
+        normals: &[Vec3],
+        uvs: &[(Float, Float)],
+    ) -> usize {
+        if let Some(&index) = self.lookup.get(&corner) {
+            return index;
+        }
+        let index = self.positions.len();
+        self.positions.push(positions[corner.position]);
+        match corner.normal {
+            Some(normal) => {
+                self.normals.push(normals[normal]);
+                self.has_normals = true;
+            }
+            None => self.normals.push(Vec3::default()),
+        }
+        match corner.uv {
+            Some(uv) => {
+                self.uvs.push(uvs[uv]);
+                self.has_uvs = true;
+            }
+            None => self.uvs.push((0., 0.)),
+        }
+        self.lookup.insert(corner, index);
+        index
+    }
+
+    fn append(&mut self, other: MeshBuilder) {
+        let offset = self.positions.len();
+        self.positions.extend(other.positions);
+        self.normals.extend(other.normals);
+        self.uvs.extend(other.uvs);
+        self.indices.extend(
+            other
+                .indices
+                .iter()
+                .map(|[a, b, c]| [a + offset, b + offset, c + offset]),
+        );
+        self.has_normals |= other.has_normals;
+        self.has_uvs |= other.has_uvs;
+        self.lookup.clear();
+    }
+
+    fn build(self, material: Arc<dyn Material + Send + Sync>) -> TriangleMesh {
+        // Only keep normals and uvs if the whole mesh has them
+        let normals = if self.has_normals && self.normals.iter().all(|n| n.length_squared() > 0.) {
+            self.normals
+        } else {
+            Vec::new()
+        };
+        let uvs = if self.has_uvs { self.uvs } else { Vec::new() };
+        TriangleMesh::new_shared(self.positions, normals, uvs, self.indices, material)
+    }
+}
+
+/// A material from a `.mtl` file
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    shininess: Float,
+    illum: u32,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: color!(0.8, 0.8, 0.8),
+            specular: color!(),
+            shininess: 0.,
+            illum: 2,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Result<Arc<dyn Material + Send + Sync>, Error> {
+        if self.illum == 3 {
+            // Sharper highlights mean less fuzz
+            let fuzz = 1. - (self.shininess / 1000.).clamp(0., 1.);
+            return Ok(Arc::new(Metal::new(self.specular, fuzz)));
+        }
+        Ok(match &self.diffuse_map {
+            Some(path) => Arc::new(Lambertian::new(ImageTexture::new(path)?)),
+            None => Arc::new(Lambertian::new(SolidColor::new(self.diffuse))),
+        })
+    }
+}
+
+fn load_mtl(path: &Path, materials: &mut HashMap<String, MtlMaterial>) -> Result<(), Error> {
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut current: Option<String> = None;
+    for line in source.lines() {
+        let mut parts = line.split_whitespace();
+        let keyword = parts.next();
+        if keyword == Some("newmtl") {
+            let name = parts.collect::<Vec<_>>().join(" ");
+            materials.insert(name.clone(), MtlMaterial::default());
+            current = Some(name);
+            continue;
+        }
+        let material = match current.as_ref().and_then(|name| materials.get_mut(name)) {
+            Some(material) => material,
+            None => continue,
+        };
+        match keyword {
+            Some("Kd") => {
+                let [r, g, b] = parse_floats(parts)?;
+                material.diffuse = color!(r, g, b);
+            }
+            Some("Ks") => {
+                let [r, g, b] = parse_floats(parts)?;
+                material.specular = color!(r, g, b);
+            }
+            Some("Ns") => {
+                let [ns] = parse_floats(parts)?;
+                material.shininess = ns;
+            }
+            Some("illum") => {
+                material.illum = parts
+                    .next()
+                    .and_then(|illum| illum.parse().ok())
+                    .unwrap_or(2);
+            }
+            Some("map_Kd") => {
+                // Options come before the file name so take the last part
+                if let Some(file) = parts.last() {
+                    material.diffuse_map = Some(dir.join(file));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
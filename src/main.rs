@@ -1,18 +1,356 @@
 #[macro_use]
 extern crate ray_tracing;
 
-use ray_tracing::camera::CameraSettings;
-use ray_tracing::image::Image;
-use ray_tracing::material::{Dielectric, Lambertian, Metal};
-use ray_tracing::raytrace_image;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ray_tracing::background::{Background, GradientBackground, SolidBackground};
+use ray_tracing::camera::{ApertureMask, CameraSettings};
+use ray_tracing::denoise::denoise;
+use ray_tracing::depth_map::{render_depth_map, DepthMapSettings};
+use ray_tracing::error::Error;
+use ray_tracing::image::{write_exr_layers, FileFormat, Image, RenderInfo, Tonemap};
+use ray_tracing::integrator::{PathIntegrator, RadianceClamp};
+use ray_tracing::job::Job;
+use ray_tracing::scene::SceneFile;
+use ray_tracing::upsample::{raytrace_upsampled, UpsampleSettings};
 use ray_tracing::world::World;
-use ray_tracing::{Color, Point3, Vec3};
+use ray_tracing::{Color, Float, Point3, Vec3};
+use ray_tracing::{MaskKind, RenderSettings};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 
-fn main() {
-    let start_time = std::time::Instant::now();
+#[derive(Parser)]
+#[command(about = "Renders scenes with a path tracer")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Renders one of the built-in scenes or a scene file
+    Render(RenderArgs),
+    /// Lists the built-in scenes
+    Scenes(ScenesArgs),
+    /// Saves a built-in scene to a scene file, keeping the layout of random scenes
+    Export(ExportArgs),
+    /// Renders the distance to a scene along parallel rays, for heightmaps and occlusion maps
+    Depth(DepthArgs),
+    /// Bundles a scene file with the files it reads into a job for render farm workers
+    Job(JobArgs),
+}
+
+#[derive(Args)]
+struct ScenesArgs {
+    /// Also render a quick thumbnail of each scene to a PNG named after it in this directory
+    #[arg(long)]
+    thumbnails: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Name of the scene, see `scenes`
+    scene: String,
+    /// Where to write the JSON scene file
+    out: PathBuf,
+}
+
+#[derive(Args)]
+struct JobArgs {
+    /// JSON scene file to bundle. Paths in it are read from the working directory
+    file: PathBuf,
+    /// Directory to write the job to, or a zip file if it ends in `.zip`
+    out: PathBuf,
+    /// First frame to render
+    #[arg(long, default_value_t = 0)]
+    first_frame: u32,
+    /// Number of frames to render
+    #[arg(long, default_value_t = 1)]
+    frames: u32,
+}
+
+#[derive(Args)]
+struct DepthArgs {
+    /// Name of the scene, see `scenes`
+    #[arg(long, default_value = "earth", conflicts_with = "file")]
+    scene: String,
+    /// JSON scene file to use instead of a built-in scene
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Direction the rays travel in. Defaults to straight down, giving a heightmap
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true, default_value = "0,-1,0")]
+    direction: Vec3,
+    #[arg(long, default_value_t = 512)]
+    width: u32,
+    #[arg(long, default_value_t = 512)]
+    height: u32,
+    /// EXR and HDR files keep the distances, 8-bit formats get them scaled from 1 at the nearest
+    /// surface to 0 at the furthest
+    #[arg(long, default_value = "depth.exr")]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// Name of the scene, see `scenes`
+    #[arg(long, default_value = "earth", conflicts_with = "file")]
+    scene: String,
+    /// JSON scene file to render instead of a built-in scene, see `scenes/`
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Defaults to 1920, or the width in the scene file
+    #[arg(long)]
+    width: Option<u32>,
+    /// Defaults to 1080, or the height in the scene file
+    #[arg(long)]
+    height: Option<u32>,
+    /// Samples per pixel. Defaults to 100, or the number in the scene file
+    #[arg(long)]
+    samples: Option<u32>,
+    /// Most times a ray can bounce. Defaults to 50, or the number in the scene file
+    #[arg(long)]
+    max_depth: Option<u32>,
+    /// Seed for a repeatable render
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value = "image.png")]
+    out: PathBuf,
+    /// Format to write. Guessed from the extension of `out` if not given
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Tonemapping for 8-bit formats
+    #[arg(long, value_enum, default_value = "none")]
+    tonemap: TonemapArg,
+    /// Stops to brighten output in 8-bit formats by
+    #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+    exposure: Float,
+    /// Make the background transparent in PNG, TIFF, BMP and EXR output
+    #[arg(long)]
+    alpha: bool,
+    /// Write 16 bits per channel to PNGs
+    #[arg(long)]
+    png16: bool,
+    /// Also write normal, depth, albedo and object ID layers. EXR output only
+    #[arg(long)]
+    aovs: bool,
+    /// Brightest light that has bounced more than once can be, to remove fireflies
+    #[arg(long)]
+    clamp: Option<Float>,
+    /// Denoise the image, guided by the normal, depth and albedo passes
+    #[arg(long)]
+    denoise: bool,
+    /// Render on the GPU, falling back to the CPU for scenes it can't handle. Needs the `gpu`
+    /// feature
+    #[arg(long, conflicts_with_all = ["aovs", "denoise"])]
+    gpu: bool,
+    /// Distance in front of the camera closer than which nothing is seen
+    #[arg(long)]
+    near: Option<Float>,
+    /// Distance in front of the camera beyond which nothing is seen
+    #[arg(long)]
+    far: Option<Float>,
+    /// Grayscale image to cut the lens to, so out of focus highlights take its shape
+    #[arg(long)]
+    aperture_mask: Option<PathBuf>,
+    /// Only trace one unshaded ray through each pixel, marking where the scene is seen or which
+    /// object is. Object IDs need EXR or HDR output
+    #[arg(long, value_enum, conflicts_with_all = ["aovs", "denoise", "gpu"])]
+    mask: Option<MaskArg>,
+    /// Trace at half resolution, then only trace noisy pixels and edges at full resolution, for
+    /// quick previews
+    #[arg(long, conflicts_with_all = ["aovs", "denoise", "gpu", "mask"])]
+    half_res: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MaskArg {
+    Coverage,
+    ObjectId,
+}
+
+impl From<MaskArg> for MaskKind {
+    fn from(mask: MaskArg) -> Self {
+        match mask {
+            MaskArg::Coverage => MaskKind::Coverage,
+            MaskArg::ObjectId => MaskKind::ObjectId,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TonemapArg {
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl From<TonemapArg> for Tonemap {
+    fn from(tonemap: TonemapArg) -> Self {
+        match tonemap {
+            TonemapArg::None => Tonemap::None,
+            TonemapArg::Reinhard => Tonemap::Reinhard,
+            TonemapArg::Aces => Tonemap::Aces,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Png,
+    Jpg,
+    Bmp,
+    Tiff,
+    /// Binary PPM
+    Ppm,
+    /// OpenEXR, keeping the full range of light
+    Exr,
+    /// Radiance HDR, keeping the full range of light
+    Hdr,
+}
+
+impl From<Format> for FileFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Png => FileFormat::Png,
+            Format::Jpg => FileFormat::Jpeg,
+            Format::Bmp => FileFormat::Bmp,
+            Format::Tiff => FileFormat::Tiff,
+            Format::Ppm => FileFormat::Ppm,
+            Format::Exr => FileFormat::Exr,
+            Format::Hdr => FileFormat::Hdr,
+        }
+    }
+}
+
+/// A built-in scene and the camera and background to view it with
+struct Scene {
+    name: &'static str,
+    description: &'static str,
+    world: fn() -> World,
+    camera: fn() -> CameraSettings,
+    /// Lit by a sky rather than only by lights in the scene
+    sky: bool,
+}
+
+const SCENES: &[Scene] = &[
+    Scene {
+        name: "cover",
+        description: "Lots of small spheres around three big ones",
+        world: World::cover_world,
+        camera: CameraSettings::cover_camera,
+        sky: true,
+    },
+    Scene {
+        name: "moving_cover",
+        description: "The cover scene with some spheres bouncing, for motion blur",
+        world: World::moving_cover_world,
+        camera: CameraSettings::cover_camera,
+        sky: true,
+    },
+    Scene {
+        name: "checkered_cover",
+        description: "The cover scene on a checkered floor",
+        world: World::checkered_cover_world,
+        camera: CameraSettings::cover_camera,
+        sky: true,
+    },
+    Scene {
+        name: "perlin_spheres",
+        description: "A marble sphere on turbulent noise ground",
+        world: World::perlin_spheres,
+        camera: CameraSettings::cover_camera,
+        sky: true,
+    },
+    Scene {
+        name: "earth",
+        description: "The earth lit by the sun through its atmosphere",
+        world: earth_world,
+        camera: earth_camera,
+        sky: false,
+    },
+    Scene {
+        name: "emissive_only",
+        description: "A sphere lit only by a glowing sphere",
+        world: World::emissive_only,
+        camera: CameraSettings::cover_camera,
+        sky: false,
+    },
+    Scene {
+        name: "cornell_box",
+        description: "The Cornell box",
+        world: World::cornell_box,
+        camera: CameraSettings::cornell_camera,
+        sky: false,
+    },
+    Scene {
+        name: "cornell_smoke",
+        description: "The Cornell box with blocks of smoke",
+        world: World::cornell_smoke,
+        camera: CameraSettings::cornell_camera,
+        sky: false,
+    },
+    Scene {
+        name: "cornell_cloud",
+        description: "The Cornell box with a cloud",
+        world: World::cornell_cloud,
+        camera: CameraSettings::cornell_camera,
+        sky: false,
+    },
+    Scene {
+        name: "cornell_fireball",
+        description: "The Cornell box with a fireball",
+        world: World::cornell_fireball,
+        camera: CameraSettings::cornell_camera,
+        sky: false,
+    },
+    Scene {
+        name: "caustic_sphere",
+        description: "A glass sphere focusing light onto a checkered floor",
+        world: World::caustic_sphere,
+        camera: CameraSettings::caustic_camera,
+        sky: false,
+    },
+    Scene {
+        name: "caustic_pool",
+        description: "Light through rippling water onto pool tiles",
+        world: World::caustic_pool,
+        camera: CameraSettings::pool_camera,
+        sky: false,
+    },
+    Scene {
+        name: "benchmark",
+        description: "A large grid of small spheres",
+        world: World::benchmark_world,
+        camera: CameraSettings::cover_camera,
+        sky: true,
+    },
+];
+
+impl Scene {
+    /// Default settings for rendering the scene
+    fn settings(&self) -> RenderSettings {
+        let background: Box<dyn Background + Sync> = if self.sky {
+            Box::new(GradientBackground::sky())
+        } else {
+            Box::new(SolidBackground::new(color!()))
+        };
+        RenderSettings {
+            samples_per_pixel: 100,
+            max_depth: 50,
+            background,
+            ..Default::default()
+        }
+    }
+}
+
+/// The earth scene, exiting if its texture can't be loaded
+fn earth_world() -> World {
+    or_exit(World::earth(), "Couldn't load the earth")
+}
 
-    // Do work
-    let camera = CameraSettings {
+fn earth_camera() -> CameraSettings {
+    CameraSettings {
         look_from: point3!(10., 4., 0.),
         look_at: point3!(),
         vup: vec3!(0., 1., 0.),
@@ -21,14 +359,374 @@ fn main() {
         focus_dist: 8.,
         t0: 0.,
         t1: 1.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    }
+}
+
+fn main() {
+    match Cli::parse().command {
+        Command::Render(args) => render(&args),
+        Command::Scenes(args) => {
+            for scene in SCENES {
+                println!("{:<18}{}", scene.name, scene.description);
+                if let Some(dir) = &args.thumbnails {
+                    write_thumbnail(scene, dir);
+                }
+            }
+        }
+        Command::Export(args) => {
+            let scene = find_scene(&args.scene);
+            let file = SceneFile::from_world(
+                &(scene.world)(),
+                &(scene.camera)(),
+                &scene.settings(),
+                1920,
+                1080,
+            );
+            let file = or_exit(file, &format!("Couldn't export `{}`", args.scene));
+            let saved = file.save(&args.out);
+            or_exit(saved, &format!("Couldn't save `{}`", args.out.display()));
+        }
+        Command::Depth(args) => depth(&args),
+        Command::Job(args) => {
+            let file = SceneFile::load(&args.file);
+            let file = or_exit(file, &format!("Couldn't load `{}`", args.file.display()));
+            let frames = (args.first_frame..args.first_frame + args.frames).collect();
+            let written = Job::new(file, frames).write(&args.out);
+            or_exit(written, &format!("Couldn't write `{}`", args.out.display()));
+        }
+    }
+}
+
+/// Unwraps `result`, or exits after printing `what` went wrong and why
+fn or_exit<T>(result: Result<T, Error>, what: &str) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("{}: {}", what, error);
+        process::exit(1);
+    })
+}
+
+fn find_scene(name: &str) -> &'static Scene {
+    match SCENES.iter().find(|scene| scene.name == name) {
+        Some(scene) => scene,
+        None => {
+            eprintln!("Unknown scene `{}`, see `scenes`", name);
+            process::exit(1);
+        }
+    }
+}
+
+/// Renders a thumbnail of `scene` to `dir`, named after the scene
+fn write_thumbnail(scene: &Scene, dir: &Path) {
+    let created = std::fs::create_dir_all(dir).map_err(Error::from);
+    or_exit(created, &format!("Couldn't create `{}`", dir.display()));
+    let settings = scene.settings();
+    let (tonemap, exposure) = (settings.tonemap, settings.exposure);
+    let image = ray_tracing::render_thumbnail(
+        (scene.world)(),
+        (scene.camera)(),
+        settings,
+        &PathIntegrator,
+        |_, _| ControlFlow::Continue(()),
+    );
+    let mut image = or_exit(image, &format!("Couldn't render `{}`", scene.name));
+    image.tonemap(tonemap, exposure);
+    let path = dir.join(format!("{}.png", scene.name));
+    let written = image.write_png(&path);
+    or_exit(written, &format!("Couldn't write `{}`", path.display()));
+}
+
+/// Parses three comma separated numbers, like `0,-1,0`
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let numbers: Result<Vec<Float>, _> = value.split(',').map(|n| n.trim().parse()).collect();
+    match numbers.map_err(|error| error.to_string())?.as_slice() {
+        &[x, y, z] => Ok(vec3!(x, y, z)),
+        _ => Err("expected three comma separated numbers".to_owned()),
+    }
+}
+
+fn depth(args: &DepthArgs) {
+    let format = match FileFormat::from_path(&args.out) {
+        Some(format) => format,
+        None => {
+            eprintln!("Can't tell the format of `{}`", args.out.display());
+            process::exit(1);
+        }
+    };
+    let world = match &args.file {
+        Some(path) => {
+            let scene = ray_tracing::scene::Scene::load(path);
+            or_exit(scene, &format!("Couldn't load `{}`", path.display())).world
+        }
+        None => (find_scene(&args.scene).world)(),
+    };
+    let direction = args.direction;
+    // Up in the image is -z when looking along y, like a map, and y otherwise
+    let vertical = direction.unit_vector()[1].abs() > 0.999;
+    let vup = if vertical {
+        vec3!(0., 0., -1.)
+    } else {
+        vec3!(0., 1., 0.)
+    };
+    let settings = DepthMapSettings {
+        direction,
+        vup,
+        bounds: None,
+        time: 0.,
+        width: args.width,
+        height: args.height,
+    };
+    let depth_map = or_exit(
+        render_depth_map(world, &settings),
+        "Couldn't render the depth map",
+    );
+    let image = if format.is_8_bit() {
+        depth_map.heightmap()
+    } else {
+        depth_map.image
     };
-    let world = World::earth();
-    let image = raytrace_image(world, camera, 1920, 1080);
-    //let image = create_cover();
-    image.write_png("image.png");
+    let written = image.write_as(&args.out, format);
+    or_exit(written, &format!("Couldn't write `{}`", args.out.display()));
+}
+
+fn render(args: &RenderArgs) {
+    let format = match args
+        .format
+        .map(FileFormat::from)
+        .or_else(|| FileFormat::from_path(&args.out))
+    {
+        Some(format) => format,
+        None => {
+            eprintln!(
+                "Can't tell the format of `{}`, use --format",
+                args.out.display()
+            );
+            process::exit(1);
+        }
+    };
+    if args.aovs && format != FileFormat::Exr {
+        eprintln!("--aovs needs EXR output");
+        process::exit(1);
+    }
+    if matches!(args.mask, Some(MaskArg::ObjectId)) && format.is_8_bit() {
+        eprintln!("--mask object-id needs EXR or HDR output");
+        process::exit(1);
+    }
 
-    // Print time
-    let end_time = std::time::Instant::now();
-    let duration = end_time - start_time;
+    let start_time = std::time::Instant::now();
+    let (name, world, mut camera, mut settings, width, height) = match &args.file {
+        Some(path) => {
+            let scene = ray_tracing::scene::Scene::load(path);
+            let scene = or_exit(scene, &format!("Couldn't load `{}`", path.display()));
+            let name = path.file_stem().map_or_else(
+                || "scene".to_owned(),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+            let width = args.width.unwrap_or(scene.width);
+            let height = args.height.unwrap_or(scene.height);
+            (
+                name,
+                scene.world,
+                scene.camera,
+                scene.settings,
+                width,
+                height,
+            )
+        }
+        None => {
+            let scene = find_scene(&args.scene);
+            let settings = scene.settings();
+            let width = args.width.unwrap_or(1920);
+            let height = args.height.unwrap_or(1080);
+            let (world, camera) = ((scene.world)(), (scene.camera)());
+            (
+                scene.name.to_owned(),
+                world,
+                camera,
+                settings,
+                width,
+                height,
+            )
+        }
+    };
+    for problem in world.validate() {
+        eprintln!("Warning: {}", problem);
+    }
+    if let Some(near) = args.near {
+        camera.near = near;
+    }
+    if let Some(far) = args.far {
+        camera.far = far;
+    }
+    if let Some(path) = &args.aperture_mask {
+        let mask = ApertureMask::load(path);
+        let mask = or_exit(mask, &format!("Couldn't load `{}`", path.display()));
+        camera.aperture_mask = Some(Arc::new(mask));
+    }
+    if let Some(samples) = args.samples {
+        settings.samples_per_pixel = samples;
+    }
+    if let Some(max_depth) = args.max_depth {
+        settings.max_depth = max_depth;
+    }
+    if args.seed.is_some() {
+        settings.seed = args.seed;
+    }
+    settings.tonemap = args.tonemap.into();
+    settings.exposure = args.exposure;
+    settings.alpha = args.alpha;
+    if let Some(max) = args.clamp {
+        settings.clamp = Some(RadianceClamp::indirect(max));
+    }
+    if let Some(mask) = args.mask {
+        let mask = ray_tracing::render_mask(world, camera, mask.into(), width, height);
+        let image = or_exit(mask, "Couldn't render");
+        println!("Took {:?}", start_time.elapsed());
+        let written = image.write_as(&args.out, format);
+        or_exit(written, &format!("Couldn't write `{}`", args.out.display()));
+        return;
+    }
+    if args.aovs || args.denoise {
+        let rendered = ray_tracing::raytrace_with_aovs(
+            world,
+            camera,
+            &settings,
+            &PathIntegrator,
+            width,
+            height,
+        );
+        let (mut image, aovs) = or_exit(rendered, "Couldn't render");
+        if args.denoise {
+            image = denoise(&image, &aovs);
+        }
+        let duration = start_time.elapsed();
+        println!("Took {:?}", duration);
+
+        let info = RenderInfo::new(&name, &settings, width, height, duration);
+        if !args.aovs {
+            write(image, &args.out, format, &info, &settings, args.png16);
+            return;
+        }
+        let layers = [
+            ("beauty", &image),
+            ("normal", &aovs.normal),
+            ("depth", &aovs.depth),
+            ("albedo", &aovs.albedo),
+            ("object_id", &aovs.object_id),
+        ];
+        let written = write_exr_layers(&args.out, &layers, Some(&info));
+        or_exit(written, &format!("Couldn't write `{}`", args.out.display()));
+        return;
+    }
+    if args.half_res {
+        let rendered = raytrace_upsampled(
+            world,
+            camera,
+            &settings,
+            &PathIntegrator,
+            width,
+            height,
+            &UpsampleSettings::default(),
+        );
+        let upsampled = or_exit(rendered, "Couldn't render");
+        let duration = start_time.elapsed();
+        println!(
+            "Took {:?}, tracing {:.0}% of pixels at full resolution",
+            duration,
+            upsampled.retraced_share() * 100.
+        );
+
+        let info = RenderInfo::new(&name, &settings, width, height, duration);
+        write(
+            upsampled.image,
+            &args.out,
+            format,
+            &info,
+            &settings,
+            args.png16,
+        );
+        return;
+    }
+    let gpu_image = if args.gpu {
+        render_gpu(&world, &camera, &settings, width, height)
+    } else {
+        None
+    };
+    let image = match gpu_image {
+        Some(image) => image,
+        None => {
+            #[cfg(not(feature = "preview"))]
+            let image = ray_tracing::raytrace_image(
+                world,
+                camera,
+                &settings,
+                &PathIntegrator,
+                width,
+                height,
+            );
+            #[cfg(feature = "preview")]
+            let image = ray_tracing::preview::render_with_preview(
+                world,
+                camera,
+                &settings,
+                &PathIntegrator,
+                width,
+                height,
+                "snapshot.png",
+            );
+            or_exit(image, "Couldn't render")
+        }
+    };
+    let duration = start_time.elapsed();
     println!("Took {:?}", duration);
+
+    let info = RenderInfo::new(&name, &settings, width, height, duration);
+    write(image, &args.out, format, &info, &settings, args.png16);
+}
+
+/// Renders on the GPU, or returns `None` after saying why it can't
+#[cfg(feature = "gpu")]
+fn render_gpu(
+    world: &World,
+    camera: &CameraSettings,
+    settings: &RenderSettings,
+    width: u32,
+    height: u32,
+) -> Option<Image> {
+    match ray_tracing::gpu::raytrace_gpu(world, camera, settings, width, height) {
+        Ok(image) => Some(image),
+        Err(reason) => {
+            eprintln!("Rendering on the CPU, as {}", reason);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "gpu"))]
+fn render_gpu(_: &World, _: &CameraSettings, _: &RenderSettings, _: u32, _: u32) -> Option<Image> {
+    eprintln!("Rendering on the CPU, as this was built without the `gpu` feature");
+    None
+}
+
+fn write(
+    mut image: Image,
+    path: &Path,
+    format: FileFormat,
+    info: &RenderInfo,
+    settings: &RenderSettings,
+    png16: bool,
+) {
+    if format.is_8_bit() {
+        image.tonemap(settings.tonemap, settings.exposure);
+    }
+    // PNG and EXR files keep the render settings alongside the image
+    let written = match format {
+        FileFormat::Png if png16 => image.write_png16_with_info(path, info),
+        FileFormat::Png => image.write_png_with_info(path, info),
+        FileFormat::Exr => write_exr_layers(path, &[("beauty", &image)], Some(info)),
+        _ => image.write_as(path, format),
+    };
+    or_exit(written, &format!("Couldn't write `{}`", path.display()));
 }
@@ -1,68 +1,213 @@
+use crate::consts::PI;
 use crate::hittable::HitRecord;
+use crate::integrator::PathState;
+use crate::light::LightPower;
+use crate::pdf::{CosinePdf, Onb, Pdf};
 use crate::ray::Ray;
-use crate::texture::Texture;
-use crate::{rand_unit_vector, schlick};
-use crate::{Color, Point3};
-use rand::distributions::{Standard, Uniform};
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use crate::sampler::SampleCtx;
+use crate::sampling;
+use crate::scene::{AbsorptionDesc, MaterialDesc, MaterialRef, ScalarDesc, TextureDesc};
+use crate::schlick;
+use crate::texture::{SolidColor, Texture};
+use crate::{Color, Float, Vec3};
+use std::sync::Arc;
+
+/// Controls how a material takes part in light paths
+#[derive(Clone, Copy)]
+pub struct Visibility {
+    /// Whether camera rays see the material. Hidden surfaces are passed through
+    pub camera: bool,
+    /// Whether rays that have already bounced see the material
+    pub indirect: bool,
+    /// Stop scattering once a path has bounced off the material this many times in a row, so
+    /// mirrors facing each other can be cut short without shortening other paths
+    pub max_depth: Option<u32>,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            indirect: true,
+            max_depth: None,
+        }
+    }
+}
 
 pub trait Material {
+    /// Scatters an incoming ray, returning the new ray and how much it's attenuated
     fn scatter(
         &self,
         ray: &Ray,
         rec: &HitRecord,
-        rng: &mut ThreadRng,
-        uniform_unit: &Uniform<f64>,
+        state: &PathState,
+        ctx: &mut SampleCtx,
     ) -> Option<(Ray, Color)>;
 
-    fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
+    /// Light given off towards the origin of `ray`
+    fn emitted(&self, _: &Ray, _: &HitRecord) -> Color {
         color!(0., 0., 0.)
     }
+
+    /// The BRDF times the cosine term for light arriving from `dir` and leaving back along `ray`
+    ///
+    /// Returns `None` for materials that only scatter in specific directions and so can't be lit
+    /// directly by lights
+    fn eval(&self, _: &Ray, _: &HitRecord, _: &Vec3) -> Option<Color> {
+        None
+    }
+
+    /// Probability density of `scatter` sending `ray` out along `dir`, over solid angle
+    ///
+    /// Zero for materials that only scatter in specific directions
+    fn scattering_pdf(&self, _: &Ray, _: &HitRecord, _: &Vec3) -> Float {
+        0.
+    }
+
+    fn visibility(&self) -> Visibility {
+        Visibility::default()
+    }
+
+    /// Base color of the surface at `rec` before lighting, for the albedo AOV that denoisers use
+    ///
+    /// Defaults to white, which suits clear and mirror-like materials
+    fn albedo(&self, _: &HitRecord) -> Color {
+        color!(1., 1., 1.)
+    }
+
+    /// Problems that make the material non-physical, like reflecting more light than arrives
+    ///
+    /// These can make renders blow up or never converge. Empty by default
+    fn validate(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Description of the material for saving to a scene file, if it can be saved
+    fn describe(&self) -> Option<MaterialDesc> {
+        None
+    }
+
+    /// Refractive index inside the material, for dielectrics that rays refract into
+    ///
+    /// Paths keep track of the dielectrics they're inside, so nested ones refract relative to
+    /// each other rather than to vacuum
+    fn ior(&self) -> Option<Float> {
+        None
+    }
 }
 
-pub struct Lambertian<'a> {
-    albedo: Box<dyn Texture + Sync + 'a>,
+pub struct Lambertian {
+    albedo: Arc<dyn Texture + Send + Sync>,
+    pub visibility: Visibility,
 }
 
-impl<'a> Lambertian<'a> {
-    pub fn new<T: Texture + Sync + 'a>(albedo: T) -> Self {
+impl Lambertian {
+    pub fn new<T: Texture + Send + Sync + 'static>(albedo: T) -> Self {
+        Self::new_shared(Arc::new(albedo))
+    }
+
+    pub fn new_shared(albedo: Arc<dyn Texture + Send + Sync>) -> Self {
         Self {
-            albedo: Box::new(albedo),
+            albedo,
+            visibility: Visibility::default(),
         }
     }
 }
 
-impl<'a> Material for Lambertian<'a> {
+impl Material for Lambertian {
     fn scatter(
         &self,
         ray: &Ray,
         rec: &HitRecord,
-        rng: &mut ThreadRng,
-        uniform_unit: &Uniform<f64>,
+        _: &PathState,
+        ctx: &mut SampleCtx,
     ) -> Option<(Ray, Color)> {
-        let target: Point3 = rec.point + rec.normal.conv() + rand_unit_vector(rng, uniform_unit);
-        let ray = Ray {
-            origin: rec.point,
-            dir: (target - rec.point).conv(),
-            time: ray.time,
-        };
+        let dir = CosinePdf::new(&rec.normal).generate(ctx)?;
+        let ray = Ray::new(rec.point, dir, ray.time);
         Some((ray, self.albedo.value(rec.u, rec.v, rec.point)))
     }
+
+    fn eval(&self, _: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        let cosine = rec.normal.dot(&dir.unit_vector()).max(0.);
+        Some(self.albedo.value(rec.u, rec.v, rec.point) * (cosine / PI))
+    }
+
+    fn scattering_pdf(&self, _: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        CosinePdf::new(&rec.normal).value(dir)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        check_texture("Lambertian albedo", self.albedo.as_ref())
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Lambertian {
+            albedo: self.albedo.describe()?,
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
+    }
 }
 
+/// A mirror, blurred by fuzz
+///
+/// The albedo, fuzz and any light given off can vary over the surface with textures, like a
+/// polished sheet with rusty patches
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    albedo: Arc<dyn Texture + Send + Sync>,
+    /// Red channel gives the fuzz at each point
+    fuzz: Arc<dyn Texture + Send + Sync>,
+    /// Light given off from the front, if set with `with_emission`
+    emission: Option<Arc<dyn Texture + Send + Sync>>,
+    pub visibility: Visibility,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Self {
+    pub fn new(albedo: Color, fuzz: Float) -> Self {
+        Self::textured(
+            SolidColor::new(albedo),
+            SolidColor::new(color!(fuzz, fuzz, fuzz)),
+        )
+    }
+
+    /// Metal with its color from `albedo` and its fuzz from the red channel of `fuzz`
+    pub fn textured<A, F>(albedo: A, fuzz: F) -> Self
+    where
+        A: Texture + Send + Sync + 'static,
+        F: Texture + Send + Sync + 'static,
+    {
+        Self::new_shared(Arc::new(albedo), Arc::new(fuzz))
+    }
+
+    pub fn new_shared(
+        albedo: Arc<dyn Texture + Send + Sync>,
+        fuzz: Arc<dyn Texture + Send + Sync>,
+    ) -> Self {
         Self {
             albedo,
-            fuzz: fuzz.min(1.),
+            fuzz,
+            emission: None,
+            visibility: Visibility::default(),
         }
     }
+
+    /// Makes the front of the metal glow with `emission`, like red hot iron. It isn't sampled
+    /// directly like a `Light`, so is only seen by rays that happen to hit it
+    pub fn with_emission(mut self, emission: Arc<dyn Texture + Send + Sync>) -> Self {
+        self.emission = Some(emission);
+        self
+    }
+
+    /// Fuzz at `rec`, at most 1
+    fn fuzz(&self, rec: &HitRecord) -> Float {
+        self.fuzz.value(rec.u, rec.v, rec.point).red.min(1.)
+    }
 }
 
 impl Material for Metal {
@@ -70,29 +215,145 @@ impl Material for Metal {
         &self,
         ray: &Ray,
         rec: &HitRecord,
-        rng: &mut ThreadRng,
-        uniform_unit: &Uniform<f64>,
+        _: &PathState,
+        ctx: &mut SampleCtx,
     ) -> Option<(Ray, Color)> {
         let reflected = ray.dir.unit_vector().reflect(&rec.normal);
-        let ray = Ray {
-            origin: rec.point,
-            dir: reflected + (self.fuzz * rand_unit_vector(rng, uniform_unit)).conv(),
-            time: ray.time,
-        };
+        let dir = reflected + self.fuzz(rec) * sampling::uniform_sphere(ctx.get_2d());
+        let ray = Ray::new(rec.point, dir, ray.time);
         if ray.dir.dot(&rec.normal) <= 0. {
             return None;
         }
-        Some((ray, self.albedo))
+        Some((ray, self.albedo(rec)))
+    }
+
+    fn emitted(&self, _: &Ray, rec: &HitRecord) -> Color {
+        match &self.emission {
+            Some(emission) if rec.front_face => emission.value(rec.u, rec.v, rec.point),
+            _ => color!(0., 0., 0.),
+        }
+    }
+
+    /// Scattering leaves the BRDF times cosine equal to albedo times the PDF, which lets fuzzy
+    /// metal be lit directly. Perfect mirrors can't be
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        if self.fuzz(rec) <= 0. {
+            return None;
+        }
+        Some(self.albedo(rec) * self.scattering_pdf(ray, rec, dir))
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        let dir = dir.unit_vector();
+        let fuzz = self.fuzz(rec);
+        if fuzz <= 0. || dir.dot(&rec.normal) <= 0. {
+            return 0.;
+        }
+        // Scattered directions point at a sphere of radius `fuzz` around the tip of the
+        // reflected direction, with points spread evenly over its surface. Add up the density at
+        // both places `dir` crosses the sphere
+        let reflected = ray.dir.unit_vector().reflect(&rec.normal);
+        let b = dir.dot(&reflected);
+        let discriminant = b * b - (1. - fuzz * fuzz);
+        if discriminant <= 0. {
+            return 0.;
+        }
+        let root = discriminant.sqrt();
+        let density: Float = [b - root, b + root]
+            .iter()
+            .filter(|&&t| t > 0.)
+            .map(|t| t * t)
+            .sum();
+        density / (4. * PI * fuzz * root)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_texture("Metal albedo", self.albedo.as_ref());
+        if let Some((min, _)) = self.fuzz.range() {
+            if min.red < 0. || min.red.is_nan() {
+                problems.push(format!("Metal fuzz {} is negative", min.red));
+            }
+        }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Metal {
+            albedo: self.albedo.describe()?,
+            fuzz: ScalarDesc::from_texture(self.fuzz.describe()?),
+            emission: describe_optional(&self.emission)?,
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
     }
 }
 
+/// Glass, water and other clear materials that refract light
 pub struct Dielectric {
-    ri: f64,
+    ri: Float,
+    /// Color light passing through or reflecting off is tinted, if set with `with_tint`
+    tint: Option<Arc<dyn Texture + Send + Sync>>,
+    /// Light given off from the front, if set with `with_emission`
+    emission: Option<Arc<dyn Texture + Send + Sync>>,
+    /// Color light inside turns and the distance it takes, if set with `with_absorption`
+    absorption: Option<(Color, Float)>,
+    pub visibility: Visibility,
 }
 
 impl Dielectric {
-    pub fn new(ri: f64) -> Self {
-        Self { ri }
+    pub fn new(ri: Float) -> Self {
+        Self {
+            ri,
+            tint: None,
+            emission: None,
+            absorption: None,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Absorbs light travelling through the inside by Beer–Lambert's law, so it turns `color`
+    /// after every `1 / density` units, like colored glass or murky water. Thick parts look
+    /// darker than thin ones, unlike `with_tint`
+    ///
+    /// Distance is measured from where light enters to where it next meets the surface from
+    /// inside, so light inside that hits another object first isn't absorbed on the way
+    pub fn with_absorption(mut self, color: Color, density: Float) -> Self {
+        self.absorption = Some((color, density));
+        self
+    }
+
+    /// Share of light left after travelling `distance` through the inside
+    fn transmittance(&self, distance: Float) -> Color {
+        match self.absorption {
+            Some((color, density)) => {
+                let power = density * distance;
+                color!(
+                    color.red.powf(power),
+                    color.green.powf(power),
+                    color.blue.powf(power)
+                )
+            }
+            None => color!(1., 1., 1.),
+        }
+    }
+
+    /// Tints light crossing or reflecting off the surface with `tint`, like stained glass
+    pub fn with_tint(mut self, tint: Arc<dyn Texture + Send + Sync>) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    /// Makes the front of the surface glow with `emission`. It isn't sampled directly like a
+    /// `Light`, so is only seen by rays that happen to hit it
+    pub fn with_emission(mut self, emission: Arc<dyn Texture + Send + Sync>) -> Self {
+        self.emission = Some(emission);
+        self
     }
 }
 
@@ -101,14 +362,18 @@ impl Material for Dielectric {
         &self,
         ray: &Ray,
         rec: &HitRecord,
-        rng: &mut ThreadRng,
-        _: &Uniform<f64>,
+        state: &PathState,
+        ctx: &mut SampleCtx,
     ) -> Option<(Ray, Color)> {
-        let attuen = Color::new(1., 1., 1.);
+        let mut attuen = self.albedo(rec);
+        if !rec.front_face {
+            // The ray has come through the inside from the last time it met the surface
+            attuen = attuen * self.transmittance(rec.t * ray.dir.length());
+        }
         let etai_over_etat = if rec.front_face {
-            1. / self.ri
+            state.media.current() / self.ri
         } else {
-            self.ri
+            self.ri / state.media.outside(self.ri)
         };
         let unit_dir = ray.dir.unit_vector();
         let cos_theta = (-unit_dir).dot(&rec.normal).min(1.0);
@@ -116,55 +381,1018 @@ impl Material for Dielectric {
         let dir = if etai_over_etat * sin_theta > 1. {
             unit_dir.reflect(&rec.normal)
         } else {
-            let reflect_prob = schlick(cos_theta, self.ri);
-            if rng.sample::<f64, _>(Standard) < reflect_prob {
+            let reflect_prob = schlick(cos_theta, etai_over_etat);
+            if ctx.get_1d() < reflect_prob {
                 unit_dir.reflect(&rec.normal)
             } else {
                 unit_dir.refract(&rec.normal, etai_over_etat)
             }
         };
-        let ray = Ray {
-            origin: rec.point,
-            dir,
-            time: ray.time,
-        };
+        let ray = Ray::new(rec.point, dir, ray.time);
         Some((ray, attuen))
     }
+
+    fn emitted(&self, _: &Ray, rec: &HitRecord) -> Color {
+        match &self.emission {
+            Some(emission) if rec.front_face => emission.value(rec.u, rec.v, rec.point),
+            _ => color!(0., 0., 0.),
+        }
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_ior("Dielectric", self.ri);
+        if let Some(tint) = &self.tint {
+            problems.extend(check_texture("Dielectric tint", tint.as_ref()));
+        }
+        if let Some((color, density)) = self.absorption {
+            problems.extend(check_albedo("Dielectric absorption", color));
+            if !(density >= 0. && density.is_finite()) {
+                problems.push(format!(
+                    "Dielectric absorption density {} is negative or not finite",
+                    density
+                ));
+            }
+        }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Dielectric {
+            ior: self.ri,
+            tint: describe_optional(&self.tint)?,
+            emission: describe_optional(&self.emission)?,
+            absorption: self.absorption.map(|(color, density)| AbsorptionDesc {
+                color: color.into(),
+                density,
+            }),
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        match &self.tint {
+            Some(tint) => tint.value(rec.u, rec.v, rec.point),
+            None => color!(1., 1., 1.),
+        }
+    }
+
+    fn ior(&self) -> Option<Float> {
+        Some(self.ri)
+    }
+}
+
+/// Blends two materials by a factor that can vary over the surface, like worn paint over metal
+/// or moss on stone
+///
+/// Each bounce picks one of the materials with the chance the factor gives, so the result is
+/// `a` where the factor is 0, `b` where it's 1 and a weighted average of them in between
+pub struct Mix {
+    a: Arc<dyn Material + Send + Sync>,
+    b: Arc<dyn Material + Send + Sync>,
+    /// Share of `b`, in the red channel
+    factor: Arc<dyn Texture + Send + Sync>,
+    pub visibility: Visibility,
+}
+
+impl Mix {
+    pub fn new<A, B, F>(a: A, b: B, factor: F) -> Self
+    where
+        A: Material + Send + Sync + 'static,
+        B: Material + Send + Sync + 'static,
+        F: Texture + Send + Sync + 'static,
+    {
+        Self::new_shared(Arc::new(a), Arc::new(b), Arc::new(factor))
+    }
+
+    pub fn new_shared(
+        a: Arc<dyn Material + Send + Sync>,
+        b: Arc<dyn Material + Send + Sync>,
+        factor: Arc<dyn Texture + Send + Sync>,
+    ) -> Self {
+        Self {
+            a,
+            b,
+            factor,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Share of `b` at `rec`
+    fn factor(&self, rec: &HitRecord) -> Float {
+        let factor = self.factor.value(rec.u, rec.v, rec.point).red;
+        factor.clamp(0., 1.)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        state: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        let chosen = if ctx.get_1d() < self.factor(rec) {
+            &self.b
+        } else {
+            &self.a
+        };
+        let (scattered, attenuation) = chosen.scatter(ray, rec, state, ctx)?;
+        // Like `Layered`, weight by both when both can be evaluated
+        match self.eval(ray, rec, &scattered.dir) {
+            Some(f) => {
+                let pdf = self.scattering_pdf(ray, rec, &scattered.dir);
+                if pdf <= 0. {
+                    return None;
+                }
+                Some((scattered, f / pdf))
+            }
+            None => Some((scattered, attenuation)),
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        let factor = self.factor(rec);
+        self.a.emitted(ray, rec) * (1. - factor) + self.b.emitted(ray, rec) * factor
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        // Where only one material shows, it can be lit directly even if the other can't
+        match self.factor(rec) {
+            factor if factor <= 0. => self.a.eval(ray, rec, dir),
+            factor if factor >= 1. => self.b.eval(ray, rec, dir),
+            factor => {
+                let a = self.a.eval(ray, rec, dir)?;
+                let b = self.b.eval(ray, rec, dir)?;
+                Some(a * (1. - factor) + b * factor)
+            }
+        }
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        let factor = self.factor(rec);
+        let pdf = |material: &Arc<dyn Material + Send + Sync>, share: Float| {
+            if share > 0. {
+                share * material.scattering_pdf(ray, rec, dir)
+            } else {
+                0.
+            }
+        };
+        pdf(&self.a, 1. - factor) + pdf(&self.b, factor)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_texture("Mix factor", self.factor.as_ref());
+        for (name, material) in [("a", &self.a), ("b", &self.b)] {
+            let prefixed = material
+                .validate()
+                .into_iter()
+                .map(|problem| format!("Mix {}: {}", name, problem));
+            problems.extend(prefixed);
+        }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Mix {
+            a: MaterialRef::Inline(Box::new(self.a.describe()?)),
+            b: MaterialRef::Inline(Box::new(self.b.describe()?)),
+            factor: ScalarDesc::from_texture(self.factor.describe()?),
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let factor = self.factor(rec);
+        self.a.albedo(rec) * (1. - factor) + self.b.albedo(rec) * factor
+    }
+
+    fn ior(&self) -> Option<Float> {
+        self.a.ior().or_else(|| self.b.ior())
+    }
+}
+
+/// Refractive index of `Layered::clearcoat`'s coat, about that of lacquer
+pub const CLEARCOAT_IOR: Float = 1.5;
+
+/// One material layered over another, like a clear coat over paint or wax over wood
+///
+/// The top layer reflects the share of light the Fresnel term for `ior` gives, which grows
+/// towards grazing angles, and the rest reaches the bottom layer. Light reflecting between the
+/// layers isn't followed, so thick coats over dark bases come out slightly dark
+pub struct Layered {
+    top: Arc<dyn Material + Send + Sync>,
+    bottom: Arc<dyn Material + Send + Sync>,
+    ior: Float,
+    pub visibility: Visibility,
+}
+
+impl Layered {
+    pub fn new<T, B>(top: T, bottom: B, ior: Float) -> Self
+    where
+        T: Material + Send + Sync + 'static,
+        B: Material + Send + Sync + 'static,
+    {
+        Self::new_shared(Arc::new(top), Arc::new(bottom), ior)
+    }
+
+    pub fn new_shared(
+        top: Arc<dyn Material + Send + Sync>,
+        bottom: Arc<dyn Material + Send + Sync>,
+        ior: Float,
+    ) -> Self {
+        Self {
+            top,
+            bottom,
+            ior,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// A glossy clear coat over `base`, with a GGX highlight spread by `roughness` as in
+    /// `PbrMaterial`
+    pub fn clearcoat<T: Material + Send + Sync + 'static>(base: T, roughness: Float) -> Self {
+        let coat = PbrMaterial::new(color!(1., 1., 1.), 1., roughness);
+        Self::new(coat, base, CLEARCOAT_IOR)
+    }
+
+    /// Share of light the top layer reflects when arriving along `ray`
+    fn fresnel(&self, ray: &Ray, rec: &HitRecord) -> Float {
+        let cosine = (-ray.dir.unit_vector()).dot(&rec.normal).clamp(0., 1.);
+        schlick(cosine, self.ior)
+    }
+}
+
+impl Material for Layered {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        state: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        let fresnel = self.fresnel(ray, rec);
+        let (scattered, attenuation) = if ctx.get_1d() < fresnel {
+            self.top.scatter(ray, rec, state, ctx)?
+        } else {
+            self.bottom.scatter(ray, rec, state, ctx)?
+        };
+        // Weight by both layers when both can be evaluated, so either choice agrees with `eval`
+        // and `scattering_pdf`. Otherwise picking a layer by its share is enough
+        match self.eval(ray, rec, &scattered.dir) {
+            Some(f) => {
+                let pdf = self.scattering_pdf(ray, rec, &scattered.dir);
+                if pdf <= 0. {
+                    return None;
+                }
+                Some((scattered, f / pdf))
+            }
+            None => Some((scattered, attenuation)),
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.top.emitted(ray, rec) + self.bottom.emitted(ray, rec) * (1. - self.fresnel(ray, rec))
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        let bottom = self.bottom.eval(ray, rec, dir)?;
+        let top = self.top.eval(ray, rec, dir)?;
+        let fresnel = self.fresnel(ray, rec);
+        Some(top * fresnel + bottom * (1. - fresnel))
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        let fresnel = self.fresnel(ray, rec);
+        fresnel * self.top.scattering_pdf(ray, rec, dir)
+            + (1. - fresnel) * self.bottom.scattering_pdf(ray, rec, dir)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_ior("Layered", self.ior);
+        for (name, material) in [("top", &self.top), ("bottom", &self.bottom)] {
+            let prefixed = material
+                .validate()
+                .into_iter()
+                .map(|problem| format!("Layered {}: {}", name, problem));
+            problems.extend(prefixed);
+        }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Layered {
+            top: MaterialRef::Inline(Box::new(self.top.describe()?)),
+            bottom: MaterialRef::Inline(Box::new(self.bottom.describe()?)),
+            ior: self.ior,
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.bottom.albedo(rec)
+    }
+
+    fn ior(&self) -> Option<Float> {
+        self.bottom.ior()
+    }
+}
+
+/// A base material under a clear glossy coat, like paint, varnish or glaze
+///
+/// A `Layered` material with a white fuzzy mirror on top
+pub struct Coated {
+    layers: Layered,
+    roughness: Float,
+    pub visibility: Visibility,
+}
+
+impl Coated {
+    /// Coats `base` with a clear layer of refractive index `ior`, with reflections blurred by
+    /// `roughness` like `Metal`'s fuzz
+    pub fn new<T: Material + Send + Sync + 'static>(base: T, ior: Float, roughness: Float) -> Self {
+        Self::new_shared(Arc::new(base), ior, roughness)
+    }
+
+    pub fn new_shared(base: Arc<dyn Material + Send + Sync>, ior: Float, roughness: Float) -> Self {
+        // A perfectly sharp coat couldn't be combined with the base's BRDF
+        let coat = Metal::new(color!(1., 1., 1.), roughness.max(1e-3));
+        Self {
+            layers: Layered::new_shared(Arc::new(coat), base, ior),
+            roughness,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Metallic flakes under a glossy clear coat
+    pub fn car_paint(color: Color) -> Self {
+        Self::new(Metal::new(color, 0.35), 1.5, 0.02)
+    }
+
+    /// Wood under a layer of varnish, with `grain` giving the wood's color
+    pub fn lacquered_wood<T: Texture + Send + Sync + 'static>(grain: T) -> Self {
+        Self::new(Lambertian::new(grain), 1.5, 0.08)
+    }
+
+    /// Glazed pottery, with a thick glassy layer over a matte body
+    pub fn ceramic_glaze(color: Color) -> Self {
+        Self::new(Lambertian::new(SolidColor::new(color)), 1.6, 0.01)
+    }
+
+    /// Looks up a preset by name, for scene descriptions
+    ///
+    /// The names are `car_paint`, `lacquered_wood` and `ceramic_glaze`. Wood is given a solid
+    /// `color` for its grain
+    pub fn preset(name: &str, color: Color) -> Option<Self> {
+        match name {
+            "car_paint" => Some(Self::car_paint(color)),
+            "lacquered_wood" => Some(Self::lacquered_wood(SolidColor::new(color))),
+            "ceramic_glaze" => Some(Self::ceramic_glaze(color)),
+            _ => None,
+        }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        state: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        self.layers.scatter(ray, rec, state, ctx)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.layers.emitted(ray, rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        self.layers.eval(ray, rec, dir)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        self.layers.scattering_pdf(ray, rec, dir)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_ior("Coated", self.layers.ior);
+        problems.extend(
+            self.layers
+                .bottom
+                .validate()
+                .into_iter()
+                .map(|problem| format!("Coated base: {}", problem)),
+        );
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Coated {
+            base: MaterialRef::Inline(Box::new(self.layers.bottom.describe()?)),
+            ior: self.layers.ior,
+            roughness: self.roughness,
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.layers.albedo(rec)
+    }
 }
 
-pub struct Light<'a> {
-    albedo: Box<dyn Texture + Sync + 'a>,
+/// Least GGX roughness `PbrMaterial` uses, as a perfectly smooth surface would need a BRDF that's
+/// infinite in one direction
+const MIN_ALPHA: Float = 1e-3;
+
+/// Share of light dielectrics reflect head on in the metallic/roughness workflow, about that of
+/// plastic or glass
+const DIELECTRIC_F0: Float = 0.04;
+
+/// A physically based material in the metallic/roughness workflow of glTF and most modern tools
+///
+/// A GGX microfacet specular layer over a Lambertian base that gets the light the layer lets
+/// through. The layer reflects 4% head on for dielectrics and the base color for metals, rising
+/// to all of it at grazing angles, and `metallic` blends between the two. `roughness` spreads
+/// the highlight. Both come from the red channel of their textures, so they can vary over the
+/// surface
+pub struct PbrMaterial {
+    base_color: Arc<dyn Texture + Send + Sync>,
+    metallic: Arc<dyn Texture + Send + Sync>,
+    roughness: Arc<dyn Texture + Send + Sync>,
+    pub visibility: Visibility,
+}
+
+/// A `PbrMaterial`'s parameters at one point, in the local space around the normal
+struct PbrSurface {
+    base: Color,
+    metallic: Float,
+    /// GGX roughness, the square of the material's perceptual roughness
+    alpha: Float,
+    frame: Onb,
+    /// Direction back along the ray, above the surface
+    view: Vec3,
+}
+
+impl PbrMaterial {
+    pub fn new(base_color: Color, metallic: Float, roughness: Float) -> Self {
+        let grey = |value: Float| SolidColor::new(color!(value, value, value));
+        Self::textured(SolidColor::new(base_color), grey(metallic), grey(roughness))
+    }
+
+    pub fn textured<B, M, R>(base_color: B, metallic: M, roughness: R) -> Self
+    where
+        B: Texture + Send + Sync + 'static,
+        M: Texture + Send + Sync + 'static,
+        R: Texture + Send + Sync + 'static,
+    {
+        Self::new_shared(
+            Arc::new(base_color),
+            Arc::new(metallic),
+            Arc::new(roughness),
+        )
+    }
+
+    pub fn new_shared(
+        base_color: Arc<dyn Texture + Send + Sync>,
+        metallic: Arc<dyn Texture + Send + Sync>,
+        roughness: Arc<dyn Texture + Send + Sync>,
+    ) -> Self {
+        Self {
+            base_color,
+            metallic,
+            roughness,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// The parameters where `ray` hits, `None` if it hits from below the shading normal
+    fn surface(&self, ray: &Ray, rec: &HitRecord) -> Option<PbrSurface> {
+        let frame = Onb::from_w(&rec.normal);
+        let back = -ray.dir.unit_vector();
+        let view = vec3!(back.dot(&frame.u), back.dot(&frame.v), back.dot(&frame.w));
+        if view.z <= 0. {
+            return None;
+        }
+        let (u, v, point) = (rec.u, rec.v, rec.point);
+        let roughness = self.roughness.value(u, v, point).red.clamp(0., 1.);
+        Some(PbrSurface {
+            base: self.base_color.value(u, v, point),
+            metallic: self.metallic.value(u, v, point).red.clamp(0., 1.),
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+            frame,
+            view,
+        })
+    }
+}
+
+impl PbrSurface {
+    /// `dir` in the local space
+    fn local(&self, dir: &Vec3) -> Vec3 {
+        let dir = dir.unit_vector();
+        vec3!(
+            dir.dot(&self.frame.u),
+            dir.dot(&self.frame.v),
+            dir.dot(&self.frame.w)
+        )
+    }
+
+    /// Share of light the specular layer reflects off a microfacet at `cosine` to the view,
+    /// Schlick's approximation
+    fn fresnel(&self, cosine: Float) -> Color {
+        let dielectric = color!(DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0);
+        let f0 = dielectric * (1. - self.metallic) + self.base * self.metallic;
+        let white = color!(1., 1., 1.);
+        f0 + (white - f0) * (1. - cosine.clamp(0., 1.)).powi(5)
+    }
+
+    /// Chance of sampling the specular layer rather than the base, roughly in proportion to how
+    /// much light each reflects
+    fn specular_chance(&self) -> Float {
+        let specular = self.fresnel(self.view.z).luminance();
+        let diffuse = (1. - self.metallic) * self.base.luminance();
+        if specular + diffuse <= 0. {
+            return 1.;
+        }
+        specular / (specular + diffuse)
+    }
+
+    /// BRDF times cosine for light arriving from local `dir`
+    fn eval(&self, dir: Vec3) -> Color {
+        if dir.z <= 0. {
+            return color!();
+        }
+        let half = (self.view + dir).unit_vector();
+        let fresnel = self.fresnel(self.view.dot(&half));
+        let masking = sampling::ggx_g1(self.view, self.alpha) * sampling::ggx_g1(dir, self.alpha);
+        let specular =
+            fresnel * (sampling::ggx_d(half.z, self.alpha) * masking / (4. * self.view.z * dir.z));
+        let diffuse = (color!(1., 1., 1.) - fresnel) * self.base * ((1. - self.metallic) / PI);
+        (specular + diffuse) * dir.z
+    }
+
+    /// Density of sampling local `dir`, from either layer
+    fn pdf(&self, dir: Vec3) -> Float {
+        if dir.z <= 0. {
+            return 0.;
+        }
+        let chance = self.specular_chance();
+        chance * sampling::ggx_reflection_pdf(self.view, dir, self.alpha)
+            + (1. - chance) * sampling::cosine_hemisphere_pdf(dir.z)
+    }
+}
+
+impl Material for PbrMaterial {
+    /// Samples the specular layer's visible normals or the base's cosine lobe, weighting by both
+    /// so either choice agrees with `eval` and `scattering_pdf`
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        _: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        let surface = self.surface(ray, rec)?;
+        let dir = if ctx.get_1d() < surface.specular_chance() {
+            let normal = sampling::ggx_visible_normal(ctx.get_2d(), surface.view, surface.alpha);
+            (-surface.view).reflect(&normal)
+        } else {
+            sampling::cosine_hemisphere(ctx.get_2d())
+        };
+        let pdf = surface.pdf(dir);
+        if pdf <= 0. {
+            return None;
+        }
+        let world = surface.frame.local(dir.x, dir.y, dir.z);
+        Some((
+            Ray::new(rec.point, world, ray.time),
+            surface.eval(dir) / pdf,
+        ))
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        let surface = match self.surface(ray, rec) {
+            Some(surface) => surface,
+            None => return Some(color!()),
+        };
+        Some(surface.eval(surface.local(dir)))
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        self.surface(ray, rec)
+            .map_or(0., |surface| surface.pdf(surface.local(dir)))
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = check_texture("PBR base color", self.base_color.as_ref());
+        for (name, texture) in [("metallic", &self.metallic), ("roughness", &self.roughness)] {
+            if let Some((min, max)) = texture.range() {
+                if !(0. ..=1.).contains(&min.red) || !(0. ..=1.).contains(&max.red) {
+                    problems.push(format!("PBR {} is outside 0 to 1", name));
+                }
+            }
+        }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Pbr {
+            base_color: self.base_color.describe()?,
+            metallic: ScalarDesc::from_texture(self.metallic.describe()?),
+            roughness: ScalarDesc::from_texture(self.roughness.describe()?),
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, rec.point)
+    }
+}
+
+/// Distance in texture coordinates, and across the surface, that `SurfaceMap::Bumps` measures
+/// slopes over
+const BUMP_STEP: Float = 1e-3;
+
+/// How `NormalMapped` tilts the shading normal
+pub enum SurfaceMap {
+    /// A tangent space normal map, with red along the tangent, green along the bitangent and
+    /// blue out of the surface, each scaled from -1 to 1 into 0 to 1
+    Normals(Arc<dyn Texture + Send + Sync>),
+    /// Heights in the red channel, tilting the normal away from uphill by `strength` times the
+    /// slope
+    ///
+    /// Slopes are measured over a small step in texture coordinates and the same distance
+    /// across the surface, so both image and solid textures make bumps
+    Bumps {
+        heights: Arc<dyn Texture + Send + Sync>,
+        strength: Float,
+    },
+}
+
+/// Another material with its shading normal tilted by a normal map or bump map, for detail like
+/// bricks or scratches that would take far too much geometry to model
+///
+/// Only the shading changes, so silhouettes and shadows stay those of the smooth surface
+pub struct NormalMapped {
+    material: Arc<dyn Material + Send + Sync>,
+    map: SurfaceMap,
+}
+
+impl NormalMapped {
+    pub fn new<T: Material + Send + Sync + 'static>(material: T, map: SurfaceMap) -> Self {
+        Self::new_shared(Arc::new(material), map)
+    }
+
+    pub fn new_shared(material: Arc<dyn Material + Send + Sync>, map: SurfaceMap) -> Self {
+        Self { material, map }
+    }
+
+    /// `rec` with its normal tilted by the map
+    fn shade<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        // Maps are drawn on the outside of the surface
+        let outward = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let tangent = rec.tangent - outward * outward.dot(&rec.tangent);
+        if tangent.length_squared() < 1e-12 {
+            return HitRecord { ..*rec };
+        }
+        let tangent = tangent.unit_vector();
+        let bitangent = outward.cross(&tangent);
+        // Keep the bitangent on the side `v` increases, for mirrored texture coordinates
+        let bitangent = if bitangent.dot(&rec.bitangent) < 0. {
+            -bitangent
+        } else {
+            bitangent
+        };
+        let local = match &self.map {
+            SurfaceMap::Normals(normals) => {
+                let color = normals.value(rec.u, rec.v, rec.point);
+                vec3!(
+                    2. * color.red - 1.,
+                    2. * color.green - 1.,
+                    2. * color.blue - 1.
+                )
+            }
+            SurfaceMap::Bumps { heights, strength } => {
+                let height = |u, v, along: Vec3| {
+                    let point = rec.point + (BUMP_STEP * along).conv();
+                    heights.value(u, v, point).red
+                };
+                let here = height(rec.u, rec.v, vec3!());
+                let slope_u = (height(rec.u + BUMP_STEP, rec.v, tangent) - here) / BUMP_STEP;
+                let slope_v = (height(rec.u, rec.v + BUMP_STEP, bitangent) - here) / BUMP_STEP;
+                vec3!(-strength * slope_u, -strength * slope_v, 1.)
+            }
+        };
+        let shading = tangent * local.x + bitangent * local.y + outward * local.z;
+        if local.z <= 0. || shading.length_squared() < 1e-12 {
+            // Maps can't turn the surface away from its own side
+            return HitRecord { ..*rec };
+        }
+        let shading = shading.unit_vector();
+        HitRecord {
+            normal: if rec.front_face { shading } else { -shading },
+            ..*rec
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        state: &PathState,
+        ctx: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        self.material.scatter(ray, &self.shade(rec), state, ctx)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(ray, &self.shade(rec))
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Option<Color> {
+        self.material.eval(ray, &self.shade(rec), dir)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, dir: &Vec3) -> Float {
+        self.material.scattering_pdf(ray, &self.shade(rec), dir)
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.material.visibility()
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        self.material
+            .validate()
+            .into_iter()
+            .map(|problem| format!("Normal mapped: {}", problem))
+            .collect()
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        let material = MaterialRef::Inline(Box::new(self.material.describe()?));
+        Some(match &self.map {
+            SurfaceMap::Normals(normals) => MaterialDesc::NormalMap {
+                material,
+                normals: normals.describe()?,
+            },
+            SurfaceMap::Bumps { heights, strength } => MaterialDesc::BumpMap {
+                material,
+                heights: heights.describe()?,
+                strength: *strength,
+            },
+        })
+    }
+
+    fn ior(&self) -> Option<Float> {
+        self.material.ior()
+    }
+}
+
+/// Gives off `color` scaled by its texture, so the glow can vary over the surface
+pub struct Light {
+    albedo: Arc<dyn Texture + Send + Sync>,
     color: Color,
+    /// Whether the back face emits light too. Defaults to `true`
+    pub two_sided: bool,
+    /// Angle in degrees of the cone around the surface normal that light is given off in.
+    /// Defaults to 180, which lights the whole hemisphere
+    pub spread: Float,
+    pub visibility: Visibility,
+    /// Total power and the area it's spread over, if set with `with_power`
+    power: Option<(LightPower, Float)>,
 }
 
-impl<'a> Light<'a> {
-    pub fn new<T: Texture + Sync + 'a>(albedo: T, color: Color) -> Self {
+impl Light {
+    pub fn new<T: Texture + Send + Sync + 'static>(albedo: T, color: Color) -> Self {
+        Self::new_shared(Arc::new(albedo), color)
+    }
+
+    pub fn new_shared(albedo: Arc<dyn Texture + Send + Sync>, color: Color) -> Self {
         Self {
-            albedo: Box::new(albedo),
+            albedo,
             color,
+            two_sided: true,
+            spread: 180.,
+            visibility: Visibility::default(),
+            power: None,
+        }
+    }
+
+    /// Makes the light give off `power` in total from a surface of `area`, so resizing it doesn't
+    /// change how brightly it lights the scene
+    ///
+    /// `color` then only sets the hue, and the texture should be white for the power to be exact.
+    /// `World::add_light_with_power` measures the area from the light's shape
+    pub fn with_power(mut self, power: LightPower, area: Float) -> Self {
+        self.power = Some((power, area));
+        self
+    }
+
+    /// Radiance leaving the surface, before the texture
+    fn radiance(&self) -> Color {
+        let (power, area) = match self.power {
+            Some(power) => power,
+            None => return self.color,
+        };
+        // Power leaving a surface of even radiance is the radiance times the area times the
+        // cosine weighted solid angle of the cone it's given off in
+        let half_angle = (self.spread.min(180.).to_radians() / 2.).min(PI / 2.);
+        let sides = if self.two_sided { 2. } else { 1. };
+        let projected = area * PI * half_angle.sin().powi(2) * sides;
+        let luminance = self.color.luminance();
+        if projected <= 0. || luminance <= 0. {
+            return color!();
+        }
+        self.color * (power.watts() / (projected * luminance))
+    }
+
+    /// A white light glowing with the color of a black body at `kelvin`, see `Color::from_kelvin`
+    pub fn from_kelvin(kelvin: Float, luminance: Float) -> Self {
+        Self::new(
+            SolidColor::new(color!(1., 1., 1.)),
+            Color::from_kelvin(kelvin) * luminance,
+        )
+    }
+}
+
+impl Material for Light {
+    fn scatter(
+        &self,
+        _: &Ray,
+        _: &HitRecord,
+        _: &PathState,
+        _: &mut SampleCtx,
+    ) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return color!();
+        }
+        let cosine = rec.normal.dot(&-ray.dir.unit_vector());
+        if cosine < (self.spread.min(180.).to_radians() / 2.).cos() {
+            return color!();
+        }
+        self.albedo.value(rec.u, rec.v, rec.point) * self.radiance()
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let radiance = self.radiance();
+        if ![radiance.red, radiance.green, radiance.blue]
+            .iter()
+            .all(|&c| c >= 0. && c.is_finite())
+        {
+            problems.push(format!(
+                "Light radiance {} isn't finite and positive",
+                radiance
+            ));
         }
+        problems
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Light {
+            color: self.color.into(),
+            texture: self.albedo.describe()?,
+            two_sided: self.two_sided,
+            spread: self.spread,
+            power: self.power.map(|(power, _)| power),
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
     }
 }
 
-impl<'a> Material for Light<'a> {
+/// Scatters light equally in all directions, for use inside volumes
+///
+/// Has no BRDF to evaluate, so lights only reach it by scattering
+pub struct Isotropic {
+    albedo: Arc<dyn Texture + Send + Sync>,
+    pub visibility: Visibility,
+}
+
+impl Isotropic {
+    pub fn new<T: Texture + Send + Sync + 'static>(albedo: T) -> Self {
+        Self::new_shared(Arc::new(albedo))
+    }
+
+    pub fn new_shared(albedo: Arc<dyn Texture + Send + Sync>) -> Self {
+        Self {
+            albedo,
+            visibility: Visibility::default(),
+        }
+    }
+}
+
+impl Material for Isotropic {
     fn scatter(
         &self,
         ray: &Ray,
         rec: &HitRecord,
-        rng: &mut ThreadRng,
-        uniform_unit: &Uniform<f64>,
+        _: &PathState,
+        ctx: &mut SampleCtx,
     ) -> Option<(Ray, Color)> {
-        return None;
-        let target: Point3 = rec.point + rec.normal.conv() + rand_unit_vector(rng, uniform_unit);
-        let ray = Ray {
-            origin: rec.point,
-            dir: (target - rec.point).conv(),
-            time: ray.time,
-        };
+        let ray = Ray::new(rec.point, sampling::uniform_sphere(ctx.get_2d()), ray.time);
         Some((ray, self.albedo.value(rec.u, rec.v, rec.point)))
     }
 
-    fn emitted(&self, _: f64, _: f64, _: Point3) -> Color {
-        self.color
+    fn scattering_pdf(&self, _: &Ray, _: &HitRecord, _: &Vec3) -> Float {
+        sampling::uniform_sphere_pdf()
+    }
+
+    fn validate(&self) -> Vec<String> {
+        check_texture("Isotropic albedo", self.albedo.as_ref())
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Isotropic {
+            albedo: self.albedo.describe()?,
+        })
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
+    }
+}
+
+/// Description of a texture that may not be set, `None` if it's set but can't be saved
+fn describe_optional(
+    texture: &Option<Arc<dyn Texture + Send + Sync>>,
+) -> Option<Option<TextureDesc>> {
+    match texture {
+        Some(texture) => texture.describe().map(Some),
+        None => Some(None),
+    }
+}
+
+/// Checks that `albedo` reflects between none and all of the light arriving
+pub fn check_albedo(name: &str, albedo: Color) -> Vec<String> {
+    let channels = [albedo.red, albedo.green, albedo.blue];
+    if channels.iter().any(|&c| c < 0. || c.is_nan()) {
+        vec![format!("{} {} is negative or not a number", name, albedo)]
+    } else if channels.iter().any(|&c| c > 1.) {
+        vec![format!(
+            "{} {} is above 1, reflecting more light than arrives",
+            name, albedo
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Like `check_albedo` for textures that know their range
+pub fn check_texture(name: &str, texture: &dyn Texture) -> Vec<String> {
+    match texture.range() {
+        Some((min, max)) => {
+            let mut problems = check_albedo(name, min);
+            if problems.is_empty() {
+                problems = check_albedo(name, max);
+            }
+            problems
+        }
+        None => Vec::new(),
+    }
+}
+
+fn check_ior(name: &str, ior: Float) -> Vec<String> {
+    if ior >= 1. && ior.is_finite() {
+        Vec::new()
+    } else {
+        vec![format!("{} index of refraction {} is below 1", name, ior)]
     }
 }
@@ -0,0 +1,84 @@
+//! Ink outlines drawn over a finished render where its depth or normal passes change sharply,
+//! for the illustration look of `ToonIntegrator`
+
+use crate::aov::Aovs;
+use crate::image::Image;
+use crate::{Color, Float};
+use rayon::prelude::*;
+
+/// Where outlines are drawn, and in what color
+#[derive(Clone, Copy, Debug)]
+pub struct OutlineSettings {
+    pub color: Color,
+    /// Length of the difference between neighboring normals, which picks out creases
+    pub normal_threshold: Float,
+    /// Difference between neighboring depths, relative to the nearer one, which picks out
+    /// silhouettes against whatever is behind them
+    pub depth_threshold: Float,
+}
+
+impl Default for OutlineSettings {
+    fn default() -> Self {
+        Self {
+            color: color!(),
+            normal_threshold: 0.5,
+            depth_threshold: 0.1,
+        }
+    }
+}
+
+/// Draws `settings.color` over the pixels of `image` where the passes from `raytrace_with_aovs`
+/// show an edge with a neighboring pixel
+///
+/// Silhouettes, including against the background, are drawn on the nearer side of the edge only,
+/// so they hug the object in front. Creases are drawn on both sides
+pub fn draw_outlines(image: &Image, aovs: &Aovs, settings: &OutlineSettings) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let depth = |index: usize| aovs.depth.data[index].red;
+    let normal = |index: usize| aovs.normal.data[index];
+    let data = (0..image.data.len())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let mut neighbors = Vec::with_capacity(4);
+            if x > 0 {
+                neighbors.push(index - 1);
+            }
+            if x + 1 < width {
+                neighbors.push(index + 1);
+            }
+            if y > 0 {
+                neighbors.push(index - width);
+            }
+            if y + 1 < height {
+                neighbors.push(index + width);
+            }
+            let edge = neighbors.into_iter().any(|neighbor| {
+                let (here, there) = (depth(index), depth(neighbor));
+                // Infinite where only the background is seen, which never gets an outline
+                if here.is_infinite() {
+                    return false;
+                }
+                let relative = (there - here) / here.max(1e-6);
+                if relative > settings.depth_threshold {
+                    // The neighbor is further away, or the background
+                    return true;
+                }
+                // A nearer neighbor draws the silhouette on its own side
+                relative >= -settings.depth_threshold
+                    && (normal(index) - normal(neighbor)).length() > settings.normal_threshold
+            });
+            if edge {
+                settings.color
+            } else {
+                image.data[index]
+            }
+        })
+        .collect();
+    Image {
+        width: image.width,
+        height: image.height,
+        data,
+        alpha: image.alpha.clone(),
+    }
+}
@@ -0,0 +1,152 @@
+//! Probability densities over directions, used to choose where rays go
+
+use crate::hittable::Hittable;
+use crate::sampler::{SampleCtx, SampleRng};
+use crate::sampling;
+use crate::{Float, Point3, Vec3};
+use rand::distributions::Standard;
+use rand::Rng;
+
+/// A way of choosing random directions, along with how likely each direction is to be chosen
+pub trait Pdf {
+    /// Probability density of `generate` choosing `dir`, over solid angle
+    fn value(&self, dir: &Vec3) -> Float;
+
+    /// Picks a random direction. `None` if no direction can be chosen
+    fn generate(&self, ctx: &mut SampleCtx) -> Option<Vec3>;
+}
+
+/// Three perpendicular unit vectors, for working in a space aligned to a surface
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis with `w` pointing along `n`
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            vec3!(0., 1., 0.)
+        } else {
+            vec3!(1., 0., 0.)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    /// Converts coordinates in this basis to world space
+    pub fn local(&self, a: Float, b: Float, c: Float) -> Vec3 {
+        self.u * a + self.v * b + self.w * c
+    }
+}
+
+/// Directions weighted towards a surface normal, matching diffuse reflection
+pub struct CosinePdf {
+    normal: Vec3,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        Self {
+            normal: normal.unit_vector(),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, dir: &Vec3) -> Float {
+        sampling::cosine_hemisphere_pdf(dir.unit_vector().dot(&self.normal))
+    }
+
+    fn generate(&self, ctx: &mut SampleCtx) -> Option<Vec3> {
+        let local = sampling::cosine_hemisphere(ctx.get_2d());
+        Some(Onb::from_w(&self.normal).local(local.x, local.y, local.z))
+    }
+}
+
+/// Directions towards a hittable, for sampling lights directly
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { object, origin }
+    }
+}
+
+impl<'a> Pdf for HittablePdf<'a> {
+    fn value(&self, dir: &Vec3) -> Float {
+        self.object.pdf_value(&self.origin, dir)
+    }
+
+    fn generate(&self, ctx: &mut SampleCtx) -> Option<Vec3> {
+        self.object.random_direction(&self.origin, ctx.rng())
+    }
+}
+
+/// Picks one of several PDFs at random to generate each direction
+pub struct MixturePdf<'a> {
+    /// Each PDF with the probability of picking it
+    pdfs: Vec<(Box<dyn Pdf + 'a>, Float)>,
+}
+
+impl<'a> MixturePdf<'a> {
+    /// Picks PDFs in proportion to their weights
+    pub fn new(pdfs: Vec<(Box<dyn Pdf + 'a>, Float)>) -> Self {
+        let total: Float = pdfs.iter().map(|(_, weight)| weight).sum();
+        let pdfs = pdfs
+            .into_iter()
+            .map(|(pdf, weight)| (pdf, weight / total))
+            .collect();
+        Self { pdfs }
+    }
+
+    /// Picks every PDF equally often
+    pub fn uniform(pdfs: Vec<Box<dyn Pdf + 'a>>) -> Self {
+        Self::new(pdfs.into_iter().map(|pdf| (pdf, 1.)).collect())
+    }
+}
+
+impl<'a> Pdf for MixturePdf<'a> {
+    fn value(&self, dir: &Vec3) -> Float {
+        self.pdfs
+            .iter()
+            .map(|(pdf, weight)| weight * pdf.value(dir))
+            .sum()
+    }
+
+    fn generate(&self, ctx: &mut SampleCtx) -> Option<Vec3> {
+        let mut choice = ctx.get_1d();
+        for (pdf, weight) in &self.pdfs {
+            if choice < *weight {
+                return pdf.generate(ctx);
+            }
+            choice -= weight;
+        }
+        // Rounding can leave a sliver past the last weight
+        self.pdfs.last()?.0.generate(ctx)
+    }
+}
+
+/// Multiple importance sampling weight for a sample from a strategy with density `pdf`, when
+/// another strategy with density `other_pdf` could also have made it
+pub fn power_heuristic(pdf: Float, other_pdf: Float) -> Float {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
+
+/// Directions spread evenly over a cone around `axis`
+pub(crate) fn sample_cone(axis: &Vec3, cos_max: Float, rng: &mut SampleRng) -> Vec3 {
+    let sample = (rng.sample(Standard), rng.sample(Standard));
+    let local = sampling::uniform_cone(sample, cos_max);
+    Onb::from_w(axis).local(local.x, local.y, local.z)
+}
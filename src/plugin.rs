@@ -0,0 +1,138 @@
+//! Custom materials, textures and objects for scene files
+//!
+//! Crates building on this one register factories by name before loading scenes. Scene files
+//! can then use them wherever a material, texture or object goes, passing the factory its
+//! parameters as JSON:
+//!
+//! ```json
+//! { "type": "plugin", "name": "toon", "params": { "bands": 3 } }
+//! ```
+//!
+//! Objects can also be given a `material`, which is built as usual and passed to the factory:
+//!
+//! ```json
+//! { "type": "plugin", "name": "lens", "params": { "radius": 2 }, "material": "glass" }
+//! ```
+//!
+//! `params` can be left out, and the factory is given `null`. Factories return `Error::Scene` for
+//! parameters they can't use
+
+use crate::error::Error;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::texture::Texture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Makes a material from its parameters in a scene file
+pub type MaterialFactory =
+    dyn Fn(&Value) -> Result<Arc<dyn Material + Send + Sync>, Error> + Send + Sync;
+
+/// Makes a texture from its parameters in a scene file
+pub type TextureFactory =
+    dyn Fn(&Value) -> Result<Arc<dyn Texture + Send + Sync>, Error> + Send + Sync;
+
+/// Makes an object from its parameters in a scene file and its material, if it was given one
+pub type HittableFactory = dyn Fn(
+        &Value,
+        Option<Arc<dyn Material + Send + Sync>>,
+    ) -> Result<Box<dyn Hittable + Send + Sync>, Error>
+    + Send
+    + Sync;
+
+#[derive(Default)]
+struct Registry {
+    materials: HashMap<String, Arc<MaterialFactory>>,
+    textures: HashMap<String, Arc<TextureFactory>>,
+    hittables: HashMap<String, Arc<HittableFactory>>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Lets scene files use the material `factory` makes as the plugin `name`, replacing any
+/// material registered under that name before
+pub fn register_material<F>(name: &str, factory: F)
+where
+    F: Fn(&Value) -> Result<Arc<dyn Material + Send + Sync>, Error> + Send + Sync + 'static,
+{
+    let mut registry = registry().write().expect("Error writing plugin registry");
+    registry
+        .materials
+        .insert(name.to_owned(), Arc::new(factory));
+}
+
+/// Lets scene files use the texture `factory` makes as the plugin `name`, replacing any texture
+/// registered under that name before
+pub fn register_texture<F>(name: &str, factory: F)
+where
+    F: Fn(&Value) -> Result<Arc<dyn Texture + Send + Sync>, Error> + Send + Sync + 'static,
+{
+    let mut registry = registry().write().expect("Error writing plugin registry");
+    registry.textures.insert(name.to_owned(), Arc::new(factory));
+}
+
+/// Lets scene files use the objects `factory` makes as the plugin `name`, replacing any object
+/// registered under that name before
+pub fn register_hittable<F>(name: &str, factory: F)
+where
+    F: Fn(
+            &Value,
+            Option<Arc<dyn Material + Send + Sync>>,
+        ) -> Result<Box<dyn Hittable + Send + Sync>, Error>
+        + Send
+        + Sync
+        + 'static,
+{
+    let mut registry = registry().write().expect("Error writing plugin registry");
+    registry
+        .hittables
+        .insert(name.to_owned(), Arc::new(factory));
+}
+
+/// Builds the material registered as `name`, failing if nothing is
+pub fn build_material(
+    name: &str,
+    params: &Value,
+) -> Result<Arc<dyn Material + Send + Sync>, Error> {
+    // Release the lock before building, so factories can build other plugins
+    let factory = registry()
+        .read()
+        .expect("Error reading plugin registry")
+        .materials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::Scene(format!("Unknown plugin material `{}`", name)))?;
+    factory(params)
+}
+
+/// Builds the texture registered as `name`, failing if nothing is
+pub fn build_texture(name: &str, params: &Value) -> Result<Arc<dyn Texture + Send + Sync>, Error> {
+    let factory = registry()
+        .read()
+        .expect("Error reading plugin registry")
+        .textures
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::Scene(format!("Unknown plugin texture `{}`", name)))?;
+    factory(params)
+}
+
+/// Builds the object registered as `name` out of `material`, failing if nothing is
+pub fn build_hittable(
+    name: &str,
+    params: &Value,
+    material: Option<Arc<dyn Material + Send + Sync>>,
+) -> Result<Box<dyn Hittable + Send + Sync>, Error> {
+    let factory = registry()
+        .read()
+        .expect("Error reading plugin registry")
+        .hittables
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::Scene(format!("Unknown plugin object `{}`", name)))?;
+    factory(params, material)
+}
@@ -0,0 +1,147 @@
+//! A window showing renders as they accumulate
+//!
+//! Needs the `preview` feature
+
+use crate::accel::AcceleratorKind;
+use crate::animation::{Keyframes, Sequence};
+use crate::camera::CameraSettings;
+use crate::error::Error;
+use crate::image::{Image, Tonemap};
+use crate::integrator::Integrator;
+use crate::world::World;
+use crate::Float;
+use crate::{raytrace_image, render_progressive, RenderSettings};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+/// A window to show images in
+pub struct Preview {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+/// What to do after showing an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreviewAction {
+    Continue,
+    /// Save the image shown
+    Snapshot,
+    /// Stop rendering, after Escape is pressed or the window is closed
+    Abort,
+}
+
+impl Preview {
+    pub fn new(title: &str, width: u32, height: u32) -> Self {
+        let window = Window::new(
+            title,
+            width as usize,
+            height as usize,
+            WindowOptions::default(),
+        )
+        .expect("Error opening preview window");
+        Self {
+            window,
+            buffer: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Draws `image`, tonemapped with `tonemap` after brightening by `exposure` stops, and checks
+    /// for key presses. S asks for a snapshot and Escape aborts
+    pub fn show(&mut self, image: &Image, tonemap: Tonemap, exposure: Float) -> PreviewAction {
+        self.buffer.resize(image.data.len(), 0);
+        let scale = exposure.exp2();
+        for (pixel, color) in self.buffer.iter_mut().zip(image.data.iter()) {
+            let bytes = tonemap.apply(*color * scale).get_bytes();
+            *pixel = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        }
+        self.window
+            .update_with_buffer(&self.buffer, image.width as usize, image.height as usize)
+            .expect("Error updating preview window");
+
+        if !self.window.is_open() || self.window.is_key_down(Key::Escape) {
+            PreviewAction::Abort
+        } else if self.window.is_key_pressed(Key::S, KeyRepeat::No) {
+            PreviewAction::Snapshot
+        } else {
+            PreviewAction::Continue
+        }
+    }
+}
+
+/// Renders progressively, showing each pass in a window
+///
+/// Press S to save the image so far to `snapshot_path` and Escape to stop early. The window only
+/// responds between passes. Snapshots that can't be saved are reported without stopping the
+/// render. Returns the last pass rendered
+pub fn render_with_preview<P: AsRef<Path>>(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    image_width: u32,
+    image_height: u32,
+    snapshot_path: P,
+) -> Result<Image, Error> {
+    let mut preview = Preview::new("Render preview", image_width, image_height);
+    let exposure = render_settings.exposure;
+    render_progressive(
+        world,
+        camera_settings,
+        render_settings,
+        integrator,
+        image_width,
+        image_height,
+        |image, passes| match preview.show(image, render_settings.tonemap, exposure) {
+            PreviewAction::Continue => ControlFlow::Continue(()),
+            PreviewAction::Snapshot => {
+                let mut snapshot = image.clone();
+                snapshot.tonemap(render_settings.tonemap, exposure);
+                match snapshot.write_png(&snapshot_path) {
+                    Ok(()) => println!("Saved snapshot after {} passes", passes),
+                    Err(error) => eprintln!("Couldn't save snapshot: {}", error),
+                }
+                ControlFlow::Continue(())
+            }
+            PreviewAction::Abort => ControlFlow::Break(()),
+        },
+    )
+}
+
+/// Plays `sequence` in a window over and over, rendering each frame like
+/// `Sequence::render_keyframed` and showing it as soon as it's done, until Escape is pressed or
+/// the window is closed
+///
+/// Renders with `AcceleratorKind::Grid` whatever `settings.accelerator` is, as building a BVH
+/// around the moving objects every frame takes longer than the few samples a preview needs.
+/// Keep `settings.samples_per_pixel` low, then render the final frames with the usual BVH
+pub fn preview_animation<S>(
+    sequence: &Sequence,
+    settings: &mut RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    camera: &Keyframes<CameraSettings>,
+    mut scene: S,
+) -> Result<(), Error>
+where
+    S: FnMut(Float) -> World,
+{
+    if sequence.frames.is_empty() {
+        return Ok(());
+    }
+    let mut preview = Preview::new("Animation preview", sequence.width, sequence.height);
+    let accelerator = std::mem::replace(&mut settings.accelerator, AcceleratorKind::Grid);
+    let mut play = || loop {
+        for frame in sequence.frames.clone() {
+            let world = scene(sequence.time(frame));
+            let camera = sequence.camera_at(camera, frame);
+            let (width, height) = (sequence.width, sequence.height);
+            let image = raytrace_image(world, camera, settings, integrator, width, height)?;
+            if preview.show(&image, settings.tonemap, settings.exposure) == PreviewAction::Abort {
+                return Ok(());
+            }
+        }
+    };
+    let result = play();
+    settings.accelerator = accelerator;
+    result
+}
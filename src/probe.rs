@@ -0,0 +1,287 @@
+//! Cubemaps of the light arriving at a point in the scene, for baking the reflection and
+//! irradiance probes game engines light their scenes with
+//!
+//! Faces are in the order and orientation OpenGL and DirectX read them: +x, -x, +y, -y, +z, -z,
+//! each as seen from inside the cube with rows from the top
+
+use crate::error::Error;
+use crate::image::Image;
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::{pixel_rng, seeded_rng};
+use crate::world::World;
+use crate::{Color, Float, Point3, RenderSettings, Vec3};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// A face of a cubemap, named by the axis it looks along
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    /// Every face, in the order cubemaps store them
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// Short name added to file names, such as `px` for +x
+    pub fn name(self) -> &'static str {
+        match self {
+            CubeFace::PositiveX => "px",
+            CubeFace::NegativeX => "nx",
+            CubeFace::PositiveY => "py",
+            CubeFace::NegativeY => "ny",
+            CubeFace::PositiveZ => "pz",
+            CubeFace::NegativeZ => "nz",
+        }
+    }
+
+    /// Direction through the point `(s, t)` of the face, each from -1 to 1 with `s` to the
+    /// right and `t` down. Not a unit vector
+    pub fn direction(self, s: Float, t: Float) -> Vec3 {
+        match self {
+            CubeFace::PositiveX => vec3!(1., -t, -s),
+            CubeFace::NegativeX => vec3!(-1., -t, s),
+            CubeFace::PositiveY => vec3!(s, 1., t),
+            CubeFace::NegativeY => vec3!(s, -1., -t),
+            CubeFace::PositiveZ => vec3!(s, -t, 1.),
+            CubeFace::NegativeZ => vec3!(-s, -t, -1.),
+        }
+    }
+
+    /// The face `dir` points through, and where, undoing `direction`
+    pub fn from_direction(dir: Vec3) -> (Self, Float, Float) {
+        let (x, y, z) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+        if x >= y && x >= z {
+            if dir.x > 0. {
+                (CubeFace::PositiveX, -dir.z / x, -dir.y / x)
+            } else {
+                (CubeFace::NegativeX, dir.z / x, -dir.y / x)
+            }
+        } else if y >= z {
+            if dir.y > 0. {
+                (CubeFace::PositiveY, dir.x / y, dir.z / y)
+            } else {
+                (CubeFace::NegativeY, dir.x / y, -dir.z / y)
+            }
+        } else if dir.z > 0. {
+            (CubeFace::PositiveZ, dir.x / z, -dir.y / z)
+        } else {
+            (CubeFace::NegativeZ, -dir.x / z, -dir.y / z)
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Six square images of the light arriving from every direction
+#[derive(Clone)]
+pub struct Cubemap {
+    /// In the order of `CubeFace::ALL`
+    pub faces: Vec<Image>,
+}
+
+impl Cubemap {
+    pub fn face(&self, face: CubeFace) -> &Image {
+        &self.faces[face.index()]
+    }
+
+    /// Width and height of each face
+    pub fn size(&self) -> u32 {
+        self.faces[0].width
+    }
+
+    /// The pixel seen looking along `dir`
+    pub fn sample(&self, dir: Vec3) -> Color {
+        let (face, s, t) = CubeFace::from_direction(dir);
+        let size = self.size();
+        let pixel = |coord: Float| (((coord + 1.) / 2. * size as Float) as u32).min(size - 1);
+        self.face(face).pixel(pixel(s), pixel(t))
+    }
+
+    /// Diffuse convolution of the cubemap, with faces `size` pixels across. Each pixel is the
+    /// light a white Lambertian surface facing its way reflects, which is the irradiance over
+    /// pi, so multiplying by an albedo gives the color of a diffuse surface
+    ///
+    /// Every pixel of the result adds up every pixel of the cubemap, so this takes time growing
+    /// with the product of their sizes. Irradiance changes slowly, so 16 or 32 pixels is plenty
+    pub fn irradiance(&self, size: u32) -> Cubemap {
+        // Direction and solid angle of each pixel of the cubemap
+        let source_size = self.size();
+        let texels: Vec<(Vec3, Float, Color)> = CubeFace::ALL
+            .iter()
+            .flat_map(|&face| {
+                face_pixels(source_size).map(move |(x, y, s, t)| {
+                    let dir = face.direction(s, t);
+                    let solid_angle = (1. + s * s + t * t).powf(-1.5);
+                    (dir.unit_vector(), solid_angle, self.face(face).pixel(x, y))
+                })
+            })
+            .collect();
+        let faces = CubeFace::ALL
+            .iter()
+            .map(|&face| {
+                let data = face_pixels(size)
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .map(|(_, _, s, t)| {
+                        let normal = face.direction(s, t).unit_vector();
+                        let (mut total, mut weights) = (color!(), 0.);
+                        for (dir, solid_angle, color) in &texels {
+                            let weight = normal.dot(dir).max(0.) * solid_angle;
+                            total += *color * weight;
+                            weights += weight;
+                        }
+                        // Dividing by the weights rather than pi keeps even light exactly even
+                        total / weights.max(Float::MIN_POSITIVE)
+                    })
+                    .collect();
+                square_image(size, data)
+            })
+            .collect();
+        Cubemap { faces }
+    }
+
+    /// Writes each face to `path` with its name added to the file name, so `probe.exr` is written
+    /// as `probe_px.exr`, `probe_nx.exr` and so on. The format is guessed from the extension
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        for (&face, image) in CubeFace::ALL.iter().zip(&self.faces) {
+            let mut name = format!("{}_{}", stem, face.name());
+            if let Some(extension) = path.extension() {
+                name = format!("{}.{}", name, extension.to_string_lossy());
+            }
+            image.clone().write(path.with_file_name(name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where to bake a probe with `render_probe`
+#[derive(Clone, Debug)]
+pub struct ProbeSettings {
+    /// Where the light is gathered, which should be clear of surfaces
+    pub position: Point3,
+    /// Width and height of each face of the radiance cubemap
+    pub size: u32,
+    /// Time the rays are traced at, for moving objects
+    pub time: Float,
+    /// Width and height of each face of the irradiance cubemap, if one is wanted, see
+    /// `Cubemap::irradiance`
+    pub irradiance_size: Option<u32>,
+}
+
+impl ProbeSettings {
+    /// A reflection probe at `position` with faces `size` pixels across and no irradiance
+    pub fn new(position: Point3, size: u32) -> Self {
+        Self {
+            position,
+            size,
+            time: 0.,
+            irradiance_size: None,
+        }
+    }
+}
+
+/// A baked probe
+pub struct Probe {
+    /// Light arriving at the probe from each direction, for reflections
+    pub radiance: Cubemap,
+    /// Diffuse lighting at the probe for surfaces facing each direction, if asked for
+    pub irradiance: Option<Cubemap>,
+}
+
+/// Renders the light arriving at `settings.position` from every direction into a cubemap, and
+/// its diffuse convolution if `settings.irradiance_size` is set
+///
+/// Each pixel takes `samples_per_pixel` samples spread over it, as with a camera with a 90° field
+/// of view looking through each face. Adaptive sampling, outlier rejection, pixel filters and
+/// transparent backgrounds are ignored. Fails if the acceleration structure can't be built
+pub fn render_probe(
+    world: World,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    settings: &ProbeSettings,
+) -> Result<Probe, Error> {
+    let mut world = world;
+    render_settings
+        .accelerator
+        .build(&mut world, settings.time, settings.time)?;
+    let world = &world;
+    let size = settings.size;
+    let samples_per_pixel = render_settings.samples_per_pixel.max(1);
+    let faces = CubeFace::ALL
+        .iter()
+        .map(|&face| {
+            let pixels: Vec<_> = face_pixels(size).collect();
+            let data = pixels
+                .into_par_iter()
+                .map_init(
+                    || {
+                        let rng = seeded_rng(render_settings.seed);
+                        render_settings.sampler.create_ctx(samples_per_pixel, rng)
+                    },
+                    |ctx, (x, y, _, _)| {
+                        // Seed each pixel of each face separately
+                        let row = face.index() as u32 * size + y;
+                        ctx.start_pixel_with(x, row, pixel_rng(render_settings.seed, x, row));
+                        let mut total = color!();
+                        for k in 0..samples_per_pixel {
+                            ctx.start_sample(k);
+                            let (dx, dy) = ctx.pixel_sample();
+                            let s = 2. * (x as Float + dx) / size as Float - 1.;
+                            let t = 2. * (y as Float + dy) / size as Float - 1.;
+                            let dir = face.direction(s, t).unit_vector();
+                            let ray = Ray {
+                                sample: ctx.ray_sample(),
+                                ..Ray::new(settings.position, dir, settings.time)
+                            };
+                            total += integrator.li(&ray, world, render_settings, ctx);
+                        }
+                        total / samples_per_pixel as Float
+                    },
+                )
+                .collect();
+            square_image(size, data)
+        })
+        .collect();
+    let radiance = Cubemap { faces };
+    let irradiance = settings
+        .irradiance_size
+        .map(|size| radiance.irradiance(size));
+    Ok(Probe {
+        radiance,
+        irradiance,
+    })
+}
+
+/// Each pixel of a face `size` pixels across, with the face coordinates of its center, see
+/// `CubeFace::direction`, in rows from the top
+fn face_pixels(size: u32) -> impl Iterator<Item = (u32, u32, Float, Float)> {
+    let coord = move |pixel: u32| 2. * (pixel as Float + 0.5) / size as Float - 1.;
+    (0..size).flat_map(move |y| (0..size).map(move |x| (x, y, coord(x), coord(y))))
+}
+
+fn square_image(size: u32, data: Vec<Color>) -> Image {
+    Image {
+        width: size,
+        height: size,
+        data,
+        alpha: None,
+    }
+}
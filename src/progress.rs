@@ -0,0 +1,102 @@
+//! Progress reporting that estimates the time left from the work done rather than lines finished
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Pixels timed on each line when estimating costs
+const PROBES_PER_LINE: u32 = 32;
+
+/// Progress units per sample of average cost, so fractional costs survive rounding
+const UNITS_PER_SAMPLE: f64 = 1000.;
+
+/// A progress bar where each tile counts for how long its samples take
+///
+/// Tiles full of glass or smoke take much longer than tiles of sky, so counting finished tiles
+/// gives a wildly wrong estimate of the time left. Instead each tile is weighted by the measured
+/// cost of its samples, and the total shrinks when tiles finish with fewer samples than planned
+pub struct RenderProgress {
+    bar: ProgressBar,
+    /// Relative cost of one sample in each tile, averaging 1
+    costs: Vec<f64>,
+    /// Samples each tile is expected to take
+    planned_samples: Vec<u64>,
+    /// Length of the bar, which the bar doesn't let us read back
+    total: Mutex<u64>,
+}
+
+impl RenderProgress {
+    /// `costs` are the relative costs of a sample in each tile and `planned_samples` how many
+    /// samples each tile should take
+    pub fn new(costs: Vec<f64>, planned_samples: Vec<u64>) -> Self {
+        let mean = costs.iter().sum::<f64>() / costs.len().max(1) as f64;
+        let costs: Vec<f64> = if mean > 0. {
+            costs.iter().map(|cost| cost / mean).collect()
+        } else {
+            vec![1.; costs.len()]
+        };
+        let total: u64 = costs
+            .iter()
+            .zip(planned_samples.iter())
+            .map(|(&cost, &samples)| work(cost, samples))
+            .sum();
+        let bar = ProgressBar::new(total);
+        bar.set_style(
+            ProgressStyle::default_bar().template(
+                "Rendering - Done {elapsed:>3} Estimated {eta:>3} {wide_bar} {percent:>3}%",
+            ),
+        );
+        Self {
+            bar,
+            costs,
+            planned_samples,
+            total: Mutex::new(total),
+        }
+    }
+
+    /// Records that `tile` finished after taking `samples` samples
+    pub fn finish_tile(&self, tile: usize, samples: u64) {
+        let cost = self.costs.get(tile).copied().unwrap_or(1.);
+        let planned = work(cost, self.planned_samples.get(tile).copied().unwrap_or(0));
+        let done = work(cost, samples);
+        if done != planned {
+            // Tiles can stop early or go on longer than planned
+            let mut total = self.total.lock().unwrap();
+            *total = (*total + done).saturating_sub(planned);
+            self.bar.set_length(*total);
+        }
+        self.bar.inc(done);
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish();
+    }
+}
+
+fn work(cost: f64, samples: u64) -> u64 {
+    (cost * samples as f64 * UNITS_PER_SAMPLE).round() as u64
+}
+
+/// Measures how long a sample takes on each of `lines` lines
+///
+/// `sample(line, pixel)` traces one sample at `pixel` along `line`. A few pixels spread along each
+/// line are timed
+pub fn estimate_line_costs<F>(lines: u32, width: u32, sample: F) -> Vec<f64>
+where
+    F: Fn(u32, u32) + Sync,
+{
+    let stride = (width / PROBES_PER_LINE).max(1);
+    (0..lines)
+        .into_par_iter()
+        .map(|line| {
+            let start = Instant::now();
+            let mut count = 0;
+            for pixel in (0..width).step_by(stride as usize) {
+                sample(line, pixel);
+                count += 1;
+            }
+            start.elapsed().as_secs_f64() / count.max(1) as f64
+        })
+        .collect()
+}
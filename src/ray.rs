@@ -1,17 +1,32 @@
-use crate::{Point3, Vec3};
+use crate::{Float, Point3, Vec3};
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Point3,
     pub dir: Vec3,
-    pub time: f64,
+    pub time: Float,
+    /// Part of the ray, in multiples of `dir`, where hits count when finding what it sees. Camera
+    /// rays are cut short by the camera's clipping distances, other rays run from 0 to infinity
+    pub t_min: Float,
+    pub t_max: Float,
+    /// Random bits for hits that are random themselves, like collisions inside media. Drawn from
+    /// the render's sampler for each ray it traces, and 0 for rays made elsewhere
+    pub sample: u64,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, dir: Vec3, time: f64) -> Self {
-        Ray { origin, dir, time }
+    pub fn new(origin: Point3, dir: Vec3, time: Float) -> Self {
+        Ray {
+            origin,
+            dir,
+            time,
+            t_min: 0.,
+            t_max: Float::INFINITY,
+            sample: 0,
+        }
     }
 
-    pub fn at(&self, t: f64) -> Point3 {
+    pub fn at(&self, t: Float) -> Point3 {
         self.origin + (self.dir * t).conv()
     }
 }
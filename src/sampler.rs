@@ -0,0 +1,576 @@
+//! Sources of the sample values that pick pixel positions, lens positions, times and bounce
+//! directions
+//!
+//! Samples are drawn in dimensions. Each pixel sample starts at the first dimension and every
+//! call takes the next one, so the same call in the same place along a path always gets the
+//! same dimension. Low discrepancy samplers spread each dimension's values evenly over the
+//! samples of a pixel
+
+use crate::Float;
+use rand::distributions::{Distribution, Standard, Uniform};
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+/// Random number generator used while rendering
+///
+/// `StdRng` by default. The `pcg` and `xoshiro` features swap in smaller, faster generators,
+/// which are plenty random enough for sampling but not for anything needing security. Renders
+/// with the same seed differ between generators
+#[cfg(not(any(feature = "pcg", feature = "xoshiro")))]
+pub type SampleRng = rand::rngs::StdRng;
+#[cfg(all(feature = "pcg", not(feature = "xoshiro")))]
+pub type SampleRng = rand_pcg::Pcg64Mcg;
+#[cfg(feature = "xoshiro")]
+pub type SampleRng = rand_xoshiro::Xoshiro256PlusPlus;
+
+/// Random number generator seeded from `seed`, or randomly if `None`
+pub fn seeded_rng(seed: Option<u64>) -> SampleRng {
+    match seed {
+        Some(seed) => SampleRng::seed_from_u64(seed),
+        None => SampleRng::from_rng(rand::thread_rng()).expect("Error seeding random numbers"),
+    }
+}
+
+pub trait Sampler {
+    /// Starts taking samples for the pixel at `x`, `y`
+    fn start_pixel(&mut self, x: u32, y: u32);
+
+    /// Starts the `index`th sample of the current pixel, going back to the first dimension
+    fn start_sample(&mut self, index: u32);
+
+    /// The next sample value, between 0 and 1
+    fn get_1d(&mut self) -> Float;
+
+    /// The next pair of sample values, each between 0 and 1
+    fn get_2d(&mut self) -> (Float, Float);
+
+    /// Position within the pixel. Taken first, before any other dimensions
+    fn pixel_sample(&mut self) -> (Float, Float) {
+        self.get_2d()
+    }
+
+    /// Position on the camera lens
+    fn lens_sample(&mut self) -> (Float, Float) {
+        self.get_2d()
+    }
+
+    /// Point during the shutter interval
+    fn time_sample(&mut self) -> Float {
+        self.get_1d()
+    }
+
+    /// Plain random numbers for anything that doesn't fit a fixed number of dimensions, like
+    /// rejection sampling
+    fn rng(&mut self) -> &mut SampleRng;
+}
+
+/// Which sampler to render with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    /// Independent random samples, which can leave clumps and gaps. Shutter times are still
+    /// spread evenly
+    Random,
+    /// Jittered within an N×N grid of cells, using the largest grid the sample count fills.
+    /// Leftover samples are random
+    Stratified,
+    /// The Halton sequence, randomly shifted for each pixel
+    Halton,
+    /// The Sobol sequence, randomly scrambled for each pixel. Best with a power of two samples
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Creates a sampler of this kind
+    ///
+    /// `samples_per_pixel` is how many samples each pixel will take and `rng` seeds the sampler's
+    /// randomization
+    pub fn create(self, samples_per_pixel: u32, rng: SampleRng) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Random => {
+                let mut sampler = RandomSampler::new(rng);
+                sampler.samples_per_pixel = samples_per_pixel;
+                Box::new(sampler)
+            }
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, rng)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(rng)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(rng)),
+        }
+    }
+
+    /// Creates a context drawing from a sampler of this kind, see `create`
+    pub fn create_ctx(self, samples_per_pixel: u32, rng: SampleRng) -> SampleCtx {
+        SampleCtx::new(self.create(samples_per_pixel, rng))
+    }
+}
+
+/// Everything one thread needs to draw samples, handed down through cameras, integrators,
+/// materials and PDFs
+///
+/// Made once for each thread or tile and reused for every pixel it renders, so the sampler can
+/// keep state between pixels and distributions are only worked out once
+pub struct SampleCtx {
+    sampler: Box<dyn Sampler>,
+    /// Between -1 and 1
+    signed_unit: Uniform<Float>,
+}
+
+impl SampleCtx {
+    pub fn new(sampler: Box<dyn Sampler>) -> Self {
+        Self {
+            sampler,
+            signed_unit: Uniform::new(-1., 1.),
+        }
+    }
+
+    /// A context with a random sampler seeded from `seed`, or randomly if `None`
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        Self::new(Box::new(RandomSampler::from_seed(seed)))
+    }
+
+    /// Starts taking samples for the pixel at `x`, `y`
+    pub fn start_pixel(&mut self, x: u32, y: u32) {
+        self.sampler.start_pixel(x, y);
+    }
+
+    /// Starts taking samples for the pixel at `x`, `y` with random numbers from `rng`, so they
+    /// don't depend on the pixels rendered before it
+    pub fn start_pixel_with(&mut self, x: u32, y: u32, rng: SampleRng) {
+        *self.sampler.rng() = rng;
+        self.sampler.start_pixel(x, y);
+    }
+
+    /// Starts the `index`th sample of the current pixel
+    pub fn start_sample(&mut self, index: u32) {
+        self.sampler.start_sample(index);
+    }
+
+    /// The next sample value, between 0 and 1
+    pub fn get_1d(&mut self) -> Float {
+        self.sampler.get_1d()
+    }
+
+    /// The next pair of sample values, each between 0 and 1
+    pub fn get_2d(&mut self) -> (Float, Float) {
+        self.sampler.get_2d()
+    }
+
+    /// Position within the pixel
+    pub fn pixel_sample(&mut self) -> (Float, Float) {
+        self.sampler.pixel_sample()
+    }
+
+    /// Position on the camera lens
+    pub fn lens_sample(&mut self) -> (Float, Float) {
+        self.sampler.lens_sample()
+    }
+
+    /// Point during the shutter interval
+    pub fn time_sample(&mut self) -> Float {
+        self.sampler.time_sample()
+    }
+
+    /// Index below `count` picked by the next sample value, each equally often
+    pub fn choose(&mut self, count: usize) -> usize {
+        ((self.get_1d() * count as Float) as usize).min(count.saturating_sub(1))
+    }
+
+    /// Plain random numbers, see `Sampler::rng`
+    pub fn rng(&mut self) -> &mut SampleRng {
+        self.sampler.rng()
+    }
+
+    /// Random bits for a ray about to be traced, see `Ray::sample`
+    pub fn ray_sample(&mut self) -> u64 {
+        self.sampler.rng().gen()
+    }
+
+    /// A pair of random values, each between -1 and 1
+    pub fn signed_pair(&mut self) -> (Float, Float) {
+        let rng = self.sampler.rng();
+        (self.signed_unit.sample(rng), self.signed_unit.sample(rng))
+    }
+}
+
+/// Independent random samples, apart from shutter times
+pub struct RandomSampler {
+    rng: SampleRng,
+    /// Samples each pixel takes. Shutter times are spread over this many even slices of the
+    /// shutter interval, one sample each, so motion blur converges faster. Defaults to 1, which
+    /// leaves times independent
+    pub samples_per_pixel: u32,
+    /// Random value for the current pixel, which picks the order of the time slices
+    pixel_seed: u32,
+    index: u32,
+}
+
+impl RandomSampler {
+    pub fn new(rng: SampleRng) -> Self {
+        Self {
+            rng,
+            samples_per_pixel: 1,
+            pixel_seed: 0,
+            index: 0,
+        }
+    }
+
+    /// A sampler seeded from `seed`, or randomly if `None`
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        Self::new(seeded_rng(seed))
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn start_pixel(&mut self, _: u32, _: u32) {
+        self.pixel_seed = self.rng.gen();
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        self.rng.sample(Standard)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.rng.sample(Standard), self.rng.sample(Standard))
+    }
+
+    fn time_sample(&mut self) -> Float {
+        let slices = self.samples_per_pixel;
+        let jitter = self.get_1d();
+        if slices <= 1 || self.index >= slices {
+            return jitter;
+        }
+        let slice = permute(self.index, slices, self.pixel_seed);
+        (slice as Float + jitter) / slices as Float
+    }
+
+    fn rng(&mut self) -> &mut SampleRng {
+        &mut self.rng
+    }
+}
+
+/// Splits every dimension into cells with one sample each, pairing up the cells of different
+/// dimensions at random
+pub struct StratifiedSampler {
+    rng: SampleRng,
+    /// Cells along each side of the grid
+    strata: u32,
+    /// Random value for the current pixel, which picks how cells are paired up
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, rng: SampleRng) -> Self {
+        let mut rng = rng;
+        Self {
+            strata: (samples_per_pixel as Float).sqrt() as u32,
+            pixel_seed: rng.gen(),
+            rng,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The current sample's cell in the next dimension, or `None` if there aren't enough cells
+    fn next_cell(&mut self, cells: u32) -> Option<u32> {
+        self.dimension += 1;
+        if self.index >= cells {
+            return None;
+        }
+        let seed = hash(&(self.pixel_seed, self.dimension)) as u32;
+        Some(permute(self.index, cells, seed))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.pixel_seed = hash(&(self.rng.gen::<u64>(), x, y));
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let cells = self.strata * self.strata;
+        let jitter = self.rng.sample::<Float, _>(Standard);
+        match self.next_cell(cells) {
+            Some(cell) => (cell as Float + jitter) / cells as Float,
+            None => jitter,
+        }
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let strata = self.strata;
+        let jitter = (self.rng.sample(Standard), self.rng.sample(Standard));
+        match self.next_cell(strata * strata) {
+            Some(cell) => (
+                ((cell % strata) as Float + jitter.0) / strata as Float,
+                ((cell / strata) as Float + jitter.1) / strata as Float,
+            ),
+            None => jitter,
+        }
+    }
+
+    fn rng(&mut self) -> &mut SampleRng {
+        &mut self.rng
+    }
+}
+
+/// Bases of the Halton sequence's dimensions
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Samples from the Halton sequence, which reverses the digits of the sample index in a
+/// different prime base for each dimension
+///
+/// Each pixel gets its own random shift of every dimension. Dimensions past the 32nd are random
+pub struct HaltonSampler {
+    rng: SampleRng,
+    pixel_seed: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(rng: SampleRng) -> Self {
+        let mut rng = rng;
+        Self {
+            pixel_seed: rng.gen(),
+            rng,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.pixel_seed = hash(&(self.rng.gen::<u64>(), x, y));
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let shift = to_unit(hash(&(self.pixel_seed, dimension)) as u32);
+                (radical_inverse(base, self.index) + shift).fract()
+            }
+            None => self.rng.sample(Standard),
+        }
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn rng(&mut self) -> &mut SampleRng {
+        &mut self.rng
+    }
+}
+
+/// Degree, coefficients and initial direction numbers of the Sobol sequence's dimensions after
+/// the first, from Joe and Kuo
+const SOBOL_PARAMETERS: [(u32, u32, &[u32]); 15] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+];
+
+/// Samples from the Sobol sequence, a base 2 sequence whose first power of two samples are
+/// spread especially evenly
+///
+/// Each pixel scrambles every dimension with its own random bits. Dimensions past the 16th are
+/// random
+pub struct SobolSampler {
+    rng: SampleRng,
+    /// Direction numbers of each dimension
+    directions: &'static [[u32; 32]],
+    pixel_seed: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl SobolSampler {
+    pub fn new(rng: SampleRng) -> Self {
+        let mut rng = rng;
+        Self {
+            directions: sobol_directions(),
+            pixel_seed: rng.gen(),
+            rng,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+/// Direction numbers of each dimension of the Sobol sequence, worked out on first use
+fn sobol_directions() -> &'static [[u32; 32]] {
+    static DIRECTIONS: OnceLock<Vec<[u32; 32]>> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        // The first dimension is the van der Corput sequence
+        let mut directions = vec![[0; 32]];
+        for (bit, direction) in directions[0].iter_mut().enumerate() {
+            *direction = 1 << (31 - bit);
+        }
+        for &(degree, coefficients, initial) in SOBOL_PARAMETERS.iter() {
+            let degree = degree as usize;
+            let mut v = [0_u32; 32];
+            for (bit, &m) in initial.iter().enumerate() {
+                v[bit] = m << (31 - bit);
+            }
+            for bit in degree..32 {
+                v[bit] = v[bit - degree] ^ (v[bit - degree] >> degree);
+                for k in 1..degree {
+                    if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                        v[bit] ^= v[bit - k];
+                    }
+                }
+            }
+            directions.push(v);
+        }
+        directions
+    })
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.pixel_seed = hash(&(self.rng.gen::<u64>(), x, y));
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let directions = match self.directions.get(dimension) {
+            Some(directions) => directions,
+            None => return self.rng.sample(Standard),
+        };
+        let mut value = 0;
+        let mut index = self.index;
+        for direction in directions.iter() {
+            if index == 0 {
+                break;
+            }
+            if index & 1 == 1 {
+                value ^= direction;
+            }
+            index >>= 1;
+        }
+        // Flipping the same bits of every sample keeps them spread evenly
+        let scramble = hash(&(self.pixel_seed, dimension)) as u32;
+        to_unit(value ^ scramble)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn rng(&mut self) -> &mut SampleRng {
+        &mut self.rng
+    }
+}
+
+/// Random number generator for the pixel at `x`, `y`, seeded from `seed` or randomly if `None`
+///
+/// Pixels get unrelated streams even from nearby seeds
+pub fn pixel_rng(seed: Option<u64>, x: u32, y: u32) -> SampleRng {
+    seeded_rng(seed.map(|seed| hash(&(seed, x, y))))
+}
+
+/// Random number generator for sample `index` of the pixel at `x`, `y`, for renders that come
+/// back to each pixel once a pass rather than taking its samples in one go
+pub fn sample_rng(seed: u64, x: u32, y: u32, index: u32) -> SampleRng {
+    SampleRng::seed_from_u64(hash(&(seed, x, y, index)))
+}
+
+/// Digits of `index` in `base` mirrored around the decimal point
+fn radical_inverse(base: u32, mut index: u32) -> Float {
+    let inv_base = 1. / base as Float;
+    let mut inv = inv_base;
+    let mut result = 0.;
+    while index > 0 {
+        result += (index % base) as Float * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+/// Converts 32 random bits to a value between 0 and 1
+fn to_unit(bits: u32) -> Float {
+    bits as Float / (1_u64 << 32) as Float
+}
+
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Where `index` goes in a random permutation of `0..length` picked by `seed`
+///
+/// Kensler's hash based permutation from "Correlated Multi-Jittered Sampling"
+fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // Permute within the next power of two, repeating until the result is in range
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            return (index.wrapping_add(seed)) % length;
+        }
+    }
+}
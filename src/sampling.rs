@@ -0,0 +1,155 @@
+//! Maps sample values between 0 and 1 to points and directions, along with the density of each
+//!
+//! Every mapping is a direct function of its sample values rather than rejection sampling, so
+//! evenly spread samples from a low discrepancy sampler stay evenly spread. Directions are in a
+//! local space around the z axis; place them around a normal with `Onb`
+
+use crate::consts::PI;
+use crate::{Float, Vec3};
+
+/// The golden ratio, which spreads spherical Fibonacci points around the axis
+const GOLDEN_RATIO: Float = 1.618_033_988_749_895;
+
+/// A point spread evenly over the unit disk in the xy plane
+///
+/// Shirley and Chiu's concentric mapping, which squashes squares into rings so nearby sample
+/// values stay nearby on the disk
+pub fn disk((u, v): (Float, Float)) -> Vec3 {
+    let (a, b) = (2. * u - 1., 2. * v - 1.);
+    if a == 0. && b == 0. {
+        return vec3!();
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4. * (b / a))
+    } else {
+        (b, PI / 2. - PI / 4. * (a / b))
+    };
+    vec3!(r * phi.cos(), r * phi.sin(), 0.)
+}
+
+/// Density of `disk` over area
+pub fn disk_pdf() -> Float {
+    1. / PI
+}
+
+/// A direction spread evenly over the unit sphere
+pub fn uniform_sphere((u, v): (Float, Float)) -> Vec3 {
+    let z = 1. - 2. * u;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * v;
+    vec3!(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Density of `uniform_sphere` over solid angle
+pub fn uniform_sphere_pdf() -> Float {
+    1. / (4. * PI)
+}
+
+/// A direction spread evenly over the hemisphere around +z
+pub fn uniform_hemisphere((u, v): (Float, Float)) -> Vec3 {
+    let z = u;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * v;
+    vec3!(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Density of `uniform_hemisphere` over solid angle
+pub fn uniform_hemisphere_pdf() -> Float {
+    1. / (2. * PI)
+}
+
+/// A direction in the hemisphere around +z, more likely the closer it is to +z in proportion
+/// to the cosine, matching diffuse reflection
+///
+/// Projects a point on the disk up onto the hemisphere, Malley's method
+pub fn cosine_hemisphere(sample: (Float, Float)) -> Vec3 {
+    let d = disk(sample);
+    let z = (1. - d.x * d.x - d.y * d.y).max(0.).sqrt();
+    vec3!(d.x, d.y, z)
+}
+
+/// Density of `cosine_hemisphere` choosing a direction at `cos_theta` from +z, over solid angle
+pub fn cosine_hemisphere_pdf(cos_theta: Float) -> Float {
+    cos_theta.max(0.) / PI
+}
+
+/// A direction spread evenly over the cone around +z of directions within `acos(cos_max)` of it
+pub fn uniform_cone((u, v): (Float, Float), cos_max: Float) -> Vec3 {
+    let cos_theta = 1. - u * (1. - cos_max);
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * v;
+    vec3!(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Density of `uniform_cone` over solid angle
+pub fn uniform_cone_pdf(cos_max: Float) -> Float {
+    1. / (2. * PI * (1. - cos_max))
+}
+
+/// The `index`th of `count` directions spread evenly over the unit sphere, without randomness
+///
+/// Spherical Fibonacci points, which wind around the sphere from the +z pole to the -z pole
+/// turning by the golden angle at each step. Good for probes and precomputed tables that want
+/// the same directions every time
+pub fn spherical_fibonacci(index: u32, count: u32) -> Vec3 {
+    let z = 1. - (2. * index as Float + 1.) / count as Float;
+    let r = (1. - z * z).max(0.).sqrt();
+    let turns = index as Float / GOLDEN_RATIO;
+    let phi = 2. * PI * (turns - turns.floor());
+    vec3!(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Density of microfacet normals at `cos_theta` from +z for the GGX distribution with roughness
+/// `alpha`, over solid angle and weighted by how much they face +z so it integrates to 1
+pub fn ggx_d(cos_theta: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let denominator = cos_theta * cos_theta * (a2 - 1.) + 1.;
+    a2 / (PI * denominator * denominator)
+}
+
+/// Share of microfacets facing `dir` that aren't hidden by others, for the GGX distribution
+/// with roughness `alpha`. Smith's masking function
+pub fn ggx_g1(dir: Vec3, alpha: Float) -> Float {
+    let cos = dir.z;
+    if cos <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    2. * cos / (cos + (a2 + (1. - a2) * cos * cos).sqrt())
+}
+
+/// A microfacet normal of the GGX distribution with roughness `alpha`, chosen in proportion to
+/// how much of it is seen from `view`, which must be above the surface
+///
+/// Heitz's sampling of visible normals, which never picks normals facing away from `view` and so
+/// wastes far fewer samples at grazing angles than sampling the whole distribution
+pub fn ggx_visible_normal((u, v): (Float, Float), view: Vec3, alpha: Float) -> Vec3 {
+    // Stretch the view to the configuration where the distribution is a hemisphere
+    let stretched = vec3!(alpha * view.x, alpha * view.y, view.z).unit_vector();
+    let length_squared = stretched.x * stretched.x + stretched.y * stretched.y;
+    let t1 = if length_squared > 0. {
+        vec3!(-stretched.y, stretched.x, 0.) / length_squared.sqrt()
+    } else {
+        vec3!(1., 0., 0.)
+    };
+    let t2 = stretched.cross(&t1);
+    // A point on the disk, squashed towards the half of it the view can see
+    let r = u.sqrt();
+    let phi = 2. * PI * v;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1. + stretched.z);
+    let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * r * phi.sin();
+    let up = (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+    let normal = t1 * p1 + t2 * p2 + stretched * up;
+    vec3!(alpha * normal.x, alpha * normal.y, normal.z.max(0.)).unit_vector()
+}
+
+/// Density of `ggx_visible_normal` choosing a normal that reflects `view` into `dir`, over the
+/// solid angle of `dir`. Both are in the local space around +z
+pub fn ggx_reflection_pdf(view: Vec3, dir: Vec3, alpha: Float) -> Float {
+    if view.z <= 0. || dir.z <= 0. {
+        return 0.;
+    }
+    let half = (view + dir).unit_vector();
+    ggx_g1(view, alpha) * ggx_d(half.z, alpha) / (4. * view.z)
+}
@@ -0,0 +1,1127 @@
+//! Scene description files, so scenes can be put together without writing Rust
+//!
+//! Scenes are JSON. Vectors, points and colors are arrays of three numbers, and everything with
+//! several kinds, like materials and objects, says which it is in a `type` field:
+//!
+//! ```json
+//! {
+//!     "camera": { "look_from": [13, 2, 3], "look_at": [0, 0, 0], "vfov": 20 },
+//!     "render": { "width": 400, "height": 225, "samples_per_pixel": 64 },
+//!     "background": { "type": "sky" },
+//!     "materials": { "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } },
+//!     "objects": [
+//!         { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+//!         { "type": "sphere", "center": [0, 1, 0], "radius": 1,
+//!           "material": { "type": "dielectric", "ior": 1.5 } }
+//!     ]
+//! }
+//! ```
+//!
+//! Materials, textures and objects registered by other crates are used with a `plugin` type, see
+//! `plugin`
+//!
+//! Objects made of a `light` material are added as lights so they're sampled directly. Meshes
+//! can't be loaded from scene files yet
+//!
+//! Worlds built in code can be saved with `SceneFile::from_world`, so randomly generated ones can
+//! be rendered again later with the same layout
+
+use crate::background::{
+    Background, EnvironmentMap, GradientBackground, PreethamSky, SolidBackground, SKY_INTENSITY,
+};
+use crate::camera::{ApertureMask, CameraSettings};
+use crate::csg::{Csg, CsgOperation};
+use crate::error::Error;
+use crate::hittable::{Cone, ConstantMedium, Cuboid, Cylinder, Hittable, MovingSphere, Sphere};
+use crate::hittable::{Triangle, XYRect, XZRect, YZRect};
+use crate::light::{LightPower, SunLight};
+use crate::material::{Coated, Dielectric, Isotropic, Lambertian, Light, Material, Metal};
+use crate::material::{Layered, Mix, NormalMapped, PbrMaterial, SurfaceMap, CLEARCOAT_IOR};
+use crate::plugin;
+use crate::texture::{Checker, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::transform::{RotateY, Translate};
+use crate::world::{HeightFog, World};
+use crate::{Color, Float, Point3, RenderSettings, Vec3};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Materials built from a scene file's `materials`, by name
+type NamedMaterials = BTreeMap<String, Arc<dyn Material + Send + Sync>>;
+
+/// A scene loaded from a file, ready to render
+pub struct Scene {
+    pub world: World,
+    pub camera: CameraSettings,
+    pub settings: RenderSettings,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Scene {
+    /// Loads a scene from a JSON file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        SceneFile::load(path)?.build()
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, Error> {
+        let file: SceneFile = serde_json::from_str(source)?;
+        file.build()
+    }
+}
+
+/// The contents of a scene file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub render: RenderDesc,
+    #[serde(default)]
+    pub background: BackgroundDesc,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sun: Option<SunDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fog: Option<FogDesc>,
+    /// Materials objects can refer to by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    pub objects: Vec<ObjectDesc>,
+}
+
+impl SceneFile {
+    pub fn build(&self) -> Result<Scene, Error> {
+        let mut world = World::default();
+        // Build each named material once, so every object using it shares it
+        let named = self
+            .materials
+            .iter()
+            .map(|(name, material)| Ok((name.clone(), material.build(self)?)))
+            .collect::<Result<_, Error>>()?;
+        for object in &self.objects {
+            let is_light = match object.material() {
+                Some(material) => matches!(self.resolve(material)?, MaterialDesc::Light { .. }),
+                None => false,
+            };
+            let hittable = self.build_object(object, &named)?;
+            if is_light {
+                world.lights.push(hittable);
+            } else {
+                world.hittables.push(hittable);
+            }
+        }
+        if let Some(sun) = &self.sun {
+            world.sun = Some(SunLight::new(
+                sun.direction.into(),
+                sun.angular_diameter,
+                sun.irradiance.into(),
+            ));
+        }
+        if let Some(fog) = &self.fog {
+            let mut height_fog = HeightFog::new(fog.density, fog.falloff, fog.color.into());
+            height_fog.height = fog.height;
+            world.fog = Some(height_fog);
+        }
+
+        let render = &self.render;
+        let settings = RenderSettings {
+            samples_per_pixel: render.samples_per_pixel,
+            max_depth: render.max_depth,
+            seed: render.seed,
+            background: self.background.build()?,
+            ..Default::default()
+        };
+        Ok(Scene {
+            world,
+            camera: self.camera.build()?,
+            settings,
+            width: render.width,
+            height: render.height,
+        })
+    }
+
+    /// Describes a world built in code, so it can be saved and rendered again later
+    ///
+    /// Fails if the world holds something scene files can't, like meshes or an atmosphere, or the
+    /// camera's aperture mask wasn't loaded from a file
+    pub fn from_world(
+        world: &World,
+        camera: &CameraSettings,
+        settings: &RenderSettings,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, Error> {
+        if world.atmosphere.is_some() {
+            let reason = "atmospheres can't be saved to scene files".to_owned();
+            return Err(Error::Scene(reason));
+        }
+        let background = settings.background.describe().ok_or_else(|| {
+            Error::Scene("the background can't be saved to a scene file".to_owned())
+        })?;
+        let mut objects = Vec::new();
+        for hittable in world.hittables.iter().chain(world.lights.iter()) {
+            describe_all(hittable.as_ref(), &mut objects)?;
+        }
+        Ok(Self {
+            camera: CameraDesc::try_from(camera)?,
+            render: RenderDesc {
+                width,
+                height,
+                samples_per_pixel: settings.samples_per_pixel,
+                max_depth: settings.max_depth,
+                seed: settings.seed,
+            },
+            background,
+            sun: world.sun.as_ref().map(|sun| SunDesc {
+                direction: sun.direction.into(),
+                angular_diameter: sun.angular_diameter,
+                irradiance: sun.irradiance.into(),
+            }),
+            fog: world.fog.map(|fog| FogDesc {
+                density: fog.density,
+                falloff: fog.falloff,
+                height: fog.height,
+                color: fog.color.into(),
+            }),
+            materials: BTreeMap::new(),
+            objects,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Error serializing scene")
+    }
+
+    /// Reads a JSON file without building the scene
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the scene to a JSON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Looks up named materials
+    fn resolve<'s>(&'s self, material: &'s MaterialRef) -> Result<&'s MaterialDesc, Error> {
+        match material {
+            MaterialRef::Named(name) => self
+                .materials
+                .get(name)
+                .ok_or_else(|| Error::Scene(format!("Unknown material `{}` in scene file", name))),
+            MaterialRef::Inline(material) => Ok(material),
+        }
+    }
+
+    fn build_material(
+        &self,
+        material: &MaterialRef,
+    ) -> Result<Arc<dyn Material + Send + Sync>, Error> {
+        self.resolve(material)?.build(self)
+    }
+
+    /// Builds an object, sharing the materials in `named` with other objects that use them
+    fn build_object(
+        &self,
+        object: &ObjectDesc,
+        named: &NamedMaterials,
+    ) -> Result<Box<dyn Hittable + Send + Sync>, Error> {
+        Ok(match object {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let shape = |material| {
+                    Box::new(Sphere::new_shared(Point3::from(*center), *radius, material))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                let shape = |material| {
+                    Box::new(MovingSphere::new_shared(
+                        Point3::from(*center0),
+                        Point3::from(*center1),
+                        *time0,
+                        *time1,
+                        *radius,
+                        material,
+                    ))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::Triangle { vertices, material } => {
+                let [p0, p1, p2] = *vertices;
+                let shape = |material| {
+                    Box::new(Triangle::new_shared(
+                        Point3::from(p0),
+                        Point3::from(p1),
+                        Point3::from(p2),
+                        material,
+                    ))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::XyRect {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => {
+                let shape =
+                    |material| Box::new(XYRect::new_shared(*x0, *x1, *y0, *y1, *k, material));
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::XzRect {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let shape =
+                    |material| Box::new(XZRect::new_shared(*x0, *x1, *z0, *z1, *k, material));
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::YzRect {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let shape =
+                    |material| Box::new(YZRect::new_shared(*y0, *y1, *z0, *z1, *k, material));
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::Cuboid { min, max, material } => {
+                let shape = |material| {
+                    Box::new(Cuboid::new_shared(
+                        Point3::from(*min),
+                        Point3::from(*max),
+                        material,
+                    ))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::Cylinder {
+                base,
+                radius,
+                height,
+                capped,
+                material,
+            } => {
+                let shape = |material| {
+                    Box::new(Cylinder::new_shared(
+                        Point3::from(*base),
+                        *radius,
+                        *height,
+                        *capped,
+                        material,
+                    ))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::Cone {
+                base,
+                radius,
+                height,
+                capped,
+                material,
+            } => {
+                let shape = |material| {
+                    Box::new(Cone::new_shared(
+                        Point3::from(*base),
+                        *radius,
+                        *height,
+                        *capped,
+                        material,
+                    ))
+                };
+                self.build_shape(material, named, shape)?
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                density,
+                albedo,
+            } => Box::new(ConstantMedium::new_boxed(
+                self.build_object(boundary, named)?,
+                *density,
+                Arc::new(Isotropic::new_shared(albedo.build()?)),
+            )),
+            ObjectDesc::Translate { object, offset } => Box::new(Translate::new_boxed(
+                self.build_object(object, named)?,
+                Vec3::from(*offset),
+            )),
+            ObjectDesc::RotateY { object, angle } => Box::new(RotateY::new_boxed(
+                self.build_object(object, named)?,
+                *angle,
+            )),
+            ObjectDesc::Csg { operation, a, b } => Box::new(Csg::new_boxed(
+                *operation,
+                self.build_object(a, named)?,
+                self.build_object(b, named)?,
+            )),
+            ObjectDesc::Plugin {
+                name,
+                params,
+                material: Some(material),
+            } => self.build_around(material, named, |material| {
+                plugin::build_hittable(name, params, Some(material))
+            })?,
+            ObjectDesc::Plugin {
+                name,
+                params,
+                material: None,
+            } => plugin::build_hittable(name, params, None)?,
+        })
+    }
+
+    /// Builds a shape around its material, measuring its area first for lights given a power
+    fn build_shape<S, H>(
+        &self,
+        material: &MaterialRef,
+        named: &NamedMaterials,
+        shape: S,
+    ) -> Result<Box<dyn Hittable + Send + Sync>, Error>
+    where
+        S: Fn(Arc<dyn Material + Send + Sync>) -> Box<H>,
+        H: Hittable + Send + Sync + 'static,
+    {
+        self.build_around(material, named, |material| Ok(shape(material)))
+    }
+
+    /// Like `build_shape`, for hittables that can fail to build
+    fn build_around<B>(
+        &self,
+        material: &MaterialRef,
+        named: &NamedMaterials,
+        build: B,
+    ) -> Result<Box<dyn Hittable + Send + Sync>, Error>
+    where
+        B: Fn(Arc<dyn Material + Send + Sync>) -> Result<Box<dyn Hittable + Send + Sync>, Error>,
+    {
+        let desc = self.resolve(material)?;
+        if let MaterialDesc::Light {
+            power: Some(power), ..
+        } = desc
+        {
+            let area = build(desc.build(self)?)?.area();
+            let light = desc.build_light()?.with_power(*power, area);
+            return build(Arc::new(light));
+        }
+        match material {
+            MaterialRef::Named(name) => build(Arc::clone(&named[name])),
+            MaterialRef::Inline(desc) => build(desc.build(self)?),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub look_from: [Float; 3],
+    pub look_at: [Float; 3],
+    #[serde(default = "up")]
+    pub vup: [Float; 3],
+    /// Vertical field of view in degrees
+    pub vfov: Float,
+    #[serde(default)]
+    pub aperture: Float,
+    /// Defaults to the distance to `look_at`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_dist: Option<Float>,
+    #[serde(default)]
+    pub t0: Float,
+    #[serde(default = "one")]
+    pub t1: Float,
+    /// Clipping distances, see `CameraSettings::near` and `CameraSettings::far`. Default to
+    /// seeing everything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub near: Option<Float>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub far: Option<Float>,
+    /// Shape of the lens, a disk if not given. See `CameraSettings::aperture_mask`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aperture_mask: Option<ApertureMaskDesc>,
+}
+
+/// A grayscale image to cut the lens to, see `ApertureMask`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApertureMaskDesc {
+    pub path: String,
+}
+
+impl TryFrom<&CameraSettings> for CameraDesc {
+    type Error = Error;
+
+    /// Fails if the aperture mask wasn't loaded from a file
+    fn try_from(camera: &CameraSettings) -> Result<Self, Error> {
+        let aperture_mask = match &camera.aperture_mask {
+            Some(mask) => {
+                let path = mask.path().ok_or_else(|| {
+                    let reason =
+                        "aperture masks not loaded from files can't be saved to scene files";
+                    Error::Scene(reason.to_owned())
+                })?;
+                Some(ApertureMaskDesc {
+                    path: path.to_string_lossy().into_owned(),
+                })
+            }
+            None => None,
+        };
+        Ok(Self {
+            look_from: camera.look_from.into(),
+            look_at: camera.look_at.into(),
+            vup: camera.vup.into(),
+            vfov: camera.vfov,
+            aperture: camera.aperture,
+            focus_dist: Some(camera.focus_dist),
+            t0: camera.t0,
+            t1: camera.t1,
+            near: Some(camera.near).filter(|&near| near > 0.),
+            far: Some(camera.far).filter(|far| far.is_finite()),
+            aperture_mask,
+        })
+    }
+}
+
+impl CameraDesc {
+    fn build(&self) -> Result<CameraSettings, Error> {
+        let look_from = Point3::from(self.look_from);
+        let look_at = Point3::from(self.look_at);
+        let aperture_mask = match &self.aperture_mask {
+            Some(mask) => Some(Arc::new(ApertureMask::load(&mask.path)?)),
+            None => None,
+        };
+        Ok(CameraSettings {
+            look_from,
+            look_at,
+            vup: Vec3::from(self.vup),
+            vfov: self.vfov,
+            aperture: self.aperture,
+            focus_dist: self
+                .focus_dist
+                .unwrap_or_else(|| (look_at - look_from).length()),
+            t0: self.t0,
+            t1: self.t1,
+            near: self.near.unwrap_or(0.),
+            far: self.far.unwrap_or(Float::INFINITY),
+            aperture_mask,
+        })
+    }
+}
+
+/// Image size and quality
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderDesc {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Default for RenderDesc {
+    fn default() -> Self {
+        let settings = RenderSettings::default();
+        Self {
+            width: 800,
+            height: 450,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackgroundDesc {
+    Solid {
+        color: [Float; 3],
+    },
+    Gradient {
+        bottom: [Float; 3],
+        top: [Float; 3],
+    },
+    /// `GradientBackground::sky`
+    Sky,
+    /// `PreethamSky`
+    Preetham {
+        /// Direction towards the sun
+        sun_direction: [Float; 3],
+        #[serde(default = "turbidity")]
+        turbidity: Float,
+        #[serde(default = "sky_intensity")]
+        intensity: Float,
+    },
+    /// An equirectangular HDR image
+    Environment {
+        path: String,
+        #[serde(default = "one")]
+        intensity: Float,
+        /// Degrees around the y axis
+        #[serde(default)]
+        rotation: Float,
+    },
+}
+
+impl Default for BackgroundDesc {
+    fn default() -> Self {
+        BackgroundDesc::Solid { color: [0.; 3] }
+    }
+}
+
+impl BackgroundDesc {
+    fn build(&self) -> Result<Box<dyn Background + Sync>, Error> {
+        Ok(match self {
+            BackgroundDesc::Solid { color: c } => Box::new(SolidBackground::new(Color::from(*c))),
+            BackgroundDesc::Gradient { bottom, top } => Box::new(GradientBackground::new(
+                Color::from(*bottom),
+                Color::from(*top),
+            )),
+            BackgroundDesc::Sky => Box::new(GradientBackground::sky()),
+            BackgroundDesc::Preetham {
+                sun_direction,
+                turbidity,
+                intensity,
+            } => {
+                let mut sky = PreethamSky::new(Vec3::from(*sun_direction), *turbidity);
+                sky.intensity = *intensity;
+                Box::new(sky)
+            }
+            BackgroundDesc::Environment {
+                path,
+                intensity,
+                rotation,
+            } => {
+                let mut map = EnvironmentMap::new(path)?;
+                map.intensity = *intensity;
+                map.rotation = *rotation;
+                Box::new(map)
+            }
+        })
+    }
+}
+
+/// `HeightFog`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FogDesc {
+    pub density: Float,
+    pub falloff: Float,
+    #[serde(default)]
+    pub height: Float,
+    pub color: [Float; 3],
+}
+
+/// Absorption inside a `Dielectric`, see `Dielectric::with_absorption`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AbsorptionDesc {
+    pub color: [Float; 3],
+    #[serde(default = "one")]
+    pub density: Float,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SunDesc {
+    /// Direction towards the sun
+    pub direction: [Float; 3],
+    #[serde(default = "sun_diameter")]
+    pub angular_diameter: Float,
+    pub irradiance: [Float; 3],
+}
+
+/// Either a plain color or a texture
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureDesc {
+    Color([Float; 3]),
+    Texture(TextureKind),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureKind {
+    Checker {
+        odd: [Float; 3],
+        even: [Float; 3],
+    },
+    Image {
+        path: String,
+    },
+    Noise {
+        seed: u64,
+        scale: Float,
+    },
+    Turbulence {
+        seed: u64,
+        scale: Float,
+    },
+    Marble {
+        seed: u64,
+        scale: Float,
+    },
+    /// A texture registered with `plugin::register_texture`
+    Plugin {
+        name: String,
+        #[serde(default, skip_serializing_if = "Value::is_null")]
+        params: Value,
+    },
+}
+
+impl TextureDesc {
+    fn build(&self) -> Result<Arc<dyn Texture + Send + Sync>, Error> {
+        Ok(match self {
+            TextureDesc::Color(c) => Arc::new(SolidColor::new(Color::from(*c))),
+            TextureDesc::Texture(texture) => match texture {
+                TextureKind::Checker { odd, even } => {
+                    Arc::new(Checker::new(Color::from(*odd), Color::from(*even)))
+                }
+                TextureKind::Image { path } => Arc::new(ImageTexture::new(path)?),
+                TextureKind::Noise { seed, scale } => Arc::new(NoiseTexture::new(*seed, *scale)),
+                TextureKind::Turbulence { seed, scale } => {
+                    Arc::new(NoiseTexture::turbulence(*seed, *scale))
+                }
+                TextureKind::Marble { seed, scale } => {
+                    Arc::new(NoiseTexture::marble(*seed, *scale))
+                }
+                TextureKind::Plugin { name, params } => plugin::build_texture(name, params)?,
+            },
+        })
+    }
+}
+
+/// Either a plain number or a texture whose red channel gives the number at each point
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScalarDesc {
+    Value(Float),
+    Texture(TextureDesc),
+}
+
+impl Default for ScalarDesc {
+    fn default() -> Self {
+        ScalarDesc::Value(0.)
+    }
+}
+
+impl ScalarDesc {
+    /// Describes a texture used for a number, as a plain number if it's a solid grey
+    pub fn from_texture(texture: TextureDesc) -> Self {
+        match texture {
+            TextureDesc::Color([r, g, b]) if r == g && g == b => ScalarDesc::Value(r),
+            texture => ScalarDesc::Texture(texture),
+        }
+    }
+
+    fn build(&self) -> Result<Arc<dyn Texture + Send + Sync>, Error> {
+        match self {
+            ScalarDesc::Value(value) => {
+                Ok(Arc::new(SolidColor::new(color!(*value, *value, *value))))
+            }
+            ScalarDesc::Texture(texture) => texture.build(),
+        }
+    }
+}
+
+/// A material given in place, or the name of one in the scene's `materials`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    Named(String),
+    Inline(Box<MaterialDesc>),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+    },
+    Metal {
+        albedo: TextureDesc,
+        #[serde(default)]
+        fuzz: ScalarDesc,
+        /// Light given off from the front
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        emission: Option<TextureDesc>,
+    },
+    /// `PbrMaterial`
+    Pbr {
+        base_color: TextureDesc,
+        #[serde(default)]
+        metallic: ScalarDesc,
+        roughness: ScalarDesc,
+    },
+    Dielectric {
+        ior: Float,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tint: Option<TextureDesc>,
+        /// Light given off from the front
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        emission: Option<TextureDesc>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        absorption: Option<AbsorptionDesc>,
+    },
+    Light {
+        color: [Float; 3],
+        #[serde(default = "white")]
+        texture: TextureDesc,
+        #[serde(default = "yes")]
+        two_sided: bool,
+        #[serde(default = "full_spread")]
+        spread: Float,
+        /// Total power, which makes `color` only set the hue
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        power: Option<LightPower>,
+    },
+    Isotropic {
+        albedo: TextureDesc,
+    },
+    Coated {
+        base: MaterialRef,
+        ior: Float,
+        #[serde(default)]
+        roughness: Float,
+    },
+    /// `Mix`, `a` where `factor` is 0 and `b` where it's 1
+    Mix {
+        a: MaterialRef,
+        b: MaterialRef,
+        factor: ScalarDesc,
+    },
+    /// `Layered`, `top` over `bottom` with the Fresnel term of a coat of index `ior`
+    Layered {
+        top: MaterialRef,
+        bottom: MaterialRef,
+        #[serde(default = "clearcoat_ior")]
+        ior: Float,
+    },
+    /// `material` with its shading normals tilted by a tangent space normal map
+    NormalMap {
+        material: MaterialRef,
+        normals: TextureDesc,
+    },
+    /// `material` with its shading normals tilted by the slopes of a height map
+    BumpMap {
+        material: MaterialRef,
+        heights: TextureDesc,
+        #[serde(default = "one")]
+        strength: Float,
+    },
+    /// One of `Coated::preset`
+    Preset {
+        name: String,
+        color: [Float; 3],
+    },
+    /// A material registered with `plugin::register_material`
+    Plugin {
+        name: String,
+        #[serde(default, skip_serializing_if = "Value::is_null")]
+        params: Value,
+    },
+}
+
+impl MaterialDesc {
+    fn build(&self, scene: &SceneFile) -> Result<Arc<dyn Material + Send + Sync>, Error> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::new_shared(albedo.build()?))
+            }
+            MaterialDesc::Metal {
+                albedo,
+                fuzz,
+                emission,
+            } => {
+                let mut metal = Metal::new_shared(albedo.build()?, fuzz.build()?);
+                if let Some(emission) = emission {
+                    metal = metal.with_emission(emission.build()?);
+                }
+                Arc::new(metal)
+            }
+            MaterialDesc::Pbr {
+                base_color,
+                metallic,
+                roughness,
+            } => Arc::new(PbrMaterial::new_shared(
+                base_color.build()?,
+                metallic.build()?,
+                roughness.build()?,
+            )),
+            MaterialDesc::Dielectric {
+                ior,
+                tint,
+                emission,
+                absorption,
+            } => {
+                let mut dielectric = Dielectric::new(*ior);
+                if let Some(absorption) = absorption {
+                    let color = absorption.color.into();
+                    dielectric = dielectric.with_absorption(color, absorption.density);
+                }
+                if let Some(tint) = tint {
+                    dielectric = dielectric.with_tint(tint.build()?);
+                }
+                if let Some(emission) = emission {
+                    dielectric = dielectric.with_emission(emission.build()?);
+                }
+                Arc::new(dielectric)
+            }
+            MaterialDesc::Light { .. } => Arc::new(self.build_light()?),
+            MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic::new_shared(albedo.build()?)),
+            MaterialDesc::Coated {
+                base,
+                ior,
+                roughness,
+            } => Arc::new(Coated::new_shared(
+                scene.build_material(base)?,
+                *ior,
+                *roughness,
+            )),
+            MaterialDesc::Mix { a, b, factor } => Arc::new(Mix::new_shared(
+                scene.build_material(a)?,
+                scene.build_material(b)?,
+                factor.build()?,
+            )),
+            MaterialDesc::Layered { top, bottom, ior } => Arc::new(Layered::new_shared(
+                scene.build_material(top)?,
+                scene.build_material(bottom)?,
+                *ior,
+            )),
+            MaterialDesc::NormalMap { material, normals } => Arc::new(NormalMapped::new_shared(
+                scene.build_material(material)?,
+                SurfaceMap::Normals(normals.build()?),
+            )),
+            MaterialDesc::BumpMap {
+                material,
+                heights,
+                strength,
+            } => Arc::new(NormalMapped::new_shared(
+                scene.build_material(material)?,
+                SurfaceMap::Bumps {
+                    heights: heights.build()?,
+                    strength: *strength,
+                },
+            )),
+            MaterialDesc::Preset { name, color: c } => Arc::new(
+                Coated::preset(name, Color::from(*c))
+                    .ok_or_else(|| Error::Scene(format!("Unknown material preset `{}`", name)))?,
+            ),
+            MaterialDesc::Plugin { name, params } => plugin::build_material(name, params)?,
+        })
+    }
+
+    /// Builds a light without its power, which needs the area of its shape
+    fn build_light(&self) -> Result<Light, Error> {
+        match self {
+            MaterialDesc::Light {
+                color: c,
+                texture,
+                two_sided,
+                spread,
+                ..
+            } => {
+                let mut light = Light::new_shared(texture.build()?, Color::from(*c));
+                light.two_sided = *two_sided;
+                light.spread = *spread;
+                Ok(light)
+            }
+            _ => panic!("Not a light"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectDesc {
+    Sphere {
+        center: [Float; 3],
+        radius: Float,
+        material: MaterialRef,
+    },
+    MovingSphere {
+        center0: [Float; 3],
+        center1: [Float; 3],
+        time0: Float,
+        time1: Float,
+        radius: Float,
+        material: MaterialRef,
+    },
+    Triangle {
+        vertices: [[Float; 3]; 3],
+        material: MaterialRef,
+    },
+    XyRect {
+        x0: Float,
+        x1: Float,
+        y0: Float,
+        y1: Float,
+        k: Float,
+        material: MaterialRef,
+    },
+    XzRect {
+        x0: Float,
+        x1: Float,
+        z0: Float,
+        z1: Float,
+        k: Float,
+        material: MaterialRef,
+    },
+    YzRect {
+        y0: Float,
+        y1: Float,
+        z0: Float,
+        z1: Float,
+        k: Float,
+        material: MaterialRef,
+    },
+    Cuboid {
+        min: [Float; 3],
+        max: [Float; 3],
+        material: MaterialRef,
+    },
+    /// A cylinder up the y axis from the center of its base, capped at both ends by default
+    Cylinder {
+        base: [Float; 3],
+        radius: Float,
+        height: Float,
+        #[serde(default = "yes")]
+        capped: bool,
+        material: MaterialRef,
+    },
+    /// A cone up the y axis from the center of its base to its tip, capped by default
+    Cone {
+        base: [Float; 3],
+        radius: Float,
+        height: Float,
+        #[serde(default = "yes")]
+        capped: bool,
+        material: MaterialRef,
+    },
+    /// Fog filling `boundary`
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: Float,
+        albedo: TextureDesc,
+    },
+    Translate {
+        object: Box<ObjectDesc>,
+        offset: [Float; 3],
+    },
+    /// Rotates `object` about the y axis by `angle` degrees
+    RotateY {
+        object: Box<ObjectDesc>,
+        angle: Float,
+    },
+    /// The union, intersection or difference of two solid objects, see `Csg`
+    Csg {
+        operation: CsgOperation,
+        a: Box<ObjectDesc>,
+        b: Box<ObjectDesc>,
+    },
+    /// A hittable registered with `plugin::register_hittable`, given `material` if there is one
+    Plugin {
+        name: String,
+        #[serde(default, skip_serializing_if = "Value::is_null")]
+        params: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<MaterialRef>,
+    },
+}
+
+impl ObjectDesc {
+    /// The material of a shape, or the shape a transform moves. `None` for volumes, CSG and
+    /// plugins without one
+    fn material(&self) -> Option<&MaterialRef> {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::MovingSphere { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::XyRect { material, .. }
+            | ObjectDesc::XzRect { material, .. }
+            | ObjectDesc::YzRect { material, .. }
+            | ObjectDesc::Cuboid { material, .. }
+            | ObjectDesc::Cylinder { material, .. }
+            | ObjectDesc::Cone { material, .. } => Some(material),
+            ObjectDesc::Translate { object, .. } | ObjectDesc::RotateY { object, .. } => {
+                object.material()
+            }
+            ObjectDesc::Plugin { material, .. } => material.as_ref(),
+            ObjectDesc::ConstantMedium { .. } | ObjectDesc::Csg { .. } => None,
+        }
+    }
+}
+
+/// Adds the description of `hittable` to `objects`, or of what it's built from for groups like
+/// BVH nodes
+fn describe_all(hittable: &dyn Hittable, objects: &mut Vec<ObjectDesc>) -> Result<(), Error> {
+    if let Some(object) = hittable.describe() {
+        objects.push(object);
+        return Ok(());
+    }
+    let children = hittable.children();
+    if children.is_empty() {
+        let reason = "the world has a hittable that can't be saved to a scene file".to_owned();
+        return Err(Error::Scene(reason));
+    }
+    for child in children {
+        describe_all(child, objects)?;
+    }
+    Ok(())
+}
+
+fn up() -> [Float; 3] {
+    [0., 1., 0.]
+}
+
+fn one() -> Float {
+    1.
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn full_spread() -> Float {
+    180.
+}
+
+fn sun_diameter() -> Float {
+    0.53
+}
+
+fn turbidity() -> Float {
+    3.
+}
+
+fn sky_intensity() -> Float {
+    SKY_INTENSITY
+}
+
+fn clearcoat_ior() -> Float {
+    CLEARCOAT_IOR
+}
+
+fn white() -> TextureDesc {
+    TextureDesc::Color([1.; 3])
+}
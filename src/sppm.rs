@@ -0,0 +1,407 @@
+//! Stochastic progressive photon mapping
+//!
+//! Handles light paths that bounce between specular and diffuse surfaces, such as light focused
+//! through glass onto a table, which path tracing struggles to find
+
+use crate::camera::{Camera, CameraSettings};
+use crate::consts::PI;
+use crate::error::Error;
+use crate::hittable::{HitRecord, HIT_EPSILON};
+use crate::image::Image;
+use crate::integrator::PathState;
+use crate::pdf::Onb;
+use crate::ray::Ray;
+use crate::sampler::{self, RandomSampler, SampleCtx, SampleRng};
+use crate::sampling;
+use crate::world::{World, AABB};
+use crate::{Color, Float, Point3, RenderSettings, Vec3};
+use rand::Rng;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Renders with stochastic progressive photon mapping
+///
+/// Unlike the other integrators this works on the whole image at once, alternating between
+/// tracing camera rays to find visible points and tracing photons from the lights. Photons are
+/// emitted from `World::lights` and `World::sun`
+pub struct SppmIntegrator {
+    pub iterations: u32,
+    pub photons_per_iteration: u32,
+    /// Starting radius photons are gathered in around each visible point
+    pub initial_radius: Float,
+    /// How quickly the gather radius shrinks, between 0 and 1
+    pub alpha: Float,
+}
+
+impl Default for SppmIntegrator {
+    fn default() -> Self {
+        Self {
+            iterations: 64,
+            photons_per_iteration: 200_000,
+            initial_radius: 0.1,
+            alpha: 0.7,
+        }
+    }
+}
+
+/// Progress of a single pixel
+#[derive(Clone)]
+struct Pixel {
+    radius: Float,
+    /// Number of photons gathered so far, reduced as the radius shrinks
+    photons: Float,
+    /// Flux gathered so far
+    tau: Color,
+    /// Light seen directly from the camera, summed over iterations
+    direct: Color,
+}
+
+/// The first diffuse surface seen through a pixel
+struct VisiblePoint<'a> {
+    /// The camera path's ray arriving at the surface
+    ray: Ray,
+    rec: HitRecord<'a>,
+    throughput: Color,
+}
+
+impl SppmIntegrator {
+    pub fn render(
+        &self,
+        world: World,
+        camera_settings: CameraSettings,
+        render_settings: &RenderSettings,
+        image_width: u32,
+        image_height: u32,
+    ) -> Result<Image, Error> {
+        let aspect_ratio = image_width as Float / image_height as Float;
+        let camera = Camera::new(&camera_settings, aspect_ratio);
+        let mut world = world;
+        world.build_bvh(camera_settings.t0, camera_settings.t1)?;
+        let world = &world;
+        let bounds = world.bounding_box(camera_settings.t0, camera_settings.t1);
+        let settings = render_settings;
+
+        // Split photons evenly between threads
+        let threads = rayon::current_num_threads().max(1) as u32;
+        let photons_per_thread = self.photons_per_iteration.div_ceil(threads);
+
+        let pixel_count = (image_width * image_height) as usize;
+        let mut pixels = vec![
+            Pixel {
+                radius: self.initial_radius,
+                photons: 0.,
+                tau: color!(),
+                direct: color!(),
+            };
+            pixel_count
+        ];
+
+        let prog_bar = indicatif::ProgressBar::new(self.iterations as u64);
+        prog_bar.set_style(indicatif::ProgressStyle::default_bar().template(
+            "Rendering - Done {elapsed:>3} Estimated {eta:>3} {wide_bar} {pos:>4}/{len:4} Passes",
+        ));
+
+        for iteration in 0..self.iterations {
+            let seed = settings
+                .seed
+                .map(|seed| seed.wrapping_add(iteration as u64 * 0x9E37_79B9));
+
+            // Find visible points
+            let visible: Vec<(Color, Option<VisiblePoint>)> = (0..pixel_count)
+                .into_par_iter()
+                .map_init(
+                    || SampleCtx::from_seed(seed),
+                    |ctx, index| {
+                        let i = index as u32 % image_width;
+                        let j = image_height - 1 - index as u32 / image_width;
+                        ctx.start_pixel_with(i, j, make_rng(seed, index as u64));
+                        let (x, y) = ctx.pixel_sample();
+                        let u = (i as Float + x) / (image_width - 1) as Float;
+                        let v = (j as Float + y) / (image_height - 1) as Float;
+                        let ray = camera.get_ray(u, v, ctx);
+                        trace_camera_ray(ray, world, settings, ctx)
+                    },
+                )
+                .collect();
+
+            // Store visible points in a grid
+            let radii: Vec<Float> = pixels.iter().map(|pixel| pixel.radius).collect();
+            let map = PhotonMap::new(&visible, radii);
+            for (pixel, (direct, _)) in pixels.iter_mut().zip(visible.iter()) {
+                pixel.direct += *direct;
+            }
+
+            // Trace photons, gathering flux and photon counts per pixel
+            let gathered = (0..threads)
+                .into_par_iter()
+                .map(|thread| {
+                    let rng = make_rng(seed, pixel_count as u64 + thread as u64);
+                    let mut ctx = SampleCtx::new(Box::new(RandomSampler::new(rng)));
+                    let mut gathered = vec![(color!(), 0_u32); pixel_count];
+                    for _ in 0..photons_per_thread {
+                        let photon = emit_photon(world, bounds.as_ref(), &mut ctx);
+                        if let Some((ray, power)) = photon {
+                            let deposit =
+                                |point, dir, power| map.gather(point, dir, power, &mut gathered);
+                            trace_photon(ray, power, world, settings, &mut ctx, deposit);
+                        }
+                    }
+                    gathered
+                })
+                .reduce(
+                    || vec![(color!(), 0_u32); pixel_count],
+                    |mut a, b| {
+                        for (a, b) in a.iter_mut().zip(b.iter()) {
+                            a.0 += b.0;
+                            a.1 += b.1;
+                        }
+                        a
+                    },
+                );
+
+            // Shrink radii and update flux
+            for ((pixel, (phi, found)), (_, point)) in
+                pixels.iter_mut().zip(gathered.iter()).zip(visible.iter())
+            {
+                if point.is_none() || *found == 0 {
+                    continue;
+                }
+                let found = *found as Float;
+                let photons = pixel.photons + self.alpha * found;
+                let radius = pixel.radius * (photons / (pixel.photons + found)).sqrt();
+                pixel.tau =
+                    (pixel.tau + *phi) * ((radius * radius) / (pixel.radius * pixel.radius));
+                pixel.photons = photons;
+                pixel.radius = radius;
+            }
+            prog_bar.inc(1);
+        }
+        prog_bar.finish();
+
+        let total_photons = (self.iterations * photons_per_thread * threads) as Float;
+        let data = pixels
+            .iter()
+            .map(|pixel| {
+                pixel.direct / self.iterations as Float
+                    + pixel.tau / (total_photons * PI * pixel.radius * pixel.radius)
+            })
+            .collect();
+
+        Ok(Image {
+            width: image_width,
+            height: image_height,
+            data,
+            alpha: None,
+        })
+    }
+}
+
+/// Visible points sorted into a grid so photons can quickly find those nearby
+struct PhotonMap<'a, 'b> {
+    visible: &'b [(Color, Option<VisiblePoint<'a>>)],
+    radii: Vec<Float>,
+    cell_size: Float,
+    grid: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl<'a, 'b> PhotonMap<'a, 'b> {
+    fn new(visible: &'b [(Color, Option<VisiblePoint<'a>>)], radii: Vec<Float>) -> Self {
+        let cell_size = radii.iter().copied().fold(0., Float::max).max(1e-6) * 2.;
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for (index, (_, point)) in visible.iter().enumerate() {
+            if let Some(point) = point {
+                // Add to every cell the gather radius overlaps
+                let radius = point3!(radii[index], radii[index], radii[index]);
+                let min = cell(point.rec.point - radius, cell_size);
+                let max = cell(point.rec.point + radius, cell_size);
+                for x in min.0..=max.0 {
+                    for y in min.1..=max.1 {
+                        for z in min.2..=max.2 {
+                            grid.entry((x, y, z)).or_default().push(index);
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            visible,
+            radii,
+            cell_size,
+            grid,
+        }
+    }
+
+    /// Adds a photon arriving at `point` travelling along `dir` to nearby visible points
+    fn gather(&self, point: Point3, dir: Vec3, power: Color, gathered: &mut [(Color, u32)]) {
+        let indices = match self.grid.get(&cell(point, self.cell_size)) {
+            Some(indices) => indices,
+            None => return,
+        };
+        let wi = -dir.unit_vector();
+        for &index in indices {
+            let visible_point = match &self.visible[index].1 {
+                Some(visible_point) => visible_point,
+                None => continue,
+            };
+            let rec = &visible_point.rec;
+            let radius = self.radii[index];
+            if (rec.point - point).length_squared() > radius * radius {
+                continue;
+            }
+            let cosine = rec.normal.dot(&wi);
+            if cosine <= 0. {
+                continue;
+            }
+            // `eval` includes the cosine term, which the density estimate doesn't want
+            if let Some(f) = rec.material.eval(&visible_point.ray, rec, &wi) {
+                gathered[index].0 += visible_point.throughput * f / cosine * power;
+                gathered[index].1 += 1;
+            }
+        }
+    }
+}
+
+fn make_rng(seed: Option<u64>, stream: u64) -> SampleRng {
+    sampler::seeded_rng(seed.map(|seed| seed.wrapping_add(stream.wrapping_mul(0x2545_F491))))
+}
+
+fn cell(point: Point3, cell_size: Float) -> (i64, i64, i64) {
+    (
+        (point.x / cell_size).floor() as i64,
+        (point.y / cell_size).floor() as i64,
+        (point.z / cell_size).floor() as i64,
+    )
+}
+
+/// Follows a camera ray through specular bounces until it reaches a diffuse surface
+///
+/// Returns the light seen along the way and the diffuse surface if there is one
+fn trace_camera_ray<'a>(
+    mut ray: Ray,
+    world: &'a World,
+    settings: &RenderSettings,
+    ctx: &mut SampleCtx,
+) -> (Color, Option<VisiblePoint<'a>>) {
+    let mut state = PathState::camera();
+    let mut direct = color!();
+    while state.depth < settings.max_depth {
+        let rec = match world.hit(&ray, ray.t_min.max(HIT_EPSILON), ray.t_max) {
+            Some(rec) => rec,
+            None => {
+                direct += state.throughput * settings.background.color_seen(&ray, &state);
+                if let Some(sun) = &world.sun {
+                    direct += state.throughput * sun.radiance(&ray.dir);
+                }
+                break;
+            }
+        };
+        direct += state.throughput * rec.material.emitted(&ray, &rec);
+        if rec.material.eval(&ray, &rec, &rec.normal).is_some() {
+            let throughput = state.throughput;
+            return (
+                direct,
+                Some(VisiblePoint {
+                    ray,
+                    rec,
+                    throughput,
+                }),
+            );
+        }
+        match rec.material.scatter(&ray, &rec, &state, ctx) {
+            Some((scattered, attenuation)) => {
+                state.media.cross(&rec, &scattered);
+                ray = scattered;
+                ray.sample = ctx.ray_sample();
+                state.throughput = state.throughput * attenuation;
+                state.depth += 1;
+            }
+            None => break,
+        }
+    }
+    (direct, None)
+}
+
+/// Picks a light and emits a photon from it, returning the photon's ray and power
+fn emit_photon(
+    world: &World,
+    scene_bounds: Option<&AABB>,
+    ctx: &mut SampleCtx,
+) -> Option<(Ray, Color)> {
+    let light_count = world.lights.len() + world.sun.is_some() as usize;
+    if light_count == 0 {
+        return None;
+    }
+    let choice = ctx.rng().gen_range(0, light_count);
+    let light_pdf = 1. / light_count as Float;
+
+    if choice == world.lights.len() {
+        // Photons from the sun start on a disc covering the scene
+        let sun = world.sun.as_ref()?;
+        let bounds = scene_bounds?;
+        let center = 0.5 * (bounds.min + bounds.max);
+        let radius = (bounds.max - bounds.min).length() / 2.;
+        let Onb { u, v, w } = Onb::from_w(&sun.direction);
+        let (dx, dy) = loop {
+            let (x, y) = ctx.signed_pair();
+            if x * x + y * y < 1. {
+                break (x, y);
+            }
+        };
+        let origin = center + (w * (2. * radius) + u * (dx * radius) + v * (dy * radius)).conv();
+        let dir = -sun.sample_direction(ctx.rng());
+        let power = sun.irradiance * (PI * radius * radius) / light_pdf;
+        return Some((Ray::new(origin, dir, 0.), power));
+    }
+
+    let rng = ctx.rng();
+    let light = &world.lights[choice];
+    let (point, normal) = light.random_point(rng)?;
+    // Emit from either side, cosine weighted
+    let normal = if rng.gen_bool(0.5) { normal } else { -normal };
+    let local = sampling::cosine_hemisphere((rng.gen(), rng.gen()));
+    let dir = Onb::from_w(&normal).local(local.x, local.y, local.z);
+
+    // Find out how much light the surface gives off in this direction
+    let probe = Ray::new(point + (dir * 1e-3).conv(), -dir, 0.);
+    let rec = light.hit(&probe, 0., 2e-3)?;
+    let emitted = rec.material.emitted(&probe, &rec);
+    let power = emitted * (light.area() * 2. * PI) / light_pdf;
+    Some((Ray::new(point, dir, 0.), power))
+}
+
+/// Follows a photon around the scene, calling `deposit` at each diffuse surface it hits
+fn trace_photon<F: FnMut(Point3, Vec3, Color)>(
+    mut ray: Ray,
+    mut power: Color,
+    world: &World,
+    settings: &RenderSettings,
+    ctx: &mut SampleCtx,
+    mut deposit: F,
+) {
+    ray.sample = ctx.ray_sample();
+    let mut state = PathState::camera();
+    while state.depth < settings.max_depth {
+        let rec = match world.hit(&ray, HIT_EPSILON, Float::INFINITY) {
+            Some(rec) => rec,
+            None => return,
+        };
+        if rec.material.eval(&ray, &rec, &rec.normal).is_some() {
+            deposit(rec.point, ray.dir, power);
+        }
+        let (scattered, attenuation) = match rec.material.scatter(&ray, &rec, &state, ctx) {
+            Some(scattered) => scattered,
+            None => return,
+        };
+        let next_power = power * attenuation;
+        // Randomly end photons that lose power, keeping the rest unbiased
+        let survive = (next_power.luminance() / power.luminance().max(1e-12)).clamp(0., 1.);
+        if ctx.get_1d() > survive {
+            return;
+        }
+        power = next_power / survive;
+        state.media.cross(&rec, &scattered);
+        ray = scattered;
+        ray.sample = ctx.ray_sample();
+        state.depth += 1;
+    }
+}
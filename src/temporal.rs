@@ -0,0 +1,194 @@
+//! Reusing earlier frames of an animation to cut noise in later ones
+
+use crate::camera::{Camera, CameraSettings};
+use crate::hittable::HIT_EPSILON;
+use crate::image::Image;
+use crate::world::World;
+use crate::Color;
+use crate::Float;
+
+/// Where each pixel's surface was in the previous frame, from the camera's movement
+///
+/// Only the camera is tracked, so moving objects leave a trail that `TemporalAccumulator`'s
+/// clamping has to hide
+pub struct MotionVectors {
+    width: u32,
+    height: u32,
+    /// Position in the previous image of what each pixel sees, in pixels from the top left, in
+    /// rows from the top. `None` where it was behind the previous camera
+    data: Vec<Option<(Float, Float)>>,
+}
+
+impl MotionVectors {
+    /// Traces a ray through the center of each pixel and projects what it hits into the previous
+    /// camera's view
+    ///
+    /// Rays that miss are treated as hitting something very far away, so the background follows
+    /// the camera's rotation
+    pub fn new(
+        world: &World,
+        camera: &CameraSettings,
+        previous: &CameraSettings,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let aspect_ratio = width as Float / height as Float;
+        let current = Camera::new(camera, aspect_ratio);
+        let previous = Camera::new(previous, aspect_ratio);
+        let data = (0..height)
+            .rev()
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let (s, t) = to_image(i as Float, j as Float, width, height);
+                let origin = current.origin();
+                let dir = (current.focus_point(s, t) - origin).conv();
+                let ray = crate::ray::Ray::new(origin, dir, camera.t0);
+                let point = match world.hit(&ray, HIT_EPSILON, Float::INFINITY) {
+                    Some(rec) => rec.point,
+                    None => ray.at(1e6 / dir.length()),
+                };
+                let (s, t) = previous.project(point)?;
+                Some(from_image(s, t, width, height))
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Image position of the center of pixel `i`, `j` counting from the bottom left, matching how
+/// renders place samples
+fn to_image(i: Float, j: Float, width: u32, height: u32) -> (Float, Float) {
+    (
+        (i + 0.5) / (width - 1) as Float,
+        (j + 0.5) / (height - 1) as Float,
+    )
+}
+
+/// Pixel coordinates from the top left of image position `s`, `t`
+fn from_image(s: Float, t: Float, width: u32, height: u32) -> (Float, Float) {
+    let x = s * (width - 1) as Float - 0.5;
+    let y = t * (height - 1) as Float - 0.5;
+    (x, (height - 1) as Float - y)
+}
+
+/// Blends each frame with the frames before it, moved to line up using motion vectors
+///
+/// Noise averages out over frames while the camera moves slowly. The previous result is clamped
+/// to the range of colors around each pixel in the new frame, so it can't leave ghosts where
+/// things have moved or come into view
+pub struct TemporalAccumulator {
+    /// Weight of each new frame, between 0 and 1. Lower reuses more history, giving less noise but
+    /// more lag
+    pub blend: Float,
+    history: Option<Image>,
+}
+
+impl TemporalAccumulator {
+    pub fn new(blend: Float) -> Self {
+        Self {
+            blend,
+            history: None,
+        }
+    }
+
+    /// Forgets earlier frames, for cuts between shots
+    pub fn reset(&mut self) {
+        self.history = None;
+    }
+
+    /// Blends `frame` with the accumulated history, keeping the result for the next frame
+    ///
+    /// `motion` maps the new frame's pixels to the previous frame's. Without it, or after a
+    /// change in size, the history is dropped and `frame` is returned as is
+    pub fn accumulate(&mut self, frame: Image, motion: Option<&MotionVectors>) -> Image {
+        let history = match (self.history.take(), motion) {
+            (Some(history), Some(motion))
+                if history.width == frame.width
+                    && history.height == frame.height
+                    && motion.width == frame.width
+                    && motion.height == frame.height =>
+            {
+                history
+            }
+            _ => {
+                self.history = Some(frame.clone());
+                return frame;
+            }
+        };
+        let motion = motion.expect("Checked above");
+        let blend = self.blend.clamp(0., 1.);
+
+        let width = frame.width as usize;
+        let data = frame
+            .data
+            .iter()
+            .zip(motion.data.iter())
+            .enumerate()
+            .map(|(index, (&current, position))| {
+                let previous = match position.and_then(|p| sample(&history, p)) {
+                    Some(previous) => previous,
+                    None => return current,
+                };
+                let (low, high) = neighborhood(&frame, index % width, index / width);
+                let previous = color!(
+                    previous.red.clamp(low.red, high.red),
+                    previous.green.clamp(low.green, high.green),
+                    previous.blue.clamp(low.blue, high.blue)
+                );
+                current * blend + previous * (1. - blend)
+            })
+            .collect();
+        let result = Image {
+            width: frame.width,
+            height: frame.height,
+            data,
+            alpha: frame.alpha.clone(),
+        };
+        self.history = Some(result.clone());
+        result
+    }
+}
+
+/// Bilinearly interpolated color at pixel coordinates `x`, `y`, or `None` off the image
+fn sample(image: &Image, (x, y): (Float, Float)) -> Option<Color> {
+    let max_x = image.width as Float - 1.;
+    let max_y = image.height as Float - 1.;
+    if !(-0.5..=max_x + 0.5).contains(&x) || !(-0.5..=max_y + 0.5).contains(&y) {
+        return None;
+    }
+    let (x, y) = (x.clamp(0., max_x), y.clamp(0., max_y));
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = (
+        (x0 + 1).min(image.width - 1),
+        (y0 + 1).min(image.height - 1),
+    );
+    let (fx, fy) = (x.fract(), y.fract());
+    let top = image.pixel(x0, y0) * (1. - fx) + image.pixel(x1, y0) * fx;
+    let bottom = image.pixel(x0, y1) * (1. - fx) + image.pixel(x1, y1) * fx;
+    Some(top * (1. - fy) + bottom * fy)
+}
+
+/// Lowest and highest value of each channel in the 3x3 pixels around `x`, `y`
+fn neighborhood(image: &Image, x: usize, y: usize) -> (Color, Color) {
+    let mut low = color!(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+    let mut high = color!(-Float::INFINITY, -Float::INFINITY, -Float::INFINITY);
+    for row in image.rows().take(y + 2).skip(y.saturating_sub(1)) {
+        for pixel in row.iter().take(x + 2).skip(x.saturating_sub(1)) {
+            low = color!(
+                low.red.min(pixel.red),
+                low.green.min(pixel.green),
+                low.blue.min(pixel.blue)
+            );
+            high = color!(
+                high.red.max(pixel.red),
+                high.green.max(pixel.green),
+                high.blue.max(pixel.blue)
+            );
+        }
+    }
+    (low, high)
+}
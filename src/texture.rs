@@ -1,11 +1,25 @@
-use crate::{Color, Point3, Vec3};
-use rand::distributions::{Distribution, Standard};
-use rand::{Rng, SeedableRng};
-use std::collections::HashSet;
-use std::path::Path;
+use crate::error::Error;
+use crate::scene::{TextureDesc, TextureKind};
+use crate::{Color, Float, Point3, Vec3};
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub trait Texture {
-    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+    fn value(&self, u: Float, v: Float, point: Point3) -> Color;
+
+    /// Smallest and largest value of each channel, if known. Used for checking materials
+    fn range(&self) -> Option<(Color, Color)> {
+        None
+    }
+
+    /// Description of the texture for saving to a scene file, if it can be saved
+    fn describe(&self) -> Option<TextureDesc> {
+        None
+    }
 }
 
 pub struct SolidColor {
@@ -19,9 +33,17 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, _: f64, _: f64, _: Point3) -> Color {
+    fn value(&self, _: Float, _: Float, _: Point3) -> Color {
         self.color
     }
+
+    fn range(&self) -> Option<(Color, Color)> {
+        Some((self.color, self.color))
+    }
+
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Color(self.color.into()))
+    }
 }
 
 pub struct Checker {
@@ -36,7 +58,7 @@ impl Checker {
 }
 
 impl Texture for Checker {
-    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+    fn value(&self, _: Float, _: Float, point: Point3) -> Color {
         let sines = (10. * point.x).sin() * (10. * point.y).sin() * (10. * point.z).sin();
         if sines < 0. {
             self.odd
@@ -44,29 +66,54 @@ impl Texture for Checker {
             self.even
         }
     }
+
+    fn range(&self) -> Option<(Color, Color)> {
+        let (odd, even) = (self.odd, self.even);
+        Some((
+            color!(
+                odd.red.min(even.red),
+                odd.green.min(even.green),
+                odd.blue.min(even.blue)
+            ),
+            color!(
+                odd.red.max(even.red),
+                odd.green.max(even.green),
+                odd.blue.max(even.blue)
+            ),
+        ))
+    }
+
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Texture(TextureKind::Checker {
+            odd: self.odd.into(),
+            even: self.even.into(),
+        }))
+    }
 }
 
 pub struct ImageTexture {
     pub data: image::RgbImage,
+    /// File the image was loaded from
+    path: PathBuf,
 }
 
 impl ImageTexture {
-    pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let data = image::open(path).expect("Error reading texture image file");
-        let data = data.to_rgb();
-        ImageTexture { data }
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let data = image::open(&path)?.to_rgb();
+        Ok(ImageTexture { data, path })
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+    fn value(&self, u: Float, v: Float, _: Point3) -> Color {
         // Clamp input coords
-        let u = u.max(0.).min(1.);
-        let v = 1. - v.max(0.).min(1.);
+        let u = u.clamp(0., 1.);
+        let v = 1. - v.clamp(0., 1.);
 
         // Translate to image coords
-        let x = (self.data.width() as f64 * u) as u32;
-        let y = (self.data.height() as f64 * v) as u32;
+        let x = (self.data.width() as Float * u) as u32;
+        let y = (self.data.height() as Float * v) as u32;
 
         // Clamp image coords
         let x = if x >= self.data.width() {
@@ -83,21 +130,27 @@ impl Texture for ImageTexture {
         // Get pixel data
         let pixel = self.data.get_pixel(x, y);
         color!(
-            pixel.0[0] as f64 / 256.,
-            pixel.0[1] as f64 / 256.,
-            pixel.0[2] as f64 / 256.
+            pixel.0[0] as Float / 256.,
+            pixel.0[1] as Float / 256.,
+            pixel.0[2] as Float / 256.
         )
     }
+
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Texture(TextureKind::Image {
+            path: self.path.to_string_lossy().into_owned(),
+        }))
+    }
 }
 
 pub struct StarTexture {}
 
 impl StarTexture {
-    pub fn new(seed: u64, count: u32) -> StarTexture {
+    pub fn new(_seed: u64, _count: u32) -> StarTexture {
         // let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
         // for _ in 0..count {
-        //     let u: f64 = rng.sample(Standard);
-        //     let v: f64 = rng.sample(Standard);
+        //     let u: Float = rng.sample(Standard);
+        //     let v: Float = rng.sample(Standard);
         //     stars.insert((u.to_be_bytes(), v.to_be_bytes()));
         // }
         StarTexture {}
@@ -105,7 +158,7 @@ impl StarTexture {
 }
 
 impl Texture for StarTexture {
-    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+    fn value(&self, u: Float, v: Float, _: Point3) -> Color {
         if hash_12(u, v) > 0.8 {
             color!(1., 1., 1.)
         } else {
@@ -114,9 +167,293 @@ impl Texture for StarTexture {
     }
 }
 
-fn hash_12(a: f64, b: f64) -> f64 {
+fn hash_12(a: Float, b: Float) -> Float {
     let p3: Vec3 = (vec3!(a, b, a) * 0.1031).fract();
     let to_add = p3.dot(&vec3!(p3.y + 33.33, p3.z + 33.33, p3.x + 33.33));
     let p3 = point3!(p3.x + to_add, p3.y + to_add, p3.z + to_add);
     ((p3.x + p3.y) * p3.z).fract()
 }
+
+/// A 3D grid of values spanning a box, smoothly interpolated between grid points
+///
+/// Useful as a density field for volumes. Points outside the box are zero
+pub struct GridTexture {
+    min: Point3,
+    max: Point3,
+    resolution: [usize; 3],
+    values: Vec<Float>,
+}
+
+impl GridTexture {
+    /// `values` are ordered x fastest, then y, then z
+    pub fn new(min: Point3, max: Point3, resolution: [usize; 3], values: Vec<Float>) -> Self {
+        assert!(
+            resolution.iter().all(|&n| n >= 2),
+            "Grid texture needs at least two points along each axis"
+        );
+        assert_eq!(
+            values.len(),
+            resolution[0] * resolution[1] * resolution[2],
+            "Grid texture values don't match its resolution"
+        );
+        Self {
+            min,
+            max,
+            resolution,
+            values,
+        }
+    }
+
+    /// Fills the grid by evaluating `f` at each grid point
+    pub fn from_fn<F: Fn(Point3) -> Float>(
+        min: Point3,
+        max: Point3,
+        resolution: [usize; 3],
+        f: F,
+    ) -> Self {
+        let mut values = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+        for k in 0..resolution[2] {
+            for j in 0..resolution[1] {
+                for i in 0..resolution[0] {
+                    let fraction = |index: usize, axis: usize| {
+                        index as Float / (resolution[axis] - 1).max(1) as Float
+                    };
+                    values.push(f(point3!(
+                        min.x + (max.x - min.x) * fraction(i, 0),
+                        min.y + (max.y - min.y) * fraction(j, 1),
+                        min.z + (max.z - min.z) * fraction(k, 2)
+                    )));
+                }
+            }
+        }
+        Self::new(min, max, resolution, values)
+    }
+
+    fn at(&self, i: usize, j: usize, k: usize) -> Float {
+        self.values[i + self.resolution[0] * (j + self.resolution[1] * k)]
+    }
+
+    /// Value at a point, interpolated between the surrounding grid points
+    pub fn sample(&self, point: Point3) -> Float {
+        let mut cell = [0; 3];
+        let mut weight = [0.; 3];
+        for axis in 0..3 {
+            let fraction = (point[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]);
+            if !(0. ..=1.).contains(&fraction) {
+                return 0.;
+            }
+            let position = fraction * (self.resolution[axis] - 1) as Float;
+            cell[axis] = (position as usize).min(self.resolution[axis] - 2);
+            weight[axis] = position - cell[axis] as Float;
+        }
+
+        let mut value = 0.;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut corner_weight = 1.;
+            for axis in 0..3 {
+                corner_weight *= if offset[axis] == 1 {
+                    weight[axis]
+                } else {
+                    1. - weight[axis]
+                };
+            }
+            value += corner_weight
+                * self.at(
+                    cell[0] + offset[0],
+                    cell[1] + offset[1],
+                    cell[2] + offset[2],
+                );
+        }
+        value
+    }
+}
+
+impl Texture for GridTexture {
+    fn value(&self, _: Float, _: Float, point: Point3) -> Color {
+        let value = self.sample(point);
+        color!(value, value, value)
+    }
+}
+
+/// Glow of hot material, turning a temperature field into blackbody colors
+///
+/// The brightness of `temperature` times `max_kelvin` gives the temperature in kelvin. Light
+/// given off grows with the fourth power of temperature, reaching `intensity` at `max_kelvin`, so
+/// cooler areas fade to a dull red glow
+pub struct Blackbody {
+    temperature: Arc<dyn Texture + Send + Sync>,
+    max_kelvin: Float,
+    intensity: Float,
+}
+
+impl Blackbody {
+    pub fn new<T: Texture + Send + Sync + 'static>(
+        temperature: T,
+        max_kelvin: Float,
+        intensity: Float,
+    ) -> Self {
+        Self {
+            temperature: Arc::new(temperature),
+            max_kelvin,
+            intensity,
+        }
+    }
+}
+
+impl Texture for Blackbody {
+    fn value(&self, u: Float, v: Float, point: Point3) -> Color {
+        let fraction = self.temperature.value(u, v, point).luminance().max(0.);
+        Color::from_kelvin(fraction * self.max_kelvin) * (self.intensity * fraction.powi(4))
+    }
+}
+
+const PERLIN_POINTS: usize = 256;
+
+/// Smoothly varying gradient noise
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// Creates noise from a seed, so the same seed always gives the same pattern
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let uniform = Uniform::from(-1.0..1.0);
+        let gradients = (0..PERLIN_POINTS)
+            .map(|_| {
+                vec3!(
+                    uniform.sample(&mut rng),
+                    uniform.sample(&mut rng),
+                    uniform.sample(&mut rng)
+                )
+                .unit_vector()
+            })
+            .collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..PERLIN_POINTS).collect();
+            perm.shuffle(&mut rng);
+            perm
+        };
+        Self {
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+            gradients,
+        }
+    }
+
+    /// Noise at a point, between about -1 and 1
+    pub fn noise(&self, point: Point3) -> Float {
+        let floor = |x: Float| x.floor();
+        let (i, j, k) = (floor(point.x), floor(point.y), floor(point.z));
+        let (u, v, w) = (point.x - i, point.y - j, point.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // Hermite smoothing hides the grid
+        let uu = u * u * (3. - 2. * u);
+        let vv = v * v * (3. - 2. * v);
+        let ww = w * w * (3. - 2. * w);
+
+        let wrap = |x: i64| (x & (PERLIN_POINTS as i64 - 1)) as usize;
+        let mut accum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)]];
+                    let (fi, fj, fk) = (di as Float, dj as Float, dk as Float);
+                    let weight = vec3!(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1. - fi) * (1. - uu))
+                        * (fj * vv + (1. - fj) * (1. - vv))
+                        * (fk * ww + (1. - fk) * (1. - ww))
+                        * gradient.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sum of several octaves of noise, each at double the frequency and half the strength
+    pub fn turbulence(&self, point: Point3, depth: u32) -> Float {
+        let mut accum = 0.;
+        let mut point = point;
+        let mut weight = 1.;
+        for _ in 0..depth {
+            accum += weight * self.noise(point);
+            weight *= 0.5;
+            point = 2. * point;
+        }
+        accum.abs()
+    }
+}
+
+enum NoiseStyle {
+    Smooth,
+    Turbulence,
+    Marble,
+}
+
+/// Procedural grey texture made from Perlin noise
+pub struct NoiseTexture {
+    noise: Perlin,
+    seed: u64,
+    scale: Float,
+    style: NoiseStyle,
+}
+
+impl NoiseTexture {
+    /// Soft blotches. Higher `scale` makes them smaller
+    pub fn new(seed: u64, scale: Float) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            seed,
+            scale,
+            style: NoiseStyle::Smooth,
+        }
+    }
+
+    /// Rough, cloudy pattern like camouflage netting
+    pub fn turbulence(seed: u64, scale: Float) -> Self {
+        Self {
+            style: NoiseStyle::Turbulence,
+            ..Self::new(seed, scale)
+        }
+    }
+
+    /// Veined stone, with turbulence warping stripes along the z axis
+    pub fn marble(seed: u64, scale: Float) -> Self {
+        Self {
+            style: NoiseStyle::Marble,
+            ..Self::new(seed, scale)
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _: Float, _: Float, point: Point3) -> Color {
+        let scaled = self.scale * point;
+        let value = match self.style {
+            NoiseStyle::Smooth => 0.5 * (1. + self.noise.noise(scaled)),
+            NoiseStyle::Turbulence => self.noise.turbulence(scaled, 7),
+            // Turbulence only shifts the phase so the veins stay broad
+            NoiseStyle::Marble => {
+                0.5 * (1. + (scaled.z + 10. * self.noise.turbulence(point, 7)).sin())
+            }
+        };
+        color!(value, value, value)
+    }
+
+    fn describe(&self) -> Option<TextureDesc> {
+        let (seed, scale) = (self.seed, self.scale);
+        Some(TextureDesc::Texture(match self.style {
+            NoiseStyle::Smooth => TextureKind::Noise { seed, scale },
+            NoiseStyle::Turbulence => TextureKind::Turbulence { seed, scale },
+            NoiseStyle::Marble => TextureKind::Marble { seed, scale },
+        }))
+    }
+}
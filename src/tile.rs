@@ -0,0 +1,58 @@
+//! Splitting images into tiles to render independently
+
+/// A rectangle of pixels, in pixels from the top left of the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Splits a `width`x`height` image into tiles of up to `size`x`size`, in rows from the top
+    /// left
+    ///
+    /// Tiles along the right and bottom edges are cut short to fit
+    pub fn split(width: u32, height: u32, size: u32) -> Vec<Tile> {
+        let size = size.max(1);
+        (0..height)
+            .step_by(size as usize)
+            .flat_map(|y| {
+                (0..width).step_by(size as usize).map(move |x| Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                })
+            })
+            .collect()
+    }
+
+    /// The tile grown by `margin` pixels on every side, cut short to fit a `width`x`height` image
+    pub fn expand(&self, margin: u32, width: u32, height: u32) -> Tile {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        Tile {
+            x,
+            y,
+            width: (self.x + self.width + margin).min(width) - x,
+            height: (self.y + self.height + margin).min(height) - y,
+        }
+    }
+
+    pub fn pixel_count(&self) -> u32 {
+        self.width * self.height
+    }
+
+    /// Pixel coordinates in the image, in rows from the top left of the tile
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let Tile {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y + height).flat_map(move |y| (x..x + width).map(move |x| (x, y)))
+    }
+}
@@ -0,0 +1,605 @@
+//! Wrappers that place a hittable somewhere other than where it was modelled
+
+use crate::animation::{Keyframes, Pose};
+use crate::error::Error;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{seeded_rng, SampleRng};
+use crate::scene::ObjectDesc;
+use crate::world::AABB;
+use crate::{Float, Point3, Vec3};
+use std::ops::Mul;
+
+/// Corners of a bounding box
+fn corners(bbox: &AABB) -> impl Iterator<Item = Point3> + '_ {
+    (0..8).map(move |i| {
+        point3!(
+            if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+            if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+            if i & 4 == 0 { bbox.min.z } else { bbox.max.z }
+        )
+    })
+}
+
+/// Smallest box containing all of `points`
+fn enclose(points: impl Iterator<Item = Point3>) -> AABB {
+    let mut min = point3!(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+    let mut max = point3!(-Float::INFINITY, -Float::INFINITY, -Float::INFINITY);
+    for p in points {
+        min = point3!(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = point3!(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    AABB::new(min, max)
+}
+
+/// Moves a hittable by an offset
+pub struct Translate {
+    object: Box<dyn Hittable + Send + Sync>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new<T: Hittable + Send + Sync + 'static>(object: T, offset: Vec3) -> Self {
+        Self::new_boxed(Box::new(object), offset)
+    }
+
+    pub fn new_boxed(object: Box<dyn Hittable + Send + Sync>, offset: Vec3) -> Self {
+        Self { object, offset }
+    }
+
+    /// `ray` in the object's own space
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: ray.origin - self.offset.conv(),
+            ..*ray
+        }
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rec = self.object.hit(&self.local_ray(ray), t_min, t_max)?;
+        Some(HitRecord {
+            point: rec.point + self.offset.conv(),
+            ..rec
+        })
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.object.hit_any(&self.local_ray(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(AABB::new(
+            bbox.min + self.offset.conv(),
+            bbox.max + self.offset.conv(),
+        ))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        self.object.materials()
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Translate {
+            object: Box::new(self.object.describe()?),
+            offset: self.offset.into(),
+        })
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        let (point, normal) = self.object.random_point(rng)?;
+        Some((point + self.offset.conv(), normal))
+    }
+
+    fn area(&self) -> Float {
+        self.object.area()
+    }
+
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        self.object
+            .random_direction(&(*origin - self.offset.conv()), rng)
+    }
+
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        self.object.pdf_value(&(*origin - self.offset.conv()), dir)
+    }
+}
+
+/// Rotates a hittable around the y axis
+pub struct RotateY {
+    object: Box<dyn Hittable + Send + Sync>,
+    /// In degrees, kept for describing the rotation
+    angle: Float,
+    sin_theta: Float,
+    cos_theta: Float,
+}
+
+impl RotateY {
+    /// Rotates anticlockwise looking down the y axis by `angle` degrees
+    pub fn new<T: Hittable + Send + Sync + 'static>(object: T, angle: Float) -> Self {
+        Self::new_boxed(Box::new(object), angle)
+    }
+
+    pub fn new_boxed(object: Box<dyn Hittable + Send + Sync>, angle: Float) -> Self {
+        let radians = angle.to_radians();
+        Self {
+            object,
+            angle,
+            sin_theta: radians.sin(),
+            cos_theta: radians.cos(),
+        }
+    }
+
+    fn rotate(&self, p: Point3) -> Point3 {
+        point3!(
+            self.cos_theta * p.x + self.sin_theta * p.z,
+            p.y,
+            -self.sin_theta * p.x + self.cos_theta * p.z
+        )
+    }
+
+    fn unrotate(&self, p: Point3) -> Point3 {
+        point3!(
+            self.cos_theta * p.x - self.sin_theta * p.z,
+            p.y,
+            self.sin_theta * p.x + self.cos_theta * p.z
+        )
+    }
+
+    /// `ray` in the object's own space
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.unrotate(ray.origin),
+            dir: self.unrotate(ray.dir.conv()).conv(),
+            ..*ray
+        }
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rec = self.object.hit(&self.local_ray(ray), t_min, t_max)?;
+        Some(HitRecord {
+            point: self.rotate(rec.point),
+            normal: self.rotate(rec.normal.conv()).conv(),
+            tangent: self.rotate(rec.tangent.conv()).conv(),
+            bitangent: self.rotate(rec.bitangent.conv()).conv(),
+            ..rec
+        })
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.object.hit_any(&self.local_ray(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(enclose(corners(&bbox).map(|p| self.rotate(p))))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        self.object.materials()
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::RotateY {
+            object: Box::new(self.object.describe()?),
+            angle: self.angle,
+        })
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        let (point, normal) = self.object.random_point(rng)?;
+        Some((self.rotate(point), self.rotate(normal.conv()).conv()))
+    }
+
+    fn area(&self) -> Float {
+        self.object.area()
+    }
+
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        let dir = self.object.random_direction(&self.unrotate(*origin), rng)?;
+        Some(self.rotate(dir.conv()).conv())
+    }
+
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        let dir = self.unrotate(dir.conv()).conv();
+        self.object.pdf_value(&self.unrotate(*origin), &dir)
+    }
+}
+
+/// An affine transformation stored as a 4x4 matrix
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[Float; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        Self { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Self::identity();
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        matrix
+    }
+
+    pub fn scale(factors: Vec3) -> Self {
+        let mut matrix = Self::identity();
+        matrix.m[0][0] = factors.x;
+        matrix.m[1][1] = factors.y;
+        matrix.m[2][2] = factors.z;
+        matrix
+    }
+
+    /// Rotation by `angle` degrees around `axis`, anticlockwise looking down the axis
+    pub fn rotation(axis: Vec3, angle: Float) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1. - cos;
+        let mut matrix = Self::identity();
+        matrix.m[0][0] = t * a.x * a.x + cos;
+        matrix.m[0][1] = t * a.x * a.y - sin * a.z;
+        matrix.m[0][2] = t * a.x * a.z + sin * a.y;
+        matrix.m[1][0] = t * a.x * a.y + sin * a.z;
+        matrix.m[1][1] = t * a.y * a.y + cos;
+        matrix.m[1][2] = t * a.y * a.z - sin * a.x;
+        matrix.m[2][0] = t * a.x * a.z - sin * a.y;
+        matrix.m[2][1] = t * a.y * a.z + sin * a.x;
+        matrix.m[2][2] = t * a.z * a.z + cos;
+        matrix
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self { m }
+    }
+
+    /// Determinant of the linear part, how much the matrix scales volumes
+    pub fn determinant(&self) -> Float {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// The inverse matrix, or `None` if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1. / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self { m: inv })
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        point3!(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3]
+        )
+    }
+
+    /// Transforms a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        vec3!(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self { m }
+    }
+}
+
+/// Applies any affine transformation to a hittable
+///
+/// Matrices combine right to left, so `translation * rotation` rotates first
+pub struct Transform {
+    object: Box<dyn Hittable + Send + Sync>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    /// Surface area after transforming, see `Hittable::area`
+    area: Float,
+}
+
+/// Points `Transform` samples to measure how it stretches the object's surface
+const AREA_SAMPLES: u32 = 64;
+
+impl Transform {
+    /// Fails if `matrix` can't be inverted, such as one scaling an axis to nothing
+    pub fn new<T: Hittable + Send + Sync + 'static>(
+        object: T,
+        matrix: Matrix4,
+    ) -> Result<Self, Error> {
+        Self::new_boxed(Box::new(object), matrix)
+    }
+
+    pub fn new_boxed(
+        object: Box<dyn Hittable + Send + Sync>,
+        matrix: Matrix4,
+    ) -> Result<Self, Error> {
+        let inverse = matrix
+            .inverse()
+            .ok_or_else(|| Error::Scene("transform matrices must be invertible".to_owned()))?;
+        let mut transform = Self {
+            object,
+            matrix,
+            inverse,
+            area: 0.,
+        };
+        transform.area = transform.measure_area();
+        Ok(transform)
+    }
+
+    /// `ray` in the object's own space
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        // The direction isn't normalized so `t` means the same in both spaces
+        Ray {
+            origin: self.inverse.transform_point(ray.origin),
+            dir: self.inverse.transform_vector(ray.dir),
+            ..*ray
+        }
+    }
+
+    /// How much the matrix stretches a patch of surface facing along the local `normal`
+    fn stretch(&self, normal: Vec3) -> Float {
+        self.matrix.determinant().abs()
+            * self
+                .inverse
+                .transpose()
+                .transform_vector(normal.unit_vector())
+                .length()
+    }
+
+    /// The object's area times its average stretch over points from `random_point`, which is
+    /// exact for flat objects and for matrices that scale every axis evenly
+    fn measure_area(&self) -> Float {
+        let area = self.object.area();
+        if area <= 0. {
+            return area;
+        }
+        let mut rng = seeded_rng(Some(0));
+        let (mut total, mut count) = (0., 0);
+        for _ in 0..AREA_SAMPLES {
+            if let Some((_, normal)) = self.object.random_point(&mut rng) {
+                total += self.stretch(normal);
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return 0.;
+        }
+        area * total / count as Float
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rec = self.object.hit(&self.local_ray(ray), t_min, t_max)?;
+        // Normals transform by the inverse transpose
+        let normal = self
+            .inverse
+            .transpose()
+            .transform_vector(rec.normal)
+            .unit_vector();
+        Some(HitRecord {
+            point: self.matrix.transform_point(rec.point),
+            normal,
+            tangent: self.matrix.transform_vector(rec.tangent).unit_vector(),
+            bitangent: self.matrix.transform_vector(rec.bitangent).unit_vector(),
+            ..rec
+        })
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.object.hit_any(&self.local_ray(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(enclose(
+            corners(&bbox).map(|p| self.matrix.transform_point(p)),
+        ))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        self.object.materials()
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        let (point, normal) = self.object.random_point(rng)?;
+        let normal = self
+            .inverse
+            .transpose()
+            .transform_vector(normal)
+            .unit_vector();
+        Some((self.matrix.transform_point(point), normal))
+    }
+
+    fn area(&self) -> Float {
+        self.area
+    }
+
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        let origin = self.inverse.transform_point(*origin);
+        let dir = self.object.random_direction(&origin, rng)?;
+        Some(self.matrix.transform_vector(dir))
+    }
+
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        let determinant = self.matrix.determinant().abs();
+        if determinant <= 0. {
+            return 0.;
+        }
+        let local = self.inverse.transform_vector(*dir);
+        let pdf = self
+            .object
+            .pdf_value(&self.inverse.transform_point(*origin), &local);
+        // Mapping unit directions through the matrix packs them together by
+        // det / |matrix * local|^3
+        let spread = self.matrix.transform_vector(local.unit_vector()).length();
+        pdf * spread.powi(3) / determinant
+    }
+}
+
+/// Moves, turns and resizes a hittable over time, following keyframed poses, so anything can be
+/// motion blurred
+///
+/// Each ray sees the object posed at the ray's time
+pub struct Animated {
+    object: Box<dyn Hittable + Send + Sync>,
+    poses: Keyframes<Pose>,
+}
+
+/// Poses `Animated::bounding_box` checks between each pair of keys
+const SWEEP_STEPS: u32 = 8;
+
+impl Animated {
+    pub fn new<T: Hittable + Send + Sync + 'static>(object: T, poses: Keyframes<Pose>) -> Self {
+        Self::new_boxed(Box::new(object), poses)
+    }
+
+    pub fn new_boxed(object: Box<dyn Hittable + Send + Sync>, poses: Keyframes<Pose>) -> Self {
+        Self { object, poses }
+    }
+
+    /// Pose at `time` with its matrix and inverse
+    fn pose(&self, time: Float) -> (Matrix4, Matrix4) {
+        let pose = self.poses.at(time);
+        (pose.matrix(), pose.inverse_matrix())
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (matrix, inverse) = self.pose(ray.time);
+        let local = Ray {
+            origin: inverse.transform_point(ray.origin),
+            dir: inverse.transform_vector(ray.dir),
+            ..*ray
+        };
+        let rec = self.object.hit(&local, t_min, t_max)?;
+        let normal = inverse
+            .transpose()
+            .transform_vector(rec.normal)
+            .unit_vector();
+        Some(HitRecord {
+            point: matrix.transform_point(rec.point),
+            normal,
+            tangent: matrix.transform_vector(rec.tangent).unit_vector(),
+            bitangent: matrix.transform_vector(rec.bitangent).unit_vector(),
+            ..rec
+        })
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let (_, inverse) = self.pose(ray.time);
+        let local = Ray {
+            origin: inverse.transform_point(ray.origin),
+            dir: inverse.transform_vector(ray.dir),
+            ..*ray
+        };
+        self.object.hit_any(&local, t_min, t_max)
+    }
+
+    /// Encloses the object posed at both ends of the interval, at every key in between and at
+    /// steps between those
+    ///
+    /// Corners sweep along arcs while the object turns, bulging past the straight lines between
+    /// steps, so the box is padded by the most an arc can bulge
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        let reach = corners(&bbox)
+            .map(|p| p.conv::<Vec3>().length())
+            .fold(0., Float::max);
+        let mut times: Vec<Float> = std::iter::once(t0)
+            .chain(self.poses.times().filter(|&time| time > t0 && time < t1))
+            .chain(std::iter::once(t1))
+            .collect();
+        times.dedup();
+
+        let mut points = Vec::new();
+        let mut bulge: Float = 0.;
+        for window in times.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            let mut previous = self.poses.at(start);
+            for step in 0..=SWEEP_STEPS {
+                let time = start + (end - start) * step as Float / SWEEP_STEPS as Float;
+                let pose = self.poses.at(time);
+                let matrix = pose.matrix();
+                points.extend(corners(&bbox).map(|p| matrix.transform_point(p)));
+
+                // Turning by `angle` moves a point `r` from the axis off the chord by at most
+                // r (1 - cos(angle / 2))
+                let turn = pose.rotation - previous.rotation;
+                let angle = (turn.x.abs() + turn.y.abs() + turn.z.abs())
+                    .to_radians()
+                    .min(crate::consts::PI);
+                let scale = [pose.scale, previous.scale]
+                    .iter()
+                    .flat_map(|s| [s.x.abs(), s.y.abs(), s.z.abs()])
+                    .fold(0., Float::max);
+                bulge = bulge.max(scale * reach * (1. - (angle / 2.).cos()));
+                previous = pose;
+            }
+        }
+        let bbox = enclose(points.into_iter());
+        let pad = point3!(bulge, bulge, bulge);
+        Some(AABB::new(bbox.min - pad, bbox.max + pad))
+    }
+
+    fn materials(&self) -> Vec<&(dyn Material + Sync)> {
+        self.object.materials()
+    }
+}
@@ -0,0 +1,339 @@
+//! Fast previews that path-trace at half resolution and only trace the pixels that need it at
+//! full resolution
+//!
+//! The half resolution render finds where the image is noisy or has edges, from the spread of
+//! each pixel's samples and from its normal, depth, albedo and brightness against its neighbors.
+//! Those pixels are traced again at full resolution and the rest are interpolated, so smooth
+//! areas cost a quarter as much while edges stay sharp
+
+use crate::aov::AovSample;
+use crate::camera::{Camera, CameraSettings};
+use crate::error::Error;
+use crate::image::Image;
+use crate::integrator::Integrator;
+use crate::sampler::{pixel_rng, seeded_rng};
+use crate::world::World;
+use crate::{covers, prepare_world, render_pixels, Color, Float, PixelSample, RenderSettings};
+use rayon::prelude::*;
+use std::iter::Sum;
+use std::ops::{Div, Mul};
+
+/// When a half resolution pixel is traced again at full resolution
+///
+/// Each threshold is how different a pixel can be from a neighbor, or how uncertain its own
+/// brightness can be, before it's traced again
+#[derive(Clone, Copy, Debug)]
+pub struct UpsampleSettings {
+    /// Error in the pixel's brightness relative to itself, with 95% confidence
+    pub variance_threshold: Float,
+    /// Length of the difference between normals
+    pub normal_threshold: Float,
+    /// Difference between depths, relative to the nearer depth
+    pub depth_threshold: Float,
+    /// Largest difference between albedo channels
+    pub albedo_threshold: Float,
+    /// Difference between brightnesses, relative to the brighter one, which catches shadow edges
+    pub contrast_threshold: Float,
+    /// Most of the image to trace again, from 0 to 1. The pixels furthest over their thresholds
+    /// are traced first, so a busy image still renders in about half the time
+    pub max_share: Float,
+}
+
+impl Default for UpsampleSettings {
+    fn default() -> Self {
+        Self {
+            variance_threshold: 0.25,
+            normal_threshold: 0.3,
+            depth_threshold: 0.05,
+            albedo_threshold: 0.1,
+            contrast_threshold: 0.5,
+            max_share: 0.25,
+        }
+    }
+}
+
+/// An image from `raytrace_upsampled`
+pub struct Upsampled {
+    pub image: Image,
+    /// Whether each pixel was traced at full resolution rather than interpolated, in rows from
+    /// the top
+    pub retraced: Vec<bool>,
+}
+
+impl Upsampled {
+    /// Share of the pixels traced at full resolution
+    pub fn retraced_share(&self) -> Float {
+        let count = self.retraced.iter().filter(|&&retraced| retraced).count();
+        count as Float / self.retraced.len().max(1) as Float
+    }
+}
+
+/// Renders at half resolution, then traces the noisy pixels and edges again at full resolution
+/// and interpolates the rest, see the module documentation
+///
+/// Traced pixels take `samples_per_pixel` samples with a box filter, ignoring adaptive sampling
+/// and outlier rejection, and are the same as in `raytrace_image` with the same seed and
+/// sampler. Fails if the acceleration structure can't be built
+pub fn raytrace_upsampled(
+    world: World,
+    camera_settings: CameraSettings,
+    render_settings: &RenderSettings,
+    integrator: &(dyn Integrator + Sync),
+    image_width: u32,
+    image_height: u32,
+    settings: &UpsampleSettings,
+) -> Result<Upsampled, Error> {
+    let mut world = world;
+    prepare_world(&mut world, &camera_settings, render_settings)?;
+    let (half_width, half_height) = (image_width.div_ceil(2), image_height.div_ceil(2));
+    let (half, half_alpha) = render_pixels(
+        &world,
+        &camera_settings,
+        render_settings,
+        half_width,
+        half_height,
+        |ray, world, ctx| {
+            let beauty = integrator.li(ray, world, render_settings, ctx);
+            let luminance = beauty.luminance();
+            Moments {
+                sample: AovSample::new(beauty, ray, world),
+                squared: luminance * luminance,
+            }
+        },
+    );
+    let selected = select(
+        &half,
+        half_width,
+        render_settings.samples_per_pixel,
+        settings,
+    );
+
+    // Interpolate every pixel, then replace the ones to trace
+    let beauty: Vec<Color> = half.iter().map(|moments| moments.sample.beauty).collect();
+    let mut data = upsample(&beauty, half_width, half_height, image_width, image_height);
+    let mut alpha = half_alpha.map(|alpha| {
+        let alpha: Vec<Color> = alpha.iter().map(|&a| color!(a, a, a)).collect();
+        let alpha = upsample(&alpha, half_width, half_height, image_width, image_height);
+        alpha.into_iter().map(|a| a.red).collect::<Vec<_>>()
+    });
+    let retraced: Vec<bool> = (0..image_width * image_height)
+        .map(|pixel| {
+            let (x, y) = (pixel % image_width, pixel / image_width);
+            selected[((y / 2) * half_width + x / 2) as usize]
+        })
+        .collect();
+    let pixels: Vec<u32> = (0..image_width * image_height)
+        .filter(|&pixel| retraced[pixel as usize])
+        .collect();
+
+    let camera = Camera::new(
+        &camera_settings,
+        image_width as Float / image_height as Float,
+    );
+    let samples_per_pixel = render_settings.samples_per_pixel.max(1);
+    let world = &world;
+    let traced: Vec<(Color, Float)> = pixels
+        .par_iter()
+        .map_init(
+            || {
+                render_settings
+                    .sampler
+                    .create_ctx(samples_per_pixel, seeded_rng(render_settings.seed))
+            },
+            |ctx, &pixel| {
+                let i = pixel % image_width;
+                let j = image_height - 1 - pixel / image_width;
+                ctx.start_pixel_with(i, j, pixel_rng(render_settings.seed, i, j));
+                let (mut total, mut hits) = (color!(), 0.);
+                for k in 0..samples_per_pixel {
+                    ctx.start_sample(k);
+                    let (x, y) = ctx.pixel_sample();
+                    let u = (i as Float + x) / (image_width - 1) as Float;
+                    let v = (j as Float + y) / (image_height - 1) as Float;
+                    let ray = camera.get_ray(u, v, ctx);
+                    if covers(world, &ray, render_settings) {
+                        total += integrator.li(&ray, world, render_settings, ctx);
+                        hits += 1.;
+                    }
+                }
+                let count = samples_per_pixel as Float;
+                (total / count, hits / count)
+            },
+        )
+        .collect();
+    for (&pixel, (color, hits)) in pixels.iter().zip(traced) {
+        data[pixel as usize] = color;
+        if let Some(alpha) = &mut alpha {
+            alpha[pixel as usize] = hits;
+        }
+    }
+
+    Ok(Upsampled {
+        image: Image {
+            width: image_width,
+            height: image_height,
+            data,
+            alpha,
+        },
+        retraced,
+    })
+}
+
+/// A sample along with its squared brightness, so the spread of a pixel's samples is known from
+/// their average
+#[derive(Clone, Copy, Default)]
+struct Moments {
+    sample: AovSample,
+    squared: Float,
+}
+
+impl Moments {
+    /// Error in the pixel's mean brightness relative to itself, with 95% confidence, if it took
+    /// `samples` samples
+    fn relative_error(&self, samples: u32) -> Float {
+        let mean = self.sample.luminance();
+        let variance = (self.squared - mean * mean).max(0.);
+        let error = 1.96 * (variance / samples.max(1) as Float).sqrt();
+        error / mean.abs().max(1e-3)
+    }
+
+    /// How far past `settings`' thresholds the difference between the pixel and `other` is, as
+    /// the largest of the differences over their thresholds
+    fn difference(&self, other: &Self, settings: &UpsampleSettings) -> Float {
+        let (a, b) = (&self.sample, &other.sample);
+        let normal = (a.normal() - b.normal()).length();
+        let albedo = a.albedo() - b.albedo();
+        let albedo = albedo
+            .red
+            .abs()
+            .max(albedo.green.abs())
+            .max(albedo.blue.abs());
+        let depth = match (a.depth(), b.depth()) {
+            (a, b) if a.is_finite() && b.is_finite() => (a - b).abs() / a.min(b).max(1e-6),
+            // Where something covers only one of them
+            (a, b) if a.is_finite() != b.is_finite() => Float::INFINITY,
+            _ => 0.,
+        };
+        let (bright_a, bright_b) = (a.luminance(), b.luminance());
+        let contrast = (bright_a - bright_b).abs() / bright_a.max(bright_b).max(1e-3);
+        (normal / settings.normal_threshold)
+            .max(albedo / settings.albedo_threshold)
+            .max(depth / settings.depth_threshold)
+            .max(contrast / settings.contrast_threshold)
+    }
+}
+
+impl Sum for Moments {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, moments| Self {
+            sample: [total.sample, moments.sample].iter().copied().sum(),
+            squared: total.squared + moments.squared,
+        })
+    }
+}
+
+impl Mul<Float> for Moments {
+    type Output = Self;
+
+    fn mul(self, value: Float) -> Self {
+        Self {
+            sample: self.sample * value,
+            squared: self.squared * value,
+        }
+    }
+}
+
+impl Div<Float> for Moments {
+    type Output = Self;
+
+    fn div(self, value: Float) -> Self {
+        self * (1. / value)
+    }
+}
+
+impl PixelSample for Moments {
+    fn luminance(&self) -> Float {
+        self.sample.luminance()
+    }
+}
+
+/// Which half resolution pixels to trace again, those furthest past their thresholds up to
+/// `settings.max_share` of them
+fn select(
+    half: &[Moments],
+    half_width: u32,
+    samples: u32,
+    settings: &UpsampleSettings,
+) -> Vec<bool> {
+    let width = half_width as usize;
+    let scores: Vec<Float> = (0..half.len())
+        .into_par_iter()
+        .map(|index| {
+            let pixel = &half[index];
+            let (x, y) = (index % width, index / width);
+            let mut neighbors = Vec::with_capacity(4);
+            if x > 0 {
+                neighbors.push(index - 1);
+            }
+            if x + 1 < width {
+                neighbors.push(index + 1);
+            }
+            if y > 0 {
+                neighbors.push(index - width);
+            }
+            if index + width < half.len() {
+                neighbors.push(index + width);
+            }
+            neighbors
+                .into_iter()
+                .map(|neighbor| pixel.difference(&half[neighbor], settings))
+                .fold(
+                    pixel.relative_error(samples) / settings.variance_threshold,
+                    Float::max,
+                )
+        })
+        .collect();
+
+    let mut over: Vec<usize> = (0..half.len())
+        .filter(|&index| scores[index] > 1.)
+        .collect();
+    over.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let max_count = (half.len() as Float * settings.max_share.clamp(0., 1.)).round() as usize;
+    over.truncate(max_count);
+    let mut selected = vec![false; half.len()];
+    for index in over {
+        selected[index] = true;
+    }
+    selected
+}
+
+/// Bilinearly scales `data`, `width` by `height` pixels, up to `full_width` by `full_height`,
+/// lining up the centers of pixels
+fn upsample(
+    data: &[Color],
+    width: u32,
+    height: u32,
+    full_width: u32,
+    full_height: u32,
+) -> Vec<Color> {
+    let scale_x = width as Float / full_width as Float;
+    let scale_y = height as Float / full_height as Float;
+    // Neighboring pixels either side of `position`, and how far it is towards the second
+    let lerp = |position: Float, size: u32| {
+        let position = position.clamp(0., (size - 1) as Float);
+        let low = position.floor() as u32;
+        (low, (low + 1).min(size - 1), position - low as Float)
+    };
+    (0..full_width * full_height)
+        .into_par_iter()
+        .map(|pixel| {
+            let (x, y) = (pixel % full_width, pixel / full_width);
+            let (x0, x1, tx) = lerp((x as Float + 0.5) * scale_x - 0.5, width);
+            let (y0, y1, ty) = lerp((y as Float + 0.5) * scale_y - 0.5, height);
+            let at = |x: u32, y: u32| data[(y * width + x) as usize];
+            let top = at(x0, y0) * (1. - tx) + at(x1, y0) * tx;
+            let bottom = at(x0, y1) * (1. - tx) + at(x1, y1) * tx;
+            top * (1. - ty) + bottom * ty
+        })
+        .collect()
+}
@@ -1,19 +1,104 @@
-use crate::hittable::{HitRecord, Hittable, MovingSphere, Sphere};
+use crate::accel::{Accelerator, Bvh};
+use crate::atmosphere::Atmosphere;
+use crate::error::Error;
+use crate::hittable::{
+    quadratic_roots, ConstantMedium, Cuboid, HeterogeneousMedium, HitRecord, Hittable,
+    MovingSphere, Sphere, Triangle, TriangleMesh, XYRect, XZRect, YZRect,
+};
+use crate::light::{LightPower, SunLight};
 use crate::material::{Dielectric, Lambertian, Light, Material, Metal};
 use crate::ray::Ray;
-use crate::texture::{Checker, ImageTexture, SolidColor, StarTexture};
-use crate::{Color, Point3, Vec3};
+use crate::sampler::SampleRng;
+use crate::scene::ObjectDesc;
+use crate::texture::{Blackbody, Checker, GridTexture, ImageTexture, NoiseTexture, SolidColor};
+use crate::transform::{RotateY, Translate};
+use crate::{Color, Float, Point3, Vec3};
 use rand::distributions::{Distribution, Standard, Uniform};
-use rand::rngs::ThreadRng;
 use rand::Rng;
+use std::sync::Arc;
 
 /// Container for all objects in a scene
 #[derive(Default)]
-pub struct World<'a> {
-    pub hittables: Vec<Box<dyn Hittable + Sync + 'a>>,
+pub struct World {
+    pub hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+    /// Emissive hittables that can be sampled directly. Kept out of the BVH
+    pub lights: Vec<Box<dyn Hittable + Send + Sync>>,
+    pub sun: Option<SunLight>,
+    pub fog: Option<HeightFog>,
+    pub atmosphere: Option<Atmosphere>,
 }
 
-impl<'a> World<'a> {
+fn closest_hit<'a>(
+    hittables: &'a [Box<dyn Hittable + Send + Sync>],
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<HitRecord<'a>> {
+    hittables
+        .iter()
+        .filter_map(|hittable| hittable.hit(ray, t_min, t_max))
+        .min_by(|rec_a, rec_b| rec_a.t.partial_cmp(&rec_b.t).unwrap())
+}
+
+/// Cheap fog that thins out with height, blended over everything seen along each ray
+///
+/// The fog is worked out in closed form rather than traced as a volume, so it gives depth cues
+/// for almost no cost but doesn't cast shadows or scatter light from the scene
+#[derive(Clone, Copy)]
+pub struct HeightFog {
+    /// Density at `height`
+    pub density: Float,
+    /// How quickly the fog thins out going up. 0 gives even fog everywhere
+    pub falloff: Float,
+    /// Height at which the fog has `density`. Defaults to 0
+    pub height: Float,
+    pub color: Color,
+}
+
+impl HeightFog {
+    pub fn new(density: Float, falloff: Float, color: Color) -> Self {
+        Self {
+            density,
+            falloff,
+            height: 0.,
+            color,
+        }
+    }
+
+    /// Fraction of light that makes it through the fog from `t` along `ray` back to its origin
+    ///
+    /// `t` can be infinite for rays that leave the scene
+    pub fn transmittance(&self, ray: &Ray, t: Float) -> Float {
+        let length = ray.dir.length();
+        if length == 0. || self.density <= 0. {
+            return 1.;
+        }
+        let distance = t * length;
+        let rise = self.falloff * ray.dir.y / length;
+        let start = self.density * (-self.falloff * (ray.origin.y - self.height)).exp();
+        // Integrate the density along the ray
+        let depth = if rise.abs() < 1e-9 {
+            start * distance
+        } else if distance.is_infinite() {
+            if rise > 0. {
+                start / rise
+            } else {
+                Float::INFINITY
+            }
+        } else {
+            start * (1. - (-rise * distance).exp()) / rise
+        };
+        (-depth).exp()
+    }
+
+    /// Blends fog over light that travelled from `t` along `ray`
+    pub fn apply(&self, ray: &Ray, t: Float, color: Color) -> Color {
+        let transmittance = self.transmittance(ray, t);
+        color * transmittance + self.color * (1. - transmittance)
+    }
+}
+
+impl World {
     /// Generates the cover image world
     pub fn cover_world() -> Self {
         let mut world = World::default();
@@ -24,41 +109,42 @@ impl<'a> World<'a> {
         let shape = Sphere::new(point3!(0., -1000., 0.), 1000., material);
         world.add(shape);
 
-        // Random small balls
+        // Random small balls. The glass ones all share a material
+        let glass: Arc<dyn Material + Send + Sync> = Arc::new(Dielectric::new(1.5));
         let mut rng = rand::thread_rng();
         for a in -11..11 {
             for b in -11..11 {
                 let center = point3!(
-                    a as f64 + 0.9 * rng.sample::<f64, _>(Standard),
+                    a as Float + 0.9 * rng.sample::<Float, _>(Standard),
                     0.2,
-                    b as f64 + 0.9 * rng.sample::<f64, _>(Standard)
+                    b as Float + 0.9 * rng.sample::<Float, _>(Standard)
                 );
                 if (center - point3!(4., 0.2, 0.)).length() <= 0.9 {
                     continue;
                 }
 
-                let mat_choice = rng.sample::<f64, _>(Standard);
-                let mat: Box<dyn Material + Sync> = if mat_choice < 0.5 {
+                let mat_choice = rng.sample::<Float, _>(Standard);
+                let mat: Arc<dyn Material + Send + Sync> = if mat_choice < 0.5 {
                     // Diffuse
                     let albedo = random_color(&mut rng, 0., 1.) * random_color(&mut rng, 0., 1.);
                     let albedo = SolidColor::new(albedo);
-                    Box::new(Lambertian::new(albedo))
+                    Arc::new(Lambertian::new(albedo))
                 } else if mat_choice < 0.75 {
                     // Metal
                     let albedo = random_color(&mut rng, 0.5, 1.);
                     let fuzz = random_f64(&mut rng, 0., 0.5);
-                    Box::new(Metal::new(albedo, fuzz))
+                    Arc::new(Metal::new(albedo, fuzz))
                 } else {
                     // Glass
-                    Box::new(Dielectric::new(1.5))
+                    Arc::clone(&glass)
                 };
-                let sphere = Sphere::new_boxed(center, 0.2, mat);
+                let sphere = Sphere::new_shared(center, 0.2, mat);
                 world.add(sphere);
             }
         }
 
         // Big balls
-        world.add(Sphere::new(point3!(0., 1., 0.), 1., Dielectric::new(1.5)));
+        world.add(Sphere::new_shared(point3!(0., 1., 0.), 1., glass));
         world.add(Sphere::new(
             point3!(-4., 1., 0.),
             1.,
@@ -82,46 +168,47 @@ impl<'a> World<'a> {
         let shape = Sphere::new(point3!(0., -1000., 0.), 1000., material);
         world.add(shape);
 
-        // Random small balls
+        // Random small balls. The glass ones all share a material
+        let glass: Arc<dyn Material + Send + Sync> = Arc::new(Dielectric::new(1.5));
         let mut rng = rand::thread_rng();
         for a in -11..11 {
             for b in -11..11 {
                 let center = point3!(
-                    a as f64 + 0.9 * rng.sample::<f64, _>(Standard),
+                    a as Float + 0.9 * rng.sample::<Float, _>(Standard),
                     0.2,
-                    b as f64 + 0.9 * rng.sample::<f64, _>(Standard)
+                    b as Float + 0.9 * rng.sample::<Float, _>(Standard)
                 );
                 if (center - point3!(4., 0.2, 0.)).length() <= 0.9 {
                     continue;
                 }
 
-                let mat_choice = rng.sample::<f64, _>(Standard);
-                let mat: Box<dyn Material + Sync> = if mat_choice < 0.5 {
+                let mat_choice = rng.sample::<Float, _>(Standard);
+                let mat: Arc<dyn Material + Send + Sync> = if mat_choice < 0.5 {
                     // Diffuse
                     let albedo = random_color(&mut rng, 0., 1.) * random_color(&mut rng, 0., 1.);
-                    Box::new(Lambertian::new(SolidColor::new(albedo)))
+                    Arc::new(Lambertian::new(SolidColor::new(albedo)))
                 } else if mat_choice < 0.75 {
                     // Metal
                     let albedo = random_color(&mut rng, 0.5, 1.);
                     let fuzz = random_f64(&mut rng, 0., 0.5);
-                    Box::new(Metal::new(albedo, fuzz))
+                    Arc::new(Metal::new(albedo, fuzz))
                 } else {
                     // Glass
-                    Box::new(Dielectric::new(1.5))
+                    Arc::clone(&glass)
                 };
                 if rng.gen_bool(0.25) {
                     let center1 = center + point3!(0., random_f64(&mut rng, 0.1, 0.3), 0.);
-                    let sphere = MovingSphere::new_boxed(center, center1, 0., 1., 0.2, mat);
+                    let sphere = MovingSphere::new_shared(center, center1, 0., 1., 0.2, mat);
                     world.add(sphere);
                 } else {
-                    let sphere = Sphere::new_boxed(center, 0.2, mat);
+                    let sphere = Sphere::new_shared(center, 0.2, mat);
                     world.add(sphere);
                 }
             }
         }
 
         // Big balls
-        world.add(Sphere::new(point3!(0., 1., 0.), 1., Dielectric::new(1.5)));
+        world.add(Sphere::new_shared(point3!(0., 1., 0.), 1., glass));
         world.add(Sphere::new(
             point3!(-4., 1., 0.),
             1.,
@@ -146,46 +233,47 @@ impl<'a> World<'a> {
         let shape = Sphere::new(point3!(0., -1000., 0.), 1000., material);
         world.add(shape);
 
-        // Random small balls
+        // Random small balls. The glass ones all share a material
+        let glass: Arc<dyn Material + Send + Sync> = Arc::new(Dielectric::new(1.5));
         let mut rng = rand::thread_rng();
         for a in -11..11 {
             for b in -11..11 {
                 let center = point3!(
-                    a as f64 + 0.9 * rng.sample::<f64, _>(Standard),
+                    a as Float + 0.9 * rng.sample::<Float, _>(Standard),
                     0.2,
-                    b as f64 + 0.9 * rng.sample::<f64, _>(Standard)
+                    b as Float + 0.9 * rng.sample::<Float, _>(Standard)
                 );
                 if (center - point3!(4., 0.2, 0.)).length() <= 0.9 {
                     continue;
                 }
 
-                let mat_choice = rng.sample::<f64, _>(Standard);
-                let mat: Box<dyn Material + Sync> = if mat_choice < 0.5 {
+                let mat_choice = rng.sample::<Float, _>(Standard);
+                let mat: Arc<dyn Material + Send + Sync> = if mat_choice < 0.5 {
                     // Diffuse
                     let albedo = random_color(&mut rng, 0., 1.) * random_color(&mut rng, 0., 1.);
-                    Box::new(Lambertian::new(SolidColor::new(albedo)))
+                    Arc::new(Lambertian::new(SolidColor::new(albedo)))
                 } else if mat_choice < 0.75 {
                     // Metal
                     let albedo = random_color(&mut rng, 0.5, 1.);
                     let fuzz = random_f64(&mut rng, 0., 0.5);
-                    Box::new(Metal::new(albedo, fuzz))
+                    Arc::new(Metal::new(albedo, fuzz))
                 } else {
                     // Glass
-                    Box::new(Dielectric::new(1.5))
+                    Arc::clone(&glass)
                 };
                 if rng.gen_bool(0.25) {
                     let center1 = center + point3!(0., random_f64(&mut rng, 0.1, 0.3), 0.);
-                    let sphere = MovingSphere::new_boxed(center, center1, 0., 1., 0.2, mat);
+                    let sphere = MovingSphere::new_shared(center, center1, 0., 1., 0.2, mat);
                     world.add(sphere);
                 } else {
-                    let sphere = Sphere::new_boxed(center, 0.2, mat);
+                    let sphere = Sphere::new_shared(center, 0.2, mat);
                     world.add(sphere);
                 }
             }
         }
 
         // Big balls
-        world.add(Sphere::new(point3!(0., 1., 0.), 1., Dielectric::new(1.5)));
+        world.add(Sphere::new_shared(point3!(0., 1., 0.), 1., glass));
         world.add(Sphere::new(
             point3!(-4., 1., 0.),
             1.,
@@ -200,10 +288,23 @@ impl<'a> World<'a> {
         world
     }
 
-    pub fn earth() -> Self {
+    /// A marble sphere on turbulent noise ground
+    ///
+    /// Best viewed with `CameraSettings::cover_camera` and `GradientBackground::sky`
+    pub fn perlin_spheres() -> Self {
+        let mut world = World::default();
+        let ground = Lambertian::new(NoiseTexture::turbulence(1, 4.));
+        world.add(Sphere::new(point3!(0., -1000., 0.), 1000., ground));
+        let marble = Lambertian::new(NoiseTexture::marble(2, 4.));
+        world.add(Sphere::new(point3!(0., 2., 0.), 2., marble));
+        world
+    }
+
+    /// Fails if `textures/earthmap.jpg` can't be loaded
+    pub fn earth() -> Result<Self, Error> {
         // Earth
         let mut world = World::default();
-        let texture = ImageTexture::new("textures/earthmap.jpg");
+        let texture = ImageTexture::new("textures/earthmap.jpg")?;
         let material = Lambertian::new(texture);
         let shape = Sphere::new(point3!(0., 0., 0.), 2., material);
         world.add(shape);
@@ -212,34 +313,442 @@ impl<'a> World<'a> {
         let material = Lambertian::new(texture);
         let shape = Sphere::new(point3!(0., -1005., 0.), 1000., material);
         world.add(shape);
-        // Light
-        let texture = SolidColor::new(color!(1., 0., 0.));
-        let material = Light::new(texture, color!(100., 20., 20.));
-        let shape = Sphere::new(point3!(0., 3., 1.), 1., material);
+        // Sunlight from the side, reddened by the atmosphere near the terminator
+        world.sun = Some(SunLight::new(vec3!(0., 0.2, 1.), 0.53, color!(6., 6., 6.)));
+        world.atmosphere = Some(Atmosphere::earth_like(point3!(0., 0., 0.), 2., 5.));
+        Ok(world)
+    }
+
+    /// A scene lit only by an emissive sphere
+    ///
+    /// There is no background light so this renders fully black if emitted light is ignored
+    pub fn emissive_only() -> Self {
+        let mut world = World::default();
+        // Ground
+        let texture = SolidColor::new(color!(0.5, 0.5, 0.5));
+        let material = Lambertian::new(texture);
+        let shape = Sphere::new(point3!(0., -1000., 0.), 1000., material);
         world.add(shape);
+        // Diffuse ball
+        let texture = SolidColor::new(color!(0.4, 0.2, 0.1));
+        let material = Lambertian::new(texture);
+        let shape = Sphere::new(point3!(0., 1., 0.), 1., material);
+        world.add(shape);
+        // Light
+        let texture = SolidColor::new(color!(1., 1., 1.));
+        let material = Light::new(texture, color!(4., 4., 4.));
+        let shape = Sphere::new(point3!(0., 4., 2.), 1., material);
+        world.add_light(shape);
+        world
+    }
+
+    /// A unit sphere of `material` at the origin, for furnace tests against a white background,
+    /// see `furnace::furnace_test`
+    pub fn furnace(material: Arc<dyn Material + Send + Sync>) -> Self {
+        let mut world = World::default();
+        world.add(Sphere::new_shared(point3!(), 1., material));
+        world
+    }
+
+    /// The Cornell box, a room with a red and a green wall lit by a light in the ceiling
+    ///
+    /// Use with `CameraSettings::cornell_camera`
+    pub fn cornell_box() -> Self {
+        let mut world = World::default();
+        let red = || Lambertian::new(SolidColor::new(color!(0.65, 0.05, 0.05)));
+        let white = || Lambertian::new(SolidColor::new(color!(0.73, 0.73, 0.73)));
+        let green = || Lambertian::new(SolidColor::new(color!(0.12, 0.45, 0.15)));
+        let light = Light::new(SolidColor::new(color!(1., 1., 1.)), color!(15., 15., 15.));
+
+        world.add(YZRect::new(0., 555., 0., 555., 555., green()));
+        world.add(YZRect::new(0., 555., 0., 555., 0., red()));
+        world.add_light(XZRect::new(213., 343., 227., 332., 554., light));
+        world.add(XZRect::new(0., 555., 0., 555., 0., white()));
+        world.add(XZRect::new(0., 555., 0., 555., 555., white()));
+        world.add(XYRect::new(0., 555., 0., 555., 555., white()));
+
+        // Blocks
+        let tall = Cuboid::new(point3!(0., 0., 0.), point3!(165., 330., 165.), white());
+        let tall = RotateY::new(tall, 15.);
+        world.add(Translate::new(tall, vec3!(265., 0., 295.)));
+        let short = Cuboid::new(point3!(0., 0., 0.), point3!(165., 165., 165.), white());
+        let short = RotateY::new(short, -18.);
+        world.add(Translate::new(short, vec3!(130., 0., 65.)));
+        world
+    }
+
+    /// The Cornell box with its blocks replaced by white and black smoke
+    ///
+    /// Best viewed with `CameraSettings::cornell_camera`
+    pub fn cornell_smoke() -> Self {
+        let mut world = World::default();
+        let red = Lambertian::new(SolidColor::new(color!(0.65, 0.05, 0.05)));
+        let white = || Lambertian::new(SolidColor::new(color!(0.73, 0.73, 0.73)));
+        let green = Lambertian::new(SolidColor::new(color!(0.12, 0.45, 0.15)));
+        let light = Light::new(SolidColor::new(color!(1., 1., 1.)), color!(7., 7., 7.));
+
+        world.add(YZRect::new(0., 555., 0., 555., 555., green));
+        world.add(YZRect::new(0., 555., 0., 555., 0., red));
+        world.add_light(XZRect::new(113., 443., 127., 432., 554., light));
+        world.add(XZRect::new(0., 555., 0., 555., 0., white()));
+        world.add(XZRect::new(0., 555., 0., 555., 555., white()));
+        world.add(XYRect::new(0., 555., 0., 555., 555., white()));
+
+        // Smoke filled blocks
+        let tall = Cuboid::new(point3!(0., 0., 0.), point3!(165., 330., 165.), white());
+        let tall = Translate::new(RotateY::new(tall, 15.), vec3!(265., 0., 295.));
+        let short = Cuboid::new(point3!(0., 0., 0.), point3!(165., 165., 165.), white());
+        let short = Translate::new(RotateY::new(short, -18.), vec3!(130., 0., 65.));
+        world.add(ConstantMedium::new(tall, 0.01, SolidColor::new(color!())));
+        world.add(ConstantMedium::new(
+            short,
+            0.01,
+            SolidColor::new(color!(1., 1., 1.)),
+        ));
+        world
+    }
+
+    /// The Cornell box with a lumpy cloud floating in the middle
+    ///
+    /// Best viewed with `CameraSettings::cornell_camera`
+    pub fn cornell_cloud() -> Self {
+        let mut world = World::default();
+        let red = Lambertian::new(SolidColor::new(color!(0.65, 0.05, 0.05)));
+        let white = || Lambertian::new(SolidColor::new(color!(0.73, 0.73, 0.73)));
+        let green = Lambertian::new(SolidColor::new(color!(0.12, 0.45, 0.15)));
+        let light = Light::new(SolidColor::new(color!(1., 1., 1.)), color!(15., 15., 15.));
+
+        world.add(YZRect::new(0., 555., 0., 555., 555., green));
+        world.add(YZRect::new(0., 555., 0., 555., 0., red));
+        world.add_light(XZRect::new(213., 343., 227., 332., 554., light));
+        world.add(XZRect::new(0., 555., 0., 555., 0., white()));
+        world.add(XZRect::new(0., 555., 0., 555., 555., white()));
+        world.add(XYRect::new(0., 555., 0., 555., 555., white()));
+
+        // Density falls off from the center, with ripples to break up the outline
+        let center = point3!(278., 250., 278.);
+        let radius = 180.;
+        let density = GridTexture::from_fn(
+            center - point3!(radius, radius, radius),
+            center + point3!(radius, radius, radius),
+            [48, 48, 48],
+            move |p| {
+                let offset = p - center;
+                let ripple = 0.15 * (offset.x * 0.05).sin() * (offset.y * 0.07).sin();
+                (1. - offset.length() / radius + ripple).clamp(0., 1.)
+            },
+        );
+        let boundary = Sphere::new(center, radius, white());
+        world.add(HeterogeneousMedium::new(boundary, density, 0.03));
+        world
+    }
+
+    /// The Cornell box with a fireball, hottest and densest at its core
+    ///
+    /// Best viewed with `CameraSettings::cornell_camera`
+    pub fn cornell_fireball() -> Self {
+        let mut world = World::default();
+        let red = Lambertian::new(SolidColor::new(color!(0.65, 0.05, 0.05)));
+        let white = || Lambertian::new(SolidColor::new(color!(0.73, 0.73, 0.73)));
+        let green = Lambertian::new(SolidColor::new(color!(0.12, 0.45, 0.15)));
+
+        world.add(YZRect::new(0., 555., 0., 555., 555., green));
+        world.add(YZRect::new(0., 555., 0., 555., 0., red));
+        world.add(XZRect::new(0., 555., 0., 555., 0., white()));
+        world.add(XZRect::new(0., 555., 0., 555., 555., white()));
+        world.add(XYRect::new(0., 555., 0., 555., 555., white()));
+
+        let center = point3!(278., 250., 278.);
+        let radius = 180.;
+        let field = move |p: Point3| {
+            let offset = p - center;
+            let ripple = 0.15 * (offset.x * 0.05).sin() * (offset.y * 0.07).sin();
+            (1. - offset.length() / radius + ripple).clamp(0., 1.)
+        };
+        let grid = || {
+            GridTexture::from_fn(
+                center - point3!(radius, radius, radius),
+                center + point3!(radius, radius, radius),
+                [48, 48, 48],
+                field,
+            )
+        };
+        let mut fire = HeterogeneousMedium::new(Sphere::new(center, radius, white()), grid(), 0.03);
+        fire.absorption = 0.8;
+        fire.scattering = 0.2;
+        fire.emission = Arc::new(Blackbody::new(grid(), 3000., 40.));
+        world.add(fire);
+        world
+    }
+
+    /// A glass sphere focusing a small bright light onto a checkered floor
+    ///
+    /// Reference scene for caustics, which path tracing only finds by chance and photon mapping
+    /// handles well. Best viewed with `CameraSettings::caustic_camera`
+    pub fn caustic_sphere() -> Self {
+        let mut world = World::default();
+        // The checker is in 3D, so keep the floor off the heights where it's split down the middle
+        let floor = Lambertian::new(Checker::new(color!(0.1, 0.1, 0.1), color!(0.8, 0.8, 0.8)));
+        world.add(XZRect::new(-10., 10., -10., 10., -0.15, floor));
+        world.add(Sphere::new(point3!(0., 0.85, 0.), 1., Dielectric::new(1.5)));
+        let light = Light::new(
+            SolidColor::new(color!(1., 1., 1.)),
+            color!(400., 400., 400.),
+        );
+        world.add_light(Sphere::new(point3!(-4., 6., -1.), 0.2, light));
+        world
+    }
+
+    /// A rippling water surface over a tiled pool, lit by a small bright light
+    ///
+    /// Reference scene for the moving net of caustics on the pool floor, seen through the water.
+    /// Best viewed with `CameraSettings::pool_camera`
+    pub fn caustic_pool() -> Self {
+        let mut world = World::default();
+        let tiles = || Lambertian::new(Checker::new(color!(0.2, 0.5, 0.7), color!(0.9, 0.9, 0.9)));
+        let (size, depth) = (3., 1.65);
+        world.add(XZRect::new(-size, size, -size, size, -depth, tiles()));
+        world.add(YZRect::new(-depth, 0., -size, size, -size, tiles()));
+        world.add(YZRect::new(-depth, 0., -size, size, size, tiles()));
+        world.add(XYRect::new(-size, size, -depth, 0., -size, tiles()));
+        world.add(XYRect::new(-size, size, -depth, 0., size, tiles()));
+        // Paving around the edge
+        let paving = || Lambertian::new(SolidColor::new(color!(0.6, 0.55, 0.5)));
+        let edge = 2. * size;
+        world.add(XZRect::new(-edge, edge, -edge, -size, 0., paving()));
+        world.add(XZRect::new(-edge, edge, size, edge, 0., paving()));
+        world.add(XZRect::new(-edge, -size, -size, size, 0., paving()));
+        world.add(XZRect::new(size, edge, -size, size, 0., paving()));
+
+        // Flat triangles over a grid of wave heights. Fine enough that the facets blur together
+        let waves = |x: Float, z: Float| {
+            -0.2 + 0.05 * (2. * x + 1.).sin() * (1.5 * z).cos() + 0.03 * (3.1 * x - 2.3 * z).sin()
+        };
+        let steps = 48;
+        let point = |i: usize, j: usize| {
+            let x = -size + 2. * size * i as Float / steps as Float;
+            let z = -size + 2. * size * j as Float / steps as Float;
+            point3!(x, waves(x, z), z)
+        };
+        for i in 0..steps {
+            for j in 0..steps {
+                let corners = [
+                    point(i, j),
+                    point(i + 1, j),
+                    point(i + 1, j + 1),
+                    point(i, j + 1),
+                ];
+                let water = || Dielectric::new(1.33);
+                world.add(Triangle::new(corners[0], corners[1], corners[2], water()));
+                world.add(Triangle::new(corners[0], corners[2], corners[3], water()));
+            }
+        }
+
+        let light = Light::new(
+            SolidColor::new(color!(1., 1., 1.)),
+            color!(250., 250., 250.),
+        );
+        world.add_light(Sphere::new(point3!(2., 8., -3.), 0.5, light));
         world
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    /// Generates a large grid of small spheres for measuring tracing speed
+    ///
+    /// Best viewed with `CameraSettings::cover_camera`
+    pub fn benchmark_world() -> Self {
+        let mut world = World::default();
+        let material = Lambertian::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+        world.add(Sphere::new(point3!(0., -1000., 0.), 1000., material));
+        for a in -50..50 {
+            for b in -50..50 {
+                let center = point3!(a as Float * 0.25, 0.1, b as Float * 0.25);
+                let material = Lambertian::new(SolidColor::new(color!(0.8, 0.3, 0.3)));
+                world.add(Sphere::new(center, 0.1, material));
+            }
+        }
+        world
+    }
+
+    /// Replaces the hittables with a single bounding volume hierarchy containing all of them
+    ///
+    /// `t0` and `t1` are the shutter times, used to bound moving objects. Also numbers each
+    /// hittable and light, see `HitRecord::object_id`, so should only be called once
+    ///
+    /// Fails without changing the world if any hittable has no bounding box
+    pub fn build_bvh(&mut self, t0: Float, t1: Float) -> Result<(), Error> {
+        self.build_accelerator::<Bvh>(t0, t1)
+    }
+
+    /// Like `build_bvh`, but with any acceleration structure
+    pub fn build_accelerator<A: Accelerator + Send + Sync + 'static>(
+        &mut self,
+        t0: Float,
+        t1: Float,
+    ) -> Result<(), Error> {
+        let unbounded = self
+            .hittables
+            .iter()
+            .any(|hittable| hittable.bounding_box(t0, t1).is_none());
+        if self.hittables.len() >= 2 && unbounded {
+            return Err(Error::Unbounded);
+        }
+        let mut id = 0;
+        let mut number = |objects: Vec<Box<dyn Hittable + Send + Sync>>| {
+            objects
+                .into_iter()
+                .map(|object| {
+                    id += 1;
+                    Box::new(Numbered { id, object }) as Box<dyn Hittable + Send + Sync>
+                })
+                .collect()
+        };
+        self.hittables = number(std::mem::take(&mut self.hittables));
+        self.lights = number(std::mem::take(&mut self.lights));
+
+        if self.hittables.len() < 2 {
+            return Ok(());
+        }
+        let hittables = std::mem::take(&mut self.hittables);
+        self.hittables.push(Box::new(A::build(hittables, t0, t1)?));
+        Ok(())
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hit_source(ray, t_min, t_max).map(|(rec, _)| rec)
+    }
+
+    /// Like `hit`, but also returns whether the closest hit is on one of `lights`
+    pub fn hit_source(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(HitRecord<'_>, bool)> {
+        let scene = closest_hit(&self.hittables, ray, t_min, t_max);
+        let t_max = scene.as_ref().map_or(t_max, |rec| rec.t);
+        match closest_hit(&self.lights, ray, t_min, t_max) {
+            Some(rec) => Some((rec, true)),
+            None => scene.map(|rec| (rec, false)),
+        }
+    }
+
+    /// Whether anything visible to bounced rays lies along `ray` between `t_min` and `t_max`,
+    /// stopping at the first hit found. Faster than `hit` for shadow rays
+    pub fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
         self.hittables
             .iter()
-            .filter_map(|hittable| hittable.as_ref().hit(ray, t_min, t_max))
-            .min_by(|rec_a, rec_b| rec_a.t.partial_cmp(&rec_b.t).unwrap())
+            .chain(&self.lights)
+            .any(|hittable| hittable.hit_any(ray, t_min, t_max))
     }
 
-    pub fn add<T: Hittable + Sync + 'a>(&mut self, hittable: T) {
+    pub fn add<T: Hittable + Send + Sync + 'static>(&mut self, hittable: T) {
         self.hittables.push(Box::new(hittable));
     }
+
+    /// Problems with the materials in the world that would spoil renders, see
+    /// `Material::validate`
+    ///
+    /// Renders don't check for these, so callers can decide how to report them
+    pub fn validate(&self) -> Vec<String> {
+        let mut seen = Vec::new();
+        let mut problems = Vec::new();
+        for hittable in self.hittables.iter().chain(self.lights.iter()) {
+            for material in hittable.materials() {
+                // Meshes share one material between many triangles
+                let address = material as *const _ as *const ();
+                if !seen.contains(&address) {
+                    seen.push(address);
+                    problems.extend(material.validate());
+                }
+            }
+        }
+        problems
+    }
+
+    /// Adds an emissive hittable as a light so it can be sampled directly
+    ///
+    /// The hittable must support `Hittable::random_point`
+    pub fn add_light<T: Hittable + Send + Sync + 'static>(&mut self, light: T) {
+        self.lights.push(Box::new(light));
+    }
+
+    /// Adds a light that gives off `power` in total however big `shape` makes it
+    ///
+    /// `shape` wraps a light material in a hittable and is called twice, once to measure the
+    /// area and once to build the light. `light` makes the material each time
+    pub fn add_light_with_power<T, L, S>(&mut self, power: LightPower, light: L, shape: S)
+    where
+        T: Hittable + Send + Sync + 'static,
+        L: Fn() -> Light,
+        S: Fn(Light) -> T,
+    {
+        let area = shape(light()).area();
+        self.add_light(shape(light().with_power(power, area)));
+    }
+
+    /// Light passing along `ray` from `t` back to its origin through fog and atmosphere, and
+    /// light they add on the way
+    ///
+    /// `t` can be infinite for rays that leave the scene
+    pub fn attenuation(&self, ray: &Ray, t: Float) -> (Color, Color) {
+        let mut transmittance = color!(1., 1., 1.);
+        let mut added = color!();
+        if let Some(atmosphere) = &self.atmosphere {
+            let (atmosphere_transmittance, scattered) =
+                atmosphere.segment(ray, t, self.sun.as_ref());
+            transmittance = atmosphere_transmittance;
+            added = scattered;
+        }
+        // Fog sits nearest the ground, so apply it last
+        if let Some(fog) = &self.fog {
+            let fog_transmittance = fog.transmittance(ray, t);
+            transmittance *= fog_transmittance;
+            added = added * fog_transmittance + fog.color * (1. - fog_transmittance);
+        }
+        (transmittance, added)
+    }
+
+    /// Fraction of light passing along `ray` from `t` back to its origin through fog and
+    /// atmosphere
+    pub fn transmittance(&self, ray: &Ray, t: Float) -> Color {
+        let mut transmittance = color!(1., 1., 1.);
+        if let Some(atmosphere) = &self.atmosphere {
+            transmittance = atmosphere.transmittance(ray, t);
+        }
+        if let Some(fog) = &self.fog {
+            transmittance *= fog.transmittance(ray, t);
+        }
+        transmittance
+    }
+
+    /// Bounds of everything in the world
+    pub fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.hittables
+            .iter()
+            .chain(self.lights.iter())
+            .filter_map(|hittable| hittable.bounding_box(t0, t1))
+            .fold(None, |acc, bounds| match acc {
+                Some(acc) => Some(AABB::surrounding_box(&acc, &bounds)),
+                None => Some(bounds),
+            })
+    }
+
+    /// Adds each triangle of a mesh
+    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
+        for triangle in Arc::new(mesh).triangles() {
+            self.add(triangle);
+        }
+    }
 }
 
 /// Inefficient way to generate a random color in a range
-fn random_color(rng: &mut ThreadRng, from: f64, to: f64) -> Color {
+fn random_color<R: Rng>(rng: &mut R, from: Float, to: Float) -> Color {
     let dist = Uniform::from(from..to);
     color!(dist.sample(rng), dist.sample(rng), dist.sample(rng))
 }
 
-/// Inefficient way to generate a random f64 in a range
-fn random_f64(rng: &mut ThreadRng, from: f64, to: f64) -> f64 {
+/// Inefficient way to generate a random Float in a range
+fn random_f64<R: Rng>(rng: &mut R, from: Float, to: Float) -> Float {
     let dist = Uniform::from(from..to);
     dist.sample(rng)
 }
@@ -269,20 +778,30 @@ impl AABB {
         AABB { min, max }
     }
 
-    pub fn surrounding_option(box_a: Option<Self>, box_b: Option<Self>) -> Self {
-        if let Some(box_a) = box_a {
-            if let Some(box_b) = box_b {
-                return Self::surrounding_box(&box_a, &box_b);
-            }
-            return box_a;
-        }
-        if let Some(box_b) = box_b {
-            return box_b;
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let size = self.max - self.min;
+        2. * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Box around whichever of `box_a` and `box_b` there are, or `None` if neither is
+    pub fn surrounding_option(box_a: Option<Self>, box_b: Option<Self>) -> Option<Self> {
+        match (box_a, box_b) {
+            (Some(box_a), Some(box_b)) => Some(Self::surrounding_box(&box_a, &box_b)),
+            (box_a, box_b) => box_a.or(box_b),
         }
-        panic!("No bounding box!");
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.entry(ray, t_min, t_max).is_some()
+    }
+
+    /// Distance along `ray` at which it enters the box, or `t_min` if it starts inside. `None` if
+    /// it misses the box between `t_min` and `t_max`
+    pub fn entry(&self, ray: &Ray, mut t_min: Float, mut t_max: Float) -> Option<Float> {
         for i in 0..3 {
             let inv_d = 1. / ray.dir[i];
             let mut t0 = (self.min[i] - ray.origin[i]) * inv_d;
@@ -290,104 +809,310 @@ impl AABB {
             if inv_d < 0. {
                 t1 = std::mem::replace(&mut t0, t1)
             }
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+            // Narrow the range with each slab
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
             if t_max < t_min {
-                return false;
+                return None;
             }
         }
-        true
+        Some(t_min)
     }
 }
 
-pub struct BvhNode<'a> {
-    pub left: Box<dyn Hittable + Sync + 'a>,
-    pub right: Box<dyn Hittable + Sync + 'a>,
-    pub bounding_box: AABB,
+/// Most boxes `BoxPacket` tests at once, and so most children a node of a `Bvh` has
+pub const BOX_LANES: usize = 4;
+
+/// Up to `BOX_LANES` boxes stored as structure of arrays, so a ray can be tested against all of
+/// them together
+///
+/// Tested lane by lane like `SphereBatch`, or with explicit SIMD using the `simd` feature
+#[derive(Clone)]
+pub struct BoxPacket {
+    /// Lowest and highest coordinates along each axis. Unused lanes are empty, with `min` above
+    /// `max`, so rays never enter them. Boxes of hittables without one are infinite
+    min: [[Float; BOX_LANES]; 3],
+    max: [[Float; BOX_LANES]; 3],
 }
 
-impl<'a> BvhNode<'a> {
-    /// Creates a search tree from a list of `Hittable`s
-    ///
-    /// Works recursively
-    pub fn make_tree(
-        mut hittables: Vec<Box<dyn Hittable + Sync + 'a>>,
-        t0: f64,
-        t1: f64,
-        rng: &mut ThreadRng,
-    ) -> BvhNode<'a> {
-        let dim: usize = rng.gen_range(0, 3);
-        hittables.sort_by(|a, b| {
-            a.bounding_box(t0, t1).unwrap().min[dim]
-                .partial_cmp(&b.bounding_box(t0, t1).unwrap().min[dim])
-                .unwrap()
-        });
-        match hittables.len() {
-            1 => panic!(),
-            2 => {
-                let left = hittables.pop().unwrap();
-                let right = hittables.pop().unwrap();
-                let bounding_box =
-                    AABB::surrounding_option(left.bounding_box(t0, t1), right.bounding_box(t0, t1));
-
-                BvhNode {
-                    left,
-                    right,
-                    bounding_box,
-                }
+impl BoxPacket {
+    /// Panics if there are more than `BOX_LANES` boxes
+    pub fn new(boxes: &[Option<AABB>]) -> Self {
+        assert!(boxes.len() <= BOX_LANES, "Error packing too many boxes");
+        let mut packet = Self {
+            min: [[Float::INFINITY; BOX_LANES]; 3],
+            max: [[Float::NEG_INFINITY; BOX_LANES]; 3],
+        };
+        for (lane, bounding_box) in boxes.iter().enumerate() {
+            for axis in 0..3 {
+                let (min, max) = match bounding_box {
+                    Some(bounding_box) => (bounding_box.min[axis], bounding_box.max[axis]),
+                    None => (Float::NEG_INFINITY, Float::INFINITY),
+                };
+                packet.min[axis][lane] = min;
+                packet.max[axis][lane] = max;
             }
-            3 => {
-                let left = hittables.pop().unwrap();
-                let right = Self::make_tree(hittables, t0, t1, rng);
-                let bounding_box =
-                    AABB::surrounding_option(left.bounding_box(t0, t1), right.bounding_box(t0, t1));
-                BvhNode {
-                    left,
-                    right: Box::new(right),
-                    bounding_box,
-                }
+        }
+        packet
+    }
+
+    /// Where `ray` enters each box, like `AABB::entry`
+    #[cfg(not(feature = "simd"))]
+    pub fn entries(&self, ray: &Ray, t_min: Float, t_max: Float) -> [Option<Float>; BOX_LANES] {
+        let mut near = [t_min; BOX_LANES];
+        let mut far = [t_max; BOX_LANES];
+        for axis in 0..3 {
+            let inv_d = 1. / ray.dir[axis];
+            let origin = ray.origin[axis];
+            let (low, high) = if inv_d < 0. {
+                (&self.max[axis], &self.min[axis])
+            } else {
+                (&self.min[axis], &self.max[axis])
+            };
+            for lane in 0..BOX_LANES {
+                let t0 = (low[lane] - origin) * inv_d;
+                let t1 = (high[lane] - origin) * inv_d;
+                // Written so NaN leaves the range alone, matching `AABB::entry`
+                near[lane] = if t0 > near[lane] { t0 } else { near[lane] };
+                far[lane] = if t1 < far[lane] { t1 } else { far[lane] };
             }
-            _ => {
-                let mid = hittables.len() / 2;
-                let left_hittables = hittables.split_off(mid);
-                let right_hittables = hittables;
-                let left = Self::make_tree(left_hittables, t0, t1, rng);
-                let right = Self::make_tree(right_hittables, t0, t1, rng);
-                let bounding_box =
-                    AABB::surrounding_option(left.bounding_box(t0, t1), right.bounding_box(t0, t1));
-                BvhNode {
-                    left: Box::new(left),
-                    right: Box::new(right),
-                    bounding_box,
-                }
+        }
+        let mut entries = [None; BOX_LANES];
+        for lane in 0..BOX_LANES {
+            if far[lane] >= near[lane] {
+                entries[lane] = Some(near[lane]);
             }
         }
+        entries
     }
+
+    /// Where `ray` enters each box, like `AABB::entry`
+    #[cfg(feature = "simd")]
+    pub fn entries(&self, ray: &Ray, t_min: Float, t_max: Float) -> [Option<Float>; BOX_LANES] {
+        #[cfg(feature = "f32")]
+        use wide::f32x4 as FloatX4;
+        #[cfg(not(feature = "f32"))]
+        use wide::f64x4 as FloatX4;
+
+        let mut near = FloatX4::splat(t_min);
+        let mut far = FloatX4::splat(t_max);
+        for axis in 0..3 {
+            let inv_d = 1. / ray.dir[axis];
+            let (low, high) = if inv_d < 0. {
+                (self.max[axis], self.min[axis])
+            } else {
+                (self.min[axis], self.max[axis])
+            };
+            let origin = FloatX4::splat(ray.origin[axis]);
+            let inv_d = FloatX4::splat(inv_d);
+            let t0 = (FloatX4::from(low) - origin) * inv_d;
+            let t1 = (FloatX4::from(high) - origin) * inv_d;
+            // Blended rather than `max` and `min` so NaN leaves the range alone
+            near = t0.simd_gt(near).bitselect(t0, near);
+            far = t1.simd_lt(far).bitselect(t1, far);
+        }
+        let enters = far.simd_ge(near).to_array();
+        let near = near.to_array();
+        let mut entries = [None; BOX_LANES];
+        for lane in 0..BOX_LANES {
+            if enters[lane] != 0. {
+                entries[lane] = Some(near[lane]);
+            }
+        }
+        entries
+    }
+}
+
+/// Number of spheres `SphereBatch` intersects at once
+const LANES: usize = 4;
+
+/// A BVH leaf holding a few spheres, stored as structure of arrays so they can be intersected
+/// together
+///
+/// The intersection is written lane by lane over fixed size arrays, which the compiler turns into
+/// SIMD instructions. Only the closest sphere is then hit again to fill in its `HitRecord`
+pub struct SphereBatch {
+    /// Coordinates of the centers and the radii, in groups of `LANES`. Unused lanes have NaN
+    /// centers, so rays never hit them
+    x: Vec<[Float; LANES]>,
+    y: Vec<[Float; LANES]>,
+    z: Vec<[Float; LANES]>,
+    radius: Vec<[Float; LANES]>,
+    spheres: Vec<Box<dyn Hittable + Send + Sync>>,
+    bounding_box: AABB,
 }
 
-impl<'a> Hittable for BvhNode<'a> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if !self.bounding_box.hit(ray, t_min, t_max) {
-            return None;
+impl SphereBatch {
+    /// Most spheres the BVH puts in one batch
+    pub const MAX_LEN: usize = 2 * LANES;
+
+    /// `half_b`, `c` and the discriminant of the quadratic for where `ray` meets each sphere in
+    /// `group`, the same sums as `sphere_roots` so the batch and `Sphere::hit` agree on which
+    /// sphere is closest. The discriminant is negative or NaN where the ray's line misses
+    fn discriminants(&self, group: usize, ray: &Ray) -> [[Float; LANES]; 3] {
+        let (origin, dir) = (ray.origin, ray.dir);
+        let a = dir.length_squared();
+        let (x, y, z, radius) = (
+            &self.x[group],
+            &self.y[group],
+            &self.z[group],
+            &self.radius[group],
+        );
+        let mut half_b = [0.; LANES];
+        let mut c = [0.; LANES];
+        let mut discriminant = [0.; LANES];
+        for lane in 0..LANES {
+            let oc_x = origin.x - x[lane];
+            let oc_y = origin.y - y[lane];
+            let oc_z = origin.z - z[lane];
+            half_b[lane] = oc_x * dir.x + oc_y * dir.y + oc_z * dir.z;
+            c[lane] = oc_x * oc_x + oc_y * oc_y + oc_z * oc_z - radius[lane] * radius[lane];
+            // Distance from the center to the ray's line
+            let k = half_b[lane] / a;
+            let (d_x, d_y, d_z) = (oc_x - k * dir.x, oc_y - k * dir.y, oc_z - k * dir.z);
+            let distance = (d_x * d_x + d_y * d_y + d_z * d_z).sqrt();
+            discriminant[lane] = a * (radius[lane] - distance) * (radius[lane] + distance);
         }
+        [half_b, c, discriminant]
+    }
+
+    /// Panics if any of `spheres` isn't a still sphere, see `Hittable::sphere`
+    pub fn new(spheres: Vec<Box<dyn Hittable + Send + Sync>>) -> Self {
+        let groups = spheres.len().div_ceil(LANES);
+        let mut batch = Self {
+            x: vec![[Float::NAN; LANES]; groups],
+            y: vec![[Float::NAN; LANES]; groups],
+            z: vec![[Float::NAN; LANES]; groups],
+            radius: vec![[0.; LANES]; groups],
+            spheres: Vec::new(),
+            bounding_box: AABB {
+                min: point3!(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+                max: point3!(
+                    Float::NEG_INFINITY,
+                    Float::NEG_INFINITY,
+                    Float::NEG_INFINITY
+                ),
+            },
+        };
+        for (i, sphere) in spheres.iter().enumerate() {
+            let (center, radius) = sphere.sphere().expect("Only spheres can be batched");
+            let (group, lane) = (i / LANES, i % LANES);
+            batch.x[group][lane] = center.x;
+            batch.y[group][lane] = center.y;
+            batch.z[group][lane] = center.z;
+            batch.radius[group][lane] = radius;
+            let sphere_box = sphere.bounding_box(0., 0.).unwrap();
+            batch.bounding_box = AABB::surrounding_box(&batch.bounding_box, &sphere_box);
+        }
+        batch.spheres = spheres;
+        batch
+    }
+}
 
-        let left_hit = self.left.hit(ray, t_min, t_max);
-        let right_hit = self.right.hit(ray, t_min, t_max);
-        match (left_hit, right_hit) {
-            (Some(left_rec), Some(right_rec)) => {
-                if left_rec.t < right_rec.t {
-                    Some(left_rec)
+impl Hittable for SphereBatch {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let a = ray.dir.length_squared();
+        let mut closest = (t_max, None);
+        for group in 0..self.x.len() {
+            let [half_b, c, discriminant] = self.discriminants(group, ray);
+            // Most rays miss every sphere in the group, so leave out the square roots
+            if !discriminant.iter().any(|&d| d >= 0.) {
+                continue;
+            }
+            for lane in 0..LANES {
+                // Unused lanes give NaN roots, which fail the tests below
+                let (near, far) =
+                    match quadratic_roots(a, half_b[lane], c[lane], discriminant[lane]) {
+                        Some(roots) => roots,
+                        None => continue,
+                    };
+                let t = if near > t_min && near < t_max {
+                    near
+                } else if far > t_min && far < t_max {
+                    far
                 } else {
-                    Some(right_rec)
+                    continue;
+                };
+                if t < closest.0 {
+                    closest = (t, Some(group * LANES + lane));
                 }
             }
-            (Some(left_rec), None) => Some(left_rec),
-            (None, Some(right_rec)) => Some(right_rec),
-            (None, None) => None,
         }
+        self.spheres[closest.1?].hit(ray, t_min, t_max)
+    }
+
+    /// Leaves the exact test, and whether the sphere's material casts shadows, to the spheres the
+    /// ray's line passes through
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        (0..self.x.len()).any(|group| {
+            let [_, _, discriminant] = self.discriminants(group, ray);
+            (0..LANES).any(|lane| {
+                discriminant[lane] >= 0.
+                    && self.spheres[group * LANES + lane].hit_any(ray, t_min, t_max)
+            })
+        })
     }
 
-    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
         Some(self.bounding_box.clone())
     }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        self.spheres
+            .iter()
+            .map(|sphere| sphere.as_ref() as &dyn Hittable)
+            .collect()
+    }
+}
+
+/// Marks hits on an object with its number
+struct Numbered {
+    id: u32,
+    object: Box<dyn Hittable + Send + Sync>,
+}
+
+impl Hittable for Numbered {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rec = self.object.hit(ray, t_min, t_max)?;
+        Some(HitRecord {
+            object_id: self.id,
+            ..rec
+        })
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.object.hit_any(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.object.bounding_box(t0, t1)
+    }
+
+    fn random_point(&self, rng: &mut SampleRng) -> Option<(Point3, Vec3)> {
+        self.object.random_point(rng)
+    }
+
+    fn area(&self) -> Float {
+        self.object.area()
+    }
+
+    fn random_direction(&self, origin: &Point3, rng: &mut SampleRng) -> Option<Vec3> {
+        self.object.random_direction(origin, rng)
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        vec![self.object.as_ref()]
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        self.object.describe()
+    }
+
+    fn sphere(&self) -> Option<(Point3, Float)> {
+        self.object.sphere()
+    }
+
+    fn pdf_value(&self, origin: &Point3, dir: &Vec3) -> Float {
+        self.object.pdf_value(origin, dir)
+    }
 }
@@ -0,0 +1,117 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::integrator::Integrator;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_image, render_progressive, AdaptiveSampling, Color, RenderSettings};
+use ray_tracing::{Float, Point3, Vec3};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// An odd width puts the middle of the image on the edge between two columns of pixels, so the
+/// left 8 columns are noisy and the right 9 are flat
+const WIDTH: u32 = 17;
+const HEIGHT: u32 = 8;
+const NOISY_PIXELS: u32 = 8 * HEIGHT;
+const FLAT_PIXELS: u32 = 9 * HEIGHT;
+
+/// Random grey in the left half of the image and flat grey in the right, counting the samples
+/// taken in each
+#[derive(Default)]
+struct HalfNoisy {
+    noisy: AtomicU32,
+    flat: AtomicU32,
+}
+
+impl Integrator for HalfNoisy {
+    fn li(&self, ray: &Ray, _: &World, _: &RenderSettings, ctx: &mut SampleCtx) -> Color {
+        if ray.dir[0] < 0. {
+            self.noisy.fetch_add(1, Ordering::Relaxed);
+            let value = 2. * ctx.get_1d();
+            color!(value, value, value)
+        } else {
+            self.flat.fetch_add(1, Ordering::Relaxed);
+            color!(0.5, 0.5, 0.5)
+        }
+    }
+}
+
+fn camera() -> CameraSettings {
+    CameraSettings {
+        look_from: point3!(0., 0., 0.),
+        look_at: point3!(0., 0., -1.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 90.,
+        aperture: 0.,
+        focus_dist: 1.,
+        t0: 0.,
+        t1: 0.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    }
+}
+
+fn settings(tile_size: u32) -> RenderSettings {
+    RenderSettings {
+        adaptive: Some(AdaptiveSampling::new(4, 32, 0.001)),
+        tile_size,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn progressive_passes_favour_noisy_tiles_and_finish() {
+    let integrator = HalfNoisy::default();
+    // Single pixel tiles give more tiles than a pass may render once warmed up
+    let tiles = (WIDTH * HEIGHT) as usize;
+    let most_per_pass = (tiles / 4).max(rayon::current_num_threads()) as u32;
+    let mut passes = 0;
+    let mut last = 0;
+    let world = World::default();
+    render_progressive(
+        world,
+        camera(),
+        &settings(1),
+        &integrator,
+        WIDTH,
+        HEIGHT,
+        |_, pass| {
+            let taken =
+                integrator.noisy.load(Ordering::Relaxed) + integrator.flat.load(Ordering::Relaxed);
+            if pass > 4 {
+                assert!(
+                    taken - last <= most_per_pass,
+                    "{} tiles in pass {}",
+                    taken - last,
+                    pass
+                );
+            }
+            last = taken;
+            passes = pass;
+            ControlFlow::Continue(())
+        },
+    )
+    .expect("Error rendering");
+    assert_eq!(integrator.flat.load(Ordering::Relaxed), FLAT_PIXELS * 4);
+    assert_eq!(integrator.noisy.load(Ordering::Relaxed), NOISY_PIXELS * 32);
+    assert!(passes >= 32);
+}
+
+#[test]
+fn flat_pixels_stop_early_and_noisy_ones_take_every_sample() {
+    let integrator = HalfNoisy::default();
+    let world = World::default();
+    raytrace_image(world, camera(), &settings(4), &integrator, WIDTH, HEIGHT)
+        .expect("Error rendering");
+    // Before rendering, the progress bar times up to one ray per pixel
+    let probes = WIDTH * HEIGHT;
+    let flat = integrator.flat.load(Ordering::Relaxed);
+    assert!((FLAT_PIXELS * 4..=FLAT_PIXELS * 4 + probes).contains(&flat));
+    let noisy = integrator.noisy.load(Ordering::Relaxed);
+    assert!((NOISY_PIXELS * 32..=NOISY_PIXELS * 32 + probes).contains(&noisy));
+}
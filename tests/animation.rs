@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::animation::{frame_path, Easing, Keyframes, Pose};
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::transform::Matrix4;
+use ray_tracing::{Float, Point3, Vec3};
+use std::path::Path;
+
+#[test]
+fn keyframes_blend_between_keys_and_hold_outside() {
+    let keys = Keyframes::new(0.).key(2., 4.).key(1., 1.);
+    assert_eq!(keys.span(), (0., 2.));
+    assert_eq!(keys.at(-1.), 0.);
+    assert_eq!(keys.at(0.5), 0.5);
+    assert_eq!(keys.at(1.5), 2.5);
+    assert_eq!(keys.at(3.), 4.);
+
+    let step = keys.clone().with_easing(Easing::Step);
+    assert_eq!(step.at(1.9), 1.);
+    let smooth = keys.with_easing(Easing::Smooth);
+    assert_eq!(smooth.at(1.), 1.);
+    assert!(smooth.at(0.1) < 0.1);
+}
+
+#[test]
+fn keys_at_the_same_time_replace_each_other() {
+    let keys = Keyframes::new(vec3!(1., 0., 0.)).key(0., vec3!(0., 1., 0.));
+    assert_eq!(keys.at(5.), vec3!(0., 1., 0.));
+}
+
+#[test]
+fn camera_moves_between_keys() {
+    let start = CameraSettings::cover_camera();
+    let end = CameraSettings {
+        look_from: point3!(-13., 2., 3.),
+        vfov: 40.,
+        ..start.clone()
+    };
+    let camera = Keyframes::new(start).key(1., end).at(0.5);
+    assert_eq!(camera.look_from, point3!(0., 2., 3.));
+    assert_eq!(camera.vfov, 30.);
+}
+
+#[test]
+fn default_pose_does_nothing() {
+    assert_eq!(Pose::default().matrix(), Matrix4::identity());
+    let pose = Pose {
+        translation: vec3!(1., 2., 3.),
+        rotation: vec3!(0., 90., 0.),
+        scale: vec3!(2., 2., 2.),
+    };
+    let p = pose.matrix().transform_point(point3!(1., 0., 0.));
+    for (axis, expected) in [1., 2., 1.].iter().enumerate() {
+        assert!(
+            (p[axis] - expected).abs() < 16. * Float::EPSILON,
+            "{} isn't (1, 2, 1)",
+            p
+        );
+    }
+}
+
+#[test]
+fn frames_are_numbered_from_one() {
+    assert_eq!(
+        frame_path(Path::new("out"), 0),
+        Path::new("out").join("frame_0001.png")
+    );
+    assert_eq!(
+        frame_path(Path::new("out"), 12344),
+        Path::new("out").join("frame_12345.png")
+    );
+}
@@ -0,0 +1,241 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, grey};
+use rand::Rng;
+use ray_tracing::camera::{ApertureMask, Camera, CameraSettings};
+use ray_tracing::hittable::Sphere;
+use ray_tracing::image::Image;
+use ray_tracing::sampler::{seeded_rng, SampleCtx, SamplerKind};
+use ray_tracing::scene::{CameraDesc, Scene};
+use ray_tracing::world::World;
+use ray_tracing::{render_mask, MaskKind};
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+fn settings() -> CameraSettings {
+    CameraSettings {
+        look_from: point3!(0., 1., 5.),
+        look_at: point3!(0., 1., 0.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 40.,
+        aperture: 0.,
+        focus_dist: 5.,
+        t0: 0.,
+        t1: 1.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    }
+}
+
+fn camera() -> Camera {
+    Camera::new(&settings(), 2.)
+}
+
+#[test]
+fn pixel_rays_cover_each_cell_of_the_pixel() {
+    let camera = camera();
+    let (width, height) = (40, 20);
+    for &kind in &[SamplerKind::Random, SamplerKind::Sobol] {
+        let mut ctx = kind.create_ctx(9, seeded_rng(Some(3)));
+        let mut cells = [0; 9];
+        for ray in camera.rays_for_pixel(12, 7, 9, width, height, &mut ctx) {
+            let (s, t) = camera
+                .project(ray.origin + ray.dir.conv::<Point3>())
+                .expect("Pixel rays go forwards");
+            let x = s * (width - 1) as Float - 12.;
+            let y = t * (height - 1) as Float - 7.;
+            assert!((0. ..1.).contains(&x) && (0. ..1.).contains(&y));
+            cells[(y * 3.) as usize * 3 + (x * 3.) as usize] += 1;
+        }
+        assert_eq!(cells, [1; 9]);
+    }
+}
+
+#[test]
+fn pixel_rays_repeat_with_the_same_seed() {
+    let camera = camera();
+    let rays = || {
+        let mut ctx = SamplerKind::Random.create_ctx(5, seeded_rng(Some(1)));
+        let rays: Vec<Vec3> = camera
+            .rays_for_pixel(3, 4, 5, 40, 20, &mut ctx)
+            .map(|ray| ray.dir)
+            .collect();
+        rays
+    };
+    assert_eq!(rays(), rays());
+}
+
+/// A sphere in the middle of the view and a smaller one to its right
+fn two_spheres() -> World {
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(0., 1., 0.), 0.5, grey()));
+    world.add(Sphere::new(point3!(1.5, 1., 0.), 0.3, grey()));
+    world
+}
+
+/// Column and row from the top of the pixel whose center is nearest where `point` is seen
+fn pixel_of(point: Point3, width: u32, height: u32) -> (u32, u32) {
+    let (s, t) = camera().project(point).expect("Point is in front");
+    let i = (s * (width - 1) as Float - 0.5).round() as u32;
+    let j = (t * (height - 1) as Float - 0.5).round() as u32;
+    (i, height - 1 - j)
+}
+
+#[test]
+fn masks_line_up_with_projected_points() {
+    let (width, height) = (40, 20);
+    let coverage = render_mask(two_spheres(), settings(), MaskKind::Coverage, width, height)
+        .expect("Error rendering mask");
+    let ids = render_mask(two_spheres(), settings(), MaskKind::ObjectId, width, height)
+        .expect("Error rendering mask");
+    let (x, y) = pixel_of(point3!(0., 1., 0.), width, height);
+    assert_eq!(coverage.pixel(x, y)[0], 1.);
+    assert_eq!(ids.pixel(x, y)[0], 1.);
+    let (x, y) = pixel_of(point3!(1.5, 1., 0.), width, height);
+    assert_eq!(coverage.pixel(x, y)[0], 1.);
+    assert_eq!(ids.pixel(x, y)[0], 2.);
+    for (x, y) in [(0, 0), (width - 1, height - 1)] {
+        assert_eq!(
+            coverage.pixel(x, y)[0],
+            0.,
+            "Corners only show the background"
+        );
+        assert_eq!(ids.pixel(x, y)[0], 0.);
+    }
+}
+
+#[test]
+fn masks_ignore_the_lens() {
+    let blurry = CameraSettings {
+        aperture: 2.,
+        ..settings()
+    };
+    let sharp = render_mask(two_spheres(), settings(), MaskKind::Coverage, 40, 20)
+        .expect("Error rendering mask");
+    let mask = render_mask(two_spheres(), blurry, MaskKind::Coverage, 40, 20)
+        .expect("Error rendering mask");
+    assert_eq!(mask.data, sharp.data);
+    let covered = mask.data.iter().filter(|color| color[0] == 1.).count();
+    assert!(covered > 0 && covered < mask.data.len());
+}
+
+#[test]
+fn clipping_distances_cut_away_geometry() {
+    // A small sphere in front of a larger one, both in the middle of the view
+    let world = || {
+        let mut world = World::default();
+        world.add(Sphere::new(point3!(0., 1., 2.), 0.3, grey()));
+        world.add(Sphere::new(point3!(0., 1., 0.), 0.5, grey()));
+        world
+    };
+    let center = |settings: CameraSettings| {
+        let ids = render_mask(world(), settings, MaskKind::ObjectId, 41, 21)
+            .expect("Error rendering mask");
+        ids.pixel(20, 10)[0]
+    };
+    assert_eq!(center(settings()), 1.);
+    let near = |near| CameraSettings { near, ..settings() };
+    assert_eq!(center(near(3.5)), 2., "The front sphere is closer than 3.5");
+    assert_eq!(center(near(6.)), 0.);
+    let far = |far| CameraSettings { far, ..settings() };
+    assert_eq!(center(far(3.)), 1.);
+    assert_eq!(center(far(2.)), 0., "Both spheres are further than 2");
+}
+
+/// A mask 4 pixels across and 2 high, letting light through its left half only, three times
+/// as much at the top
+fn half_mask() -> ApertureMask {
+    let data = vec![3., 3., 0., 0., 1., 1., 0., 0.];
+    ApertureMask::from_pixels(4, 2, data).expect("Error creating mask")
+}
+
+#[test]
+fn aperture_masks_are_sampled_by_brightness() {
+    let mask = half_mask();
+    let mut rng = seeded_rng(Some(5));
+    let mut top = 0;
+    for _ in 0..10_000 {
+        let (x, y) = mask.sample((rng.gen(), rng.gen()));
+        assert!((-1. ..=0.).contains(&x), "{} is in the dark half", x);
+        assert!((-0.5..=0.5).contains(&y), "{} is off the mask", y);
+        if y > 0. {
+            top += 1;
+        }
+    }
+    assert!((top as Float / 10_000. - 0.75).abs() < 0.02, "{}", top);
+
+    let black = ApertureMask::from_pixels(2, 2, vec![0.; 4]);
+    assert!(black.is_err(), "Black masks let no light through");
+}
+
+#[test]
+fn masked_lenses_shape_the_rays() {
+    let settings = CameraSettings {
+        aperture: 2.,
+        aperture_mask: Some(Arc::new(half_mask())),
+        ..settings()
+    };
+    let camera = Camera::new(&settings, 2.);
+    let mut ctx = SampleCtx::from_seed(Some(1));
+    for _ in 0..1000 {
+        let ray = camera.get_ray(0.5, 0.5, &mut ctx);
+        let offset = ray.origin - settings.look_from;
+        assert!(
+            offset[0] <= 0.,
+            "{} is in the dark half of the lens",
+            offset
+        );
+        assert!(offset[1].abs() <= 0.5 && offset[2] == 0.);
+        // Rays still meet on the plane of focus
+        let focus = ray.at(5. / -ray.dir[2]);
+        let expected = point3!(0., 1., 0.);
+        assert!((0..3).all(|i| close(focus[i], expected[i])), "{}", focus);
+    }
+}
+
+#[test]
+fn scene_files_load_aperture_masks() {
+    let dir = std::env::temp_dir().join(format!("ray-tracing-{}-aperture", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Error creating directory");
+    let path = dir.join("star.png");
+    let mut data = vec![color!(); 9];
+    data[4] = color!(1., 1., 1.);
+    let image = Image {
+        width: 3,
+        height: 3,
+        data,
+        alpha: None,
+    };
+    image.write_png(&path).expect("Error writing mask");
+
+    let json = format!(
+        r#"{{
+            "camera": {{ "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40,
+                         "aperture": 0.5, "aperture_mask": {{ "path": {:?} }} }},
+            "objects": []
+        }}"#,
+        path.to_string_lossy()
+    );
+    let scene = Scene::from_json(&json).expect("Error building scene");
+    let mask = scene
+        .camera
+        .aperture_mask
+        .as_ref()
+        .expect("Expected a mask");
+    assert_eq!(mask.path(), Some(path.as_path()));
+    // Only the middle pixel is lit
+    let (x, y) = mask.sample((0.9, 0.1));
+    assert!(x.abs() <= 1. / 3. && y.abs() <= 1. / 3.);
+    let described = CameraDesc::try_from(&scene.camera)
+        .expect("Error describing camera")
+        .aperture_mask
+        .expect("Expected a mask");
+    assert_eq!(described.path, path.to_string_lossy());
+
+    std::fs::remove_dir_all(&dir).expect("Error removing mask");
+}
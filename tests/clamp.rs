@@ -0,0 +1,69 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::background::SolidBackground;
+use ray_tracing::hittable::XZRect;
+use ray_tracing::integrator::{ClampMode, PathIntegrator, PathType, RadianceClamp};
+use ray_tracing::material::Metal;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::texture::SolidColor;
+use ray_tracing::world::World;
+use ray_tracing::{Color, Float, Point3, RenderSettings, Vec3};
+use std::sync::Arc;
+
+/// Light a camera ray brings back from a perfect mirror giving off 3, which reflects a background
+/// of `background`, clamped to 4 by `mode`
+fn mirrored(background: Float, mode: ClampMode, indirect_only: bool) -> (Color, Color) {
+    let emission = Arc::new(SolidColor::new(color!(3., 3., 3.)));
+    let mirror = Metal::new(color!(1., 1., 1.), 0.).with_emission(emission);
+    let mut world = World::default();
+    world.add(XZRect::new(-1., 1., -1., 1., 0., mirror));
+    let settings = RenderSettings {
+        max_depth: 10,
+        background: Box::new(SolidBackground::new(color!(
+            background, background, background
+        ))),
+        clamp: Some(RadianceClamp {
+            max: 4.,
+            mode,
+            indirect_only,
+        }),
+        ..Default::default()
+    };
+    let ray = Ray::new(point3!(0., 1., 0.), vec3!(0.3, -1., 0.), 0.);
+    let mut ctx = SampleCtx::from_seed(Some(1));
+    let paths = PathIntegrator.light_paths(&ray, &world, &settings, &mut ctx);
+    (paths[PathType::Emission], paths[PathType::SpecularDirect])
+}
+
+#[test]
+fn per_bounce_clamps_each_bright_bounce() {
+    // Each bounce is within the limit, so nothing is clamped even though their total isn't
+    let (emission, reflected) = mirrored(3., ClampMode::PerBounce, false);
+    assert!(close(emission[0], 3.) && close(reflected[0], 3.));
+    // Only the over-bright bounce is brought down
+    let (emission, reflected) = mirrored(10., ClampMode::PerBounce, false);
+    assert!(close(emission[0], 3.) && close(reflected[0], 4.));
+}
+
+#[test]
+fn per_sample_clamps_the_total_of_every_bounce() {
+    for &background in &[3., 10.] {
+        let (emission, reflected) = mirrored(background, ClampMode::PerSample, false);
+        assert!(close(emission[0] + reflected[0], 4.));
+        // Scaled together, keeping their shares of the total
+        assert!(close(emission[0] / reflected[0], 3. / background));
+    }
+}
+
+#[test]
+fn indirect_clamps_leave_direct_light_alone() {
+    for &mode in &[ClampMode::PerBounce, ClampMode::PerSample] {
+        let (emission, reflected) = mirrored(10., mode, true);
+        assert!(close(emission[0], 3.) && close(reflected[0], 10.));
+    }
+}
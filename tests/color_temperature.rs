@@ -0,0 +1,50 @@
+mod common;
+
+use common::{close, hit_floor};
+use ray_tracing::material::Light;
+use ray_tracing::{blackbody, Color};
+
+#[test]
+fn daylight_is_near_white_and_embers_are_red() {
+    let daylight = Color::from_kelvin(6500.);
+    for i in 0..3 {
+        assert!((daylight[i] - 1.).abs() < 0.05, "{:?}", daylight);
+    }
+    let embers = Color::from_kelvin(1000.);
+    assert!(
+        embers[0] > 2. * embers[1] && embers[1] >= embers[2],
+        "{:?}",
+        embers
+    );
+    // Hotter bodies are bluer, at the same brightness
+    let mut last = 0.;
+    for &kelvin in &[1000., 1900., 2700., 5500., 6500., 10000.] {
+        let color = Color::from_kelvin(kelvin);
+        assert!(close(color.luminance(), 1.));
+        let blueness = color[2] / color[0];
+        assert!(blueness >= last, "{}K is less blue than the last", kelvin);
+        last = blueness;
+    }
+}
+
+#[test]
+fn blackbodies_peak_where_wiens_law_says() {
+    for &kelvin in &[3000., 5000., 7000.] {
+        let peak = 2.8978e6 / kelvin;
+        let at_peak = blackbody(peak, kelvin);
+        assert!(at_peak > blackbody(peak - 10., kelvin));
+        assert!(at_peak > blackbody(peak + 10., kelvin));
+    }
+    assert!(blackbody(550., 6000.) > blackbody(550., 5000.));
+    assert_eq!(blackbody(550., 0.), 0.);
+}
+
+#[test]
+fn kelvin_lights_glow_as_bright_as_asked() {
+    for &(kelvin, luminance) in &[(1900., 2.), (6500., 5.)] {
+        let light = Light::from_kelvin(kelvin, luminance);
+        let emitted: Color = hit_floor(light, 0., |ray, rec| rec.material.emitted(ray, rec));
+        assert!(close(emitted.luminance(), luminance), "{:?}", emitted);
+        assert!(close(emitted[0], Color::from_kelvin(kelvin)[0] * luminance));
+    }
+}
@@ -0,0 +1,243 @@
+//! Helpers for testing hittables against what they should hit, and for scene files
+//!
+//! Each test file only uses some of these
+#![allow(dead_code)]
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::hittable::{HitRecord, Hittable, XZRect, HIT_EPSILON};
+use ray_tracing::material::{Lambertian, Material};
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::{MaterialDesc, ObjectDesc, Scene};
+use ray_tracing::texture::SolidColor;
+use ray_tracing::world::AABB;
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::path::PathBuf;
+
+/// How far hits can be from where they're expected, relative to the distance along the ray
+#[cfg(not(feature = "f32"))]
+pub const TOLERANCE: Float = 1e-6;
+#[cfg(feature = "f32")]
+pub const TOLERANCE: Float = 1e-3;
+
+/// A plain grey material for shapes under test
+pub fn grey() -> Lambertian {
+    grey_of(0.5)
+}
+
+/// A matte material reflecting `value` of each channel
+pub fn grey_of(value: Float) -> Lambertian {
+    Lambertian::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+/// Calls `test` with a ray coming down at `x` onto a floor made of `material` from -1 to 1, whose
+/// `u` is `(x + 1) / 2`, and where it hits
+pub fn hit_floor<M, F, T>(material: M, x: Float, test: F) -> T
+where
+    M: Material + Send + Sync + 'static,
+    F: FnOnce(&Ray, &HitRecord) -> T,
+{
+    let floor = XZRect::new(-1., 1., -1., 1., 0., material);
+    let ray = Ray::new(Point3::new(x, 2., 0.1), Vec3::new(0., -1., 0.3), 0.);
+    let rec = floor
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Expected to hit the floor");
+    test(&ray, &rec)
+}
+
+/// A path in the temporary directory unique to this test run
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ray-tracing-{}-{}", std::process::id(), name))
+}
+
+/// Builds a scene file from `fields`, such as `"objects"`, with a camera looking down -z at the
+/// origin from 5 away
+pub fn scene_from_json(fields: &str) -> Scene {
+    let json = format!(
+        r#"{{
+            "camera": {{ "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 }},
+            {}
+        }}"#,
+        fields
+    );
+    Scene::from_json(&json).expect("Error building scene")
+}
+
+/// How a scene file would describe the materials of `scene`'s objects, in order
+pub fn describe_materials(scene: &Scene) -> Vec<Option<MaterialDesc>> {
+    scene
+        .world
+        .hittables
+        .iter()
+        .flat_map(|hittable| hittable.materials())
+        .map(|material| material.describe())
+        .collect()
+}
+
+/// How a scene file would describe `scene`'s objects, in order
+pub fn describe_objects(scene: &Scene) -> Vec<Option<ObjectDesc>> {
+    scene
+        .world
+        .hittables
+        .iter()
+        .map(|hittable| hittable.describe())
+        .collect()
+}
+
+/// Whether `a` and `b` are within `TOLERANCE` of each other, relative to their size
+pub fn close(a: Float, b: Float) -> bool {
+    (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.)
+}
+
+/// Asserts `hittable` is hit by `ray` at `t`, and that the hit record agrees with itself: the
+/// point is along the ray at `t`, and the normal is a unit vector facing back along the ray
+pub fn assert_hit<'a>(hittable: &'a dyn Hittable, ray: &Ray, t: Float) -> HitRecord<'a> {
+    let rec = hittable
+        .hit(ray, HIT_EPSILON, Float::INFINITY)
+        .unwrap_or_else(|| panic!("Expected a hit at t = {}, got none", t));
+    assert!(
+        close(rec.t, t),
+        "Expected a hit at t = {}, got {}",
+        t,
+        rec.t
+    );
+    assert_consistent(ray, &rec);
+    rec
+}
+
+/// Asserts `ray` misses `hittable`
+pub fn assert_miss(hittable: &dyn Hittable, ray: &Ray) {
+    if let Some(rec) = hittable.hit(ray, HIT_EPSILON, Float::INFINITY) {
+        panic!("Expected a miss, got a hit at t = {}", rec.t);
+    }
+}
+
+/// Asserts `rec` describes a hit along `ray`
+pub fn assert_consistent(ray: &Ray, rec: &HitRecord) {
+    let expected = ray.at(rec.t);
+    for axis in 0..3 {
+        assert!(
+            close(rec.point[axis], expected[axis]),
+            "Hit point {} isn't along the ray at t = {}, which is {}",
+            rec.point,
+            rec.t,
+            expected
+        );
+    }
+    assert!(
+        close(rec.normal.length(), 1.),
+        "Normal {} isn't a unit vector",
+        rec.normal
+    );
+    assert!(
+        rec.normal.dot(&ray.dir) <= 0.,
+        "Normal {} faces along the ray {}",
+        rec.normal,
+        ray.dir
+    );
+    assert!(
+        (0. ..=1.).contains(&rec.u),
+        "u = {} is outside 0 to 1",
+        rec.u
+    );
+    assert!(
+        (0. ..=1.).contains(&rec.v),
+        "v = {} is outside 0 to 1",
+        rec.v
+    );
+}
+
+/// Random point inside the box
+pub fn random_point(rng: &mut StdRng, bounds: &AABB) -> Point3 {
+    let mut axis = |axis: usize| rng.gen_range(bounds.min[axis], bounds.max[axis]);
+    Point3::new(axis(0), axis(1), axis(2))
+}
+
+/// Random unit vector
+pub fn random_direction(rng: &mut StdRng) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.gen_range(-1., 1.),
+            rng.gen_range(-1., 1.),
+            rng.gen_range(-1., 1.),
+        );
+        let length_squared = v.length_squared();
+        if length_squared > 1e-6 && length_squared <= 1. {
+            return v / length_squared.sqrt();
+        }
+    }
+}
+
+/// `count` seeded random rays aimed at points in `target` from up to `spread` away from it,
+/// about half of which hit what's inside and half of which pass by, at random times from 0 to 1
+pub fn random_rays(seed: u64, count: usize, target: &AABB, spread: Float) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let outer = AABB::new(
+        target.min - Point3::new(spread, spread, spread),
+        target.max + Point3::new(spread, spread, spread),
+    );
+    (0..count)
+        .map(|_| {
+            let origin = random_point(&mut rng, &outer);
+            let dir = if rng.gen::<bool>() {
+                (random_point(&mut rng, target) - origin).conv()
+            } else {
+                random_direction(&mut rng)
+            };
+            Ray::new(origin, dir, rng.gen())
+        })
+        .collect()
+}
+
+/// Compares `hittable` against `expected`, a simple formula for the nearest `t` past
+/// `HIT_EPSILON` where each ray hits the same shape, for a few thousand random rays around the
+/// shape's bounding box
+///
+/// Rays passing within rounding of an edge can go either way, so `expected` can return `None`
+/// to skip them
+pub fn fuzz<F>(hittable: &dyn Hittable, seed: u64, expected: F)
+where
+    F: Fn(&Ray) -> Option<Option<Float>>,
+{
+    let bounds = hittable
+        .bounding_box(0., 1.)
+        .expect("Fuzzed hittables need a bounding box");
+    for ray in random_rays(seed, 5000, &bounds, 4.) {
+        let expected = match expected(&ray) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        match expected {
+            Some(t) => {
+                assert_hit(hittable, &ray, t);
+            }
+            None => assert_miss(hittable, &ray),
+        }
+    }
+}
+
+/// Asserts every hit of random rays over times 0 to 1 lies inside the bounding box `hittable`
+/// gives for that time range
+pub fn assert_bounded(hittable: &dyn Hittable, seed: u64) {
+    let bounds = hittable
+        .bounding_box(0., 1.)
+        .expect("Hittable has no bounding box");
+    let mut hits = 0;
+    for ray in random_rays(seed, 5000, &bounds, 4.) {
+        if let Some(rec) = hittable.hit(&ray, HIT_EPSILON, Float::INFINITY) {
+            hits += 1;
+            for axis in 0..3 {
+                let margin = TOLERANCE * rec.t.max(1.);
+                assert!(
+                    rec.point[axis] >= bounds.min[axis] - margin
+                        && rec.point[axis] <= bounds.max[axis] + margin,
+                    "Hit {} is outside the bounding box from {} to {}",
+                    rec.point,
+                    bounds.min,
+                    bounds.max
+                );
+            }
+        }
+    }
+    assert!(hits > 0, "No random ray hit the hittable");
+}
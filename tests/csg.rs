@@ -0,0 +1,182 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{assert_bounded, assert_hit, assert_miss, close, fuzz, grey};
+use ray_tracing::csg::{Csg, CsgOperation};
+use ray_tracing::hittable::{Cuboid, Hittable, Sphere, HIT_EPSILON};
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::{ObjectDesc, Scene};
+use ray_tracing::{Float, Point3, Vec3};
+
+/// Rays passing this close to a tangent or where surfaces cross are left out of fuzzing, as
+/// rounding can decide whether they hit
+#[cfg(not(feature = "f32"))]
+const MARGIN: Float = 1e-6;
+#[cfg(feature = "f32")]
+const MARGIN: Float = 1e-3;
+
+/// Where `ray` is inside the sphere, from the textbook quadratic. Outer `None` near a tangent
+fn sphere_interval(ray: &Ray, center: Point3, radius: Float) -> Option<Option<(Float, Float)>> {
+    let oc: Vec3 = (ray.origin - center).conv();
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant.abs() < MARGIN * half_b * half_b {
+        return None;
+    }
+    if discriminant < 0. {
+        return Some(None);
+    }
+    let root = discriminant.sqrt();
+    Some(Some(((-half_b - root) / a, (-half_b + root) / a)))
+}
+
+/// Nearest `t` past `HIT_EPSILON` where `ray` crosses the surface of `operation` applied to two
+/// spheres, from where it's inside each
+fn csg_t(
+    ray: &Ray,
+    operation: CsgOperation,
+    a: (Point3, Float),
+    b: (Point3, Float),
+) -> Option<Option<Float>> {
+    let a = sphere_interval(ray, a.0, a.1)?;
+    let b = sphere_interval(ray, b.0, b.1)?;
+    let inside = |t: Float| {
+        let within = |interval: Option<(Float, Float)>| {
+            interval.is_some_and(|(near, far)| near < t && t < far)
+        };
+        let (a, b) = (within(a), within(b));
+        match operation {
+            CsgOperation::Union => a || b,
+            CsgOperation::Intersection => a && b,
+            CsgOperation::Difference => a && !b,
+        }
+    };
+    let mut ends: Vec<Float> = a
+        .iter()
+        .chain(b.iter())
+        .flat_map(|&(n, f)| [n, f])
+        .collect();
+    ends.sort_by(|x, y| x.partial_cmp(y).expect("Ends aren't NaN"));
+    let crowded = ends.windows(2).any(|pair| pair[1] - pair[0] < MARGIN);
+    if crowded || ends.iter().any(|t| (t - HIT_EPSILON).abs() < MARGIN) {
+        return None;
+    }
+    let step = MARGIN / 2.;
+    Some(
+        ends.into_iter()
+            .find(|&t| t > HIT_EPSILON && inside(t - step) != inside(t + step)),
+    )
+}
+
+#[test]
+fn spheres_combine_like_their_intervals() {
+    let a = (point3!(0., 0., 0.), 1.);
+    let b = (point3!(0.8, 0.3, 0.), 0.7);
+    let operations = [
+        CsgOperation::Union,
+        CsgOperation::Intersection,
+        CsgOperation::Difference,
+    ];
+    for (seed, &operation) in operations.iter().enumerate() {
+        let csg = Csg::new(
+            operation,
+            Sphere::new(a.0, a.1, grey()),
+            Sphere::new(b.0, b.1, grey()),
+        );
+        fuzz(&csg, seed as u64, |ray| csg_t(ray, operation, a, b));
+        assert_bounded(&csg, seed as u64 + 10);
+    }
+}
+
+#[test]
+fn lenses_are_where_spheres_overlap() {
+    let lens = Csg::new(
+        CsgOperation::Intersection,
+        Sphere::new(point3!(-1.5, 0., 0.), 2., grey()),
+        Sphere::new(point3!(1.5, 0., 0.), 2., grey()),
+    );
+    let across = Ray::new(point3!(-5., 0., 0.), vec3!(1., 0., 0.), 0.);
+    let entry = assert_hit(&lens, &across, 4.5);
+    assert!(entry.front_face);
+    let exit = assert_hit(&lens, &Ray::new(point3!(), vec3!(1., 0., 0.), 0.), 0.5);
+    assert!(!exit.front_face);
+    assert_miss(
+        &lens,
+        &Ray::new(point3!(0., 1.5, 5.), vec3!(0., 0., -1.), 0.),
+    );
+
+    let bounds = lens.bounding_box(0., 1.).expect("Lenses are bounded");
+    assert_eq!(bounds.min, point3!(-0.5, -2., -2.));
+    assert_eq!(bounds.max, point3!(0.5, 2., 2.));
+}
+
+#[test]
+fn hollow_spheres_are_entered_from_the_hollow() {
+    let hollow = Csg::new(
+        CsgOperation::Difference,
+        Sphere::new(point3!(), 1., grey()),
+        Sphere::new(point3!(), 0.8, grey()),
+    );
+    let outward = Ray::new(point3!(), vec3!(0., 0., 1.), 0.);
+    let inner = assert_hit(&hollow, &outward, 0.8);
+    assert!(inner.front_face, "The inner surface faces into the hollow");
+    assert!(close(inner.normal[2], -1.));
+    let inward = Ray::new(point3!(0., 0., 3.), vec3!(0., 0., -1.), 0.);
+    assert_hit(&hollow, &inward, 2.);
+    let shell = Ray::new(point3!(0., 0., 0.9), vec3!(0., 0., -1.), 0.);
+    let rec = assert_hit(&hollow, &shell, 0.1);
+    assert!(!rec.front_face, "Leaving the shell into the hollow");
+}
+
+#[test]
+fn boxes_can_cut_spheres() {
+    let cut = Csg::new(
+        CsgOperation::Difference,
+        Sphere::new(point3!(), 1., grey()),
+        Cuboid::new(point3!(0., -2., -2.), point3!(2., 2., 2.), grey()),
+    );
+    let down = Ray::new(point3!(0.5, 5., 0.), vec3!(0., -1., 0.), 0.);
+    assert_miss(&cut, &down);
+    let across = Ray::new(point3!(-5., 0., 0.), vec3!(1., 0., 0.), 0.);
+    assert_hit(&cut, &across, 4.);
+    let rec = assert_hit(
+        &cut,
+        &Ray::new(point3!(-0.5, 0., 0.), vec3!(1., 0., 0.), 0.),
+        0.5,
+    );
+    assert!(!rec.front_face);
+    assert!(
+        close(rec.normal[0], -1.),
+        "The cut face faces out of the sphere"
+    );
+}
+
+#[test]
+fn scene_files_build_csg() {
+    let scene = Scene::from_json(
+        r#"{
+            "camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 },
+            "objects": [{
+                "type": "csg", "operation": "difference",
+                "a": { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+                       "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } },
+                "b": { "type": "sphere", "center": [0, 0, 0], "radius": 0.8,
+                       "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } }
+            }]
+        }"#,
+    )
+    .expect("Error building scene");
+    let csg = &scene.world.hittables[0];
+    assert_eq!(csg.materials().len(), 2);
+    assert!(matches!(
+        csg.describe(),
+        Some(ObjectDesc::Csg {
+            operation: CsgOperation::Difference,
+            ..
+        })
+    ));
+}
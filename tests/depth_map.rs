@@ -0,0 +1,72 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, grey};
+use ray_tracing::depth_map::{render_depth_map, DepthMapSettings};
+use ray_tracing::error::Error;
+use ray_tracing::hittable::Cuboid;
+use ray_tracing::world::World;
+use ray_tracing::{Float, Point3};
+
+/// A unit cube at the origin, and a box twice as tall one unit past it along x
+fn boxes() -> World {
+    let mut world = World::default();
+    world.add(Cuboid::new(point3!(), point3!(1., 1., 1.), grey()));
+    world.add(Cuboid::new(
+        point3!(2., 0., 0.),
+        point3!(3., 2., 1.),
+        grey(),
+    ));
+    world
+}
+
+/// Red channel of each pixel in the top row
+fn top_row(image: &ray_tracing::image::Image) -> Vec<Float> {
+    let row = image.data.iter().take(image.width as usize);
+    row.map(|color| color[0]).collect()
+}
+
+#[test]
+fn depths_are_measured_from_the_top_of_the_bounds() {
+    let depth_map =
+        render_depth_map(boxes(), &DepthMapSettings::top_down(6, 2)).expect("Error rendering");
+    assert_eq!((depth_map.image.width, depth_map.image.height), (6, 2));
+    assert_eq!(depth_map.image.data.len(), 12);
+    assert!(close(depth_map.pixel_size, 0.5));
+    let depths = top_row(&depth_map.image);
+    assert!(close(depths[0], 1.) && close(depths[1], 1.));
+    assert!(depths[2].is_infinite() && depths[3].is_infinite());
+    assert!(close(depths[4], 0.) && close(depths[5], 0.));
+    assert_eq!(depth_map.image.data[..6], depth_map.image.data[6..]);
+}
+
+#[test]
+fn heightmaps_put_the_nearest_surface_at_one() {
+    let depth_map =
+        render_depth_map(boxes(), &DepthMapSettings::top_down(6, 2)).expect("Error rendering");
+    let heights = top_row(&depth_map.heightmap());
+    assert_eq!(heights, [0., 0., 0., 0., 1., 1.]);
+}
+
+#[test]
+fn pixels_stay_square() {
+    let depth_map =
+        render_depth_map(boxes(), &DepthMapSettings::top_down(6, 6)).expect("Error rendering");
+    assert!(close(depth_map.pixel_size, 0.5));
+    let first_row = top_row(&depth_map.image);
+    assert!(
+        first_row.iter().all(|depth| depth.is_infinite()),
+        "The boxes are centered, leaving room above them"
+    );
+}
+
+#[test]
+fn depth_maps_of_nothing_are_unbounded() {
+    let settings = DepthMapSettings::top_down(4, 4);
+    assert!(matches!(
+        render_depth_map(World::default(), &settings),
+        Err(Error::Unbounded)
+    ));
+}
@@ -0,0 +1,179 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::accel::AcceleratorKind;
+use ray_tracing::background::GradientBackground;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::hittable::Sphere;
+use ray_tracing::integrator::PathIntegrator;
+use ray_tracing::material::{Dielectric, Lambertian, Metal};
+use ray_tracing::sampler::SamplerKind;
+use ray_tracing::texture::{NoiseTexture, SolidColor};
+use ray_tracing::upsample::{raytrace_upsampled, UpsampleSettings};
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_image, render_progressive, render_thumbnail, Color, RenderSettings};
+use ray_tracing::{Float, Point3, Vec3};
+use ray_tracing::{THUMBNAIL_HEIGHT, THUMBNAIL_SAMPLES, THUMBNAIL_WIDTH};
+use std::ops::ControlFlow;
+
+fn scene() -> (World, CameraSettings) {
+    let mut world = World::default();
+    let ground = Lambertian::new(NoiseTexture::turbulence(1, 4.));
+    world.add(Sphere::new(point3!(0., -1000., 0.), 1000., ground));
+    world.add(Sphere::new(point3!(-2., 1., 0.), 1., Dielectric::new(1.5)));
+    world.add(Sphere::new(
+        point3!(0., 1., 0.),
+        1.,
+        Metal::new(color!(0.8, 0.8, 0.8), 0.3),
+    ));
+    let diffuse = Lambertian::new(SolidColor::new(color!(0.4, 0.2, 0.1)));
+    world.add(Sphere::new(point3!(2., 1., 0.), 1., diffuse));
+    let camera = CameraSettings {
+        look_from: point3!(0., 2., 8.),
+        look_at: point3!(0., 1., 0.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 40.,
+        aperture: 0.1,
+        focus_dist: 8.,
+        t0: 0.,
+        t1: 1.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    };
+    (world, camera)
+}
+
+fn render(settings: &RenderSettings) -> Vec<Color> {
+    let (world, camera) = scene();
+    raytrace_image(world, camera, settings, &PathIntegrator, 32, 24)
+        .expect("Error rendering")
+        .data
+}
+
+fn render_progressively(settings: &RenderSettings) -> Vec<Color> {
+    let (world, camera) = scene();
+    render_progressive(world, camera, settings, &PathIntegrator, 32, 24, |_, _| {
+        ControlFlow::Continue(())
+    })
+    .expect("Error rendering")
+    .data
+}
+
+fn settings(seed: Option<u64>) -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 4,
+        max_depth: 8,
+        seed,
+        background: Box::new(GradientBackground::sky()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn same_seed_gives_identical_pixels() {
+    for &sampler in &[SamplerKind::Random, SamplerKind::Sobol] {
+        let mut settings = settings(Some(42));
+        settings.sampler = sampler;
+        assert!(render(&settings) == render(&settings));
+    }
+}
+
+#[test]
+fn tile_size_does_not_change_pixels() {
+    let mut settings = settings(Some(7));
+    let first = render(&settings);
+    settings.tile_size = 5;
+    assert!(first == render(&settings));
+}
+
+#[test]
+fn progressive_renders_repeat_whatever_the_tile_size() {
+    for &sampler in &[
+        SamplerKind::Random,
+        SamplerKind::Stratified,
+        SamplerKind::Sobol,
+    ] {
+        let mut settings = settings(Some(42));
+        settings.sampler = sampler;
+        let first = render_progressively(&settings);
+        assert!(first == render_progressively(&settings));
+        settings.tile_size = 5;
+        assert!(first == render_progressively(&settings));
+        // Each pass takes new samples rather than repeating the first
+        settings.samples_per_pixel = 1;
+        assert!(first != render_progressively(&settings));
+    }
+}
+
+#[test]
+fn accelerator_does_not_change_pixels() {
+    let mut settings = settings(Some(9));
+    let first = render(&settings);
+    settings.accelerator = AcceleratorKind::Grid;
+    assert!(first == render(&settings));
+}
+
+#[test]
+fn different_seeds_give_different_pixels() {
+    assert!(render(&settings(Some(1))) != render(&settings(Some(2))));
+}
+
+#[test]
+fn thumbnails_are_small_quick_and_repeatable() {
+    let thumbnail = || {
+        let (world, camera) = scene();
+        let mut settings = settings(Some(3));
+        settings.samples_per_pixel = 100;
+        let mut passes = 0;
+        let image = render_thumbnail(world, camera, settings, &PathIntegrator, |_, pass| {
+            passes = pass;
+            ControlFlow::Continue(())
+        })
+        .expect("Error rendering thumbnail");
+        assert_eq!(passes, THUMBNAIL_SAMPLES);
+        image
+    };
+    let image = thumbnail();
+    assert_eq!(
+        (image.width, image.height),
+        (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)
+    );
+    assert!(image.data == thumbnail().data);
+}
+
+#[test]
+fn worlds_render_on_other_threads() {
+    let (world, camera) = scene();
+    let background = std::thread::spawn(move || {
+        let settings = settings(Some(5));
+        raytrace_image(world, camera, &settings, &PathIntegrator, 32, 24)
+            .expect("Error rendering")
+            .data
+    });
+    let pixels = background.join().expect("Error joining render thread");
+    assert!(render(&settings(Some(5))) == pixels);
+}
+
+#[test]
+fn upsampled_renders_trace_edges_like_full_renders() {
+    let settings = settings(Some(11));
+    let full = render(&settings);
+    for &max_share in &[0.25, 0.1] {
+        let (world, camera) = scene();
+        let upsample = UpsampleSettings {
+            max_share,
+            ..Default::default()
+        };
+        let upsampled =
+            raytrace_upsampled(world, camera, &settings, &PathIntegrator, 32, 24, &upsample)
+                .expect("Error rendering");
+        let share = upsampled.retraced_share();
+        assert!(share > 0. && share <= max_share, "{} retraced", share);
+        for (pixel, &retraced) in upsampled.retraced.iter().enumerate() {
+            if retraced {
+                assert!(upsampled.image.data[pixel] == full[pixel]);
+            }
+        }
+    }
+}
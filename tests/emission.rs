@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::image::Image;
+use ray_tracing::integrator::PathIntegrator;
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_image, Color, Point3, RenderSettings};
+
+/// Renders `World::emissive_only` at 32x18, with alpha if `alpha` is set
+fn render(camera: CameraSettings, alpha: bool) -> Image {
+    // The default background is black, so all the light comes from the glowing sphere
+    let settings = RenderSettings {
+        samples_per_pixel: 4,
+        max_depth: 8,
+        seed: Some(1),
+        alpha,
+        ..Default::default()
+    };
+    raytrace_image(
+        World::emissive_only(),
+        camera,
+        &settings,
+        &PathIntegrator,
+        32,
+        18,
+    )
+    .expect("Error rendering")
+}
+
+#[test]
+fn emissive_only_scenes_are_lit_by_their_lights() {
+    let image = render(CameraSettings::cover_camera(), false);
+    let lit = image
+        .data
+        .iter()
+        .filter(|color| color.luminance() > 0.)
+        .count();
+    assert!(
+        lit > image.data.len() / 4,
+        "Only {} of {} pixels are lit",
+        lit,
+        image.data.len()
+    );
+    // Pixels that only show the background are left black
+    let coverage = render(CameraSettings::cover_camera(), true).alpha;
+    let coverage = coverage.expect("Expected alpha");
+    let background: Vec<_> = (0..image.data.len())
+        .filter(|&pixel| coverage[pixel] == 0.)
+        .collect();
+    assert!(!background.is_empty());
+    for pixel in background {
+        assert_eq!(image.data[pixel], color!());
+    }
+}
+
+#[test]
+fn lights_seen_directly_show_their_radiance() {
+    let camera = CameraSettings {
+        look_at: point3!(0., 4., 2.),
+        aperture: 0.,
+        ..CameraSettings::cover_camera()
+    };
+    // The light fills the middle of the image
+    let image = render(camera, false);
+    let middle = image.pixel(16, 9);
+    for i in 0..3 {
+        assert!(close(middle[i], 4.), "{:?}", middle);
+    }
+}
@@ -0,0 +1,198 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{grey, temp_path};
+use ray_tracing::accel::{Accelerator, Bvh, Grid};
+use ray_tracing::atmosphere::Atmosphere;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::error::Error;
+use ray_tracing::hittable::{HitRecord, Hittable, Sphere};
+use ray_tracing::image::{write_exr_layers, Image};
+use ray_tracing::loader::obj::load_obj;
+use ray_tracing::material::{Dielectric, Metal};
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::{Scene, SceneFile};
+use ray_tracing::texture::ImageTexture;
+use ray_tracing::world::{World, AABB};
+use ray_tracing::RenderSettings;
+use ray_tracing::{Color, Float, Point3};
+use std::fs;
+
+/// A scene file with one sphere made of `material`
+fn scene_json(material: &str) -> String {
+    format!(
+        r#"{{
+            "camera": {{ "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 }},
+            "objects": [
+                {{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": {} }}
+            ]
+        }}"#,
+        material
+    )
+}
+
+/// Hits nothing and has no bounding box, like an infinite plane would
+struct Unbounded;
+
+impl Hittable for Unbounded {
+    fn hit(&self, _: &Ray, _: Float, _: Float) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, _: Float, _: Float) -> Option<AABB> {
+        None
+    }
+}
+
+#[test]
+fn missing_texture_images_are_reported() {
+    let missing = temp_path("missing.png");
+    assert!(matches!(ImageTexture::new(&missing), Err(Error::Image(_))));
+}
+
+#[test]
+fn missing_scene_files_are_reported() {
+    let missing = temp_path("missing.json");
+    assert!(matches!(Scene::load(&missing), Err(Error::Io(_))));
+}
+
+#[test]
+fn invalid_scene_files_are_reported() {
+    assert!(matches!(
+        Scene::from_json("{ \"camera\": "),
+        Err(Error::Json(_))
+    ));
+    let unknown_type = scene_json(r#"{ "type": "velvet" }"#);
+    assert!(matches!(
+        Scene::from_json(&unknown_type),
+        Err(Error::Json(_))
+    ));
+}
+
+#[test]
+fn unknown_materials_are_reported() {
+    match Scene::from_json(&scene_json(r#""missing""#)) {
+        Err(Error::Scene(reason)) => {
+            assert_eq!(reason, "Unknown material `missing` in scene file")
+        }
+        _ => panic!("Expected an unknown material error"),
+    }
+    let preset = scene_json(r#"{ "type": "preset", "name": "velvet", "color": [1, 1, 1] }"#);
+    assert!(matches!(Scene::from_json(&preset), Err(Error::Scene(_))));
+}
+
+#[test]
+fn images_written_to_missing_directories_are_reported() {
+    let path = temp_path("missing").join("image.png");
+    let image = Image::new(2, 2);
+    assert!(matches!(image.clone().write_png(&path), Err(Error::Io(_))));
+    assert!(matches!(image.clone().write_ppm(&path), Err(Error::Io(_))));
+    assert!(matches!(image.clone().write_exr(&path), Err(Error::Exr(_))));
+    assert!(matches!(
+        image.write(temp_path("image.unknown")),
+        Err(Error::UnknownFormat(_))
+    ));
+}
+
+#[test]
+fn exr_layers_must_exist_and_match_in_size() {
+    let path = temp_path("layers.exr");
+    assert!(matches!(
+        write_exr_layers(&path, &[], None),
+        Err(Error::Layers(_))
+    ));
+    let (small, large) = (Image::new(2, 2), Image::new(3, 2));
+    match write_exr_layers(&path, &[("beauty", &small), ("depth", &large)], None) {
+        Err(Error::Layers(reason)) => {
+            assert_eq!(
+                reason,
+                "EXR layer `depth` is 3x2, but the first layer is 2x2"
+            )
+        }
+        _ => panic!("Expected a layers error"),
+    }
+    assert!(!path.exists());
+}
+
+#[test]
+fn malformed_obj_files_are_reported() {
+    let path = temp_path("malformed.obj");
+    fs::write(&path, "v 0 0 0\nv 1 0 0\nf 1 2 3\n").expect("Error writing OBJ file");
+    let loaded = load_obj(&path);
+    fs::remove_file(&path).expect("Error removing OBJ file");
+    match loaded {
+        Err(Error::Parse(reason)) => assert_eq!(reason, "Invalid face `3` in obj file"),
+        _ => panic!("Expected a parse error"),
+    }
+}
+
+#[test]
+fn bvh_of_unbounded_hittables_is_reported() {
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(), 1., grey()));
+    world.add(Unbounded);
+    world.add(Sphere::new(point3!(3., 0., 0.), 1., grey()));
+    assert!(matches!(world.build_bvh(0., 1.), Err(Error::Unbounded)));
+    assert_eq!(world.hittables.len(), 3, "The world is left as it was");
+}
+
+#[test]
+fn worlds_scene_files_cant_hold_are_reported() {
+    let camera = CameraSettings::cover_camera();
+    let settings = RenderSettings::default();
+    let save = |world: &World| SceneFile::from_world(world, &camera, &settings, 4, 3);
+
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(), 1., grey()));
+    assert!(save(&world).is_ok());
+    world.add(Unbounded);
+    match save(&world) {
+        Err(Error::Scene(reason)) => assert_eq!(
+            reason,
+            "the world has a hittable that can't be saved to a scene file"
+        ),
+        _ => panic!("Expected a scene error"),
+    }
+
+    let world = World {
+        atmosphere: Some(Atmosphere::earth_like(point3!(), 2., 5.)),
+        ..Default::default()
+    };
+    assert!(matches!(save(&world), Err(Error::Scene(_))));
+}
+
+#[test]
+fn accelerators_of_nothing_are_reported() {
+    assert!(matches!(Bvh::build(Vec::new(), 0., 1.), Err(Error::Empty)));
+    assert!(matches!(Grid::build(Vec::new(), 0., 1.), Err(Error::Empty)));
+}
+
+#[test]
+fn surrounding_nothing_is_unbounded() {
+    assert!(AABB::surrounding_option(None, None).is_none());
+    let bbox = AABB {
+        min: point3!(),
+        max: point3!(1., 1., 1.),
+    };
+    let surrounding = AABB::surrounding_option(None, Some(bbox.clone()));
+    assert_eq!(surrounding.map(|bbox| bbox.max), Some(bbox.max));
+}
+
+#[test]
+fn non_physical_materials_are_reported() {
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(0., 0., 0.), 1., grey()));
+    assert!(world.validate().is_empty());
+    world.add(Sphere::new(
+        point3!(3., 0., 0.),
+        1.,
+        Metal::new(color!(0.8, 0.8, 0.8), -0.5),
+    ));
+    world.add(Sphere::new(point3!(-3., 0., 0.), 1., Dielectric::new(0.8)));
+    let problems = world.validate();
+    assert_eq!(problems.len(), 2, "{:?}", problems);
+    assert!(problems[0].contains("fuzz"));
+    assert!(problems[1].contains("refraction"));
+}
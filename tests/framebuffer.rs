@@ -0,0 +1,40 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::framebuffer::{BufferPrecision, FrameBuffer};
+use ray_tracing::{Color, Float};
+
+/// Adds `n` random samples between 0 and 1 to a one pixel buffer, returning how far its mean
+/// is from the exact mean, relative to it
+fn mean_error(precision: BufferPrecision, n: u32) -> Float {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut buffer = FrameBuffer::new(1, 1, precision);
+    let mut sum = 0.;
+    for _ in 0..n {
+        let value: f64 = rng.gen();
+        sum += value;
+        buffer.add(0, color!(value as Float, 0., 0.));
+    }
+    let exact = (sum / n as f64) as Float;
+    (buffer.mean(0)[0] - exact).abs() / exact
+}
+
+#[test]
+fn single_precision_buffers_hold_millions_of_samples() {
+    assert!(mean_error(BufferPrecision::Single, 1_000_000) < 1e-4);
+}
+
+#[test]
+fn half_precision_buffers_hold_a_thousand_samples() {
+    assert!(mean_error(BufferPrecision::Half, 1000) < 0.005);
+
+    let mut buffer = FrameBuffer::new(1, 1, BufferPrecision::Half);
+    buffer.add(0, color!(65504., 70000., 1.));
+    let mean = buffer.mean(0);
+    assert_eq!(
+        (mean[0], mean[1].is_infinite(), mean[2]),
+        (65504., true, 1.)
+    );
+}
@@ -0,0 +1,98 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::furnace::{furnace_test, FurnaceSettings};
+use ray_tracing::material::{Coated, Dielectric, Lambertian, Layered, Metal, Mix, PbrMaterial};
+use ray_tracing::texture::SolidColor;
+use ray_tracing::{Color, Float};
+
+/// Allowed on top of the measured uncertainty, for the light glass loses to paths cut off by
+/// `max_depth`
+const TOLERANCE: Float = 0.005;
+
+#[test]
+fn white_lambertian_is_lossless() {
+    let material = Lambertian::new(SolidColor::new(color!(1., 1., 1.)));
+    let result = furnace_test(material, &FurnaceSettings::default());
+    assert!(result.is_lossless(TOLERANCE), "{:?}", result);
+}
+
+#[test]
+fn grey_lambertian_reflects_its_albedo() {
+    let material = Lambertian::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+    let result = furnace_test(material, &FurnaceSettings::default());
+    assert!((result.radiance[0] - 0.5).abs() <= result.error[0] + TOLERANCE);
+}
+
+#[test]
+fn white_mirror_is_lossless() {
+    let result = furnace_test(
+        Metal::new(color!(1., 1., 1.), 0.),
+        &FurnaceSettings::default(),
+    );
+    assert!(result.is_lossless(TOLERANCE), "{:?}", result);
+}
+
+#[test]
+fn glass_is_lossless() {
+    let result = furnace_test(Dielectric::new(1.5), &FurnaceSettings::default());
+    assert!(result.is_lossless(TOLERANCE), "{:?}", result);
+}
+
+#[test]
+fn rough_and_layered_materials_conserve_energy() {
+    let settings = FurnaceSettings::default();
+    let result = furnace_test(Metal::new(color!(1., 1., 1.), 0.5), &settings);
+    assert!(
+        result.conserves_energy(TOLERANCE),
+        "rough metal {:?}",
+        result
+    );
+    let result = furnace_test(Coated::car_paint(color!(1., 1., 1.)), &settings);
+    assert!(result.conserves_energy(TOLERANCE), "car paint {:?}", result);
+    let result = furnace_test(Coated::ceramic_glaze(color!(1., 1., 1.)), &settings);
+    assert!(
+        result.conserves_energy(TOLERANCE),
+        "ceramic glaze {:?}",
+        result
+    );
+}
+
+#[test]
+fn pbr_conserves_energy() {
+    let settings = FurnaceSettings::default();
+    for &metallic in &[0., 0.5, 1.] {
+        for &roughness in &[0.1, 0.5, 1.] {
+            let material = PbrMaterial::new(color!(1., 1., 1.), metallic, roughness);
+            let result = furnace_test(material, &settings);
+            assert!(
+                result.conserves_energy(TOLERANCE),
+                "metallic {} roughness {} {:?}",
+                metallic,
+                roughness,
+                result
+            );
+        }
+    }
+    let result = furnace_test(PbrMaterial::new(color!(1., 1., 1.), 1., 0.), &settings);
+    assert!(result.is_lossless(TOLERANCE), "smooth metal {:?}", result);
+}
+
+#[test]
+fn mixed_and_layered_materials_conserve_energy() {
+    let settings = FurnaceSettings::default();
+    let white = || Lambertian::new(SolidColor::new(color!(1., 1., 1.)));
+    let mirror = || Metal::new(color!(1., 1., 1.), 0.);
+    let half = SolidColor::new(color!(0.5, 0.5, 0.5));
+    let result = furnace_test(Mix::new(white(), mirror(), half), &settings);
+    assert!(result.is_lossless(TOLERANCE), "mix {:?}", result);
+    let result = furnace_test(Layered::clearcoat(white(), 0.2), &settings);
+    assert!(result.conserves_energy(TOLERANCE), "clearcoat {:?}", result);
+}
+
+#[test]
+fn too_bright_albedo_is_caught() {
+    let material = Lambertian::new(SolidColor::new(color!(1.2, 1., 1.)));
+    let result = furnace_test(material, &FurnaceSettings::default());
+    assert!(!result.conserves_energy(TOLERANCE), "{:?}", result);
+}
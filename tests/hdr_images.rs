@@ -0,0 +1,91 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::temp_path;
+use ray_tracing::image::Image;
+use ray_tracing::{Color, Float};
+use std::fs::{self, File};
+use std::io::BufReader;
+
+/// A 2x3 image brighter than white, with a different value in each pixel so flipped rows or
+/// columns show
+///
+/// Values share exponents within each pixel, so Radiance files store them exactly
+fn image() -> Image {
+    Image {
+        width: 2,
+        height: 3,
+        data: vec![
+            color!(4., 2., 1.),
+            color!(1., 0.5, 0.25),
+            color!(16., 8., 0.),
+            color!(0.5, 3., 1.5),
+            color!(0., 0., 64.),
+            color!(0.25, 0.25, 0.25),
+        ],
+        alpha: None,
+    }
+}
+
+/// Asserts pixels read back from a file match `image`'s within `tolerance` of each value
+fn assert_matches(read: &[Color], tolerance: Float) {
+    let expected = image().data;
+    assert_eq!(read.len(), expected.len());
+    for (read, expected) in read.iter().zip(&expected) {
+        for i in 0..3 {
+            let error = (read[i] - expected[i]).abs();
+            assert!(
+                error <= tolerance * expected[i],
+                "Read {:?}, expected {:?}",
+                read,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn exr_keeps_bright_values_and_row_order() {
+    let path = temp_path("bright.exr");
+    image().write_exr(&path).expect("Error writing EXR");
+    let read = exr::prelude::read_first_rgba_layer_from_file(
+        &path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![color!(); resolution.width() * resolution.height()],
+            )
+        },
+        |(width, data), position, (r, g, b, _): (f32, f32, f32, f32)| {
+            data[position.y() * *width + position.x()] = color!(r as Float, g as Float, b as Float);
+        },
+    );
+    fs::remove_file(&path).expect("Error removing EXR");
+    let (width, data) = read
+        .expect("Error reading EXR")
+        .layer_data
+        .channel_data
+        .pixels;
+    assert_eq!(width, 2);
+    assert_matches(&data, 0.);
+}
+
+#[test]
+fn hdr_keeps_bright_values_and_row_order() {
+    let path = temp_path("bright.hdr");
+    image().write_hdr(&path).expect("Error writing HDR");
+    let file = File::open(&path).expect("Error opening HDR");
+    let decoder = image::hdr::HdrDecoder::new(BufReader::new(file)).expect("Error reading HDR");
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().expect("Error reading HDR");
+    fs::remove_file(&path).expect("Error removing HDR");
+    assert_eq!((metadata.width, metadata.height), (2, 3));
+    let data: Vec<_> = pixels
+        .iter()
+        .map(|pixel| color!(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float))
+        .collect();
+    // Radiance files keep 8 bits of each value
+    assert_matches(&data, 1. / 128.);
+}
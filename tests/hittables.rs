@@ -0,0 +1,923 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{
+    assert_bounded, assert_hit, assert_miss, close, describe_objects, fuzz, grey, random_direction,
+    random_rays, scene_from_json,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::accel::{Accelerator, Bvh, Grid};
+use ray_tracing::animation::{Keyframes, Pose};
+use ray_tracing::consts::PI;
+use ray_tracing::hittable::{
+    sphere_roots, Cone, ConstantMedium, Cuboid, Cylinder, HeterogeneousMedium, HitRecord, Hittable,
+    MovingSphere, Sphere, Triangle, TriangleMesh, XYRect, XZRect, HIT_EPSILON,
+};
+use ray_tracing::material::Material;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::seeded_rng;
+use ray_tracing::scene::ObjectDesc;
+use ray_tracing::texture::{GridTexture, SolidColor};
+use ray_tracing::transform::{Animated, Matrix4, RotateY, Transform, Translate};
+use ray_tracing::world::{BoxPacket, World, AABB};
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::sync::Arc;
+
+/// Rays passing this close to an edge or tangent to a surface are left out of fuzzing, as rounding
+/// can decide whether they hit
+#[cfg(not(feature = "f32"))]
+const MARGIN: Float = 1e-6;
+#[cfg(feature = "f32")]
+const MARGIN: Float = 1e-3;
+
+/// Nearest `t` past `HIT_EPSILON` where `ray` meets the sphere, from the textbook quadratic
+fn sphere_t(ray: &Ray, center: Point3, radius: Float) -> Option<Option<Float>> {
+    let oc: Vec3 = (ray.origin - center).conv();
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant.abs() < MARGIN * half_b * half_b {
+        return None;
+    }
+    if discriminant < 0. {
+        return Some(None);
+    }
+    let root = discriminant.sqrt();
+    let roots = [(-half_b - root) / a, (-half_b + root) / a];
+    if roots.iter().any(|t| (t - HIT_EPSILON).abs() < MARGIN) {
+        return None;
+    }
+    Some(roots.iter().copied().find(|&t| t > HIT_EPSILON))
+}
+
+/// Nearest `t` past `HIT_EPSILON` where `ray` enters or leaves the box, from the slab method
+fn box_t(ray: &Ray, min: Point3, max: Point3) -> Option<Option<Float>> {
+    let (mut near, mut far) = (Float::NEG_INFINITY, Float::INFINITY);
+    for axis in 0..3 {
+        let t0 = (min[axis] - ray.origin[axis]) / ray.dir[axis];
+        let t1 = (max[axis] - ray.origin[axis]) / ray.dir[axis];
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    if (near - far).abs() < MARGIN * far.abs().max(1.) {
+        return None;
+    }
+    if near > far {
+        return Some(None);
+    }
+    if [near, far].iter().any(|t| (t - HIT_EPSILON).abs() < MARGIN) {
+        return None;
+    }
+    Some([near, far].iter().copied().find(|&t| t > HIT_EPSILON))
+}
+
+/// Where `ray` meets the plane of the triangle, if it's inside the triangle by its barycentric
+/// coordinates
+fn triangle_t(ray: &Ray, p: [Point3; 3]) -> Option<Option<Float>> {
+    let e1: Vec3 = (p[1] - p[0]).conv();
+    let e2: Vec3 = (p[2] - p[0]).conv();
+    let normal = e1.cross(&e2);
+    let denominator = normal.dot(&ray.dir);
+    if denominator.abs() < MARGIN * ray.dir.length() * normal.length() {
+        return None;
+    }
+    let t = normal.dot(&(p[0] - ray.origin).conv()) / denominator;
+    let point: Vec3 = (ray.at(t) - p[0]).conv();
+    // Solve `point = b1 e1 + b2 e2` using dot products with both edges
+    let (d11, d12, d22) = (e1.dot(&e1), e1.dot(&e2), e2.dot(&e2));
+    let (dp1, dp2) = (point.dot(&e1), point.dot(&e2));
+    let det = d11 * d22 - d12 * d12;
+    let b1 = (d22 * dp1 - d12 * dp2) / det;
+    let b2 = (d11 * dp2 - d12 * dp1) / det;
+    let edges = [b1, b2, 1. - b1 - b2];
+    if edges.iter().any(|b| b.abs() < MARGIN) || (t - HIT_EPSILON).abs() < MARGIN {
+        return None;
+    }
+    let inside = edges.iter().all(|&b| b > 0.);
+    Some(if inside && t > HIT_EPSILON {
+        Some(t)
+    } else {
+        None
+    })
+}
+
+/// Nearest `t` past `HIT_EPSILON` where `ray` meets a cylinder or cone around the y axis standing
+/// on `base`, whose radius changes by `slope` for each unit up from `radius` at the base, closed
+/// by discs at the heights in `caps`
+///
+/// Solves `x² + z² = (radius + slope y)²` for the whole surface, then keeps the hits between the
+/// base and `height`
+fn quadric_t(
+    ray: &Ray,
+    base: Point3,
+    radius: Float,
+    slope: Float,
+    height: Float,
+    caps: &[Float],
+) -> Option<Option<Float>> {
+    let (o, d): (Vec3, Vec3) = ((ray.origin - base).conv(), ray.dir);
+    let r0 = radius + slope * o[1];
+    let a = d[0] * d[0] + d[2] * d[2] - slope * slope * d[1] * d[1];
+    let half_b = o[0] * d[0] + o[2] * d[2] - slope * r0 * d[1];
+    let c = o[0] * o[0] + o[2] * o[2] - r0 * r0;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant.abs() < MARGIN * half_b * half_b || a.abs() < MARGIN * d.length_squared() {
+        return None;
+    }
+    let mut hits = Vec::new();
+    if discriminant > 0. {
+        let root = discriminant.sqrt();
+        for t in [(-half_b - root) / a, (-half_b + root) / a] {
+            let y = o[1] + t * d[1];
+            if y.abs() < MARGIN || (y - height).abs() < MARGIN {
+                return None;
+            }
+            if y > 0. && y < height {
+                hits.push(t);
+            }
+        }
+    }
+    for &y in caps {
+        let t = (y - o[1]) / d[1];
+        let (x, z) = (o[0] + t * d[0], o[2] + t * d[2]);
+        let outside = (x * x + z * z).sqrt() - (radius + slope * y);
+        if outside.abs() < MARGIN {
+            return None;
+        }
+        if outside < 0. {
+            hits.push(t);
+        }
+    }
+    if hits.iter().any(|t| (t - HIT_EPSILON).abs() < MARGIN) {
+        return None;
+    }
+    let hits = hits.into_iter().filter(|&t| t > HIT_EPSILON);
+    Some(hits.fold(None, |nearest, t| {
+        Some(nearest.map_or(t, |n: Float| n.min(t)))
+    }))
+}
+
+#[test]
+fn sphere_hits_from_outside_and_inside() {
+    let sphere = Sphere::new(point3!(0., 0., -5.), 1., grey());
+    let rec = assert_hit(&sphere, &Ray::new(point3!(), vec3!(0., 0., -1.), 0.), 4.);
+    assert!(rec.front_face);
+    let rec = assert_hit(
+        &sphere,
+        &Ray::new(point3!(0., 0., -5.), vec3!(0., 2., 0.), 0.),
+        0.5,
+    );
+    assert!(!rec.front_face);
+    assert_miss(&sphere, &Ray::new(point3!(), vec3!(0., 0., 1.), 0.));
+    assert_miss(
+        &sphere,
+        &Ray::new(point3!(0., 1.5, 0.), vec3!(0., 0., -1.), 0.),
+    );
+}
+
+#[test]
+fn sphere_matches_quadratic() {
+    let (center, radius) = (point3!(1., -2., 0.5), 1.5);
+    let sphere = Sphere::new(center, radius, grey());
+    fuzz(&sphere, 1, |ray| sphere_t(ray, center, radius));
+}
+
+/// A ray just skimming a huge ground sphere hits it exactly where the sphere's surface is, rather
+/// than somewhere rounding has moved it to
+#[test]
+#[cfg_attr(
+    feature = "f32",
+    ignore = "single precision can't place points this far out"
+)]
+fn grazing_ground_hit_is_on_the_surface() {
+    let (center, radius) = (point3!(0., -100_000., 0.), 100_000.);
+    let ground = Sphere::new(center, radius, grey());
+    let origin = point3!(0., 1., 0.);
+    for step in 1..50 {
+        let drop = -5e-3 - 1e-4 * step as Float;
+        let ray = Ray::new(origin, vec3!(1., drop, 0.3), 0.);
+        let rec = ground
+            .hit(&ray, HIT_EPSILON, Float::INFINITY)
+            .expect("ray heading down hits the ground");
+        let distance = (rec.point - center).length();
+        assert!(
+            (distance - radius).abs() < 1e-6,
+            "hit {} from the surface",
+            distance - radius
+        );
+    }
+}
+
+#[test]
+fn sphere_roots_are_nearest_first() {
+    let (near, far) = sphere_roots(vec3!(0., 0., 5.), vec3!(0., 0., -2.), 1.).unwrap();
+    assert_eq!((near, far), (2., 3.));
+    assert!(sphere_roots(vec3!(0., 2., 5.), vec3!(0., 0., -1.), 1.).is_none());
+}
+
+#[test]
+fn rect_matches_plane() {
+    let rect = XYRect::new(-1., 2., 0., 1., 3., grey());
+    let (min, max) = (point3!(-1., 0., 3.), point3!(2., 1., 3.));
+    fuzz(&rect, 2, |ray| {
+        let t = (3. - ray.origin[2]) / ray.dir[2];
+        let point = ray.at(t);
+        let edge = (0..2)
+            .flat_map(|axis| [point[axis] - min[axis], max[axis] - point[axis]])
+            .fold(Float::INFINITY, Float::min);
+        if edge.abs() < MARGIN || (t - HIT_EPSILON).abs() < MARGIN {
+            None
+        } else {
+            Some(Some(t).filter(|&t| edge > 0. && t > HIT_EPSILON))
+        }
+    });
+}
+
+#[test]
+fn cuboid_matches_slabs() {
+    let (min, max) = (point3!(-1., 0., -2.), point3!(0.5, 3., 1.));
+    let cuboid = Cuboid::new(min, max, grey());
+    fuzz(&cuboid, 3, |ray| box_t(ray, min, max));
+}
+
+#[test]
+fn triangle_matches_barycentric() {
+    let p = [
+        point3!(0., 0., 0.),
+        point3!(2., 0.5, -1.),
+        point3!(-0.5, 1.5, 0.5),
+    ];
+    let triangle = Triangle::new(p[0], p[1], p[2], grey());
+    fuzz(&triangle, 4, |ray| triangle_t(ray, p));
+}
+
+#[test]
+fn cylinders_match_quadratic() {
+    let base = point3!(1., -0.5, 2.);
+    let open = Cylinder::new(base, 0.8, 2., false, grey());
+    fuzz(&open, 13, |ray| quadric_t(ray, base, 0.8, 0., 2., &[]));
+    let capped = Cylinder::new(base, 0.8, 2., true, grey());
+    fuzz(&capped, 14, |ray| {
+        quadric_t(ray, base, 0.8, 0., 2., &[0., 2.])
+    });
+}
+
+#[test]
+fn cones_match_quadratic() {
+    let base = point3!(-1., 0., 0.5);
+    let open = Cone::new(base, 1.5, 3., false, grey());
+    fuzz(&open, 15, |ray| quadric_t(ray, base, 1.5, -0.5, 3., &[]));
+    let capped = Cone::new(base, 1.5, 3., true, grey());
+    fuzz(&capped, 16, |ray| {
+        quadric_t(ray, base, 1.5, -0.5, 3., &[0.])
+    });
+}
+
+#[test]
+fn cylinder_normals_and_texture_coordinates() {
+    let capped = Cylinder::new(point3!(), 1., 2., true, grey());
+    let side = assert_hit(
+        &capped,
+        &Ray::new(point3!(-3., 1., 0.), vec3!(1., 0., 0.), 0.),
+        2.,
+    );
+    assert_eq!(side.normal, vec3!(-1., 0., 0.));
+    assert!(side.front_face);
+    assert!(
+        close(side.u, 1.) || close(side.u, 0.),
+        "-x is where u wraps"
+    );
+    assert!(close(side.v, 0.5));
+    let down = Ray::new(point3!(0.5, 5., 0.), vec3!(0., -1., 0.), 0.);
+    let top = assert_hit(&capped, &down, 3.);
+    assert_eq!(top.normal, vec3!(0., 1., 0.));
+    assert!(close(top.u, 0.75) && close(top.v, 0.5));
+
+    let open = Cylinder::new(point3!(), 1., 2., false, grey());
+    assert_miss(&open, &down);
+    let inside = assert_hit(&open, &Ray::new(point3!(), vec3!(0., 1., 1.), 0.), 1.);
+    assert!(!inside.front_face, "Open cylinders show their inside");
+    assert_eq!(inside.normal, vec3!(0., 0., -1.));
+}
+
+#[test]
+fn cone_normals_lean_out_from_the_axis() {
+    let cone = Cone::new(point3!(), 1., 1., true, grey());
+    let rec = assert_hit(
+        &cone,
+        &Ray::new(point3!(3., 0.5, 0.), vec3!(-1., 0., 0.), 0.),
+        2.5,
+    );
+    let lean = Float::sqrt(0.5);
+    assert!(close(rec.normal[0], lean) && close(rec.normal[1], lean));
+    assert!(close(rec.v, 0.5));
+    let base = assert_hit(
+        &cone,
+        &Ray::new(point3!(0., -2., 0.), vec3!(0., 1., 0.), 0.),
+        2.,
+    );
+    assert_eq!(base.normal, vec3!(0., -1., 0.));
+    assert!(close(cone.area(), PI * (1. + Float::sqrt(2.))));
+}
+
+#[test]
+fn scene_files_build_cylinders_and_cones() {
+    let scene = scene_from_json(
+        r#""objects": [
+            { "type": "cylinder", "base": [0, 0, 0], "radius": 1, "height": 2,
+              "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } },
+            { "type": "cone", "base": [3, 0, 0], "radius": 1, "height": 2, "capped": false,
+              "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } }
+        ]"#,
+    );
+    let described = describe_objects(&scene);
+    assert!(matches!(
+        described[..],
+        [
+            Some(ObjectDesc::Cylinder { capped: true, .. }),
+            Some(ObjectDesc::Cone { capped: false, .. })
+        ]
+    ));
+    let cone = scene.world.hittables[1]
+        .bounding_box(0., 1.)
+        .expect("Cones are bounded");
+    assert_eq!(
+        (cone.min, cone.max),
+        (point3!(2., 0., -1.), point3!(4., 2., 1.))
+    );
+}
+
+/// Asserts `ray` hits `hittable` with a tangent frame that agrees with its texture coordinates:
+/// both vectors are unit length along the surface, and aiming a little way along each moves the
+/// hit towards larger `u` and `v` respectively
+fn assert_tangents(hittable: &dyn Hittable, ray: &Ray) {
+    let rec = hittable
+        .hit(ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Expected a hit");
+    for (along, name) in [(rec.tangent, "tangent"), (rec.bitangent, "bitangent")] {
+        assert!(
+            close(along.length(), 1.),
+            "The {} isn't a unit vector",
+            name
+        );
+        assert!(
+            along.dot(&rec.normal).abs() < 1e-6,
+            "The {} {} leaves the surface",
+            name,
+            along
+        );
+    }
+    let nudged = |along: Vec3| {
+        let target = rec.point + (1e-4 * along).conv();
+        let ray = Ray::new(ray.origin, (target - ray.origin).conv(), ray.time);
+        hittable
+            .hit(&ray, HIT_EPSILON, Float::INFINITY)
+            .expect("Expected a hit next to the first")
+    };
+    assert!(
+        nudged(rec.tangent).u > rec.u,
+        "u doesn't grow along the tangent"
+    );
+    assert!(
+        nudged(rec.bitangent).v > rec.v,
+        "v doesn't grow along the bitangent"
+    );
+}
+
+#[test]
+fn tangents_follow_texture_coordinates() {
+    let toward = |from: Point3, to: Point3| Ray::new(from, (to - from).conv(), 0.);
+    let sphere = Sphere::new(point3!(), 1., grey());
+    assert_tangents(&sphere, &toward(point3!(3., 1., 2.), point3!()));
+    assert_tangents(
+        &sphere,
+        &toward(point3!(0.2, 0.3, 0.1), point3!(-1., -2., 1.)),
+    );
+    let rect = XZRect::new(-1., 1., -2., 2., 0., grey());
+    assert_tangents(&rect, &toward(point3!(0.5, 3., 0.), point3!(0.2, 0., 0.3)));
+    assert_tangents(&rect, &toward(point3!(0.5, -3., 0.), point3!(0.2, 0., 0.3)));
+    let triangle = Triangle::new(
+        point3!(0., 0., 0.),
+        point3!(2., 0.5, -1.),
+        point3!(-0.5, 1.5, 0.5),
+        grey(),
+    );
+    assert_tangents(
+        &triangle,
+        &toward(point3!(0., 0., 5.), point3!(0.3, 0.5, 0.)),
+    );
+    let cylinder = Cylinder::new(point3!(), 1., 2., true, grey());
+    assert_tangents(
+        &cylinder,
+        &toward(point3!(3., 1., 1.), point3!(0., 0.8, 0.)),
+    );
+    assert_tangents(
+        &cylinder,
+        &toward(point3!(0.2, 5., 0.), point3!(0.3, 2., 0.1)),
+    );
+    let cone = Cone::new(point3!(), 1., 2., true, grey());
+    assert_tangents(&cone, &toward(point3!(-3., 0.5, 1.), point3!(0., 0.5, 0.)));
+    let cuboid = || Cuboid::new(point3!(-1., -1., -1.), point3!(1., 1., 1.), grey());
+    let turned = RotateY::new(cuboid(), 30.);
+    assert_tangents(&turned, &toward(point3!(3., 0.2, 3.), point3!(0., 0.1, 0.)));
+    let matrix = Matrix4::rotation(vec3!(1., 1., 0.), 45.);
+    let sphere =
+        Transform::new(Sphere::new(point3!(), 1., grey()), matrix).expect("Error transforming");
+    assert_tangents(&sphere, &toward(point3!(1., 3., 2.), point3!()));
+
+    let mut world = World::default();
+    world.add_mesh(TriangleMesh::new(
+        vec![
+            point3!(0., 0., 0.),
+            point3!(2., 0., 0.),
+            point3!(0., 0., 2.),
+        ],
+        Vec::new(),
+        // Texture coordinates running the other way to the vertex order
+        vec![(0., 0.), (0., 1.), (1., 0.)],
+        vec![[0, 1, 2]],
+        grey(),
+    ));
+    let triangle = world.hittables[0].as_ref();
+    assert_tangents(
+        triangle,
+        &toward(point3!(0.5, 2., 0.5), point3!(0.5, 0., 0.5)),
+    );
+}
+
+#[test]
+fn translated_sphere_matches_moved_sphere() {
+    let offset = vec3!(3., -1., 2.);
+    let sphere = Translate::new(Sphere::new(point3!(), 1., grey()), offset);
+    fuzz(&sphere, 5, |ray| sphere_t(ray, offset.conv(), 1.));
+}
+
+#[test]
+fn transforms_need_invertible_matrices() {
+    let flat = Matrix4::scale(vec3!(1., 0., 1.));
+    assert!(Transform::new(Sphere::new(point3!(), 1., grey()), flat).is_err());
+}
+
+#[test]
+fn transformed_lights_are_sampled_directly() {
+    let stretch = Matrix4::scale(vec3!(2., 1., 3.));
+    let rect = Transform::new(
+        XZRect::new(-1., 1., -1., 1., 0., grey()),
+        Matrix4::translation(vec3!(0., 3., 0.)) * stretch,
+    )
+    .expect("Error transforming");
+    assert!(close(rect.area(), 24.), "{}", rect.area());
+    let sphere = Transform::new(
+        Sphere::new(point3!(), 1., grey()),
+        Matrix4::translation(vec3!(0., 0., 4.)) * stretch,
+    )
+    .expect("Error transforming");
+
+    let origin = point3!();
+    let count = 20_000;
+    for light in [&rect as &dyn Hittable, &sphere] {
+        // The solid angle the light covers, from the share of all directions that hit it
+        let mut rng = StdRng::seed_from_u64(1);
+        let hits = (0..count)
+            .filter(|_| {
+                let ray = Ray::new(origin, random_direction(&mut rng), 0.);
+                light.hit(&ray, HIT_EPSILON, Float::INFINITY).is_some()
+            })
+            .count();
+        let solid_angle = 4. * PI * hits as Float / count as Float;
+
+        // Sampled directions all hit it, and one over their density averages to the same
+        let mut rng = seeded_rng(Some(2));
+        let mut total = 0.;
+        for _ in 0..count {
+            let dir = light
+                .random_direction(&origin, &mut rng)
+                .expect("Expected a direction");
+            let ray = Ray::new(origin, dir, 0.);
+            assert!(light.hit(&ray, HIT_EPSILON, Float::INFINITY).is_some());
+            total += 1. / light.pdf_value(&origin, &dir);
+        }
+        let estimate = total / count as Float;
+        assert!(
+            (estimate / solid_angle - 1.).abs() < 0.05,
+            "{} against {}",
+            estimate,
+            solid_angle
+        );
+    }
+}
+
+#[test]
+fn spheres_can_share_a_material() {
+    let material: Arc<dyn Material + Send + Sync> = Arc::new(grey());
+    let left = Sphere::new_shared(point3!(-2., 0., 0.), 1., Arc::clone(&material));
+    let right = Sphere::new_shared(point3!(2., 0., 0.), 1., Arc::clone(&material));
+    let address = |rec: HitRecord| rec.material as *const _ as *const ();
+    let down = vec3!(0., -1., 0.);
+    let left = assert_hit(&left, &Ray::new(point3!(-2., 5., 0.), down, 0.), 4.);
+    let right = assert_hit(&right, &Ray::new(point3!(2., 5., 0.), down, 0.), 4.);
+    assert_eq!(address(left), address(right));
+    assert_eq!(Arc::strong_count(&material), 3);
+}
+
+#[test]
+fn box_packet_matches_single_boxes() {
+    let boxes = [
+        Some(AABB::new(point3!(-1., 0., -2.), point3!(0.5, 3., 1.))),
+        None,
+        Some(AABB::new(point3!(1., -1., 0.), point3!(2., 0., 0.))),
+    ];
+    let packet = BoxPacket::new(&boxes);
+    let bounds = AABB::new(point3!(-1., -1., -2.), point3!(2., 3., 1.));
+    for ray in random_rays(11, 5000, &bounds, 4.) {
+        let entries = packet.entries(&ray, HIT_EPSILON, 10.);
+        for (bounding_box, entry) in boxes.iter().zip(entries) {
+            let expected = match bounding_box {
+                Some(bounding_box) => bounding_box.entry(&ray, HIT_EPSILON, 10.),
+                None => Some(HIT_EPSILON),
+            };
+            assert_eq!(entry, expected);
+        }
+        assert_eq!(entries[3], None, "Unused lanes are never entered");
+    }
+}
+
+#[test]
+fn bvh_of_a_few_boxes_finds_the_nearest() {
+    for count in 2..6 {
+        let mut world = World::default();
+        for i in 0..count {
+            let min = point3!(3. * i as Float, 0., 0.);
+            world.add(Cuboid::new(min, min + point3!(1., 1., 1.), grey()));
+        }
+        world.build_bvh(0., 1.).expect("Error building BVH");
+        let ray = Ray::new(point3!(-5., 0.5, 0.5), vec3!(1., 0., 0.), 0.);
+        let rec = world
+            .hit(&ray, HIT_EPSILON, Float::INFINITY)
+            .expect("Ray along the row hits the first box");
+        assert!((rec.t - 5.).abs() < MARGIN);
+    }
+}
+
+#[test]
+fn bvh_of_one_hittable_holds_it() {
+    let hittables: Vec<Box<dyn Hittable + Send + Sync>> =
+        vec![Box::new(Sphere::new(point3!(), 1., grey()))];
+    let bvh = Bvh::build(hittables, 0., 1.).expect("Error building BVH");
+    assert_eq!(bvh.children().len(), 1);
+    assert_eq!(bvh.node_boxes().len(), 1);
+    let ray = Ray::new(point3!(0., 0., -5.), vec3!(0., 0., 1.), 0.);
+    let rec = bvh
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Ray through the center hits the sphere");
+    assert!((rec.t - 4.).abs() < MARGIN);
+}
+
+#[test]
+fn bvh_splits_clusters_apart() {
+    // Two clusters of boxes far apart along x, each spread along z, so halving along z would mix
+    // them
+    let mut hittables: Vec<Box<dyn Hittable + Send + Sync>> = Vec::new();
+    for cluster in [0., 100.] {
+        for i in 0..8 {
+            let min = point3!(cluster, 0., i as Float * 2.);
+            hittables.push(Box::new(Cuboid::new(
+                min,
+                min + point3!(1., 1., 1.),
+                grey(),
+            )));
+        }
+    }
+    let bvh = Bvh::build(hittables, 0., 1.).expect("Error building BVH");
+    let boxes = bvh.node_boxes();
+    let children = boxes.iter().filter(|(depth, _)| *depth == 1);
+    for (_, bbox) in children {
+        assert!(
+            bbox.max[0] - bbox.min[0] < 50.,
+            "Each child holds boxes from one cluster"
+        );
+    }
+}
+
+/// Spheres and boxes along a diagonal, enough for a tree several levels deep, with the box
+/// around them
+fn diagonal_scene() -> (Vec<Box<dyn Hittable + Send + Sync>>, AABB) {
+    let mut hittables: Vec<Box<dyn Hittable + Send + Sync>> = Vec::new();
+    for i in 0..400 {
+        let offset = i as Float * 0.05;
+        let corner = point3!(offset, (i % 7) as Float * 0.3, offset);
+        if i % 3 == 0 {
+            hittables.push(Box::new(Cuboid::new(
+                corner,
+                corner + point3!(0.2, 0.1, 0.3),
+                grey(),
+            )));
+        } else {
+            hittables.push(Box::new(Sphere::new(corner, 0.1, grey())));
+        }
+    }
+    let bounds = AABB::new(point3!(-0.1, -0.1, -0.1), point3!(20.2, 2., 20.3));
+    (hittables, bounds)
+}
+
+/// Builds `A` around `hittables` and checks it finds the same closest hits as testing each
+/// hittable for random rays around `bounds`
+fn assert_matches_a_linear_scan<A: Accelerator>(
+    hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+    bounds: &AABB,
+    seed: u64,
+) -> A {
+    let closest = |hittables: &[Box<dyn Hittable + Send + Sync>], ray: &Ray| {
+        hittables
+            .iter()
+            .filter_map(|hittable| hittable.hit(ray, HIT_EPSILON, Float::INFINITY))
+            .map(|rec| rec.t)
+            .reduce(Float::min)
+    };
+    let rays = random_rays(seed, 2000, bounds, 2.);
+    let expected: Vec<_> = rays.iter().map(|ray| closest(&hittables, ray)).collect();
+    let accelerator = A::build(hittables, 0., 1.).expect("Error building accelerator");
+    for (ray, expected) in rays.iter().zip(expected) {
+        let rec = accelerator.hit(ray, HIT_EPSILON, Float::INFINITY);
+        assert_eq!(rec.map(|rec| rec.t), expected);
+        assert_eq!(
+            accelerator.hit_any(ray, HIT_EPSILON, Float::INFINITY),
+            expected.is_some()
+        );
+    }
+    accelerator
+}
+
+#[test]
+fn bvh_matches_a_linear_scan() {
+    let (hittables, bounds) = diagonal_scene();
+    let bvh: Bvh = assert_matches_a_linear_scan(hittables, &bounds, 12);
+    assert!(bvh.children().len() < 400, "Spheres are batched");
+}
+
+#[test]
+fn grid_matches_a_linear_scan() {
+    let (hittables, bounds) = diagonal_scene();
+    let grid: Grid = assert_matches_a_linear_scan(hittables, &bounds, 19);
+    assert_eq!(grid.children().len(), 400);
+    assert!(
+        grid.node_boxes().len() > 100,
+        "Hittables are spread over cells"
+    );
+
+    // A ground sphere far larger than the rest is tested by every ray instead of stretching the
+    // cells over it
+    let (mut hittables, _) = diagonal_scene();
+    hittables.push(Box::new(Sphere::new(
+        point3!(10., -1000., 10.),
+        1000.,
+        grey(),
+    )));
+    let grid: Grid = assert_matches_a_linear_scan(hittables, &bounds, 20);
+    assert_eq!(grid.node_boxes()[0].1.min[1], -0.1);
+
+    // Flat layers of hittables, and hittables all larger than the rest
+    let flat: Vec<Box<dyn Hittable + Send + Sync>> = (0..50)
+        .map(|i| {
+            let x = (i % 10) as Float;
+            let z = (i / 10) as Float;
+            Box::new(XZRect::new(x, x + 0.8, z, z + 0.8, 0., grey())) as _
+        })
+        .collect();
+    let bounds = AABB::new(point3!(0., -0.5, 0.), point3!(10., 0.5, 5.));
+    let _: Grid = assert_matches_a_linear_scan(flat, &bounds, 21);
+    let single: Vec<Box<dyn Hittable + Send + Sync>> =
+        vec![Box::new(Sphere::new(point3!(), 1., grey()))];
+    let bounds = AABB::new(point3!(-1., -1., -1.), point3!(1., 1., 1.));
+    let _: Grid = assert_matches_a_linear_scan(single, &bounds, 22);
+}
+
+/// Tests everything it holds, to check worlds accept any acceleration structure
+struct List(Vec<Box<dyn Hittable + Send + Sync>>);
+
+impl Hittable for List {
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        for hittable in &self.0 {
+            if let Some(rec) = hittable.hit(ray, t_min, t_max) {
+                t_max = rec.t;
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let boxes = self.0.iter().map(|hittable| hittable.bounding_box(t0, t1));
+        boxes.reduce(AABB::surrounding_option).flatten()
+    }
+}
+
+impl Accelerator for List {
+    fn build(
+        hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+        _: Float,
+        _: Float,
+    ) -> Result<Self, ray_tracing::error::Error> {
+        Ok(List(hittables))
+    }
+}
+
+#[test]
+fn worlds_build_other_accelerators() {
+    let mut world = World::default();
+    for i in 0..3 {
+        world.add(Sphere::new(point3!(3. * i as Float, 0., 0.), 1., grey()));
+    }
+    world
+        .build_accelerator::<List>(0., 1.)
+        .expect("Error building accelerator");
+    assert_eq!(world.hittables.len(), 1);
+    let ray = Ray::new(point3!(3., 0., -5.), vec3!(0., 0., 1.), 0.);
+    let rec = world
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Ray hits the middle sphere");
+    assert_eq!(rec.object_id, 2);
+}
+
+#[test]
+fn hits_are_inside_bounding_boxes() {
+    let cuboid = || Cuboid::new(point3!(-1., -0.5, -2.), point3!(1., 0.5, 2.), grey());
+    assert_bounded(&Sphere::new(point3!(1., 2., 3.), 0.5, grey()), 6);
+    assert_bounded(
+        &MovingSphere::new(point3!(), point3!(0., 2., 0.), 0., 1., 0.5, grey()),
+        7,
+    );
+    assert_bounded(&RotateY::new(cuboid(), 30.), 8);
+    assert_bounded(
+        &Transform::new(cuboid(), Matrix4::rotation(vec3!(1., 1., 0.), 45.))
+            .expect("Error transforming"),
+        9,
+    );
+    assert_bounded(
+        &Triangle::new(
+            point3!(0., 0., 0.),
+            point3!(1., 0., 0.),
+            point3!(0., 1., 1.),
+            grey(),
+        ),
+        10,
+    );
+    assert_bounded(
+        &Cylinder::new(point3!(0., 1., 0.), 0.5, 2., false, grey()),
+        17,
+    );
+    assert_bounded(&Cone::new(point3!(0., 1., 0.), 0.5, 2., true, grey()), 18);
+}
+
+#[test]
+fn animated_hittables_move_with_time_and_stay_bounded() {
+    let poses = Keyframes::new(Pose::default()).key(
+        1.,
+        Pose {
+            translation: vec3!(4., 0., 0.),
+            ..Pose::default()
+        },
+    );
+    let sphere = Animated::new(Sphere::new(point3!(), 1., grey()), poses);
+    let at = |time| Ray::new(point3!(2., 0., 5.), vec3!(0., 0., -1.), time);
+    assert_miss(&sphere, &at(0.));
+    assert_hit(&sphere, &at(0.5), 4.);
+    assert_bounded(&sphere, 11);
+
+    let spin = Keyframes::new(Pose::default())
+        .key(
+            0.5,
+            Pose {
+                translation: vec3!(0., 1., 0.),
+                rotation: vec3!(30., 90., 0.),
+                scale: vec3!(2., 1., 1.),
+            },
+        )
+        .key(
+            1.,
+            Pose {
+                rotation: vec3!(0., 180., 45.),
+                ..Pose::default()
+            },
+        );
+    let cuboid = Cuboid::new(point3!(-2., -0.2, -0.5), point3!(2., 0.2, 0.5), grey());
+    assert_bounded(&Animated::new(cuboid, spin), 12);
+}
+
+#[test]
+fn media_along_a_ray_collide_independently() {
+    // Two slabs of fog one unit thick, each letting through e^-1 of the rays
+    let slab = |x0: Float| {
+        let boundary = Cuboid::new(point3!(x0, -1., -1.), point3!(x0 + 1., 1., 1.), grey());
+        ConstantMedium::new(boundary, 1., SolidColor::new(color!(0.5, 0.5, 0.5)))
+    };
+    let (near, far) = (slab(1.), slab(3.));
+    let mut rng = StdRng::seed_from_u64(0);
+    let n = 20_000;
+    let mut through_both = 0;
+    for _ in 0..n {
+        let ray = Ray {
+            sample: rng.gen(),
+            ..Ray::new(point3!(), vec3!(1., 0., 0.), 0.)
+        };
+        let hits = (
+            near.hit(&ray, 0., Float::INFINITY),
+            far.hit(&ray, 0., Float::INFINITY),
+        );
+        if let (None, None) = hits {
+            through_both += 1;
+        }
+
+        // The same ray always collides in the same place
+        let again = near.hit(&ray, 0., Float::INFINITY);
+        assert_eq!(hits.0.map(|rec| rec.t), again.map(|rec| rec.t));
+    }
+    let fraction = through_both as Float / n as Float;
+    let expected = (-2. as Float).exp();
+    assert!(
+        (fraction - expected).abs() < 0.01,
+        "{} of rays passed through both, expected {}",
+        fraction,
+        expected
+    );
+}
+
+#[test]
+fn transformed_media_let_through_as_much_light() {
+    let slab = || {
+        let boundary = Cuboid::new(point3!(1., -1., -1.), point3!(2., 1., 1.), grey());
+        ConstantMedium::new(boundary, 1., SolidColor::new(color!(0.5, 0.5, 0.5)))
+    };
+    // Share of rays from `origin` along `dir` that pass through without colliding
+    let transmittance = |medium: &dyn Hittable, origin: Point3, dir: Vec3| {
+        let mut rng = StdRng::seed_from_u64(0);
+        let n = 20_000;
+        let through = (0..n)
+            .filter(|_| {
+                let ray = Ray {
+                    sample: rng.gen(),
+                    ..Ray::new(origin, dir, 0.)
+                };
+                medium.hit(&ray, 0., Float::INFINITY).is_none()
+            })
+            .count();
+        through as Float / n as Float
+    };
+    let expected = (-1. as Float).exp();
+    let plain = transmittance(&slab(), point3!(), vec3!(1., 0., 0.));
+    let translated = Translate::new(slab(), vec3!(0., 10., 0.));
+    let translated = transmittance(&translated, point3!(0., 10., 0.), vec3!(1., 0., 0.));
+    let rotated = RotateY::new(slab(), 180.);
+    let rotated = transmittance(&rotated, point3!(), vec3!(-1., 0., 0.));
+    for found in [plain, translated, rotated] {
+        assert!(
+            (found - expected).abs() < 0.01,
+            "{} of rays passed through, expected {}",
+            found,
+            expected
+        );
+    }
+}
+
+#[test]
+fn heterogeneous_media_of_even_density_follow_beer_lambert() {
+    // A unit thick slab with a majorant of 2, filled with `value` of it
+    let slab = |value: Float| {
+        let (min, max) = (point3!(1., -1., -1.), point3!(2., 1., 1.));
+        let density = GridTexture::new(min, max, [2, 2, 2], vec![value; 8]);
+        HeterogeneousMedium::new(Cuboid::new(min, max, grey()), density, 2.)
+    };
+    let ray = Ray::new(point3!(), vec3!(1., 0., 0.), 0.);
+    let n = 20_000;
+    // Half the majorant, then more than it, which is held to the majorant
+    for &(value, expected) in &[(0.5, (-1. as Float).exp()), (3., (-2. as Float).exp())] {
+        let medium = slab(value);
+        let mut rng = seeded_rng(Some(0));
+        let mut total = 0.;
+        let mut through = 0;
+        for _ in 0..n {
+            let transmittance = medium.transmittance(&ray, 0., Float::INFINITY, &mut rng);
+            assert!((0. ..=1.).contains(&transmittance));
+            total += transmittance;
+            let ray = Ray {
+                sample: rng.gen(),
+                ..ray
+            };
+            through += medium.hit(&ray, 0., Float::INFINITY).is_none() as u32;
+        }
+        // Ratio tracking and the collisions of delta tracking agree
+        for found in [total / n as Float, through as Float / n as Float] {
+            assert!(
+                (found - expected).abs() < 0.01,
+                "{} of light passed through, expected {}",
+                found,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::temp_path;
+use ray_tracing::error::Error;
+use ray_tracing::image::Image;
+use ray_tracing::job::{Job, JobFile, JOB_FILE};
+use ray_tracing::scene::SceneFile;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+/// A scene file with three spheres, two textured with the image at `texture`
+fn scene_file(texture: &Path) -> SceneFile {
+    let texture = serde_json::to_string(&texture.to_string_lossy()).expect("Error quoting path");
+    let json = format!(
+        r#"{{
+            "camera": {{ "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 }},
+            "render": {{ "width": 8, "height": 6, "samples_per_pixel": 2, "seed": 3 }},
+            "materials": {{
+                "photo": {{ "type": "lambertian", "albedo": {{ "type": "image", "path": {0} }} }}
+            }},
+            "objects": [
+                {{ "type": "sphere", "center": [-1, 0, 0], "radius": 1, "material": "photo" }},
+                {{ "type": "sphere", "center": [1, 0, 0], "radius": 1,
+                  "material": {{ "type": "metal", "albedo": [0.5, 0.5, 0.5], "fuzz": 0 }} }},
+                {{ "type": "sphere", "center": [0, 2, 0], "radius": 1,
+                  "material": {{ "type": "lambertian",
+                    "albedo": {{ "type": "image", "path": {0} }} }} }}
+            ]
+        }}"#,
+        texture
+    );
+    serde_json::from_str(&json).expect("Error parsing scene")
+}
+
+/// Where the job puts the first file the scene reads, from `path`
+fn bundled_name(path: &Path) -> String {
+    let name = path.file_name().expect("Error naming texture");
+    format!("files/0_{}", name.to_string_lossy())
+}
+
+/// A texture to bundle, written to `path`
+fn write_texture(path: &Path) {
+    Image::new_test(4, 4)
+        .write_png(path)
+        .expect("Error writing texture");
+}
+
+/// Names and contents of the files in an uncompressed zip, read from its local headers
+fn read_zip(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let bytes = fs::read(path).expect("Error reading zip");
+    let u16_at = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while u32_at(at) == 0x0403_4b50 {
+        assert_eq!(u16_at(at + 8), 0, "Entries are stored");
+        let (size, name_len) = (u32_at(at + 18), u16_at(at + 26));
+        let name = String::from_utf8(bytes[at + 30..at + 30 + name_len].to_vec()).unwrap();
+        let start = at + 30 + name_len + u16_at(at + 28);
+        entries.push((name, bytes[start..start + size].to_vec()));
+        at = start + size;
+    }
+    assert_eq!(
+        u32_at(at),
+        0x0201_4b50,
+        "Central directory follows the files"
+    );
+    let end = bytes.len() - 22;
+    assert_eq!(u32_at(end), 0x0605_4b50);
+    assert_eq!(u16_at(end + 10), entries.len());
+    assert_eq!(u32_at(end + 16), at);
+    entries
+}
+
+#[test]
+fn jobs_bundle_the_files_scenes_read() {
+    let texture = temp_path("job-texture.png");
+    write_texture(&texture);
+    let job = Job::new(scene_file(&texture), vec![4, 5, 6]);
+    assert_eq!(
+        job.dependencies().expect("Error listing files"),
+        vec![texture.to_string_lossy().into_owned()]
+    );
+    let dir = temp_path("job-dir");
+    job.write_dir(&dir).expect("Error writing job");
+    fs::remove_file(&texture).expect("Error removing texture");
+
+    let file: JobFile = serde_json::from_str(
+        &fs::read_to_string(dir.join(JOB_FILE)).expect("Error reading job file"),
+    )
+    .expect("Error parsing job file");
+    assert_eq!(file.files, vec![bundled_name(&texture)]);
+    assert!(dir.join(&file.files[0]).is_file());
+
+    let loaded = Job::load(&dir).expect("Error loading job");
+    assert_eq!(loaded.frames, vec![4, 5, 6]);
+    assert_eq!(loaded.scene.render.width, 8);
+    assert_ne!(loaded.frame_seed(4), loaded.frame_seed(5));
+    assert_eq!(loaded.frame_seed(4), job.frame_seed(4));
+    let scene = loaded.scene.build().expect("Error building bundled scene");
+    assert_eq!(scene.world.hittables.len(), 3);
+    fs::remove_dir_all(&dir).expect("Error removing job");
+}
+
+#[test]
+fn zipped_jobs_hold_the_same_files_as_directories() {
+    let texture = temp_path("zip-texture.png");
+    write_texture(&texture);
+    let job = Job::new(scene_file(&texture), vec![0]);
+    let (dir, zip) = (temp_path("zip-job"), temp_path("job.zip"));
+    job.write(&dir).expect("Error writing job");
+    job.write(&zip).expect("Error zipping job");
+    assert!(dir.is_dir() && zip.is_file());
+
+    let entries = read_zip(&zip);
+    let names: Vec<_> = entries.iter().map(|(name, _)| name.clone()).collect();
+    let expected = [
+        "job.json".to_owned(),
+        "scene.json".to_owned(),
+        bundled_name(&texture),
+    ];
+    assert_eq!(names, expected);
+    for (name, data) in &entries {
+        let expected = fs::read(dir.join(name)).expect("Error reading bundled file");
+        assert!(*data == expected, "{} matches the directory", name);
+    }
+    fs::remove_file(&texture).expect("Error removing texture");
+    fs::remove_file(&zip).expect("Error removing zip");
+    fs::remove_dir_all(&dir).expect("Error removing job");
+}
+
+#[test]
+fn jobs_with_missing_files_are_not_written() {
+    let texture = temp_path("missing-texture.png");
+    let dir = temp_path("missing-job");
+    let result = Job::new(scene_file(&texture), vec![0]).write_dir(&dir);
+    match result {
+        Err(Error::Scene(reason)) => assert!(reason.contains("missing-texture.png")),
+        _ => panic!("Missing texture isn't reported"),
+    }
+    assert!(!dir.exists());
+}
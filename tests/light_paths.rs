@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, grey};
+use ray_tracing::background::GradientBackground;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::hittable::Sphere;
+use ray_tracing::integrator::{PathIntegrator, PathType};
+use ray_tracing::material::{Dielectric, Light, Metal};
+use ray_tracing::texture::SolidColor;
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_image, raytrace_light_paths, Color, RenderSettings};
+use ray_tracing::{Float, Point3, Vec3};
+
+/// Matte ground holding a mirror and a glass ball under a light and the sky, so every type of
+/// path shows
+fn scene() -> (World, CameraSettings) {
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(0., -1000., 0.), 1000., grey()));
+    world.add(Sphere::new(
+        point3!(-1.2, 1., 0.),
+        1.,
+        Metal::new(color!(0.8, 0.8, 0.8), 0.),
+    ));
+    world.add(Sphere::new(point3!(1.2, 1., 0.), 1., Dielectric::new(1.5)));
+    let light = Light::new(SolidColor::new(color!(1., 1., 1.)), color!(4., 4., 4.));
+    world.add_light(Sphere::new(point3!(0., 2.5, -1.), 0.4, light));
+    let camera = CameraSettings {
+        look_from: point3!(0., 2., 8.),
+        look_at: point3!(0., 1., 0.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 40.,
+        aperture: 0.,
+        focus_dist: 8.,
+        t0: 0.,
+        t1: 0.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    };
+    (world, camera)
+}
+
+#[test]
+fn light_path_images_add_up_to_the_render() {
+    let settings = RenderSettings {
+        samples_per_pixel: 4,
+        max_depth: 8,
+        seed: Some(3),
+        background: Box::new(GradientBackground::sky()),
+        ..Default::default()
+    };
+    let (width, height) = (32, 24);
+    let (world, camera) = scene();
+    let paths = raytrace_light_paths(world, camera, &settings, width, height)
+        .expect("Error rendering light paths");
+    let (world, camera) = scene();
+    let beauty = raytrace_image(world, camera, &settings, &PathIntegrator, width, height)
+        .expect("Error rendering");
+    for &(path_type, ref image) in &paths {
+        let lit = image.data.iter().any(|color| color.luminance() > 0.);
+        assert!(lit, "No {} light", path_type.name());
+    }
+    assert_eq!(paths.len(), PathType::ALL.len());
+    for (pixel, expected) in beauty.data.iter().enumerate() {
+        let sum: Color = paths.iter().map(|(_, image)| image.data[pixel]).sum();
+        for i in 0..3 {
+            assert!(close(sum[i], expected[i]), "{:?} isn't {:?}", sum, expected);
+        }
+    }
+}
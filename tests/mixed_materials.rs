@@ -0,0 +1,157 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, describe_materials, grey, grey_of, hit_floor, scene_from_json};
+use ray_tracing::integrator::PathState;
+use ray_tracing::material::{Coated, Layered, Material, Metal, Mix};
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::scene::{MaterialDesc, MaterialRef, ScalarDesc};
+use ray_tracing::texture::{SolidColor, Texture};
+use ray_tracing::{Color, Float, Point3, Vec3};
+
+/// 0 where `u` is below a half and 1 above
+struct Halves;
+
+impl Texture for Halves {
+    fn value(&self, u: Float, _: Float, _: Point3) -> Color {
+        if u < 0.5 {
+            color!()
+        } else {
+            color!(1., 1., 1.)
+        }
+    }
+}
+
+/// A mix of matte grey where x is negative and a mirror where it's positive
+fn patchwork() -> Mix {
+    Mix::new(grey(), Metal::new(color!(0.9, 0.9, 0.9), 0.), Halves)
+}
+
+#[test]
+fn mixes_show_each_material_where_the_factor_picks_it() {
+    let up = vec3!(0., 1., 0.);
+    let matte = hit_floor(patchwork(), -0.5, |ray, rec| {
+        let mut ctx = SampleCtx::from_seed(Some(1));
+        let scattered = rec
+            .material
+            .scatter(ray, rec, &PathState::camera(), &mut ctx);
+        (rec.material.eval(ray, rec, &up), scattered)
+    });
+    assert!(matte.0.is_some(), "Matte areas can be lit directly");
+    assert_eq!(
+        matte.1.expect("Expected to scatter").1,
+        color!(0.5, 0.5, 0.5)
+    );
+
+    let shiny = hit_floor(patchwork(), 0.5, |ray, rec| {
+        let mut ctx = SampleCtx::from_seed(Some(1));
+        let scattered = rec
+            .material
+            .scatter(ray, rec, &PathState::camera(), &mut ctx);
+        (rec.material.eval(ray, rec, &up), scattered)
+    });
+    assert!(shiny.0.is_none(), "Mirrors can't be lit directly");
+    let (ray, attenuation) = shiny.1.expect("Expected to scatter");
+    assert_eq!(attenuation, color!(0.9, 0.9, 0.9));
+    let expected = vec3!(0., 1., 0.3).unit_vector();
+    assert!((0..3).all(|i| close(ray.dir.unit_vector()[i], expected[i])));
+}
+
+#[test]
+fn even_mixes_average_their_materials() {
+    let half = SolidColor::new(color!(0.5, 0.5, 0.5));
+    let mix = Mix::new(grey_of(0.2), grey_of(0.6), half);
+    let dir = vec3!(0.3, 1., 0.).unit_vector();
+    let (albedo, f, pdf) = hit_floor(mix, 0., |ray, rec| {
+        (
+            rec.material.albedo(rec),
+            rec.material.eval(ray, rec, &dir),
+            rec.material.scattering_pdf(ray, rec, &dir),
+        )
+    });
+    let expected = hit_floor(grey_of(0.4), 0., |ray, rec| {
+        (
+            rec.material.albedo(rec),
+            rec.material.eval(ray, rec, &dir),
+            rec.material.scattering_pdf(ray, rec, &dir),
+        )
+    });
+    let same = |a: Color, b: Color| (0..3).all(|i| close(a[i], b[i]));
+    assert!(same(albedo, expected.0));
+    assert!(same(
+        f.expect("Expected eval"),
+        expected.1.expect("Expected eval")
+    ));
+    assert!(close(pdf, expected.2));
+}
+
+#[test]
+fn layers_reflect_more_towards_grazing_angles() {
+    // A black base leaves only the coat
+    let coat = Layered::clearcoat(grey_of(0.), 0.1);
+    let reflected = |dir: Vec3| {
+        let mut ctx = SampleCtx::from_seed(Some(3));
+        hit_floor(Layered::clearcoat(grey_of(0.), 0.1), 0., |_, rec| {
+            let ray = Ray::new(rec.point - dir.conv::<Point3>(), dir, 0.);
+            let total: Color = (0..2000)
+                .filter_map(|_| {
+                    rec.material
+                        .scatter(&ray, rec, &PathState::camera(), &mut ctx)
+                })
+                .map(|(_, attenuation)| attenuation)
+                .fold(color!(), |total, attenuation| total + attenuation);
+            total[0] / 2000.
+        })
+    };
+    let head_on = reflected(vec3!(0., -1., 0.));
+    let grazing = reflected(vec3!(1., -0.1, 0.));
+    assert!(head_on > 0.02 && head_on < 0.08, "{}", head_on);
+    assert!(grazing > 3. * head_on, "{} against {}", grazing, head_on);
+    assert!(coat.validate().is_empty());
+    let coated = Coated::new(grey_of(1.5), 0.5, 0.);
+    assert_eq!(coated.validate().len(), 2);
+}
+
+#[test]
+fn scene_files_build_mixed_and_layered_materials() {
+    let scene = scene_from_json(
+        r#""materials": {
+            "paint": { "type": "lambertian", "albedo": [0.7, 0.1, 0.1] }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+              "material": { "type": "mix", "a": "paint",
+                            "b": { "type": "metal", "albedo": [0.8, 0.8, 0.8] },
+                            "factor": 0.25 } },
+            { "type": "sphere", "center": [3, 0, 0], "radius": 1,
+              "material": { "type": "layered",
+                            "top": { "type": "pbr", "base_color": [1, 1, 1],
+                                     "metallic": 1, "roughness": 0.1 },
+                            "bottom": "paint" } }
+        ]"#,
+    );
+    let described = describe_materials(&scene);
+    assert!(matches!(
+        &described[..],
+        [
+            Some(MaterialDesc::Mix {
+                a: MaterialRef::Inline(a),
+                b: MaterialRef::Inline(b),
+                factor: ScalarDesc::Value(factor),
+            }),
+            Some(MaterialDesc::Layered {
+                top: MaterialRef::Inline(top),
+                bottom: MaterialRef::Inline(bottom),
+                ior,
+            })
+        ] if matches!(**a, MaterialDesc::Lambertian { .. })
+            && matches!(**b, MaterialDesc::Metal { .. })
+            && *factor == 0.25
+            && matches!(**top, MaterialDesc::Pbr { .. })
+            && matches!(**bottom, MaterialDesc::Lambertian { .. })
+            && *ior == 1.5
+    ));
+}
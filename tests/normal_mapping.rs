@@ -0,0 +1,106 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, describe_materials, grey, scene_from_json, TOLERANCE};
+use ray_tracing::consts::PI;
+use ray_tracing::hittable::{Hittable, XZRect, HIT_EPSILON};
+use ray_tracing::material::{Material, NormalMapped, SurfaceMap};
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::{MaterialDesc, TextureDesc};
+use ray_tracing::texture::{SolidColor, Texture};
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::sync::Arc;
+
+/// Heights rising along `u`
+struct Ramp;
+
+impl Texture for Ramp {
+    fn value(&self, u: Float, _: Float, _: Point3) -> Color {
+        color!(u, u, u)
+    }
+}
+
+/// Lambertian `eval` for light arriving from `dir` onto a floor made of `material`, seen from
+/// straight above. The floor's tangent is +x and its bitangent +z
+fn lit_from(material: impl Material + Send + Sync + 'static, dir: Vec3) -> Float {
+    let floor = XZRect::new(-1., 1., -1., 1., 0., material);
+    let ray = Ray::new(point3!(0.1, 2., 0.2), vec3!(0., -1., 0.), 0.);
+    let rec = floor
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Expected to hit the floor");
+    rec.material
+        .eval(&ray, &rec, &dir)
+        .expect("Lambertian materials can be evaluated")[0]
+}
+
+fn normal_map(color: Color) -> NormalMapped {
+    NormalMapped::new(
+        grey(),
+        SurfaceMap::Normals(Arc::new(SolidColor::new(color))),
+    )
+}
+
+#[test]
+fn flat_normal_maps_change_nothing() {
+    let up = vec3!(0., 1., 0.);
+    let flat = lit_from(normal_map(color!(0.5, 0.5, 1.)), up);
+    assert!(close(flat, lit_from(grey(), up)));
+    assert!(close(flat, 0.5 / PI));
+}
+
+#[test]
+fn normal_maps_tilt_towards_red_and_green() {
+    // Tilted 45° towards the tangent
+    let towards_tangent = normal_map(color!(1., 0.5, 1.));
+    let tilted = vec3!(1., 1., 0.).unit_vector();
+    assert!(close(lit_from(towards_tangent, tilted), 0.5 / PI));
+    let towards_bitangent = || normal_map(color!(0.5, 1., 1.));
+    let light = vec3!(0., 1., 0.5);
+    let away = vec3!(0., 1., -0.5);
+    assert!(lit_from(towards_bitangent(), light) > lit_from(towards_bitangent(), away));
+}
+
+#[test]
+fn bumps_tilt_away_from_uphill() {
+    let bumps = || {
+        NormalMapped::new(
+            grey(),
+            SurfaceMap::Bumps {
+                heights: Arc::new(Ramp),
+                strength: 1.,
+            },
+        )
+    };
+    // The ramp rises one unit per unit of `u`, so the normal leans 45° towards -x
+    let downhill = vec3!(-1., 1., 0.).unit_vector();
+    assert!(close(lit_from(bumps(), downhill), 0.5 / PI));
+    let uphill = vec3!(1., 1., 0.).unit_vector();
+    assert!(lit_from(bumps(), uphill).abs() < TOLERANCE);
+}
+
+#[test]
+fn scene_files_build_normal_and_bump_maps() {
+    let scene = scene_from_json(
+        r#""objects": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+              "material": { "type": "normal_map", "normals": [0.5, 0.5, 1],
+                            "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } } },
+            { "type": "sphere", "center": [3, 0, 0], "radius": 1,
+              "material": { "type": "bump_map", "heights": [0, 0, 0],
+                            "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } } }
+        ]"#,
+    );
+    let described = describe_materials(&scene);
+    assert!(matches!(
+        described[..],
+        [
+            Some(MaterialDesc::NormalMap {
+                normals: TextureDesc::Color([0.5, 0.5, 1.]),
+                ..
+            }),
+            Some(MaterialDesc::BumpMap { strength, .. })
+        ] if strength == 1.
+    ));
+}
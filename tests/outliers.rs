@@ -0,0 +1,89 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::integrator::Integrator;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::{SampleCtx, SamplerKind};
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_image, Color, RenderSettings};
+use ray_tracing::{Float, Point3, Vec3};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Grey everywhere except the bottom left ninth of the left pixels, which is very bright
+#[derive(Default)]
+struct Firefly {
+    samples: AtomicU32,
+}
+
+impl Integrator for Firefly {
+    fn li(&self, ray: &Ray, _: &World, _: &RenderSettings, _: &mut SampleCtx) -> Color {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        // The camera's view is 2 wide at z = -1, spread over 1 pixel width and height
+        let u = (ray.dir[0] / -ray.dir[2] + 1.) / 2.;
+        let v = (ray.dir[1] / -ray.dir[2] + 1.) / 2.;
+        if u < 1. / 3. && v.fract() < 1. / 3. {
+            color!(1000., 1000., 1000.)
+        } else {
+            color!(0.5, 0.5, 0.5)
+        }
+    }
+}
+
+fn camera() -> CameraSettings {
+    CameraSettings {
+        look_from: point3!(0., 0., 0.),
+        look_at: point3!(0., 0., -1.),
+        vup: vec3!(0., 1., 0.),
+        vfov: 90.,
+        aperture: 0.,
+        focus_dist: 1.,
+        t0: 0.,
+        t1: 0.,
+        near: 0.,
+        far: Float::INFINITY,
+        aperture_mask: None,
+    }
+}
+
+/// Renders a 2 by 2 image, returning its pixels and how many samples were taken
+fn render(samples_per_pixel: u32, outlier_batches: u32) -> (Vec<Color>, u32) {
+    let integrator = Firefly::default();
+    let settings = RenderSettings {
+        samples_per_pixel,
+        outlier_batches: Some(outlier_batches),
+        sampler: SamplerKind::Stratified,
+        seed: Some(1),
+        ..Default::default()
+    };
+    let image = raytrace_image(World::default(), camera(), &settings, &integrator, 2, 2)
+        .expect("Error rendering");
+    // Before rendering, the progress bar times one ray per pixel of images this small
+    (image.data, integrator.samples.load(Ordering::Relaxed) - 4)
+}
+
+#[test]
+fn median_batches_reject_fireflies_and_keep_even_light() {
+    // Stratified samples put exactly one of each pixel's 9 samples in the bright corner
+    let (pixels, _) = render(9, 1);
+    let firefly = (1000. + 8. * 0.5) / 9.;
+    let expected = [firefly, 0.5, firefly, 0.5];
+    for (pixel, &expected) in pixels.iter().zip(&expected) {
+        assert!(close(pixel[0], expected), "{:?}", pixels);
+    }
+    let (pixels, _) = render(9, 3);
+    for pixel in pixels {
+        assert!(close(pixel[0], 0.5), "{:?}", pixel);
+    }
+}
+
+#[test]
+fn outlier_batches_take_exactly_the_samples_asked_for() {
+    for &(samples_per_pixel, batches) in &[(10, 3), (2, 5), (1, 3)] {
+        let (_, taken) = render(samples_per_pixel, batches);
+        assert_eq!(taken, 4 * samples_per_pixel);
+    }
+}
@@ -0,0 +1,102 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::grey;
+use ray_tracing::aov::Aovs;
+use ray_tracing::camera::CameraSettings;
+use ray_tracing::hittable::Sphere;
+use ray_tracing::image::Image;
+use ray_tracing::integrator::ToonIntegrator;
+use ray_tracing::outline::{draw_outlines, OutlineSettings};
+use ray_tracing::world::World;
+use ray_tracing::{raytrace_with_aovs, Color, Float, Point3, RenderSettings, Vec3};
+
+const WIDTH: u32 = 6;
+
+/// A row of pixels colored by `color` at each column
+fn row(color: impl Fn(u32) -> Color) -> Image {
+    Image {
+        width: WIDTH,
+        height: 1,
+        data: (0..WIDTH).map(color).collect(),
+        alpha: None,
+    }
+}
+
+/// Columns drawn as outline over a white image with the given passes
+fn outlined(normal: impl Fn(u32) -> Color, depth: impl Fn(u32) -> Float) -> Vec<u32> {
+    let white = row(|_| color!(1., 1., 1.));
+    let aovs = Aovs {
+        normal: row(normal),
+        depth: row(|x| {
+            let depth = depth(x);
+            color!(depth, depth, depth)
+        }),
+        albedo: white.clone(),
+        object_id: white.clone(),
+    };
+    let image = draw_outlines(&white, &aovs, &OutlineSettings::default());
+    (0..WIDTH)
+        .filter(|&x| image.data[x as usize] == color!())
+        .collect()
+}
+
+#[test]
+fn creases_are_outlined_on_both_sides() {
+    let normal = |x| {
+        if x < 3 {
+            color!(0., 0., 1.)
+        } else {
+            color!(1., 0., 0.)
+        }
+    };
+    assert_eq!(outlined(normal, |_| 5.), vec![2, 3]);
+    assert!(outlined(|_| color!(0., 0., 1.), |_| 5.).is_empty());
+}
+
+#[test]
+fn silhouettes_are_outlined_on_the_near_side() {
+    let facing = |_| color!(0., 0., 1.);
+    // Both surfaces face the camera, so only their depths tell them apart
+    assert_eq!(outlined(facing, |x| if x < 3 { 2. } else { 5. }), vec![2]);
+    assert_eq!(outlined(facing, |x| if x < 4 { 5. } else { 2. }), vec![4]);
+    let background = |x| if x < 3 { 2. } else { Float::INFINITY };
+    assert_eq!(outlined(facing, background), vec![2]);
+}
+
+#[test]
+fn toon_renders_outline_spheres_in_front_of_each_other() {
+    let mut world = World::default();
+    world.add(Sphere::new(point3!(0., 0., 0.), 2., grey()));
+    world.add(Sphere::new(point3!(0., 0., 3.), 0.5, grey()));
+    let camera = CameraSettings {
+        look_from: point3!(0., 0., 10.),
+        look_at: point3!(),
+        vup: vec3!(0., 1., 0.),
+        vfov: 30.,
+        focus_dist: 10.,
+        ..Default::default()
+    };
+    let settings = RenderSettings {
+        samples_per_pixel: 1,
+        seed: Some(1),
+        ..Default::default()
+    };
+    let (image, aovs) =
+        raytrace_with_aovs(world, camera, &settings, &ToonIntegrator::default(), 41, 41)
+            .expect("Error rendering");
+    let outlines = OutlineSettings {
+        color: color!(1., 0., 1.),
+        ..Default::default()
+    };
+    let image = draw_outlines(&image, &aovs, &outlines);
+    let middle: Vec<bool> = (0..41)
+        .map(|x| image.pixel(x, 20) == outlines.color)
+        .collect();
+    assert!(!middle[20], "The middle of the front sphere isn't an edge");
+    // The small sphere's silhouette lies inside the big sphere, where both face the camera
+    let inner = (12..20).filter(|&x| middle[x]).count();
+    assert!(inner > 0, "{:?}", middle);
+}
@@ -0,0 +1,117 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{describe_materials, scene_from_json};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::consts::PI;
+use ray_tracing::hittable::{Hittable, XZRect, HIT_EPSILON};
+use ray_tracing::integrator::PathState;
+use ray_tracing::material::PbrMaterial;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::scene::{MaterialDesc, ScalarDesc, TextureDesc};
+use ray_tracing::{Color, Float, Point3, Vec3};
+
+const SAMPLES: usize = 100_000;
+
+/// Asserts scattering off a floor of `material` gives the weights and directions `eval` and
+/// `scattering_pdf` describe, checking their integrals over the hemisphere above it
+fn assert_consistent(material: PbrMaterial, view: Vec3) {
+    let floor = XZRect::new(-1., 1., -1., 1., 0., material);
+    let ray = Ray::new((-view).conv::<Point3>(), view, 0.);
+    let rec = floor
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Expected to hit the floor");
+
+    let mut ctx = SampleCtx::from_seed(Some(2));
+    let mut weight = color!();
+    let mut scattered = 0;
+    for _ in 0..SAMPLES {
+        if let Some((out, attenuation)) =
+            rec.material
+                .scatter(&ray, &rec, &PathState::camera(), &mut ctx)
+        {
+            assert!(out.dir[1] > 0., "{} goes under the floor", out.dir);
+            weight += attenuation;
+            scattered += 1;
+        }
+    }
+    let weight = weight / SAMPLES as Float;
+    let scattered = scattered as Float / SAMPLES as Float;
+
+    // The same integrals with directions spread evenly over the hemisphere
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut reflected = color!();
+    let mut density = 0.;
+    for _ in 0..SAMPLES {
+        let (y, phi): (Float, Float) = (rng.gen(), 2. * PI * rng.gen::<Float>());
+        let r = (1. - y * y).sqrt();
+        let dir = vec3!(r * phi.cos(), y, r * phi.sin());
+        reflected += rec
+            .material
+            .eval(&ray, &rec, &dir)
+            .expect("Expected PBR to be lit directly");
+        density += rec.material.scattering_pdf(&ray, &rec, &dir);
+    }
+    let reflected = reflected * (2. * PI / SAMPLES as Float);
+    let density = density * 2. * PI / SAMPLES as Float;
+
+    for i in 0..3 {
+        assert!(
+            (weight[i] - reflected[i]).abs() < 0.02,
+            "scattering reflects {}, eval {}",
+            weight,
+            reflected
+        );
+    }
+    assert!(
+        (scattered - density).abs() < 0.02,
+        "{} scattered, pdf integrates to {}",
+        scattered,
+        density
+    );
+}
+
+#[test]
+fn pbr_scattering_agrees_with_eval_and_pdf() {
+    let straight = vec3!(0., -1., 0.);
+    let slanted = vec3!(1., -1., 0.).unit_vector();
+    assert_consistent(PbrMaterial::new(color!(0.8, 0.5, 0.2), 0., 0.5), straight);
+    assert_consistent(PbrMaterial::new(color!(0.8, 0.5, 0.2), 0., 0.7), slanted);
+    assert_consistent(PbrMaterial::new(color!(0.9, 0.6, 0.3), 1., 0.6), slanted);
+    assert_consistent(PbrMaterial::new(color!(0.5, 0.5, 0.5), 0.5, 0.8), straight);
+}
+
+#[test]
+fn scene_files_build_pbr_materials() {
+    let scene = scene_from_json(
+        r#""objects": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+              "material": { "type": "pbr", "base_color": [0.9, 0.6, 0.3],
+                            "metallic": 1, "roughness": 0.25 } },
+            { "type": "sphere", "center": [3, 0, 0], "radius": 1,
+              "material": { "type": "pbr", "base_color": [0.5, 0.5, 0.5],
+                            "roughness": { "type": "checker", "odd": [0.2, 0.2, 0.2],
+                                           "even": [0.8, 0.8, 0.8] } } }
+        ]"#,
+    );
+    let described = describe_materials(&scene);
+    assert!(matches!(
+        described[..],
+        [
+            Some(MaterialDesc::Pbr {
+                base_color: TextureDesc::Color([0.9, 0.6, 0.3]),
+                metallic: ScalarDesc::Value(metallic),
+                roughness: ScalarDesc::Value(roughness),
+            }),
+            Some(MaterialDesc::Pbr {
+                metallic: ScalarDesc::Value(dielectric),
+                roughness: ScalarDesc::Texture(TextureDesc::Texture(_)),
+                ..
+            })
+        ] if metallic == 1. && roughness == 0.25 && dielectric == 0.
+    ));
+}
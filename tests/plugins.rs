@@ -0,0 +1,155 @@
+#[macro_use]
+extern crate ray_tracing;
+
+use ray_tracing::error::Error;
+use ray_tracing::hittable::{Hittable, Sphere};
+use ray_tracing::material::{Lambertian, Material};
+use ray_tracing::plugin;
+use ray_tracing::scene::{MaterialDesc, Scene, TextureDesc};
+use ray_tracing::texture::{SolidColor, Texture};
+use ray_tracing::{Color, Float, Point3};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// A scene of `objects`, a comma separated list
+fn scene_of(objects: &str) -> Result<Scene, Error> {
+    let source = format!(
+        r#"{{
+            "camera": {{ "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 }},
+            "objects": [{}]
+        }}"#,
+        objects
+    );
+    Scene::from_json(&source)
+}
+
+/// A scene of two spheres made of `first` and `second`
+fn scene(first: &str, second: &str) -> Result<Scene, Error> {
+    scene_of(&format!(
+        r#"{{ "type": "sphere", "center": [-1, 0, 0], "radius": 0.5, "material": {} }},
+           {{ "type": "sphere", "center": [1, 0, 0], "radius": 0.5, "material": {} }}"#,
+        first, second
+    ))
+}
+
+/// Albedo of the plain colored Lambertian material each object in `scene` is made of
+fn albedos(scene: &Scene) -> Vec<[Float; 3]> {
+    let hittables = scene.world.hittables.iter();
+    let materials = hittables.flat_map(|hittable| hittable.materials());
+    materials
+        .map(|material| match material.describe() {
+            Some(MaterialDesc::Lambertian {
+                albedo: TextureDesc::Color(color),
+            }) => color,
+            _ => panic!("Expected a plain Lambertian material"),
+        })
+        .collect()
+}
+
+fn gray(params: &Value) -> Result<Arc<dyn Texture + Send + Sync>, Error> {
+    let value = params["value"]
+        .as_f64()
+        .ok_or_else(|| Error::Scene("Gray needs a value".to_owned()))? as Float;
+    Ok(Arc::new(SolidColor::new(color!(value, value, value))))
+}
+
+/// A sphere at `center` in the parameters, with a radius of 1 unless given one
+fn ball(
+    params: &Value,
+    material: Option<Arc<dyn Material + Send + Sync>>,
+) -> Result<Box<dyn Hittable + Send + Sync>, Error> {
+    let center: [Float; 3] = serde_json::from_value(params["center"].clone())?;
+    let radius = params["radius"].as_f64().unwrap_or(1.) as Float;
+    let material = material.ok_or_else(|| Error::Scene("A ball needs a material".to_owned()))?;
+    Ok(Box::new(Sphere::new_shared(
+        center.into(),
+        radius,
+        material,
+    )))
+}
+
+fn matte(params: &Value) -> Result<Arc<dyn Material + Send + Sync>, Error> {
+    let albedo: [Float; 3] = serde_json::from_value(params["albedo"].clone())?;
+    Ok(Arc::new(Lambertian::new(SolidColor::new(albedo.into()))))
+}
+
+#[test]
+fn scene_files_build_registered_materials_and_textures() {
+    plugin::register_material("test_matte", matte);
+    plugin::register_texture("test_gray", gray);
+    let scene = scene(
+        r#"{ "type": "plugin", "name": "test_matte", "params": { "albedo": [0.1, 0.2, 0.3] } }"#,
+        r#"{ "type": "lambertian",
+             "albedo": { "type": "plugin", "name": "test_gray", "params": { "value": 0.5 } } }"#,
+    )
+    .expect("Error building scene");
+    assert_eq!(albedos(&scene), [[0.1, 0.2, 0.3], [0.5, 0.5, 0.5]]);
+}
+
+#[test]
+fn registering_again_replaces_the_factory() {
+    plugin::register_material("test_replaced", |_| {
+        Ok(Arc::new(Lambertian::new(SolidColor::new(color!(
+            1., 0., 0.
+        )))))
+    });
+    plugin::register_material("test_replaced", |_| {
+        Ok(Arc::new(Lambertian::new(SolidColor::new(color!(
+            0., 1., 0.
+        )))))
+    });
+    let material = r#"{ "type": "plugin", "name": "test_replaced" }"#;
+    let scene = scene(material, material).expect("Error building scene");
+    assert_eq!(albedos(&scene), [[0., 1., 0.], [0., 1., 0.]]);
+}
+
+#[test]
+fn unknown_plugins_are_reported() {
+    let material = r#"{ "type": "plugin", "name": "test_missing" }"#;
+    match scene(material, material) {
+        Err(Error::Scene(reason)) => assert_eq!(reason, "Unknown plugin material `test_missing`"),
+        _ => panic!("Expected an unknown plugin error"),
+    }
+}
+
+#[test]
+fn factory_errors_are_reported() {
+    plugin::register_texture("test_gray_failing", gray);
+    let material = r#"{ "type": "lambertian",
+                        "albedo": { "type": "plugin", "name": "test_gray_failing" } }"#;
+    assert!(matches!(scene(material, material), Err(Error::Scene(_))));
+}
+
+#[test]
+fn scene_files_build_registered_objects() {
+    plugin::register_hittable("test_ball", ball);
+    let scene = scene_of(
+        r#"{ "type": "plugin", "name": "test_ball", "params": { "center": [0, 0, 0] },
+             "material": { "type": "lambertian", "albedo": [0.1, 0.2, 0.3] } },
+           { "type": "plugin", "name": "test_ball", "params": { "center": [0, 3, 0] },
+             "material": { "type": "light", "color": [1, 1, 1], "power": { "watts": 10 } } }"#,
+    )
+    .expect("Error building scene");
+    assert_eq!(albedos(&scene), [[0.1, 0.2, 0.3]]);
+    let ball = scene.world.hittables[0]
+        .bounding_box(0., 1.)
+        .expect("Balls are bounded");
+    assert_eq!(ball.max, point3!(1., 1., 1.));
+    assert_eq!(scene.world.lights.len(), 1, "Balls of light are lights");
+    assert!(scene.world.lights[0].area() > 0.);
+}
+
+#[test]
+fn object_factory_errors_are_reported() {
+    plugin::register_hittable("test_ball_failing", ball);
+    let ball =
+        r#"{ "type": "plugin", "name": "test_ball_failing", "params": { "center": [0, 0, 0] } }"#;
+    assert!(matches!(scene_of(ball), Err(Error::Scene(_))));
+    let missing = r#"{ "type": "plugin", "name": "test_missing_object" }"#;
+    match scene_of(missing) {
+        Err(Error::Scene(reason)) => {
+            assert_eq!(reason, "Unknown plugin object `test_missing_object`")
+        }
+        _ => panic!("Expected an unknown plugin error"),
+    }
+}
@@ -0,0 +1,72 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::temp_path;
+use ray_tracing::image::Image;
+use ray_tracing::Color;
+use std::fs;
+
+/// A 2x2 image with a known byte for each channel once gamma corrected
+///
+/// 0.25 is 0.5 after gamma correction, which is 127, and 4 is clipped to 255
+fn image() -> Image {
+    Image {
+        width: 2,
+        height: 2,
+        data: vec![
+            color!(1., 0.25, 0.),
+            color!(0., 4., 0.25),
+            color!(0.25, 0.25, 0.25),
+            color!(-1., 0.01, 1.),
+        ],
+        alpha: None,
+    }
+}
+
+/// Bytes of `image` in rows from the top
+const BYTES: [u8; 12] = [255, 127, 0, 0, 255, 127, 127, 127, 127, 0, 25, 255];
+
+#[test]
+fn plain_ppm_matches_known_image() {
+    let path = temp_path("plain.ppm");
+    image().write_ppm(&path).expect("Error writing PPM file");
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let expected = "P3\n2 2\n255\n255 127 0\n0 255 127\n127 127 127\n0 25 255\n";
+    assert_eq!(written, expected);
+}
+
+#[test]
+fn binary_ppm_matches_known_image() {
+    let path = temp_path("binary.ppm");
+    image()
+        .write_ppm_binary(&path)
+        .expect("Error writing PPM file");
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut expected = b"P6\n2 2\n255\n".to_vec();
+    expected.extend_from_slice(&BYTES);
+    assert_eq!(written, expected);
+}
+
+#[test]
+fn ppm_matches_png() {
+    let ppm_path = temp_path("compare.ppm");
+    let png_path = temp_path("compare.png");
+    image()
+        .write_ppm_binary(&ppm_path)
+        .expect("Error writing PPM file");
+    image()
+        .write_png(&png_path)
+        .expect("Error writing PNG file");
+    let ppm = fs::read(&ppm_path).unwrap();
+    let png = image::open(&png_path).unwrap().to_rgb();
+    fs::remove_file(&ppm_path).unwrap();
+    fs::remove_file(&png_path).unwrap();
+
+    assert_eq!(&ppm[ppm.len() - BYTES.len()..], &png.into_raw()[..]);
+}
@@ -0,0 +1,118 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::background::SolidBackground;
+use ray_tracing::hittable::Sphere;
+use ray_tracing::integrator::PathIntegrator;
+use ray_tracing::material::Light;
+use ray_tracing::probe::{render_probe, CubeFace, ProbeSettings};
+use ray_tracing::texture::SolidColor;
+use ray_tracing::world::World;
+use ray_tracing::{Color, Float, Point3, RenderSettings, Vec3};
+
+fn settings() -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 4,
+        max_depth: 4,
+        seed: Some(1),
+        ..Default::default()
+    }
+}
+
+/// With odd sized irradiance faces, so one pixel faces straight along each axis
+fn probe_settings() -> ProbeSettings {
+    ProbeSettings {
+        irradiance_size: Some(3),
+        ..ProbeSettings::new(point3!(), 16)
+    }
+}
+
+#[test]
+fn faces_map_directions_both_ways() {
+    for &face in &CubeFace::ALL {
+        for &(s, t) in &[(0., 0.), (0.5, -0.25), (-0.9, 0.7)] {
+            let (found, found_s, found_t) = CubeFace::from_direction(face.direction(s, t) * 3.);
+            assert_eq!(found, face);
+            assert!(close(found_s, s) && close(found_t, t));
+        }
+    }
+    // Faces are seen from inside, with y up on the sides
+    assert_eq!(CubeFace::PositiveX.direction(1., -1.), vec3!(1., 1., -1.));
+    assert_eq!(CubeFace::PositiveZ.direction(1., -1.), vec3!(1., 1., 1.));
+}
+
+#[test]
+fn even_light_gives_even_probes() {
+    let sky = color!(0.2, 0.4, 0.6);
+    let mut settings = settings();
+    settings.background = Box::new(SolidBackground::new(sky));
+    let probe = render_probe(
+        World::default(),
+        &settings,
+        &PathIntegrator,
+        &probe_settings(),
+    )
+    .expect("Error baking probe");
+    let irradiance = probe.irradiance.expect("Expected irradiance");
+    assert_eq!((probe.radiance.size(), irradiance.size()), (16, 3));
+    for cubemap in &[probe.radiance, irradiance] {
+        assert_eq!(cubemap.faces.len(), 6);
+        for color in cubemap.faces.iter().flat_map(|face| &face.data) {
+            assert!((0..3).all(|i| close(color[i], sky[i])), "{}", color);
+        }
+    }
+}
+
+#[test]
+fn probes_see_lights_on_the_right_face() {
+    let mut world = World::default();
+    let white = SolidColor::new(color!(1., 1., 1.));
+    let light = Light::new(white, color!(4., 4., 4.));
+    world.add(Sphere::new(point3!(10., 0., 0.), 3., light));
+    let probe =
+        render_probe(world, &settings(), &PathIntegrator, &probe_settings()).expect("Error baking");
+
+    let towards = probe.radiance.sample(vec3!(1., 0., 0.));
+    assert_eq!(
+        towards,
+        probe.radiance.face(CubeFace::PositiveX).pixel(8, 8)
+    );
+    assert_eq!(towards, color!(4., 4., 4.));
+    let away = probe.radiance.face(CubeFace::NegativeX);
+    assert!(away.data.iter().all(|&color| color == color!()));
+
+    // A sphere subtending a cone of half angle asin(0.3), seen head on
+    let irradiance = probe.irradiance.expect("Expected irradiance");
+    let solid_angle = 2. * ray_tracing::consts::PI * (1. - (0.91 as Float).sqrt());
+    let expected = 4. * solid_angle / ray_tracing::consts::PI;
+    let facing = irradiance.sample(vec3!(1., 0., 0.));
+    assert!((facing[0] / expected - 1.).abs() < 0.1, "{}", facing);
+    let behind = irradiance.face(CubeFace::NegativeX);
+    assert!(behind.data.iter().all(|&color| color == color!()));
+}
+
+#[test]
+fn cubemaps_write_a_file_per_face() {
+    let probe = render_probe(
+        World::default(),
+        &settings(),
+        &PathIntegrator,
+        &ProbeSettings::new(point3!(), 2),
+    )
+    .expect("Error baking probe");
+    assert!(probe.irradiance.is_none());
+    let dir = std::env::temp_dir().join(format!("ray-tracing-{}-probe", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Error creating directory");
+    probe
+        .radiance
+        .write(dir.join("probe.png"))
+        .expect("Error writing cubemap");
+    for &face in &CubeFace::ALL {
+        let path = dir.join(format!("probe_{}.png", face.name()));
+        assert!(path.is_file(), "{} wasn't written", path.display());
+    }
+    std::fs::remove_dir_all(&dir).expect("Error removing cubemap");
+}
@@ -0,0 +1,77 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::hittable::{Hittable, XZRect, HIT_EPSILON};
+use ray_tracing::integrator::PathState;
+use ray_tracing::material::Dielectric;
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::{Float, Point3, Vec3};
+
+const AIR: Float = 1.;
+const WATER: Float = 1.33;
+const GLASS: Float = 1.5;
+
+/// Sines of the angles from the normal of a ray crossing the surface of a dielectric with index
+/// `ior`, while inside `media`, on the way in or out, and the index of the innermost medium
+/// afterwards
+fn crossing(ior: Float, media: &[Float], entering: bool) -> (Float, Float, Float) {
+    let surface = XZRect::new(-1., 1., -1., 1., 0., Dielectric::new(ior));
+    // Coming down onto the front of the surface or up onto its back
+    let ray = if entering {
+        Ray::new(point3!(-1.5, 2., 0.), vec3!(0.6, -0.8, 0.), 0.)
+    } else {
+        Ray::new(point3!(-1.5, -2., 0.), vec3!(0.6, 0.8, 0.), 0.)
+    };
+    let rec = surface
+        .hit(&ray, HIT_EPSILON, Float::INFINITY)
+        .expect("Expected to hit the surface");
+    assert_eq!(rec.front_face, entering);
+    let mut state = PathState::camera();
+    for &ior in media {
+        state.media.enter(ior);
+    }
+    // Some rays are reflected, so find one that passed through
+    let scattered = (0..100)
+        .map(|seed| {
+            let mut ctx = SampleCtx::from_seed(Some(seed));
+            let (scattered, _) = rec
+                .material
+                .scatter(&ray, &rec, &state, &mut ctx)
+                .expect("Dielectrics always scatter");
+            scattered
+        })
+        .find(|scattered| scattered.dir[1] * ray.dir[1] > 0.)
+        .expect("Expected a ray to refract");
+    state.media.cross(&rec, &scattered);
+    let sine = |dir: Vec3| dir[0].abs() / dir.length();
+    (sine(ray.dir), sine(scattered.dir), state.media.current())
+}
+
+/// Asserts crossing into a medium with index `to` from one with index `from` follows Snell's law
+/// and leaves the path inside `to`
+fn assert_snell(from: Float, to: Float, (incident, refracted, current): (Float, Float, Float)) {
+    assert!(
+        close(from * incident, to * refracted),
+        "{} × {} isn't {} × {}",
+        from,
+        incident,
+        to,
+        refracted
+    );
+    assert_eq!(current, to);
+}
+
+#[test]
+fn nested_dielectrics_refract_by_the_indices_either_side() {
+    // Into a glass of water with an air bubble, and back out
+    assert_snell(AIR, GLASS, crossing(GLASS, &[], true));
+    assert_snell(GLASS, WATER, crossing(WATER, &[GLASS], true));
+    assert_snell(WATER, AIR, crossing(AIR, &[GLASS, WATER], true));
+    assert_snell(AIR, WATER, crossing(AIR, &[GLASS, WATER, AIR], false));
+    assert_snell(WATER, GLASS, crossing(WATER, &[GLASS, WATER], false));
+    assert_snell(GLASS, AIR, crossing(GLASS, &[GLASS], false));
+}
@@ -0,0 +1,82 @@
+use ray_tracing::sampler::{seeded_rng, SampleCtx, SamplerKind};
+use ray_tracing::Float;
+
+/// Cell of an `n`×`n` grid that the pair of values falls in
+fn cell((x, y): (Float, Float), n: u32) -> usize {
+    let n = n as Float;
+    ((y * n) as usize) * n as usize + (x * n) as usize
+}
+
+/// Asserts the `index`th dimension of every sample of a pixel, taken by `take`, puts exactly one
+/// sample in each cell of an `n`×`n` grid
+fn assert_one_per_cell(n: u32, take: impl Fn(&mut SampleCtx) -> (Float, Float)) {
+    let mut ctx = SamplerKind::Stratified.create_ctx(n * n, seeded_rng(Some(0)));
+    for pixel in 0..4 {
+        ctx.start_pixel(pixel, 0);
+        let mut counts = vec![0; (n * n) as usize];
+        for index in 0..n * n {
+            ctx.start_sample(index);
+            counts[cell(take(&mut ctx), n)] += 1;
+        }
+        assert!(counts.iter().all(|&count| count == 1), "{:?}", counts);
+    }
+}
+
+#[test]
+fn stratified_samples_fill_every_cell_once() {
+    for n in 1..6 {
+        // Positions in the pixel, then a later dimension
+        assert_one_per_cell(n, |ctx| ctx.pixel_sample());
+        assert_one_per_cell(n, |ctx| {
+            ctx.pixel_sample();
+            ctx.get_1d();
+            ctx.get_2d()
+        });
+    }
+}
+
+#[test]
+fn samples_stay_in_the_unit_interval() {
+    let kinds = [
+        SamplerKind::Random,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+    for &kind in &kinds {
+        // Leftover samples beyond the stratified grid too
+        let mut ctx = kind.create_ctx(20, seeded_rng(Some(1)));
+        for pixel in 0..4 {
+            ctx.start_pixel(pixel, pixel);
+            for index in 0..20 {
+                ctx.start_sample(index);
+                let (x, y) = ctx.pixel_sample();
+                let mut values = vec![x, y, ctx.time_sample()];
+                for _ in 0..40 {
+                    let (a, b) = ctx.get_2d();
+                    values.extend([ctx.get_1d(), a, b]);
+                }
+                for value in values {
+                    assert!((0. ..1.).contains(&value), "{:?} gave {}", kind, value);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn sobol_samples_spread_each_dimension_evenly() {
+    let mut ctx = SamplerKind::Sobol.create_ctx(16, seeded_rng(Some(2)));
+    ctx.start_pixel(0, 0);
+    let mut counts = vec![vec![0; 16]; 8];
+    for index in 0..16 {
+        ctx.start_sample(index);
+        for counts in &mut counts {
+            counts[(ctx.get_1d() * 16.) as usize] += 1;
+        }
+    }
+    for (dimension, counts) in counts.iter().enumerate() {
+        let even = counts.iter().all(|&count| count == 1);
+        assert!(even, "Dimension {} gave {:?}", dimension, counts);
+    }
+}
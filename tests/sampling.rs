@@ -0,0 +1,190 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{close, TOLERANCE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::consts::PI;
+use ray_tracing::sampling::{
+    cosine_hemisphere, cosine_hemisphere_pdf, disk, disk_pdf, ggx_d, ggx_reflection_pdf,
+    ggx_visible_normal, spherical_fibonacci, uniform_cone, uniform_cone_pdf, uniform_hemisphere,
+    uniform_hemisphere_pdf, uniform_sphere, uniform_sphere_pdf,
+};
+use ray_tracing::{Float, Vec3};
+
+/// Seeded random pairs of sample values
+///
+/// Random rather than on a grid, which the mappings bend into rings that line up with the bands
+/// tests count samples in
+fn samples() -> impl Iterator<Item = (Float, Float)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..50_000).map(move |_| (rng.gen(), rng.gen()))
+}
+
+/// Asserts the share of `directions` with z between each pair of `bands` is what `expected`
+/// gives for that band, and that all of them are unit vectors
+fn assert_bands(directions: &[Vec3], bands: &[Float], expected: impl Fn(Float, Float) -> Float) {
+    for dir in directions {
+        assert!(close(dir.length(), 1.), "{} isn't a unit vector", dir);
+    }
+    for pair in bands.windows(2) {
+        let (low, high) = (pair[0], pair[1]);
+        let inside = directions
+            .iter()
+            .filter(|dir| dir[2] >= low && dir[2] < high)
+            .count();
+        let share = inside as Float / directions.len() as Float;
+        let expected = expected(low, high);
+        assert!(
+            (share - expected).abs() < 0.01,
+            "{} of directions have z from {} to {}, expected {}",
+            share,
+            low,
+            high,
+            expected
+        );
+    }
+}
+
+#[test]
+fn spheres_and_hemispheres_are_covered_evenly() {
+    let sphere: Vec<_> = samples().map(uniform_sphere).collect();
+    assert_bands(&sphere, &[-1., -0.5, 0., 0.7, 1.01], |a, b| {
+        (b.min(1.) - a) / 2.
+    });
+    let hemisphere: Vec<_> = samples().map(uniform_hemisphere).collect();
+    assert_bands(&hemisphere, &[0., 0.3, 0.8, 1.01], |a, b| b.min(1.) - a);
+    assert!(close(uniform_sphere_pdf() * 4. * PI, 1.));
+    assert!(close(uniform_hemisphere_pdf() * 2. * PI, 1.));
+}
+
+#[test]
+fn cosine_directions_lean_towards_the_pole() {
+    let directions: Vec<_> = samples().map(cosine_hemisphere).collect();
+    // The share within `acos(z)` of the pole is `1 - z²`
+    let share = |a: Float, b: Float| b.min(1.).powi(2) - a * a;
+    assert_bands(&directions, &[0., 0.3, 0.6, 0.9, 1.01], share);
+    // Dividing the cosine by the density estimates the integral of the cosine, which is π
+    let estimate: Float = directions
+        .iter()
+        .map(|dir| dir[2] / cosine_hemisphere_pdf(dir[2]))
+        .sum::<Float>()
+        / directions.len() as Float;
+    assert!(close(estimate, PI));
+    assert_eq!(cosine_hemisphere_pdf(-0.5), 0.);
+}
+
+#[test]
+fn cones_stay_within_their_angle() {
+    let cos_max = 0.8;
+    let directions: Vec<_> = samples()
+        .map(|sample| uniform_cone(sample, cos_max))
+        .collect();
+    assert!(directions.iter().all(|dir| dir[2] >= cos_max - TOLERANCE));
+    assert_bands(&directions, &[0.8, 0.9, 0.95, 1.01], |a, b| {
+        (b.min(1.) - a) / (1. - cos_max)
+    });
+    let solid_angle = 2. * PI * (1. - cos_max);
+    assert!(close(uniform_cone_pdf(cos_max) * solid_angle, 1.));
+}
+
+#[test]
+fn disks_are_covered_evenly() {
+    let points: Vec<_> = samples().map(disk).collect();
+    assert!(points
+        .iter()
+        .all(|p| p.length() <= 1. + TOLERANCE && p[2] == 0.));
+    for &radius in &[0.25, 0.5, 0.75] {
+        let inside = points.iter().filter(|p| p.length() < radius).count();
+        let share = inside as Float / points.len() as Float;
+        let expected = radius * radius;
+        assert!(
+            (share - expected).abs() < 0.01,
+            "{} of points are within {}, expected {}",
+            share,
+            radius,
+            expected
+        );
+    }
+    assert_eq!(disk((0.5, 0.5)).length(), 0.);
+    assert!(close(disk_pdf() * PI, 1.));
+}
+
+#[test]
+fn fibonacci_points_spread_over_the_sphere() {
+    let count = 500;
+    let points: Vec<_> = (0..count)
+        .map(|index| spherical_fibonacci(index, count))
+        .collect();
+    assert_bands(&points, &[-1., -0.5, 0., 0.7, 1.01], |a, b| {
+        (b.min(1.) - a) / 2.
+    });
+    let mean = points.iter().fold(Vec3::default(), |sum, &p| sum + p) / count as Float;
+    assert!(mean.length() < 0.01, "Points bunch up towards {}", mean);
+    // Each point takes up about `4π / count` of the sphere, so neighbours are about the square
+    // root of that apart
+    let spacing = (4. * PI / count as Float).sqrt();
+    for (i, a) in points.iter().enumerate() {
+        let nearest = points
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, b)| (*a - *b).length())
+            .fold(Float::INFINITY, Float::min);
+        assert!(nearest > 0.5 * spacing, "Point {} is crowded", i);
+    }
+}
+
+#[test]
+fn ggx_reflections_match_their_density() {
+    for &(alpha, view_z) in &[(0.3, 0.9), (0.5, 0.5), (0.9, 0.15 as Float)] {
+        let view = vec3!((1. - view_z * view_z).sqrt(), 0., view_z);
+        // Projected normals cover the disk under the surface once
+        let projected: Float = samples()
+            .map(|sample| {
+                let dir = uniform_hemisphere(sample);
+                ggx_d(dir[2], alpha) * dir[2] / uniform_hemisphere_pdf()
+            })
+            .sum::<Float>()
+            / 50_000.;
+        assert!(
+            (projected - 1.).abs() < 0.05,
+            "{} for alpha {}",
+            projected,
+            alpha
+        );
+
+        let reflections: Vec<_> = samples()
+            .map(|sample| {
+                let normal = ggx_visible_normal(sample, view, alpha);
+                assert!(normal[2] >= 0. && normal.dot(&view) >= -TOLERANCE);
+                (-view).reflect(&normal)
+            })
+            .collect();
+        // The density integrated over each band of heights, on a grid of heights and angles
+        // around z, as solid angle is even in both
+        let band_density = |low: Float, high: Float| {
+            let steps = 400;
+            let (dz, dphi) = (
+                (high.min(1.) - low) / steps as Float,
+                2. * PI / steps as Float,
+            );
+            let mut total = 0.;
+            for i in 0..steps {
+                let z = low + (i as Float + 0.5) * dz;
+                for j in 0..steps {
+                    let phi = (j as Float + 0.5) * dphi;
+                    let r = (1. - z * z).sqrt();
+                    let dir = vec3!(r * phi.cos(), r * phi.sin(), z);
+                    total += ggx_reflection_pdf(view, dir, alpha) * dz * dphi;
+                }
+            }
+            total
+        };
+        assert_bands(&reflections, &[0., 0.3, 0.6, 0.9, 1.01], band_density);
+        let below = reflections.iter().filter(|dir| dir[2] < 0.).count() as Float / 50_000.;
+        assert!((1. - below - band_density(0., 1.01)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,91 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::background::{Background, PreethamSky, SKY_INTENSITY};
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::{BackgroundDesc, Scene};
+use ray_tracing::{Color, Float, Point3, Vec3};
+
+/// Light seen looking along `dir`
+fn look(sky: &PreethamSky, dir: Vec3) -> Color {
+    sky.color(&Ray::new(point3!(), dir, 0.))
+}
+
+/// Sun 30° up towards +x
+fn afternoon() -> PreethamSky {
+    let elevation = (30. as Float).to_radians();
+    PreethamSky::new(vec3!(elevation.cos(), elevation.sin(), 0.), 3.)
+}
+
+#[test]
+fn skies_brighten_towards_the_sun_and_horizon() {
+    let sky = afternoon();
+    let zenith = look(&sky, vec3!(0., 1., 0.));
+    let near_sun = look(&sky, vec3!(1., 0.8, 0.));
+    let away = look(&sky, vec3!(-1., 0.8, 0.));
+    let horizon = look(&sky, vec3!(-1., 0.05, 0.));
+    assert!(
+        zenith.luminance() > 0.1 && zenith.luminance() < 2.,
+        "{}",
+        zenith
+    );
+    assert!(near_sun.luminance() > 2. * away.luminance());
+    assert!(horizon.luminance() > away.luminance());
+    // Blue overhead, whiter by the sun
+    assert!(zenith[2] > zenith[0]);
+    assert!(near_sun[2] / near_sun[0] < zenith[2] / zenith[0]);
+
+    // Below the horizon is the horizon's color
+    assert_eq!(
+        look(&sky, vec3!(-1., -0.5, 0.)),
+        look(&sky, vec3!(-1., 0., 0.))
+    );
+    assert!(look(&sky, vec3!(0., -1., 0.)).luminance() > 0.);
+}
+
+#[test]
+fn suns_redden_as_they_set() {
+    let noon = PreethamSky::new(vec3!(0., 1., 0.), 3.).sun_light(0.53);
+    let evening = PreethamSky::new(vec3!(1., 0.05, 0.), 3.).sun_light(0.53);
+    assert_eq!(noon.direction, vec3!(0., 1., 0.));
+    assert!(noon.irradiance.luminance() > 3. * evening.irradiance.luminance());
+    let redness = |color: Color| color[0] / color[2];
+    assert!(redness(evening.irradiance) > 2. * redness(noon.irradiance));
+    assert!(noon.irradiance[2] > 0.5 * noon.irradiance[0]);
+
+    let night = PreethamSky::new(vec3!(1., -0.2, 0.), 3.).sun_light(0.53);
+    assert_eq!(night.irradiance, color!());
+}
+
+#[test]
+fn scene_files_build_preetham_skies() {
+    let scene = Scene::from_json(
+        r#"{
+            "camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 },
+            "background": { "type": "preetham", "sun_direction": [0, 1, 1] },
+            "sun": { "direction": [0, 1, 1], "irradiance": [10, 9, 8] },
+            "objects": []
+        }"#,
+    )
+    .expect("Error building scene");
+    let described = scene.settings.background.describe();
+    match described {
+        Some(BackgroundDesc::Preetham {
+            sun_direction,
+            turbidity,
+            intensity,
+        }) => {
+            let expected = vec3!(0., 1., 1.).unit_vector();
+            let found = Vec3::from(sun_direction);
+            assert!((0..3).all(|i| close(found[i], expected[i])), "{}", found);
+            assert_eq!((turbidity, intensity), (3., SKY_INTENSITY));
+        }
+        _ => panic!("Expected a Preetham sky"),
+    }
+    let ray = Ray::new(point3!(), vec3!(0., 1., 0.), 0.);
+    let expected = PreethamSky::new(vec3!(0., 1., 1.), 3.).color(&ray);
+    assert_eq!(scene.settings.background.color(&ray), expected);
+}
@@ -0,0 +1,180 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::{describe_materials, hit_floor, scene_from_json};
+use ray_tracing::hittable::{HitRecord, Hittable, Sphere, HIT_EPSILON};
+use ray_tracing::integrator::PathState;
+use ray_tracing::material::{Dielectric, Light, Material, Metal};
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::scene::{AbsorptionDesc, MaterialDesc, ScalarDesc, TextureDesc};
+use ray_tracing::texture::{SolidColor, Texture};
+use ray_tracing::{Color, Float, Point3, Vec3};
+use std::sync::Arc;
+
+/// `low` where `u` is below a half and `high` above
+struct Halves {
+    low: Color,
+    high: Color,
+}
+
+impl Texture for Halves {
+    fn value(&self, u: Float, _: Float, _: Point3) -> Color {
+        if u < 0.5 {
+            self.low
+        } else {
+            self.high
+        }
+    }
+}
+
+fn halves(low: Float, high: Float) -> Halves {
+    Halves {
+        low: color!(low, low, low),
+        high: color!(high, high, high),
+    }
+}
+
+/// Attenuation of one scattered ray
+fn attenuation(ray: &Ray, rec: &HitRecord) -> Color {
+    let mut ctx = SampleCtx::from_seed(Some(1));
+    let (_, attenuation) = rec
+        .material
+        .scatter(ray, rec, &PathState::camera(), &mut ctx)
+        .expect("Expected the ray to scatter");
+    attenuation
+}
+
+#[test]
+fn metal_fuzz_follows_its_texture() {
+    let metal = || Metal::textured(SolidColor::new(color!(1., 1., 1.)), halves(0., 0.5));
+    let up = vec3!(0., 1., 0.);
+    // Sharp mirrors can't be lit directly, fuzzy metal can
+    let sharp = hit_floor(metal(), -0.5, |ray, rec| rec.material.eval(ray, rec, &up));
+    assert!(sharp.is_none());
+    let fuzzy = hit_floor(metal(), 0.5, |ray, rec| rec.material.eval(ray, rec, &up));
+    assert!(fuzzy.is_some());
+    assert!(
+        hit_floor(metal(), 0.5, |ray, rec| {
+            rec.material.scattering_pdf(ray, rec, &up)
+        }) > 0.
+    );
+}
+
+#[test]
+fn metal_albedo_and_emission_follow_their_textures() {
+    let metal = || {
+        Metal::textured(halves(0.2, 0.9), SolidColor::new(color!()))
+            .with_emission(Arc::new(halves(0., 3.)))
+    };
+    let left = hit_floor(metal(), -0.5, |ray, rec| {
+        (attenuation(ray, rec), rec.material.emitted(ray, rec))
+    });
+    let right = hit_floor(metal(), 0.5, |ray, rec| {
+        (attenuation(ray, rec), rec.material.emitted(ray, rec))
+    });
+    assert_eq!(left, (color!(0.2, 0.2, 0.2), color!()));
+    assert_eq!(right, (color!(0.9, 0.9, 0.9), color!(3., 3., 3.)));
+    let plain = hit_floor(Metal::new(color!(0.5, 0.5, 0.5), 0.), 0.5, |ray, rec| {
+        rec.material.emitted(ray, rec)
+    });
+    assert_eq!(plain, color!());
+}
+
+#[test]
+fn light_emission_follows_its_texture() {
+    let light = || Light::new(halves(0.5, 2.), color!(2., 1., 0.5));
+    let emitted = |x| hit_floor(light(), x, |ray, rec| rec.material.emitted(ray, rec));
+    assert_eq!(emitted(-0.5), color!(1., 0.5, 0.25));
+    assert_eq!(emitted(0.5), color!(4., 2., 1.));
+}
+
+#[test]
+fn dielectrics_can_be_tinted_and_glow() {
+    let glass = || {
+        Dielectric::new(1.5)
+            .with_tint(Arc::new(halves(0.4, 0.8)))
+            .with_emission(Arc::new(SolidColor::new(color!(0., 1., 0.))))
+    };
+    let tinted = |x| {
+        hit_floor(glass(), x, |ray, rec| {
+            (attenuation(ray, rec), rec.material.emitted(ray, rec))
+        })
+    };
+    assert_eq!(tinted(-0.5), (color!(0.4, 0.4, 0.4), color!(0., 1., 0.)));
+    assert_eq!(tinted(0.5).0, color!(0.8, 0.8, 0.8));
+    let clear = hit_floor(Dielectric::new(1.5), 0., attenuation);
+    assert_eq!(clear, color!(1., 1., 1.));
+}
+
+#[test]
+fn absorbing_dielectrics_darken_with_distance() {
+    let glass = || Dielectric::new(1.5).with_absorption(color!(0.5, 0.8, 1.), 2.);
+    // Light that crossed a sphere from its center to its surface
+    let through = |radius: Float| {
+        let sphere = Sphere::new(point3!(), radius, glass());
+        let ray = Ray::new(point3!(), vec3!(0., 0., 2.), 0.);
+        let rec = sphere
+            .hit(&ray, HIT_EPSILON, Float::INFINITY)
+            .expect("Expected to hit the sphere from inside");
+        assert!(!rec.front_face);
+        attenuation(&ray, &rec)
+    };
+    let close = |a: Color, b: Color| (0..3).all(|i| (a[i] - b[i]).abs() < 1e-6);
+    assert!(close(through(0.5), color!(0.5, 0.8, 1.)));
+    assert!(close(through(1.), color!(0.25, 0.64, 1.)));
+    // Entering isn't absorbed, however far the ray came
+    let entering = hit_floor(glass(), 0., attenuation);
+    assert_eq!(entering, color!(1., 1., 1.));
+    assert!(Dielectric::new(1.5)
+        .with_absorption(color!(0.5, 0.5, 0.5), -1.)
+        .validate()
+        .iter()
+        .any(|problem| problem.contains("density")));
+}
+
+#[test]
+fn scene_files_build_textured_metals_and_dielectrics() {
+    let scene = scene_from_json(
+        r#""objects": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+              "material": { "type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.25 } },
+            { "type": "sphere", "center": [3, 0, 0], "radius": 1,
+              "material": { "type": "metal",
+                            "albedo": { "type": "noise", "seed": 1, "scale": 4 },
+                            "fuzz": { "type": "checker", "odd": [0, 0, 0],
+                                      "even": [0.5, 0.5, 0.5] },
+                            "emission": [1, 0.5, 0] } },
+            { "type": "sphere", "center": [6, 0, 0], "radius": 1,
+              "material": { "type": "dielectric", "ior": 1.5, "tint": [0.9, 1, 0.9],
+                            "absorption": { "color": [0.2, 0.5, 0.9] } } }
+        ]"#,
+    );
+    let described = describe_materials(&scene);
+    assert!(matches!(
+        described[..],
+        [
+            Some(MaterialDesc::Metal {
+                albedo: TextureDesc::Color([0.8, 0.8, 0.8]),
+                fuzz: ScalarDesc::Value(fuzz),
+                emission: None,
+            }),
+            Some(MaterialDesc::Metal {
+                albedo: TextureDesc::Texture(_),
+                fuzz: ScalarDesc::Texture(TextureDesc::Texture(_)),
+                emission: Some(TextureDesc::Color([1., 0.5, 0.])),
+            }),
+            Some(MaterialDesc::Dielectric {
+                tint: Some(TextureDesc::Color([0.9, 1., 0.9])),
+                emission: None,
+                absorption: Some(AbsorptionDesc {
+                    color: [0.2, 0.5, 0.9],
+                    density,
+                }),
+                ..
+            })
+        ] if fuzz == 0.25 && density == 1.
+    ));
+}
@@ -0,0 +1,62 @@
+#[macro_use]
+extern crate ray_tracing;
+
+mod common;
+
+use common::close;
+use ray_tracing::background::SolidBackground;
+use ray_tracing::hittable::Cuboid;
+use ray_tracing::integrator::{Integrator, PathIntegrator};
+use ray_tracing::material::{Material, Metal};
+use ray_tracing::ray::Ray;
+use ray_tracing::sampler::SampleCtx;
+use ray_tracing::world::World;
+use ray_tracing::{Color, Point3, RenderSettings, Vec3};
+use std::sync::Arc;
+
+/// Mirror that stops paths after one bounce off it in a row
+fn mirror() -> Arc<dyn Material + Send + Sync> {
+    let mut mirror = Metal::new(color!(0.5, 0.5, 0.5), 0.);
+    mirror.visibility.max_depth = Some(1);
+    Arc::new(mirror)
+}
+
+/// Light seen along a ray that bounces off a mirrored floor, then a mirrored wall, then reaches
+/// a white background
+fn seen_off(
+    floor: Arc<dyn Material + Send + Sync>,
+    wall: Arc<dyn Material + Send + Sync>,
+) -> Color {
+    let mut world = World::default();
+    world.add(Cuboid::new_shared(
+        point3!(-10., -2., -10.),
+        point3!(10., -1., 10.),
+        floor,
+    ));
+    world.add(Cuboid::new_shared(
+        point3!(3., 0.5, -10.),
+        point3!(4., 10., 10.),
+        wall,
+    ));
+    let settings = RenderSettings {
+        max_depth: 10,
+        background: Box::new(SolidBackground::new(color!(1., 1., 1.))),
+        ..Default::default()
+    };
+    let ray = Ray::new(point3!(0., 0., 0.), vec3!(1., -1., 0.), 0.);
+    let mut ctx = SampleCtx::from_seed(Some(1));
+    PathIntegrator.li(&ray, &world, &settings, &mut ctx)
+}
+
+#[test]
+fn bounce_limits_count_bounces_off_each_material() {
+    let color = seen_off(mirror(), mirror());
+    assert!(close(color[0], 0.25), "{:?}", color);
+}
+
+#[test]
+fn bounce_limits_stop_paths_that_stay_on_a_material() {
+    let shared = mirror();
+    let color = seen_off(shared.clone(), shared);
+    assert_eq!(color, color!());
+}